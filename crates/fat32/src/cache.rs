@@ -2,7 +2,7 @@
 //! The reason why BlockCache uses Vec<u8>: https://github.com/rcore-os/rCore-Tutorial-v3/pull/79
 //! alse see atricle: https://gitlab.eduxiji.net/2019301887/oskernel2022-npucore/-/blob/master/Doc/debug/Virt-IO%E9%A9%B1%E5%8A%A8bug.md
 
-use super::device::BlockDevice;
+use super::device::{BlockDevice, DevError};
 use super::{BLOCK_CACHE_LIMIT, BLOCK_SIZE};
 
 // use alloc::collections::VecDeque;
@@ -32,7 +32,7 @@ pub trait Cache {
     ///
     /// - `block_ids`: block ids in this cache
     /// - `block_device`: The pointer to the block_device.
-    fn sync(&mut self);
+    fn sync(&mut self) -> Result<(), DevError>;
 }
 
 pub struct BlockCache {
@@ -46,15 +46,15 @@ pub struct BlockCache {
 
 impl BlockCache {
     // load a block from the disk
-    pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>) -> Self {
+    pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>) -> Result<Self, DevError> {
         let mut cache = vec![0 as u8; BLOCK_SIZE];
-        block_device.read_block(block_id, &mut cache);
-        Self {
+        block_device.read_block(block_id, &mut cache)?;
+        Ok(Self {
             cache,
             block_id,
             block_device,
             modified: false,
-        }
+        })
     }
     fn addr_of_offset(&self, offset: usize) -> usize {
         &self.cache[offset] as *const _ as usize
@@ -88,21 +88,46 @@ impl Cache for BlockCache {
         f(self.get_mut(offset))
     }
     // write the content back to disk
-    fn sync(&mut self) {
+    fn sync(&mut self) -> Result<(), DevError> {
         // TODO need to consider reference count?
         if self.modified {
+            // stays modified on failure, so that a later sync can retry
+            self.block_device
+                .write_block(self.block_id, &self.cache)
+                .map_err(|err| {
+                    let device_id = device_id(&self.block_device);
+                    let mut write_failed = WRITE_FAILED.lock();
+                    if !write_failed.contains(&device_id) {
+                        write_failed.push(device_id);
+                    }
+                    err
+                })?;
             self.modified = false;
-            self.block_device.write_block(self.block_id, &self.cache)
         }
+        Ok(())
     }
 }
 
 impl Drop for BlockCache {
     fn drop(&mut self) {
-        self.sync()
+        // An evicted block has no caller to report to, the failure is recorded in WRITE_FAILED
+        let _ = self.sync();
     }
 }
 
+/// Block devices on which a block write has failed, identified by [`device_id`].
+/// Only the file system on such a device becomes read-only (see [`crate::FileSystem::is_read_only`]).
+static WRITE_FAILED: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+fn device_id(block_device: &Arc<dyn BlockDevice>) -> usize {
+    Arc::as_ptr(block_device) as *const () as usize
+}
+
+/// Whether a block write to `block_device` has ever failed
+pub fn write_failed(block_device: &Arc<dyn BlockDevice>) -> bool {
+    WRITE_FAILED.lock().contains(&device_id(block_device))
+}
+
 pub struct BlockCacheManager {
     lru: LruCache<usize, Arc<RwLock<BlockCache>>>,
 }
@@ -119,16 +144,16 @@ impl BlockCacheManager {
         &mut self,
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
-    ) -> Arc<RwLock<BlockCache>> {
+    ) -> Result<Arc<RwLock<BlockCache>>, DevError> {
         // if the block is already in lru_cache, just return the copy
         if let Some(pair) = self.lru.get(&block_id) {
-            Arc::clone(pair)
+            Ok(Arc::clone(pair))
         } else {
             // if the block is not in lru_cache, create a new block_cache
             let block_cache = Arc::new(RwLock::new(BlockCache::new(
                 block_id,
                 Arc::clone(&block_device),
-            )));
+            )?));
             // if the lru_cache is full, write back the least recently used block_cache
             if self.lru.len() == BLOCK_CACHE_LIMIT {
                 let (_, peek_cache) = self.lru.peek_lru().unwrap();
@@ -139,13 +164,30 @@ impl BlockCacheManager {
             } else {
                 self.lru.put(block_id, Arc::clone(&block_cache));
             }
-            block_cache
+            Ok(block_cache)
         }
     }
-    pub fn sync_all(&mut self) {
+    /// Write back all blocks, returns the first error after trying all of them
+    pub fn sync_all(&mut self) -> Result<(), DevError> {
+        let mut res = Ok(());
         for (_, block_cache) in self.lru.iter() {
-            block_cache.write().sync();
+            if let Err(err) = block_cache.write().sync() {
+                res = res.and(Err(err));
+            }
+        }
+        res
+    }
+    /// Write back the given blocks that are cached, returns the first error after trying all of them
+    pub fn sync_blocks(&mut self, block_ids: &[usize]) -> Result<(), DevError> {
+        let mut res = Ok(());
+        for block_id in block_ids {
+            if let Some(block_cache) = self.lru.peek(block_id) {
+                if let Err(err) = block_cache.write().sync() {
+                    res = res.and(Err(err));
+                }
+            }
         }
+        res
     }
 }
 
@@ -163,11 +205,14 @@ pub static BLOCK_CACHE_MANAGER: Lazy<Mutex<BlockCacheManager>> =
 pub fn get_block_cache(
     block_id: usize,
     block_device: Arc<dyn BlockDevice>,
-) -> Arc<RwLock<BlockCache>> {
+) -> Result<Arc<RwLock<BlockCache>>, DevError> {
     BLOCK_CACHE_MANAGER
         .lock()
         .get_block_cache(block_id, block_device)
 }
-pub fn sync_all() {
-    BLOCK_CACHE_MANAGER.lock().sync_all();
+pub fn sync_all() -> Result<(), DevError> {
+    BLOCK_CACHE_MANAGER.lock().sync_all()
+}
+pub fn sync_blocks(block_ids: &[usize]) -> Result<(), DevError> {
+    BLOCK_CACHE_MANAGER.lock().sync_blocks(block_ids)
 }
@@ -1,7 +1,20 @@
 use core::any::Any;
 use core::marker::{Send, Sync};
 
+/// Errors of block I/O, reported up to the callers of the file system
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DevError {
+    /// The device failed to read the block with this id
+    ReadFailed(usize),
+    /// The device failed to write the block with this id
+    WriteFailed(usize),
+    /// The file system has become read-only after a write failure
+    ReadOnly,
+}
+
 pub trait BlockDevice: Send + Sync + Any {
-    fn read_block(&self, blk_id: usize, buf: &mut [u8]);
-    fn write_block(&self, blk_id: usize, buf: &[u8]);
+    fn read_block(&self, blk_id: usize, buf: &mut [u8]) -> Result<(), DevError>;
+    fn write_block(&self, blk_id: usize, buf: &[u8]) -> Result<(), DevError>;
+    /// Number of blocks on the device
+    fn num_blocks(&self) -> usize;
 }
@@ -15,6 +15,7 @@
 // ...
 // Note: Fat32 specifies that the size of the directory file is 0
 
+use super::device::DevError;
 use super::entry::{LongDirEntry, ShortDirEntry};
 use super::vf::{DirEntryPos, VirtFile, VirtFileType};
 
@@ -46,6 +47,13 @@ pub enum DirError {
     ListLFNIllegal,
     CreateFileError,
     MissingName,
+    Device(DevError),
+}
+
+impl From<DevError> for DirError {
+    fn from(err: DevError) -> Self {
+        DirError::Device(err)
+    }
 }
 
 pub trait Dir {
@@ -71,7 +79,7 @@ impl Dir for VirtFile {
             if !current.is_dir() {
                 return Err(DirError::NotDir);
             }
-            if let Some(vfile) = current.find_by_name(path[i]) {
+            if let Some(vfile) = current.find_by_name(path[i])? {
                 current = vfile;
             } else {
                 return Err(DirError::NoMatch);
@@ -82,7 +90,7 @@ impl Dir for VirtFile {
     fn remove(&self, path: Vec<&str>) -> Result<(), DirError> {
         match self.find(path) {
             Ok(file) => {
-                file.clear();
+                file.clear()?;
                 Ok(())
             }
             Err(e) => Err(e),
@@ -106,7 +114,7 @@ impl Dir for VirtFile {
     fn create(&self, name: &str, file_type: VirtFileType) -> Result<VirtFile, DirError> {
         // serach same name file
         assert!(self.is_dir());
-        let option = self.find_by_name(name);
+        let option = self.find_by_name(name)?;
         if let Some(file) = option {
             if file.virt_file_type() == file_type {
                 return Err(DirError::FileHasExist);
@@ -153,7 +161,7 @@ impl Dir for VirtFile {
                     sde.gen_check_sum(),
                 );
                 // Write the long file name directory entry
-                let write_size = self.write_at(entry_offset, lde.as_bytes());
+                let write_size = self.write_at(entry_offset, lde.as_bytes())?;
                 assert_eq!(write_size, DIRENT_SIZE);
                 // Update the write position
                 entry_offset += DIRENT_SIZE;
@@ -169,46 +177,43 @@ impl Dir for VirtFile {
             let order: u8 = 1 | 0x40;
             let name_array = long_name_split(name)[0];
             let lde = LongDirEntry::new_form_name_slice(order, name_array, sde.gen_check_sum());
-            let write_size = self.write_at(entry_offset, lde.as_bytes());
+            let write_size = self.write_at(entry_offset, lde.as_bytes())?;
             assert_eq!(write_size, DIRENT_SIZE);
             entry_offset += DIRENT_SIZE;
         }
 
         // write short dirent(there is also a short dirent for long file name)
-        let wirte_size = self.write_at(entry_offset, sde.as_bytes());
+        let wirte_size = self.write_at(entry_offset, sde.as_bytes())?;
         assert_eq!(wirte_size, DIRENT_SIZE);
+        let first_cluster = self.first_cluster()?;
         assert!(
-            self.first_cluster() >= 2,
+            first_cluster >= 2,
             "[fat32::Dir::create] first_cluster:{}",
-            self.first_cluster()
+            first_cluster
         );
 
         // validation
-        if let Some(file) = self.find_by_name(name) {
+        if let Some(file) = self.find_by_name(name)? {
             // 如果是目录类型, 需要创建.和..
             if file_type == VirtFileType::Dir {
                 // First, write ".." to make the directory obtain the first cluster
                 // (otherwise, increase_size will not allocate a cluster and will
                 // return directly, causing the first_cluster to be 0, leading to a panic)
                 let (_name, _ext) = short_name_format("..");
-                let mut parent_sde = ShortDirEntry::new(
-                    self.first_cluster() as u32,
-                    &_name,
-                    &_ext,
-                    VirtFileType::Dir,
-                );
+                let mut parent_sde =
+                    ShortDirEntry::new(first_cluster as u32, &_name, &_ext, VirtFileType::Dir);
                 // According to FAT32 specifications, the directory file size is 0,
                 // so do not update the size of the directory file.
-                file.write_at(DIRENT_SIZE, parent_sde.as_bytes_mut());
+                file.write_at(DIRENT_SIZE, parent_sde.as_bytes_mut())?;
 
                 let (_name, _ext) = short_name_format(".");
                 let mut self_sde = ShortDirEntry::new(
-                    file.first_cluster() as u32,
+                    file.first_cluster()? as u32,
                     &_name,
                     &_ext,
                     VirtFileType::Dir,
                 );
-                file.write_at(0, self_sde.as_bytes_mut());
+                file.write_at(0, self_sde.as_bytes_mut())?;
             }
             Ok(file)
         } else {
@@ -219,7 +224,7 @@ impl Dir for VirtFile {
 
 impl VirtFile {
     // Dir Functions
-    fn find_by_lfn(&self, name: &str) -> Option<VirtFile> {
+    fn find_by_lfn(&self, name: &str) -> Result<Option<VirtFile>, DevError> {
         let name_vec = long_name_split(name);
         let name_cnt = name_vec.len();
 
@@ -228,9 +233,9 @@ impl VirtFile {
         let mut lde_pos_vec: Vec<DirEntryPos> = Vec::new();
         let name_last = name_vec[name_cnt - 1].clone();
         loop {
-            let mut read_size = self.read_at(index, lde.as_bytes_mut());
+            let mut read_size = self.read_at(index, lde.as_bytes_mut())?;
             if read_size != DIRENT_SIZE {
-                return None;
+                return Ok(None);
             }
 
             // First, match the last long file name directory entry,
@@ -255,9 +260,9 @@ impl VirtFile {
                 // long name directory entries
                 let mut is_match = true;
                 for i in 1..order as usize {
-                    read_size = self.read_at(index + i * DIRENT_SIZE, lde.as_bytes_mut());
+                    read_size = self.read_at(index + i * DIRENT_SIZE, lde.as_bytes_mut())?;
                     if read_size != DIRENT_SIZE {
-                        return None;
+                        return Ok(None);
                     }
                     // Match the previous name field, and exit if it fails
                     if lde.name_utf16() != name_vec[name_cnt - 1 - i]
@@ -272,17 +277,17 @@ impl VirtFile {
                     let checksum = lde.check_sum();
                     let mut sde = ShortDirEntry::empty();
                     let sde_offset = index + name_cnt * DIRENT_SIZE;
-                    read_size = self.read_at(sde_offset, sde.as_bytes_mut());
+                    read_size = self.read_at(sde_offset, sde.as_bytes_mut())?;
                     if read_size != DIRENT_SIZE {
-                        return None;
+                        return Ok(None);
                     }
                     if !sde.is_deleted() && checksum == sde.gen_check_sum() {
-                        let sde_pos = self.dirent_cluster_pos(sde_offset).unwrap();
+                        let sde_pos = self.dirent_cluster_pos(sde_offset)?;
                         for i in 0..order as usize {
                             // The positions of the long name directory entries are stored,
                             // with the first one at the top of the stack.
-                            let lde_pos = self.dirent_cluster_pos(index + i * DIRENT_SIZE);
-                            lde_pos_vec.push(lde_pos.unwrap());
+                            let lde_pos = self.dirent_cluster_pos(index + i * DIRENT_SIZE)?;
+                            lde_pos_vec.push(lde_pos);
                         }
                         let file_type = if sde.attr() == ATTR_DIRECTORY {
                             VirtFileType::Dir
@@ -290,9 +295,9 @@ impl VirtFile {
                             VirtFileType::File
                         };
 
-                        let clus_chain = self.generate_cluster_chain(sde_offset);
+                        let clus_chain = self.generate_cluster_chain(sde_offset)?;
 
-                        return Some(VirtFile::new(
+                        return Ok(Some(VirtFile::new(
                             String::from(name),
                             sde_pos,
                             lde_pos_vec,
@@ -300,7 +305,7 @@ impl VirtFile {
                             Arc::clone(&self.device),
                             Arc::new(RwLock::new(clus_chain)),
                             file_type,
-                        ));
+                        )));
                     }
                 }
             }
@@ -308,27 +313,27 @@ impl VirtFile {
         }
     }
 
-    fn find_by_sfn(&self, name: &str) -> Option<VirtFile> {
+    fn find_by_sfn(&self, name: &str) -> Result<Option<VirtFile>, DevError> {
         let name = name.to_ascii_uppercase();
         let mut sde = ShortDirEntry::empty();
         let mut index = 0;
         loop {
-            let read_size = self.read_at(index, sde.as_bytes_mut());
+            let read_size = self.read_at(index, sde.as_bytes_mut())?;
             if read_size != DIRENT_SIZE {
-                return None;
+                return Ok(None);
             }
 
             // check if the names are the same:
             if !sde.is_deleted() && name == sde.get_name_uppercase() {
-                let sde_pos = self.dirent_cluster_pos(index).unwrap();
+                let sde_pos = self.dirent_cluster_pos(index)?;
                 let lde_pos_vec: Vec<DirEntryPos> = Vec::new();
                 let file_type = if sde.attr() == ATTR_DIRECTORY {
                     VirtFileType::Dir
                 } else {
                     VirtFileType::File
                 };
-                let clus_chain = self.generate_cluster_chain(index);
-                return Some(VirtFile::new(
+                let clus_chain = self.generate_cluster_chain(index)?;
+                return Ok(Some(VirtFile::new(
                     String::from(name),
                     sde_pos,
                     lde_pos_vec,
@@ -336,7 +341,7 @@ impl VirtFile {
                     Arc::clone(&self.device),
                     Arc::new(RwLock::new(clus_chain)),
                     file_type,
-                ));
+                )));
             } else {
                 index += DIRENT_SIZE;
                 continue;
@@ -344,7 +349,7 @@ impl VirtFile {
         }
     }
 
-    pub fn find_by_name(&self, name: &str) -> Option<VirtFile> {
+    pub fn find_by_name(&self, name: &str) -> Result<Option<VirtFile>, DevError> {
         assert!(self.is_dir());
         let (name_, ext_) = split_name_ext(name);
         if name_.len() > 8 || ext_.len() > 3 {
@@ -363,7 +368,7 @@ impl VirtFile {
         let mut sde = ShortDirEntry::empty();
         let mut index = 0;
         loop {
-            let read_size = self.read_at(index, sde.as_bytes_mut());
+            let read_size = self.read_at(index, sde.as_bytes_mut())?;
             // Reached the end of the directory file ->
             // exceeded dir_size, need to allocate a new cluster ->
             // handled in write_at ->
@@ -394,7 +399,7 @@ impl VirtFile {
         let mut entry = LongDirEntry::empty();
         let mut offset = 0usize;
         loop {
-            let read_size = self.read_at(offset, entry.as_bytes_mut());
+            let read_size = self.read_at(offset, entry.as_bytes_mut())?;
             // Finished reading
             if read_size != DIRENT_SIZE || entry.is_empty() {
                 return Ok(list);
@@ -417,7 +422,7 @@ impl VirtFile {
                 for _ in 0..order {
                     name.insert_str(0, &entry.name().as_str());
                     offset += DIRENT_SIZE;
-                    let read_size = self.read_at(offset, entry.as_bytes_mut());
+                    let read_size = self.read_at(offset, entry.as_bytes_mut())?;
                     if read_size != DIRENT_SIZE || entry.is_empty() {
                         return Err(DirError::ListLFNIllegal);
                     }
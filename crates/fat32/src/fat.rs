@@ -7,7 +7,7 @@
 
 use super::cache::get_block_cache;
 use super::cache::Cache;
-use super::device::BlockDevice;
+use super::device::{BlockDevice, DevError};
 use super::read_le_u32;
 use super::{BLOCK_SIZE, CLUSTER_MASK, END_OF_CLUSTER};
 
@@ -71,9 +71,9 @@ impl ClusterChain {
     /// Initail cluster_vec from disk.
     ///
     // 从磁盘读取簇号链表, 减少磁盘读取次数
-    pub(crate) fn generate(&mut self) {
+    pub(crate) fn generate(&mut self) -> Result<(), DevError> {
         if self.cluster_vec.is_empty() {
-            return;
+            return Ok(());
         }
         loop {
             let current_cluster = self.cluster_vec.last().unwrap().clone();
@@ -81,7 +81,7 @@ impl ClusterChain {
             let block_offset = offset / BLOCK_SIZE;
             let offset_left = offset % BLOCK_SIZE;
             let block_id = self.fat1_offset / BLOCK_SIZE + block_offset;
-            let next_cluster = get_block_cache(block_id, Arc::clone(&self.device))
+            let next_cluster = get_block_cache(block_id, Arc::clone(&self.device))?
                 .read()
                 .read(offset_left, |&cluster: &u32| cluster);
             if next_cluster >= END_OF_CLUSTER {
@@ -90,6 +90,7 @@ impl ClusterChain {
                 self.cluster_vec.push(next_cluster);
             };
         }
+        Ok(())
     }
     /// Shrink cluster_vec to new_size.
     pub(crate) fn truncate(&mut self, new_size: usize) {
@@ -116,7 +117,7 @@ impl FATManager {
     }
     // Only used for std test when creating fat32 file system.
     #[allow(unused)]
-    pub fn new(fat_offset: usize, device: Arc<dyn BlockDevice>) -> Self {
+    pub fn new(fat_offset: usize, device: Arc<dyn BlockDevice>) -> Result<Self, DevError> {
         let fat = Self {
             device: Arc::clone(&device),
             recycled_cluster: VecDeque::new(),
@@ -126,14 +127,14 @@ impl FATManager {
         // 由于簇号从 2 开始, 现在将簇号 0, 1 的内容填充方便找到正确的簇(防止误操作)
         let block_id = fat.fat1_offset / BLOCK_SIZE;
         assert!(fat.fat1_offset % BLOCK_SIZE == 0);
-        get_block_cache(block_id, Arc::clone(&device))
+        get_block_cache(block_id, Arc::clone(&device))?
             .write()
             .modify(0, |buf: &mut [u32; 2]| {
                 buf[0] = END_OF_CLUSTER;
                 buf[1] = END_OF_CLUSTER;
             });
 
-        fat
+        Ok(fat)
     }
     /// Given any valid cluster number N, return the sector number and offset of the entry for that cluster number in the FAT.
     ///
@@ -152,14 +153,14 @@ impl FATManager {
         (block_id, offset_in_block)
     }
     // 从 start_from 开始找 在FAT表中找到空闲的簇
-    fn find_blank_cluster(&self, start_from: u32) -> u32 {
+    fn find_blank_cluster(&self, start_from: u32) -> Result<u32, DevError> {
         // 加 1 过滤已经分配的簇号 (该簇号还未初始值为EOC, 防止找到同样的簇号)
         let mut cluster = start_from + 1;
         let mut done = false;
         let mut buffer = [0u8; BLOCK_SIZE];
         loop {
             let (block_id, offset) = self.cluster_id_pos(cluster);
-            get_block_cache(block_id, Arc::clone(&self.device))
+            get_block_cache(block_id, Arc::clone(&self.device))?
                 .read()
                 .read(0, |buf: &[u8; BLOCK_SIZE]| {
                     buffer.copy_from_slice(buf);
@@ -176,11 +177,11 @@ impl FATManager {
                 break;
             }
         }
-        cluster & CLUSTER_MASK
+        Ok(cluster & CLUSTER_MASK)
     }
-    pub fn get_blank_cluster(&mut self, start_from: u32) -> u32 {
+    pub fn get_blank_cluster(&mut self, start_from: u32) -> Result<u32, DevError> {
         if let Some(cluster) = self.recycled_cluster.pop_front() {
-            cluster & CLUSTER_MASK
+            Ok(cluster & CLUSTER_MASK)
         } else {
             self.find_blank_cluster(start_from)
         }
@@ -191,82 +192,83 @@ impl FATManager {
     // Query the next cluster of the specific cluster
     //
     // 最后一个簇的值, next_cluster 可能等于 EOC
-    pub fn get_next_cluster(&self, cluster: u32) -> Option<u32> {
+    pub fn get_next_cluster(&self, cluster: u32) -> Result<Option<u32>, DevError> {
         let (block_id, offset_in_block) = self.cluster_id_pos(cluster);
 
-        let next_cluster: u32 = get_block_cache(block_id, Arc::clone(&self.device))
+        let next_cluster: u32 = get_block_cache(block_id, Arc::clone(&self.device))?
             .read()
             .read(offset_in_block, |&next_cluster: &u32| next_cluster);
 
         assert!(next_cluster >= 2);
         if next_cluster >= END_OF_CLUSTER {
-            None
+            Ok(None)
         } else {
-            Some(next_cluster)
+            Ok(Some(next_cluster))
         }
     }
     // Set the next cluster of the specific cluster
     //
     // 在磁盘的FAT表中的簇号 cluster(offset) 处写入 cluster 的 value(下一个簇号)
-    pub fn set_next_cluster(&self, cluster: u32, next_cluster: u32) {
+    pub fn set_next_cluster(&self, cluster: u32, next_cluster: u32) -> Result<(), DevError> {
         let (block_id, offset_in_block) = self.cluster_id_pos(cluster);
-        get_block_cache(block_id, Arc::clone(&self.device))
+        get_block_cache(block_id, Arc::clone(&self.device))?
             .write()
             .modify(offset_in_block, |value: &mut u32| {
                 *value = next_cluster;
             });
+        Ok(())
     }
     // Get the ith cluster of a cluster chain
-    pub fn get_cluster_at(&self, start_cluster: u32, index: u32) -> Option<u32> {
+    pub fn get_cluster_at(&self, start_cluster: u32, index: u32) -> Result<Option<u32>, DevError> {
         let mut cluster = start_cluster;
         for _ in 0..index {
-            let option = self.get_next_cluster(cluster);
+            let option = self.get_next_cluster(cluster)?;
             if let Some(c) = option {
                 cluster = c
             } else {
-                return None;
+                return Ok(None);
             }
         }
-        Some(cluster & CLUSTER_MASK)
+        Ok(Some(cluster & CLUSTER_MASK))
     }
     // Get the last cluster of a cluster chain
-    pub fn cluster_chain_tail(&self, start_cluster: u32) -> u32 {
+    pub fn cluster_chain_tail(&self, start_cluster: u32) -> Result<u32, DevError> {
         let mut curr_cluster = start_cluster;
         // start cluster 是 fat 表中的 index, 从 2 开始有效
         assert!(curr_cluster >= 2);
         loop {
-            let option = self.get_next_cluster(curr_cluster);
+            let option = self.get_next_cluster(curr_cluster)?;
             if let Some(cluster) = option {
                 curr_cluster = cluster
             } else {
-                return curr_cluster & CLUSTER_MASK;
+                return Ok(curr_cluster & CLUSTER_MASK);
             }
         }
     }
     // Get all clusters of a cluster chain starting from the specified cluster
-    pub fn get_all_cluster_id(&self, start_cluster: u32) -> Vec<u32> {
+    pub fn get_all_cluster_id(&self, start_cluster: u32) -> Result<Vec<u32>, DevError> {
         let mut curr_cluster = start_cluster;
         let mut vec: Vec<u32> = Vec::new();
         loop {
             vec.push(curr_cluster & CLUSTER_MASK);
-            let option = self.get_next_cluster(curr_cluster);
+            let option = self.get_next_cluster(curr_cluster)?;
             if let Some(next_cluster) = option {
                 curr_cluster = next_cluster;
             } else {
-                return vec;
+                return Ok(vec);
             }
         }
     }
-    pub fn cluster_chain_len(&self, start_cluster: u32) -> u32 {
+    pub fn cluster_chain_len(&self, start_cluster: u32) -> Result<u32, DevError> {
         let mut curr_cluster = start_cluster;
         let mut len = 0;
         loop {
             len += 1;
-            let option = self.get_next_cluster(curr_cluster);
+            let option = self.get_next_cluster(curr_cluster)?;
             if let Some(next_cluster) = option {
                 curr_cluster = next_cluster;
             } else {
-                return len;
+                return Ok(len);
             }
         }
     }
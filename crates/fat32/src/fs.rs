@@ -5,12 +5,13 @@ use alloc::vec::Vec;
 use core::clone::Clone;
 use core::option::Option;
 use core::option::Option::{None, Some};
+use core::sync::atomic::{AtomicBool, Ordering};
 use spin::RwLock;
 
 use super::bpb::{BIOSParameterBlock, BasicBPB, FSInfo, BPB32};
-use super::cache::get_block_cache;
 use super::cache::Cache;
-use super::device::BlockDevice;
+use super::cache::{get_block_cache, write_failed};
+use super::device::{BlockDevice, DevError};
 use super::entry::ShortDirEntry;
 use super::fat::FATManager;
 use super::vf::VirtFileType;
@@ -23,6 +24,7 @@ pub struct FileSystem {
     pub(crate) bpb: BIOSParameterBlock, // read only
     pub(crate) fat: Arc<RwLock<FATManager>>,
     pub(crate) root_dir_entry: Arc<RwLock<ShortDirEntry>>, // 虚拟根目录项。根目录无目录项，引入以与其他文件一致
+    pub(crate) read_only: AtomicBool,
 }
 
 impl FileSystem {
@@ -41,13 +43,23 @@ impl FileSystem {
     pub fn free_cluster_cnt(&self) -> usize {
         *self.free_cluster_cnt.read()
     }
-    pub fn set_free_clusters(&self, cnt: usize) {
-        get_block_cache(self.bpb.fat_info_sector(), Arc::clone(&self.device))
+    pub fn set_free_clusters(&self, cnt: usize) -> Result<(), DevError> {
+        get_block_cache(self.bpb.fat_info_sector(), Arc::clone(&self.device))?
             .write()
             .modify(0, |fsinfo: &mut FSInfo| {
                 fsinfo.set_free_clusters(cnt as u32)
             });
         *self.free_cluster_cnt.write() = cnt;
+        Ok(())
+    }
+    /// Whether modifications are refused. Besides being set explicitly, this happens after
+    /// a block write to its device has failed: the data on disk may already be inconsistent, and
+    /// writing more would make it worse (like `errors=remount-ro` on Linux).
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Acquire) || write_failed(&self.device)
+    }
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Release);
     }
     pub fn first_sector_of_cluster(&self, cluster: u32) -> usize {
         self.bpb.first_sector_of_cluster(cluster)
@@ -60,7 +72,7 @@ impl FileSystem {
     }
     #[allow(unused)]
     // only for std test
-    pub fn create(device: Arc<dyn BlockDevice>) -> Arc<RwLock<Self>> {
+    pub fn create(device: Arc<dyn BlockDevice>) -> Result<Arc<RwLock<Self>>, DevError> {
         let basic_bpb = BasicBPB {
             _bs_jmp_boot: [0xEB, 0x58, 0x90],
             _bs_oem_name: *b"mk.fat32",
@@ -93,7 +105,7 @@ impl FileSystem {
             _bs_fil_sys_type: *b"FAT32   ",
         };
         let bpb = BIOSParameterBlock { basic_bpb, bpb32 };
-        get_block_cache(0, Arc::clone(&device))
+        get_block_cache(0, Arc::clone(&device))?
             .write()
             .modify(0, |b: &mut BIOSParameterBlock| *b = bpb);
         let fsinfo = FSInfo {
@@ -106,13 +118,13 @@ impl FileSystem {
             trail_sig: 0xAA550000,
         };
         let free_cluster_cnt = fsinfo.free_cluster_cnt() as usize;
-        get_block_cache(1, Arc::clone(&device))
+        get_block_cache(1, Arc::clone(&device))?
             .write()
             .modify(0, |f: &mut FSInfo| *f = fsinfo);
-        let fat = FATManager::new(bpb.fat1_offset(), Arc::clone(&device));
+        let fat = FATManager::new(bpb.fat1_offset(), Arc::clone(&device))?;
         let root_dir_cluster = bpb.root_cluster();
         // Set root next cluster
-        fat.set_next_cluster(root_dir_cluster as u32, END_OF_CLUSTER);
+        fat.set_next_cluster(root_dir_cluster as u32, END_OF_CLUSTER)?;
         let mut name_bytes = [0x20u8; 11];
         name_bytes[0] = ROOT;
         let root_dir_entry = ShortDirEntry::new_from_name_bytes(
@@ -126,14 +138,15 @@ impl FileSystem {
             bpb,
             fat: Arc::new(RwLock::new(fat)),
             root_dir_entry: Arc::new(RwLock::new(root_dir_entry)),
+            read_only: AtomicBool::new(false),
         }));
-        fs
+        Ok(fs)
     }
-    pub fn open(device: Arc<dyn BlockDevice>) -> Arc<RwLock<Self>> {
-        let bpb = get_block_cache(0, Arc::clone(&device))
+    pub fn open(device: Arc<dyn BlockDevice>) -> Result<Arc<RwLock<Self>>, DevError> {
+        let bpb = get_block_cache(0, Arc::clone(&device))?
             .read()
             .read(0, |bpb: &BIOSParameterBlock| *bpb);
-        let free_cluster_cnt = get_block_cache(bpb.fat_info_sector(), Arc::clone(&device))
+        let free_cluster_cnt = get_block_cache(bpb.fat_info_sector(), Arc::clone(&device))?
             .read()
             .read(0, |fsinfo: &FSInfo| {
                 assert!(
@@ -151,60 +164,68 @@ impl FileSystem {
             &name_bytes,
             VirtFileType::Dir,
         );
-        Arc::new(RwLock::new(Self {
+        Ok(Arc::new(RwLock::new(Self {
             device,
             free_cluster_cnt: Arc::new(RwLock::new(free_cluster_cnt)),
             bpb,
             fat: Arc::new(RwLock::new(fat)),
             root_dir_entry: Arc::new(RwLock::new(root_dir_entry)),
-        }))
+            read_only: AtomicBool::new(false),
+        })))
     }
-    fn clear_cluster(&self, cluster: u32) {
+    fn clear_cluster(&self, cluster: u32) -> Result<(), DevError> {
         let block_id = self.first_sector_of_cluster(cluster);
         for i in 0..self.sector_pre_cluster() {
-            get_block_cache(block_id + i, Arc::clone(&self.device))
+            get_block_cache(block_id + i, Arc::clone(&self.device))?
                 .write()
                 .modify(0, |cache: &mut [u8; BLOCK_SIZE]| {
                     cache.copy_from_slice(&[0u8; BLOCK_SIZE])
                 })
         }
+        Ok(())
     }
     /// Generate a new cluster chain with length num in FAT table (not in struct ClusterChain), return the first cluster id
-    pub fn alloc_cluster_chain(&self, num: usize, start_cluster: u32) -> Option<u32> {
+    pub fn alloc_cluster_chain(
+        &self,
+        num: usize,
+        start_cluster: u32,
+    ) -> Result<Option<u32>, DevError> {
         let free_cluster_cnt = self.free_cluster_cnt();
         if free_cluster_cnt < num {
-            return None;
+            return Ok(None);
         }
-        let first_cluster_id = self.fat.write().get_blank_cluster(start_cluster);
+        let first_cluster_id = self.fat.write().get_blank_cluster(start_cluster)?;
         assert!(first_cluster_id >= 2);
-        self.clear_cluster(first_cluster_id);
+        self.clear_cluster(first_cluster_id)?;
         let mut curr_cluster_id = first_cluster_id;
         for _ in 1..num {
-            let cluster_id = self.fat.write().get_blank_cluster(curr_cluster_id);
+            let cluster_id = self.fat.write().get_blank_cluster(curr_cluster_id)?;
             assert!(cluster_id >= 2);
-            self.clear_cluster(cluster_id);
+            self.clear_cluster(cluster_id)?;
             self.fat
                 .write()
-                .set_next_cluster(curr_cluster_id, cluster_id);
+                .set_next_cluster(curr_cluster_id, cluster_id)?;
             curr_cluster_id = cluster_id;
         }
         self.fat
             .write()
-            .set_next_cluster(curr_cluster_id, END_OF_CLUSTER);
-        self.set_free_clusters(free_cluster_cnt - num);
-        Some(first_cluster_id)
+            .set_next_cluster(curr_cluster_id, END_OF_CLUSTER)?;
+        self.set_free_clusters(free_cluster_cnt - num)?;
+        Ok(Some(first_cluster_id))
     }
-    pub fn dealloc_cluster(&self, clusters: Vec<u32>) {
+    pub fn dealloc_cluster(&self, clusters: Vec<u32>) -> Result<(), DevError> {
         let num = clusters.len();
         if num == 0 {
-            return;
+            return Ok(());
         }
         let free_cluster_cnt = self.free_cluster_cnt();
         for i in 0..num {
-            self.fat.write().set_next_cluster(clusters[i], FREE_CLUSTER);
+            self.fat
+                .write()
+                .set_next_cluster(clusters[i], FREE_CLUSTER)?;
             self.fat.write().recycle(clusters[i]);
         }
-        self.set_free_clusters(free_cluster_cnt + num);
+        self.set_free_clusters(free_cluster_cnt + num)
     }
     pub fn root_dir_entry(&self) -> Arc<RwLock<ShortDirEntry>> {
         self.root_dir_entry.clone()
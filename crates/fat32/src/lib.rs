@@ -49,6 +49,9 @@ pub const ATTR_VOLUME_ID: u8 = 0x08;
 pub const ATTR_DIRECTORY: u8 = 0x10;
 pub const ATTR_ARCHIVE: u8 = 0x20;
 pub const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+/// FAT32 has no symbolic links: a regular file tagged with this attribute is treated
+/// as a symbolic link, and its content is the link target
+pub const ATTR_SYMLINK: u8 = ATTR_SYSTEM;
 
 // Directory Entry
 pub const DIRENT_SIZE: usize = 32;
@@ -9,14 +9,15 @@ use core::{assert, assert_ne};
 use spin::RwLock;
 
 use super::cache::get_block_cache;
+use super::cache::sync_blocks;
 use super::cache::Cache;
-use super::device::BlockDevice;
+use super::device::{BlockDevice, DevError};
 use super::entry::{LongDirEntry, ShortDirEntry};
 use super::fat::ClusterChain;
 use super::fs::FileSystem;
 use super::{
-    ATTR_ARCHIVE, ATTR_DIRECTORY, ATTR_LONG_NAME, BLOCK_SIZE, DIRENT_SIZE, END_OF_CLUSTER,
-    NEW_VIRT_FILE_CLUSTER, ROOT_DIR_ENTRY_CLUSTER,
+    ATTR_ARCHIVE, ATTR_DIRECTORY, ATTR_LONG_NAME, ATTR_SYMLINK, BLOCK_SIZE, DIRENT_SIZE,
+    END_OF_CLUSTER, NEW_VIRT_FILE_CLUSTER, ROOT_DIR_ENTRY_CLUSTER,
 };
 
 #[derive(Clone)]
@@ -30,7 +31,7 @@ pub struct VirtFile {
     pub(crate) attr: VirtFileType,
 }
 
-pub fn root(fs: Arc<RwLock<FileSystem>>) -> VirtFile {
+pub fn root(fs: Arc<RwLock<FileSystem>>) -> Result<VirtFile, DevError> {
     let fs = Arc::clone(&fs);
     let device = Arc::clone(&fs.read().device);
     let root_dir_cluster = fs.read().bpb.root_cluster();
@@ -39,8 +40,8 @@ pub fn root(fs: Arc<RwLock<FileSystem>>) -> VirtFile {
         Arc::clone(&device),
         fs.read().bpb.fat1_offset(),
     )));
-    cluster_chain.write().generate();
-    VirtFile::new(
+    cluster_chain.write().generate()?;
+    Ok(VirtFile::new(
         String::from("/"),
         DirEntryPos {
             cluster: ROOT_DIR_ENTRY_CLUSTER,
@@ -51,7 +52,7 @@ pub fn root(fs: Arc<RwLock<FileSystem>>) -> VirtFile {
         device,
         cluster_chain,
         VirtFileType::Dir,
-    )
+    ))
 }
 
 impl VirtFile {
@@ -78,43 +79,61 @@ impl VirtFile {
     // Dir Func
 
     /// pass in the offset of sde in dir file, then calculate the block_id and offset_in_block, then get the first_cluster of the sde corresponding file, construct the cluster_chain
-    pub fn generate_cluster_chain(&self, sde_offset: usize) -> ClusterChain {
+    pub fn generate_cluster_chain(&self, sde_offset: usize) -> Result<ClusterChain, DevError> {
         let fat_offset = self.fs.read().bpb.fat1_offset();
-        let (block_id, offset_in_block) = self.dirent_block_pos(sde_offset).unwrap();
-        let start_cluster: u32 = get_block_cache(block_id, Arc::clone(&self.device))
+        let (block_id, offset_in_block) = self.dirent_block_pos(sde_offset)?;
+        let start_cluster: u32 = get_block_cache(block_id, Arc::clone(&self.device))?
             .read()
             .read(offset_in_block, |sde: &ShortDirEntry| sde.first_cluster());
         let mut ret = ClusterChain::new(start_cluster, Arc::clone(&self.device), fat_offset);
         // generate cluster_vec(cluster_chain)
-        ret.generate();
-        ret
+        ret.generate()?;
+        Ok(ret)
     }
     pub fn name(&self) -> &str {
         self.name.as_str()
     }
-    pub fn clear_direntry(&self) {
+    /// Fail with [`DevError::ReadOnly`] if the file system refuses modifications
+    fn check_writable(&self) -> Result<(), DevError> {
+        if self.fs.read().is_read_only() {
+            Err(DevError::ReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+    /// Whether the file system the file belongs to refuses modifications
+    pub fn is_read_only(&self) -> bool {
+        self.fs.read().is_read_only()
+    }
+    pub fn clear_direntry(&self) -> Result<(), DevError> {
         for i in 0..self.lde_pos.len() {
             self.modify_lde(i, |lde: &mut LongDirEntry| {
                 lde.delete();
-            });
+            })?;
         }
         self.modify_sde(|sde: &mut ShortDirEntry| {
             sde.delete();
-        });
+        })
     }
-    pub fn clear_content(&self) -> usize {
-        let first_cluster = self.first_cluster() as u32;
+    pub fn clear_content(&self) -> Result<usize, DevError> {
+        self.check_writable()?;
+        let first_cluster = self.first_cluster()? as u32;
         let cluster_cnt;
         if first_cluster >= 2 && first_cluster < END_OF_CLUSTER {
-            let all_clusters = self.fs.read().fat.read().get_all_cluster_id(first_cluster);
+            let all_clusters = self
+                .fs
+                .read()
+                .fat
+                .read()
+                .get_all_cluster_id(first_cluster)?;
             cluster_cnt = all_clusters.len();
-            self.fs.write().dealloc_cluster(all_clusters);
+            self.fs.write().dealloc_cluster(all_clusters)?;
         } else {
             cluster_cnt = 0;
         }
-        self.set_file_size(0);
-        self.set_first_cluster(NEW_VIRT_FILE_CLUSTER as usize);
-        cluster_cnt
+        self.set_file_size(0)?;
+        self.set_first_cluster(NEW_VIRT_FILE_CLUSTER as usize)?;
+        Ok(cluster_cnt)
     }
     pub fn sde_pos(&self) -> (usize, usize) {
         assert!(self.sde_pos.cluster < END_OF_CLUSTER);
@@ -125,6 +144,11 @@ impl VirtFile {
         let block_id = offset / BLOCK_SIZE;
         (block_id, offset_in_block)
     }
+    /// Position of the short directory entry, which identifies the file on disk.
+    /// The root directory has no entry and is reported as `(ROOT_DIR_ENTRY_CLUSTER, 0)`.
+    pub fn dirent_id(&self) -> (u32, usize) {
+        (self.sde_pos.cluster, self.sde_pos.offset_in_cluster)
+    }
     pub fn lde_pos(&self, index: usize) -> (usize, usize) {
         assert!(self.lde_pos[index].cluster < END_OF_CLUSTER);
         let cluster_id = self.lde_pos[index].cluster;
@@ -134,43 +158,53 @@ impl VirtFile {
         let block_id = offset / BLOCK_SIZE;
         (block_id, offset_in_block)
     }
-    pub fn read_sde<V>(&self, f: impl FnOnce(&ShortDirEntry) -> V) -> V {
+    pub fn read_sde<V>(&self, f: impl FnOnce(&ShortDirEntry) -> V) -> Result<V, DevError> {
         // fat32 fs has no root dir entry. we handle it specially
         if self.sde_pos.cluster == ROOT_DIR_ENTRY_CLUSTER {
             let root_dir_entry = self.fs.read().root_dir_entry();
             let root_dir_entry_read = root_dir_entry.read();
-            return f(&root_dir_entry_read);
+            return Ok(f(&root_dir_entry_read));
         }
         let (block_id, offset_in_block) = self.sde_pos();
-        get_block_cache(block_id, Arc::clone(&self.device))
+        Ok(get_block_cache(block_id, Arc::clone(&self.device))?
             .read()
-            .read(offset_in_block, f)
+            .read(offset_in_block, f))
     }
-    pub fn modify_sde<V>(&self, f: impl FnOnce(&mut ShortDirEntry) -> V) -> V {
+    pub fn modify_sde<V>(&self, f: impl FnOnce(&mut ShortDirEntry) -> V) -> Result<V, DevError> {
         // fat32 fs has no root dir entry. we handle it specially
         if self.sde_pos.cluster == ROOT_DIR_ENTRY_CLUSTER {
             let root_dir_entry = self.fs.read().root_dir_entry();
             let mut root_dir_entry_write = root_dir_entry.write();
-            return f(&mut root_dir_entry_write);
+            return Ok(f(&mut root_dir_entry_write));
         }
+        self.check_writable()?;
         let (block_id, offset_in_block) = self.sde_pos();
-        get_block_cache(block_id, Arc::clone(&self.device))
+        Ok(get_block_cache(block_id, Arc::clone(&self.device))?
             .write()
-            .modify(offset_in_block, f)
+            .modify(offset_in_block, f))
     }
-    pub fn read_lde<V>(&self, index: usize, f: impl FnOnce(&LongDirEntry) -> V) -> V {
+    pub fn read_lde<V>(
+        &self,
+        index: usize,
+        f: impl FnOnce(&LongDirEntry) -> V,
+    ) -> Result<V, DevError> {
         let (block_id, offset_in_block) = self.lde_pos(index);
-        get_block_cache(block_id, Arc::clone(&self.device))
+        Ok(get_block_cache(block_id, Arc::clone(&self.device))?
             .read()
-            .read(offset_in_block, f)
+            .read(offset_in_block, f))
     }
-    pub fn modify_lde<V>(&self, index: usize, f: impl FnOnce(&mut LongDirEntry) -> V) -> V {
+    pub fn modify_lde<V>(
+        &self,
+        index: usize,
+        f: impl FnOnce(&mut LongDirEntry) -> V,
+    ) -> Result<V, DevError> {
+        self.check_writable()?;
         let (block_id, offset_in_block) = self.lde_pos(index);
-        get_block_cache(block_id, Arc::clone(&self.device))
+        Ok(get_block_cache(block_id, Arc::clone(&self.device))?
             .write()
-            .modify(offset_in_block, f)
+            .modify(offset_in_block, f))
     }
-    pub fn file_size(&self) -> usize {
+    pub fn file_size(&self) -> Result<usize, DevError> {
         self.read_sde(|sde| sde.file_size() as usize)
     }
     pub fn is_dir(&self) -> bool {
@@ -179,50 +213,62 @@ impl VirtFile {
     pub fn is_file(&self) -> bool {
         self.attr == VirtFileType::File
     }
+    /// Whether the directory entry of the file has been deleted
+    pub fn is_deleted(&self) -> Result<bool, DevError> {
+        self.read_sde(|sde| sde.is_deleted())
+    }
+    /// Whether the file is a symbolic link (tagged with [`ATTR_SYMLINK`])
+    pub fn is_symlink(&self) -> Result<bool, DevError> {
+        Ok(self.is_file() && self.read_sde(|sde| sde.attr() == ATTR_SYMLINK)?)
+    }
+    /// Tag the file as a symbolic link, its content should be the link target
+    pub fn set_symlink(&self) -> Result<(), DevError> {
+        self.modify_sde(|sde| sde.set_attr(ATTR_SYMLINK))
+    }
     /// pass in sde or lde offset in dir file, return its position in disk (block_id, offset_in_block)
-    pub fn dirent_block_pos(&self, offset: usize) -> Option<(usize, usize)> {
+    pub fn dirent_block_pos(&self, offset: usize) -> Result<(usize, usize), DevError> {
         let cluster_size = self.fs.read().cluster_size();
         let cluster_index = offset / cluster_size;
         let offset_in_cluster = offset % cluster_size;
-        let start_cluster = self.first_cluster();
+        let start_cluster = self.first_cluster()?;
         let cluster = self
             .fs
             .read()
             .fat
             .read()
-            .get_cluster_at(start_cluster as u32, cluster_index as u32)
+            .get_cluster_at(start_cluster as u32, cluster_index as u32)?
             .unwrap();
         let offset_in_disk = self.fs.read().bpb.offset(cluster);
         let block_id = offset_in_disk / BLOCK_SIZE + offset_in_cluster / BLOCK_SIZE;
         assert!(offset_in_disk % BLOCK_SIZE == 0);
         let offset_in_block = offset_in_cluster % BLOCK_SIZE;
-        Some((block_id, offset_in_block))
+        Ok((block_id, offset_in_block))
     }
     /// pass in sde or lde offset in dir file, return its position in disk (cluster_id, offset_in_cluster)
-    pub fn dirent_cluster_pos(&self, offset: usize) -> Option<DirEntryPos> {
+    pub fn dirent_cluster_pos(&self, offset: usize) -> Result<DirEntryPos, DevError> {
         let cluster_size = self.fs.read().cluster_size();
         let cluster_index = offset / cluster_size;
         let offset_in_cluster = offset % cluster_size;
-        let start_cluster = self.first_cluster();
+        let start_cluster = self.first_cluster()?;
         let cluster = self
             .fs
             .read()
             .fat
             .read()
-            .get_cluster_at(start_cluster as u32, cluster_index as u32)
+            .get_cluster_at(start_cluster as u32, cluster_index as u32)?
             .unwrap();
-        Some(DirEntryPos::new(cluster, offset_in_cluster))
+        Ok(DirEntryPos::new(cluster, offset_in_cluster))
     }
-    pub fn set_first_cluster(&self, cluster: usize) {
-        self.modify_sde(|sde| sde.set_first_cluster(cluster as u32));
+    pub fn set_first_cluster(&self, cluster: usize) -> Result<(), DevError> {
+        self.modify_sde(|sde| sde.set_first_cluster(cluster as u32))
     }
-    pub fn set_file_size(&self, size: usize) {
-        self.modify_sde(|sde| sde.set_file_size(size as u32));
+    pub fn set_file_size(&self, size: usize) -> Result<(), DevError> {
+        self.modify_sde(|sde| sde.set_file_size(size as u32))
     }
-    pub fn first_cluster(&self) -> usize {
+    pub fn first_cluster(&self) -> Result<usize, DevError> {
         self.read_sde(|sde| sde.first_cluster() as usize)
     }
-    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, DevError> {
         #[cfg(feature = "time-tracer")]
         time_trace!("read_at");
         let spc = self.fs.read().bpb.sectors_per_cluster();
@@ -230,7 +276,7 @@ impl VirtFile {
         let mut index = offset;
         let end = offset + buf.len();
         if buf.len() == 0 {
-            return 0;
+            return Ok(0);
         }
         #[cfg(feature = "time-tracer")]
         start_trace!("cluster");
@@ -259,7 +305,7 @@ impl VirtFile {
                 if index >= left && index < right && index < end {
                     let offset_in_block = index - left;
                     let len = (BLOCK_SIZE - offset_in_block).min(end - index);
-                    get_block_cache(block_id, Arc::clone(&self.device))
+                    get_block_cache(block_id, Arc::clone(&self.device))?
                         .read()
                         .read(0, |cache: &[u8; BLOCK_SIZE]| {
                             let dst = &mut buf[already_read..already_read + len];
@@ -279,20 +325,21 @@ impl VirtFile {
                 break;
             }
         }
-        already_read
+        Ok(already_read)
     }
-    pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, DevError> {
         #[cfg(feature = "time-tracer")]
         time_trace!("write_at");
         let spc = self.fs.read().bpb.sectors_per_cluster();
         let cluster_size = self.fs.read().cluster_size();
         if buf.len() == 0 {
-            return 0;
+            return Ok(0);
         }
+        self.check_writable()?;
         let mut index = offset;
         let end = offset + buf.len();
         let new_size = offset + buf.len();
-        self.incerase_size(new_size);
+        self.incerase_size(new_size)?;
         let pre_cluster_cnt = offset / cluster_size;
         let clus_chain = self.cluster_chain.read();
         let mut cluster_iter = clus_chain.cluster_vec.iter().skip(pre_cluster_cnt);
@@ -310,7 +357,7 @@ impl VirtFile {
                 if index >= left && index < right && index < end {
                     let offset_in_block = index - left;
                     let len = (BLOCK_SIZE - offset_in_block).min(end - index);
-                    get_block_cache(block_id, Arc::clone(&self.device))
+                    get_block_cache(block_id, Arc::clone(&self.device))?
                         .write()
                         .modify(0, |cache: &mut [u8; BLOCK_SIZE]| {
                             let src = &buf[already_write..already_write + len];
@@ -330,14 +377,14 @@ impl VirtFile {
                 break;
             }
         }
-        already_write
+        Ok(already_write)
     }
-    fn incerase_size(&self, new_size: usize) {
-        let first_cluster = self.first_cluster() as u32;
+    fn incerase_size(&self, new_size: usize) -> Result<(), DevError> {
+        let first_cluster = self.first_cluster()? as u32;
         // fat32 stipulate that directory file size is 0
-        let old_size = self.file_size();
+        let old_size = self.file_size()?;
         if new_size <= old_size {
-            return;
+            return Ok(());
         }
         let cluster_size = self.fs.read().cluster_size();
         // compute how many clusters are needed
@@ -357,23 +404,22 @@ impl VirtFile {
             if !self.is_dir() {
                 self.modify_sde(|sde| {
                     sde.set_file_size(new_size as u32);
-                });
+                })?;
             }
             // ensure cluster chain is generated
-            self.cluster_chain.write().generate();
-            return;
+            return self.cluster_chain.write().generate();
         }
         let option = self
             .fs
             .write()
-            .alloc_cluster_chain(need_cluster_cnt, first_cluster);
+            .alloc_cluster_chain(need_cluster_cnt, first_cluster)?;
         if let Some(start_cluster) = option {
             // if file is new created, set first cluster
             if first_cluster == NEW_VIRT_FILE_CLUSTER {
                 self.cluster_chain.write().refresh(start_cluster);
                 self.modify_sde(|sde| {
                     sde.set_first_cluster(start_cluster);
-                });
+                })?;
             } else {
                 let last_cluster = self
                     .cluster_chain
@@ -387,28 +433,28 @@ impl VirtFile {
                     .write()
                     .fat
                     .write()
-                    .set_next_cluster(last_cluster, start_cluster);
+                    .set_next_cluster(last_cluster, start_cluster)?;
             }
             if !self.is_dir() {
                 self.modify_sde(|sde| {
                     sde.set_file_size(new_size as u32);
-                });
+                })?;
             }
             // generate cluster chain in ClusterChain
-            self.cluster_chain.write().generate();
+            self.cluster_chain.write().generate()
         } else {
             panic!("Alloc Cluster Failed! Out of Space!");
         }
     }
-    pub fn modify_size(&self, new_size: usize) {
-        let old_size = self.file_size();
+    pub fn modify_size(&self, new_size: usize) -> Result<(), DevError> {
+        self.check_writable()?;
+        let old_size = self.file_size()?;
         let cluster_size = self.fs.read().cluster_size();
         if new_size == 0 {
-            self.clear_content();
-            return;
+            return self.clear_content().map(|_| ());
         }
         if new_size >= old_size {
-            self.incerase_size(new_size);
+            self.incerase_size(new_size)
         } else {
             let left = (new_size + cluster_size - 1) / cluster_size;
             let right = (old_size + cluster_size - 1) / cluster_size;
@@ -420,12 +466,12 @@ impl VirtFile {
             }
             drop(cluster_chain);
             self.cluster_chain.write().truncate(left);
-            self.fs.write().dealloc_cluster(release_clsuter_vec);
+            self.fs.write().dealloc_cluster(release_clsuter_vec)?;
             // fat32 stipulate that directory file size is 0
             assert!(!self.is_dir());
             self.modify_sde(|sde| {
                 sde.set_file_size(new_size as u32);
-            });
+            })?;
             let last_clus = self
                 .cluster_chain
                 .read()
@@ -438,60 +484,78 @@ impl VirtFile {
                 .write()
                 .fat
                 .write()
-                .set_next_cluster(last_clus, END_OF_CLUSTER);
+                .set_next_cluster(last_clus, END_OF_CLUSTER)
         }
     }
     // clear all content of file including dirent
-    pub fn clear(&self) -> usize {
-        let first_cluster = self.first_cluster() as u32;
-        for i in 0..self.lde_pos.len() {
-            self.modify_lde(i, |lde: &mut LongDirEntry| {
-                lde.delete();
-            });
-        }
-        self.modify_sde(|sde: &mut ShortDirEntry| {
-            sde.delete();
-        });
+    pub fn clear(&self) -> Result<usize, DevError> {
+        let first_cluster = self.first_cluster()? as u32;
+        self.clear_direntry()?;
         if first_cluster >= 2 && first_cluster < END_OF_CLUSTER {
             let all_clusters = self.cluster_chain.read().cluster_vec.clone();
             self.cluster_chain.write().cluster_vec.clear();
             let cluster_cnt = all_clusters.len();
-            self.fs.write().dealloc_cluster(all_clusters);
-            cluster_cnt
+            self.fs.write().dealloc_cluster(all_clusters)?;
+            Ok(cluster_cnt)
         } else {
-            0
+            Ok(0)
+        }
+    }
+    /// Write back the blocks of the file: its content, its directory entries,
+    /// the FAT entries of its cluster chain and the FSInfo sector
+    pub fn sync(&self) -> Result<(), DevError> {
+        let mut block_ids = Vec::new();
+        let fs = self.fs.read();
+        let spc = fs.bpb.sectors_per_cluster();
+        for &cluster in self.cluster_chain.read().cluster_vec.iter() {
+            let start_block_id = fs.bpb.offset(cluster) / BLOCK_SIZE;
+            block_ids.extend(start_block_id..start_block_id + spc);
+            block_ids.push(fs.fat.read().cluster_id_pos(cluster).0);
+        }
+        block_ids.push(fs.bpb.fat_info_sector());
+        drop(fs);
+        // fat32 fs has no root dir entry
+        if self.sde_pos.cluster != ROOT_DIR_ENTRY_CLUSTER {
+            block_ids.push(self.sde_pos().0);
         }
+        for i in 0..self.lde_pos.len() {
+            block_ids.push(self.lde_pos(i).0);
+        }
+        block_ids.sort_unstable();
+        block_ids.dedup();
+        sync_blocks(&block_ids)
     }
     /// Return: (st_size, st_blksize, st_blocks, is_dir, time)
     /// TODO time ...
-    pub fn stat(&self) -> (usize, usize, usize, bool, usize) {
-        self.read_sde(|sde: &ShortDirEntry| {
-            let first_cluster = sde.first_cluster();
-            let mut file_size = sde.file_size() as usize;
-            let spc = self.fs.read().sector_pre_cluster();
-            let cluster_size = self.fs.read().cluster_size();
-            let cluster_cnt = self.fs.read().fat.read().cluster_chain_len(first_cluster) as usize;
-            let block_cnt = cluster_cnt * spc;
-            if self.is_dir() {
-                // fat32 stipulate that directory file size is 0
-                file_size = cluster_cnt * cluster_size;
-            }
-            (file_size, BLOCK_SIZE, block_cnt, self.is_dir(), 0)
-        })
+    pub fn stat(&self) -> Result<(usize, usize, usize, bool, usize), DevError> {
+        let (first_cluster, mut file_size) =
+            self.read_sde(|sde: &ShortDirEntry| (sde.first_cluster(), sde.file_size() as usize))?;
+        let spc = self.fs.read().sector_pre_cluster();
+        let cluster_size = self.fs.read().cluster_size();
+        let cluster_cnt = self.fs.read().fat.read().cluster_chain_len(first_cluster)? as usize;
+        let block_cnt = cluster_cnt * spc;
+        if self.is_dir() {
+            // fat32 stipulate that directory file size is 0
+            file_size = cluster_cnt * cluster_size;
+        }
+        Ok((file_size, BLOCK_SIZE, block_cnt, self.is_dir(), 0))
     }
     // return (d_name, d_off, d_type)
-    pub fn dir_info(&self, offset: usize) -> Option<(String, usize, usize, usize)> {
+    pub fn dir_info(
+        &self,
+        offset: usize,
+    ) -> Result<Option<(String, usize, usize, usize)>, DevError> {
         if !self.is_dir() {
-            return None;
+            return Ok(None);
         }
         let mut entry = LongDirEntry::empty();
         let mut index = offset;
         let mut name = String::new();
         let mut is_long = false;
         loop {
-            let read_size = self.read_at(index, entry.as_bytes_mut());
+            let read_size = self.read_at(index, entry.as_bytes_mut())?;
             if read_size != DIRENT_SIZE || entry.is_empty() {
-                return None;
+                return Ok(None);
             }
             if entry.is_deleted() {
                 index += DIRENT_SIZE;
@@ -508,7 +572,12 @@ impl VirtFile {
                 let attribute = sde.attr();
                 let first_cluster = sde.first_cluster();
                 index += DIRENT_SIZE;
-                return Some((name, index, first_cluster as usize, attribute as usize));
+                return Ok(Some((
+                    name,
+                    index,
+                    first_cluster as usize,
+                    attribute as usize,
+                )));
             } else {
                 is_long = true;
                 name.insert_str(0, &entry.name().as_str());
@@ -1,10 +1,17 @@
 pub const AT_FDCWD: isize = -100;
+/// 不跟随路径最后一级的符号链接
+pub const AT_SYMLINK_NOFOLLOW: usize = 0x100;
+/// linkat 跟随 oldpath 最后一级的符号链接
+pub const AT_SYMLINK_FOLLOW: usize = 0x400;
 
 pub const TCGETS: usize = 0x5401;
 pub const TCSETS: usize = 0x5402;
+pub const TIOCSCTTY: usize = 0x540e;
 pub const TIOCGPGRP: usize = 0x540f;
 pub const TIOCSPGRP: usize = 0x5410;
 pub const TIOCGWINSZ: usize = 0x5413;
+pub const TIOCNOTTY: usize = 0x5422;
+pub const TIOCGSID: usize = 0x5429;
 pub const RTC_RD_TIME: usize = 0xffffffff80247009; // 这个值还需考量
 
 bitflags! {
@@ -98,6 +105,39 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// mount(2) flags
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct MountFlags: u32 {
+        /// 只读挂载
+        const MS_RDONLY = 1;
+        const MS_NOSUID = 1 << 1;
+        const MS_NODEV = 1 << 2;
+        const MS_NOEXEC = 1 << 3;
+        const MS_SYNCHRONOUS = 1 << 4;
+        /// 修改已有挂载点的 flags
+        const MS_REMOUNT = 1 << 5;
+        const MS_NOATIME = 1 << 10;
+        /// 将一个已存在的目录树绑定到另一个位置
+        const MS_BIND = 1 << 12;
+        const MS_MOVE = 1 << 13;
+        const MS_REC = 1 << 14;
+        const MS_SILENT = 1 << 15;
+    }
+}
+
+bitflags! {
+    /// umount2(2) flags
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct UmountFlags: u32 {
+        const MNT_FORCE = 1;
+        /// 延迟卸载: 立即从目录树中摘除, 已打开的文件仍可继续使用
+        const MNT_DETACH = 1 << 1;
+        const MNT_EXPIRE = 1 << 2;
+        const UMOUNT_NOFOLLOW = 1 << 3;
+    }
+}
+
 #[repr(C)]
 pub struct Statfs {
     pub f_type: u64,
@@ -48,10 +48,18 @@ bitflags! {
          const FILES = 0x0000400;
          /* set if signal handlers and blocked signals shared */
          const SIGHAND = 0x00000800;
+         /* set if a pidfd should be placed in parent */
+         const PIDFD = 0x00001000;
+         /* set if we want to let tracing continue on the child too */
+         const PTRACE = 0x00002000;
+         /* set if the parent wants the child to wake it up on mm_release */
+         const VFORK = 0x00004000;
          /* set if we want to have the same parent as the cloner */
          const PARENT = 0x00008000;
          /* Same thread group? */
          const THREAD = 0x00010000;
+         /* New mount namespace group */
+         const NEWNS = 0x00020000;
          /* share system V SEM_UNDO semantics */
          const SYSVSEM = 0x00040000;
          /* create a new TLS for the child */
@@ -62,11 +70,54 @@ bitflags! {
          const CHILD_CLEARTID = 0x00200000;
          /* Unused, ignored */
          const CLONE_DETACHED = 0x00400000;
+         /* set if the tracing process can't force CLONE_PTRACE on this clone */
+         const UNTRACED = 0x00800000;
         /* set the TID in the child */
          const CHILD_SETTID = 0x01000000;
+         /* New cgroup namespace */
+         const NEWCGROUP = 0x02000000;
+         /* New utsname namespace */
+         const NEWUTS = 0x04000000;
+         /* New ipc namespace */
+         const NEWIPC = 0x08000000;
+         /* New user namespace */
+         const NEWUSER = 0x10000000;
+         /* New pid namespace */
+         const NEWPID = 0x20000000;
+         /* New network namespace */
+         const NEWNET = 0x40000000;
+         /* Clone io context */
+         const IO = 0x80000000;
+         /* Clear any signal handler and reset to SIG_DFL (clone3 only) */
+         const CLEAR_SIGHAND = 0x100000000;
+         /* Clone into a specific cgroup given the right permissions (clone3 only) */
+         const INTO_CGROUP = 0x200000000;
     }
 }
 
+/// clone 的低 8 位为子进程退出时发给父进程的信号
+pub const CSIGNAL: usize = 0xff;
+
+/// clone3 的参数
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CloneArgs {
+    pub flags: u64,
+    pub pidfd: u64,
+    pub child_tid: u64,
+    pub parent_tid: u64,
+    pub exit_signal: u64,
+    pub stack: u64,
+    pub stack_size: u64,
+    pub tls: u64,
+    pub set_tid: u64,
+    pub set_tid_size: u64,
+    pub cgroup: u64,
+}
+
+/// 第一版 clone_args 的大小, 不含 set_tid 及之后的字段
+pub const CLONE_ARGS_SIZE_VER0: usize = 64;
+
 #[allow(unused)]
 pub struct RUsage {
     pub ru_utime: TimeVal, /* user CPU time used */
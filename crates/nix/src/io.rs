@@ -3,3 +3,16 @@ pub struct Iovec {
     pub iov_base: usize,
     pub iov_len: usize,
 }
+
+bitflags! {
+    /// getrandom(2) flags
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct GetRandomFlags: u32 {
+        /// 随机数池尚未初始化时返回 EAGAIN 而不是阻塞
+        const GRND_NONBLOCK = 1;
+        /// 从 /dev/random 读取, 与 /dev/urandom 使用同一个随机数池
+        const GRND_RANDOM = 1 << 1;
+        /// 随机数池尚未初始化时也不阻塞
+        const GRND_INSECURE = 1 << 2;
+    }
+}
@@ -33,6 +33,40 @@ bitflags! {
     }
 }
 
+// see [man msync](https://man7.org/linux/man-pages/man2/msync.2.html)
+bitflags! {
+#[derive(Clone, Copy, Debug)]
+    pub struct MsyncFlags: usize {
+        /// Schedule the write back and return immediately
+        const MS_ASYNC = 1;
+        /// Invalidate other mappings of the same file
+        const MS_INVALIDATE = 2;
+        /// Write back and wait for it to complete
+        const MS_SYNC = 4;
+    }
+}
+
+// see [man mremap](https://man7.org/linux/man-pages/man2/mremap.2.html)
+bitflags! {
+#[derive(Clone, Copy, Debug)]
+    pub struct MremapFlags: usize {
+        /// The mapping may be moved to a new address if it cannot grow in place
+        const MREMAP_MAYMOVE = 1;
+        /// Move the mapping to new_address, unmapping whatever was there
+        const MREMAP_FIXED = 2;
+        /// Keep the old range mapped after moving
+        const MREMAP_DONTUNMAP = 4;
+    }
+}
+
+// see [man madvise](https://man7.org/linux/man-pages/man2/madvise.2.html)
+pub const MADV_NORMAL: usize = 0; /* no special treatment */
+pub const MADV_RANDOM: usize = 1; /* expect random page references */
+pub const MADV_SEQUENTIAL: usize = 2; /* expect sequential page references */
+pub const MADV_WILLNEED: usize = 3; /* will need these pages */
+pub const MADV_DONTNEED: usize = 4; /* don't need these pages */
+pub const MADV_FREE: usize = 8; /* free pages only if memory pressure */
+
 pub struct SharedMemoryIdentifierDs {
     pub shm_perm: CreateMode, /* Ownership and permissions */
     pub shm_size: usize,      /* Size of segment (bytes) */
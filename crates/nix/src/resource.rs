@@ -1,3 +1,6 @@
+/// 没有限制
+pub const RLIM_INFINITY: usize = usize::MAX;
+
 #[derive(Clone, Copy, Debug)]
 pub struct RLimit {
     pub rlim_cur: usize, /* Soft limit */
@@ -9,7 +12,7 @@ impl RLimit {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[repr(u32)]
 pub enum Resource {
     CPU = 0,
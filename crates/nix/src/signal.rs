@@ -1,4 +1,5 @@
 pub const MAX_SIGNUM: u32 = 64;
+pub const SIGRTMIN: u32 = 32;
 pub const SIG_DFL: usize = 0;
 pub const SIG_IGN: usize = 1;
 
@@ -254,20 +255,86 @@ pub fn is_signal_valid(signum: u32) -> bool {
     signum < MAX_SIGNUM
 }
 
+/// siginfo_t, 与 Linux 64 位的布局一致, 共 128 字节. `_sifields` 联合体只列出用到的成员
 #[repr(C)]
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub struct SigInfo {
-    si_signo: i32,  /* Signal number */
-    si_errno: i32,  /* An errno value */
-    si_code: i32,   /* Signal code */
-    si_trapno: i32, /* Trap number that caused hardware-generated signal (unused on most architectures) */
-    si_pid: u32,    /* Sending process ID */
-    si_uid: u32,    /* Real user ID of sending process */
-    si_status: i32, /* Exit value or signal */
-    si_utime: i32,  /* User time consumed */
-    si_stime: i32,  /* System time consumed */
+    pub si_signo: i32, /* Signal number */
+    pub si_errno: i32, /* An errno value */
+    pub si_code: i32,  /* Signal code */
+    __pad: i32,
+    pub si_pid: i32,     /* Sending process ID, or timer ID for SI_TIMER */
+    pub si_uid: u32,     /* Real user ID of sending process, or overrun count for SI_TIMER */
+    pub si_value: usize, /* Signal value, or exit value or signal (si_status) for SIGCHLD */
+    __rest: [usize; 12],
 }
 
+impl SigInfo {
+    pub fn new(si_signo: i32, si_code: i32, si_pid: u32, si_status: i32) -> Self {
+        Self {
+            si_signo,
+            si_errno: 0,
+            si_code,
+            __pad: 0,
+            si_pid: si_pid as i32,
+            si_uid: 0,
+            si_value: si_status as u32 as usize,
+            __rest: [0; 12],
+        }
+    }
+    pub fn empty() -> Self {
+        Self::new(0, 0, 0, 0)
+    }
+    pub fn as_bytes(&self) -> &[u8] {
+        let size = core::mem::size_of::<Self>();
+        unsafe { core::slice::from_raw_parts(self as *const _ as usize as *const u8, size) }
+    }
+}
+
+// 通用的 si_code
+pub const SI_USER: i32 = 0;
+pub const SI_KERNEL: i32 = 0x80;
+pub const SI_QUEUE: i32 = -1;
+pub const SI_TIMER: i32 = -2;
+pub const SI_TKILL: i32 = -6;
+
+/// struct sigevent, 定时器等异步事件的通知方式, 共 64 字节
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SigEvent {
+    pub sigev_value: usize,
+    pub sigev_signo: i32,
+    pub sigev_notify: i32,
+    /// SIGEV_THREAD_ID 时接收信号的线程
+    pub sigev_notify_thread_id: i32,
+    __pad: [i32; 11],
+}
+
+impl SigEvent {
+    pub fn empty() -> Self {
+        Self {
+            sigev_value: 0,
+            sigev_signo: 0,
+            sigev_notify: 0,
+            sigev_notify_thread_id: 0,
+            __pad: [0; 11],
+        }
+    }
+}
+
+// sigev_notify
+pub const SIGEV_SIGNAL: i32 = 0;
+pub const SIGEV_NONE: i32 = 1;
+pub const SIGEV_THREAD: i32 = 2;
+pub const SIGEV_THREAD_ID: i32 = 4;
+
+// SIGCHLD 的 si_code
+pub const CLD_EXITED: i32 = 1;
+pub const CLD_KILLED: i32 = 2;
+pub const CLD_DUMPED: i32 = 3;
+pub const CLD_STOPPED: i32 = 5;
+pub const CLD_CONTINUED: i32 = 6;
+
 #[repr(C)]
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub struct UContext {
@@ -283,11 +350,7 @@ impl UContext {
         Self {
             uc_flags: 0,
             uc_link: core::ptr::null_mut(),
-            uc_stack: SignalStack {
-                ss_sp: 0,
-                ss_flags: 0,
-                ss_size: 0,
-            },
+            uc_stack: SignalStack::disabled(),
             sigmask: SigMask::empty(),
             __unused: [0; 1024 / 8 - core::mem::size_of::<SigMask>()],
             uc_mcontext: MContext {
@@ -306,12 +369,32 @@ pub struct SignalStack {
     pub ss_size: usize,
 }
 
+// ss_flags
+pub const SS_ONSTACK: i32 = 1;
+pub const SS_DISABLE: i32 = 2;
+/// 备用栈的最小大小
+pub const MINSIGSTKSZ: usize = 2048;
+
+impl SignalStack {
+    pub fn disabled() -> Self {
+        Self {
+            ss_sp: 0,
+            ss_flags: SS_DISABLE,
+            ss_size: 0,
+        }
+    }
+    /// sp 是否在备用栈上
+    pub fn contains(&self, sp: usize) -> bool {
+        self.ss_flags & SS_DISABLE == 0 && sp > self.ss_sp && sp - self.ss_sp <= self.ss_size
+    }
+}
+
 // The mcontext_t type is machine-dependent and opaque.
-// TODO layout musl (You can learn more form musl-libc)
-#[repr(C)]
+// greps[0] 为 pc, greps[i] 为 x_i. 与 sigcontext 一样按 16 字节对齐, 因此 uc_mcontext 位于偏移 176 处
+#[repr(C, align(16))]
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub struct MContext {
-    pub greps: [usize; 32],    // general registers
+    pub greps: [usize; 32],    // pc and general registers
     pub __reserved: [u8; 528], // size of mcontext_t is 784 bytes
 }
 
@@ -28,6 +28,38 @@ pub const AT_SECURE: usize = 23; // secure mode boolean
 pub const AT_RANDOM: usize = 25; // address of 16 random bytes
 
 pub const RUSAGE_SELF: isize = 0;
+pub const RUSAGE_CHILDREN: isize = -1;
+
+bitflags! {
+    /// wait4/waitid 的 options
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct WaitOptions: u32 {
+        /// 没有子进程状态改变时立即返回
+        const WNOHANG = 1;
+        /// 报告停止的子进程, 与 WSTOPPED 相同
+        const WUNTRACED = 2;
+        /// 报告退出的子进程, 仅用于 waitid
+        const WEXITED = 4;
+        /// 报告被 SIGCONT 恢复的子进程
+        const WCONTINUED = 8;
+        /// 不回收子进程, 仅用于 waitid
+        const WNOWAIT = 0x0100_0000;
+        /// 只等待当前线程的子进程
+        const __WNOTHREAD = 0x2000_0000;
+        const __WALL = 0x4000_0000;
+        const __WCLONE = 0x8000_0000;
+    }
+}
+
+impl WaitOptions {
+    pub const WSTOPPED: Self = Self::WUNTRACED;
+}
+
+// waitid 的 idtype
+pub const P_ALL: usize = 0;
+pub const P_PID: usize = 1;
+pub const P_PGID: usize = 2;
+pub const P_PIDFD: usize = 3;
 
 pub const SCHED_OTHER: isize = 0;
 pub const SCHED_FIFO: isize = 1;
@@ -95,6 +95,17 @@ impl TimeVal {
     pub fn into_ticks(&self) -> usize {
         self.sec * QEMU_CLOCK_FREQ + self.usec / USEC_PER_SEC * QEMU_CLOCK_FREQ
     }
+
+    pub fn from_us(us: usize) -> Self {
+        Self {
+            sec: us / USEC_PER_SEC,
+            usec: us % USEC_PER_SEC,
+        }
+    }
+
+    pub fn into_us(&self) -> usize {
+        self.sec * USEC_PER_SEC + self.usec
+    }
 }
 
 /// Linux 间隔计数
@@ -149,6 +160,13 @@ impl TimeSpec {
         self.tv_sec as usize * NSEC_PER_SEC + self.tv_nsec as usize
     }
 
+    pub fn from_ns(ns: usize) -> Self {
+        Self {
+            tv_sec: (ns / NSEC_PER_SEC) as u64,
+            tv_nsec: (ns % NSEC_PER_SEC) as u64,
+        }
+    }
+
     pub fn from_ticks(tiks: usize) -> Self {
         let tv_sec = tiks / QEMU_CLOCK_FREQ;
         let tv_nsec = (tiks % QEMU_CLOCK_FREQ) * NSEC_PER_SEC / QEMU_CLOCK_FREQ;
@@ -198,18 +216,76 @@ impl itimerval {
         }
     }
 }
-#[derive(Debug, Clone)]
-pub struct IntervalTimer {
-    /// Creation time of the timer.
-    pub creation_time: TimeVal,
-    pub timer_value: itimerval,
+// POSIX 定时器使用的时钟
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+
+// timer_settime 的 flags
+pub const TIMER_ABSTIME: usize = 1;
+
+/// timer_settime/timer_gettime 使用的定时器设置
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy)]
+pub struct itimerspec {
+    pub it_interval: TimeSpec,
+    pub it_value: TimeSpec,
+}
+
+impl itimerspec {
+    pub fn empty() -> Self {
+        Self {
+            it_interval: TimeSpec::empty(),
+            it_value: TimeSpec::empty(),
+        }
+    }
+}
+
+/// RTC 时间, 即 `struct rtc_time`, 由 `RTC_RD_TIME` 返回
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RtcTime {
+    pub tm_sec: i32,
+    pub tm_min: i32,
+    pub tm_hour: i32,
+    pub tm_mday: i32,
+    pub tm_mon: i32,  // 0 ~ 11
+    pub tm_year: i32, // 自 1900 年起的年数
+    pub tm_wday: i32, // 0 为星期日
+    pub tm_yday: i32, // 0 ~ 365
+    pub tm_isdst: i32,
 }
 
-impl IntervalTimer {
-    pub fn new(timer_value: itimerval, creation_time: TimeVal) -> Self {
+impl RtcTime {
+    /// 将自 1970-01-01 00:00:00 UTC 起的秒数转换为日历时间
+    pub fn from_secs(secs: usize) -> Self {
+        let days = (secs / 86400) as i64;
+        let rem = (secs % 86400) as i32;
+        // 1970-01-01 是星期四
+        let wday = ((days + 4) % 7) as i32;
+        // 按以 3 月 1 日为年初的公历计算年月日, 见 Howard Hinnant 的 civil_from_days
+        let z = days + 719468;
+        let era = z / 146097;
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let mday = doy - (153 * mp + 2) / 5 + 1;
+        let mon = if mp < 10 { mp + 2 } else { mp - 10 };
+        let year = yoe + era * 400 + if mon < 2 { 1 } else { 0 };
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        const MONTH_DAYS: [i64; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+        let yday = MONTH_DAYS[mon as usize] + mday - 1 + if leap && mon >= 2 { 1 } else { 0 };
         Self {
-            creation_time,
-            timer_value,
+            tm_sec: rem % 60,
+            tm_min: rem / 60 % 60,
+            tm_hour: rem / 3600,
+            tm_mday: mday as i32,
+            tm_mon: mon as i32,
+            tm_year: (year - 1900) as i32,
+            tm_wday: wday,
+            tm_yday: yday as i32,
+            tm_isdst: 0,
         }
     }
 }
@@ -23,6 +23,17 @@ pub fn print(args: fmt::Arguments<'_>) {
     drop(lck);
 }
 
+/// 原样输出字节, 不要求是合法的 UTF-8
+pub fn write_bytes(bytes: &[u8]) {
+    #[cfg(feature = "multi-harts")]
+    let lck = CONSOLE_PRINT_LOCK.lock();
+    for &c in bytes {
+        console_putchar(c as i32);
+    }
+    #[cfg(feature = "multi-harts")]
+    drop(lck);
+}
+
 #[macro_export]
 macro_rules! print {
     ($fmt:literal $(, $($arg: tt)+)?) => {
@@ -17,6 +17,9 @@ pub const THREAD_LIMIT: usize = 4096 * 2;
 
 pub const MMAP_BASE: usize = 0x6000_0000;
 
+/// 用户映射的地址上界, 即 Sv39 地址空间低半部分的末尾. 跳板页与 Trap 上下文位于高半部分
+pub const USER_SPACE_END: usize = 1 << 38;
+
 pub const SHM_BASE: usize = 0x7000_0000;
 
 pub const LINK_BASE: usize = 0x2000_0000;
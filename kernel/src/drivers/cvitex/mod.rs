@@ -1,9 +1,10 @@
 extern crate alloc;
 
 use super::{BlkDriver, DeviceType, DeviceWapper, Driver};
+use crate::random::add_randomness;
 use alloc::sync::Arc;
 
-use fat32::BlockDevice;
+use fat32::{BlockDevice, DevError, BLOCK_SIZE};
 
 use spin::Mutex;
 
@@ -43,28 +44,50 @@ impl Driver for CvSd {
 }
 
 impl BlkDriver for CvSd {
-    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> Result<(), DevError> {
         cv1812h_sd::clk_enable(true);
-        cv1812h_sd::read_block(block_id as _, buf).expect("can't read block by using CvSd");
+        let ret = cv1812h_sd::read_block(block_id as _, buf);
         cv1812h_sd::clk_enable(false);
+        ret.map_err(|_| {
+            warn!("[CvSd] can't read block {}", block_id);
+            DevError::ReadFailed(block_id)
+        })
     }
 
-    fn write_block(&self, block_id: usize, buf: &[u8]) {
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), DevError> {
         cv1812h_sd::clk_enable(true);
-        cv1812h_sd::write_block(block_id as _, buf).expect("can't write block by using CvSd");
+        let ret = cv1812h_sd::write_block(block_id as _, buf);
         cv1812h_sd::clk_enable(false);
+        ret.map_err(|_| {
+            warn!("[CvSd] can't write block {}", block_id);
+            DevError::WriteFailed(block_id)
+        })
     }
 }
 
 impl BlockDevice for CvSdWrapper {
-    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
-        self.0.lock().read_block(block_id, buf);
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> Result<(), DevError> {
+        let ret = self.0.lock().read_block(block_id, buf);
         // self.0.read_block(block_id, buf);
+        add_randomness(block_id);
+        ret
     }
 
-    fn write_block(&self, block_id: usize, buf: &[u8]) {
-        self.0.lock().write_block(block_id, buf);
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), DevError> {
+        let ret = self.0.lock().write_block(block_id, buf);
         // self.0.write_block(block_id, buf);
+        add_randomness(block_id);
+        ret
+    }
+
+    /// The driver does not read the card's CSD register, so the size of the
+    /// FAT32 volume at block 0 (BPB_TotSec32) is taken as the capacity
+    fn num_blocks(&self) -> usize {
+        let mut block = [0u8; BLOCK_SIZE];
+        if self.read_block(0, &mut block).is_err() {
+            return 0;
+        }
+        u32::from_le_bytes([block[32], block[33], block[34], block[35]]) as usize
     }
 }
 
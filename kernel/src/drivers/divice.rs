@@ -1,4 +1,5 @@
 use alloc::{sync::Arc, vec::Vec};
+use fat32::DevError;
 
 pub enum DeviceWapper {
     // RTC(Arc<dyn RtcDriver>),
@@ -77,8 +78,8 @@ pub trait RtcDriver: Driver {
 }
 
 pub trait BlkDriver: Driver {
-    fn read_block(&self, block_id: usize, buf: &mut [u8]);
-    fn write_block(&self, block_id: usize, buf: &[u8]);
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> Result<(), DevError>;
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), DevError>;
 }
 
 #[derive(Debug)]
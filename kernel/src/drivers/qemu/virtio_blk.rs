@@ -1,9 +1,10 @@
 //!  Block device under VirtIO.
 
 use super::virtio_impl::HalImpl;
+use crate::random::add_randomness;
 use core::ptr::NonNull;
 
-use fat32::{BlockDevice, BLOCK_SIZE};
+use fat32::{BlockDevice, DevError, BLOCK_SIZE};
 // use crate::fat32::{BlockDevice, BLOCK_SIZE};
 
 use spin::Mutex;
@@ -20,13 +21,28 @@ unsafe impl Send for VirtIOBlock {}
 unsafe impl Sync for VirtIOBlock {}
 
 impl BlockDevice for VirtIOBlock {
-    fn read_block(&self, blk_id: usize, buf: &mut [u8]) {
+    fn read_block(&self, blk_id: usize, buf: &mut [u8]) -> Result<(), DevError> {
         assert_eq!(buf.len(), BLOCK_SIZE);
-        self.0.lock().read_blocks(blk_id, buf).unwrap();
+        self.0
+            .lock()
+            .read_blocks(blk_id, buf)
+            .map_err(|_| DevError::ReadFailed(blk_id))?;
+        add_randomness(blk_id);
+        Ok(())
     }
 
-    fn write_block(&self, blk_id: usize, buf: &[u8]) {
-        self.0.lock().write_blocks(blk_id, buf).unwrap();
+    fn write_block(&self, blk_id: usize, buf: &[u8]) -> Result<(), DevError> {
+        self.0
+            .lock()
+            .write_blocks(blk_id, buf)
+            .map_err(|_| DevError::WriteFailed(blk_id))?;
+        add_randomness(blk_id);
+        Ok(())
+    }
+
+    fn num_blocks(&self) -> usize {
+        // in 512-byte sectors, the same as BLOCK_SIZE
+        self.0.lock().capacity() as usize
     }
 }
 
@@ -0,0 +1,72 @@
+use super::Device;
+use crate::syscall::impls::Errno;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::min;
+use fat32::{BlockDevice, BLOCK_SIZE};
+
+/// 块设备文件, 如 `/dev/vda`, 按字节偏移读写底层块设备
+pub struct Disk {
+    device: Arc<dyn BlockDevice>,
+    /// 设备容量 (字节)
+    size: usize,
+}
+
+impl Disk {
+    pub fn new(device: Arc<dyn BlockDevice>) -> Self {
+        let size = device.num_blocks() * BLOCK_SIZE;
+        Self { device, size }
+    }
+}
+
+impl Device for Disk {
+    fn rdev(&self) -> (u32, u32) {
+        (254, 0)
+    }
+    fn is_block(&self) -> bool {
+        true
+    }
+    fn size(&self) -> usize {
+        self.size
+    }
+    /// 读到设备末尾为止, 从末尾开始读返回 0
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, Errno> {
+        let end = min(buf.len(), self.size.saturating_sub(offset));
+        let buf = &mut buf[..end];
+        let mut block = Vec::from([0u8; BLOCK_SIZE]);
+        let mut read_size = 0;
+        while read_size < buf.len() {
+            let pos = offset + read_size;
+            let block_offset = pos % BLOCK_SIZE;
+            let len = min(BLOCK_SIZE - block_offset, buf.len() - read_size);
+            self.device.read_block(pos / BLOCK_SIZE, &mut block)?;
+            buf[read_size..read_size + len]
+                .copy_from_slice(&block[block_offset..block_offset + len]);
+            read_size += len;
+        }
+        Ok(read_size)
+    }
+    /// 不足一块的写入需要先读出整块, 修改后再写回; 写到设备末尾为止, 从末尾开始写返回 ENOSPC
+    fn write(&self, offset: usize, buf: &[u8]) -> Result<usize, Errno> {
+        if offset >= self.size && !buf.is_empty() {
+            return Err(Errno::ENOSPC);
+        }
+        let buf = &buf[..min(buf.len(), self.size.saturating_sub(offset))];
+        let mut block = Vec::from([0u8; BLOCK_SIZE]);
+        let mut write_size = 0;
+        while write_size < buf.len() {
+            let pos = offset + write_size;
+            let block_id = pos / BLOCK_SIZE;
+            let block_offset = pos % BLOCK_SIZE;
+            let len = min(BLOCK_SIZE - block_offset, buf.len() - write_size);
+            if len < BLOCK_SIZE {
+                self.device.read_block(block_id, &mut block)?;
+            }
+            block[block_offset..block_offset + len]
+                .copy_from_slice(&buf[write_size..write_size + len]);
+            self.device.write_block(block_id, &block)?;
+            write_size += len;
+        }
+        Ok(write_size)
+    }
+}
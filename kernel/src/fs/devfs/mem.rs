@@ -0,0 +1,65 @@
+//! 内存类字符设备, 主设备号为 1, 与 Linux 的 `drivers/char/mem.c` 对应
+
+use super::Device;
+use crate::random::{add_device_data, get_random_bytes, wait_for_random};
+use crate::syscall::impls::Errno;
+
+/// `/dev/null`: 读总是返回 EOF, 写入的数据全部丢弃
+pub struct Null;
+
+impl Device for Null {
+    fn rdev(&self) -> (u32, u32) {
+        (1, 3)
+    }
+    fn read(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize, Errno> {
+        Ok(0)
+    }
+    fn write(&self, _offset: usize, buf: &[u8]) -> Result<usize, Errno> {
+        Ok(buf.len())
+    }
+}
+
+/// `/dev/zero`: 读到无穷无尽的 0, 写入的数据全部丢弃
+pub struct Zero;
+
+impl Device for Zero {
+    fn rdev(&self) -> (u32, u32) {
+        (1, 5)
+    }
+    fn read(&self, _offset: usize, buf: &mut [u8]) -> Result<usize, Errno> {
+        buf.fill(0);
+        Ok(buf.len())
+    }
+    fn write(&self, _offset: usize, buf: &[u8]) -> Result<usize, Errno> {
+        Ok(buf.len())
+    }
+}
+
+/// `/dev/random` 与 `/dev/urandom`: 从内核随机数池读到无穷无尽的随机数, 写入的数据被混入随机数池
+/// 但不计入熵. `/dev/random` 在随机数池初始化之前阻塞, `/dev/urandom` 从不阻塞
+pub struct Random {
+    minor: u32,
+}
+
+impl Random {
+    pub fn new(minor: u32) -> Self {
+        Self { minor }
+    }
+}
+
+impl Device for Random {
+    fn rdev(&self) -> (u32, u32) {
+        (1, self.minor)
+    }
+    fn read(&self, _offset: usize, buf: &mut [u8]) -> Result<usize, Errno> {
+        if self.minor == 8 {
+            wait_for_random()?;
+        }
+        get_random_bytes(buf);
+        Ok(buf.len())
+    }
+    fn write(&self, _offset: usize, buf: &[u8]) -> Result<usize, Errno> {
+        add_device_data(buf);
+        Ok(buf.len())
+    }
+}
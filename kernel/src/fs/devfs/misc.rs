@@ -0,0 +1,79 @@
+//! misc 字符设备, 主设备号为 10
+
+use super::Device;
+use crate::mm::copyout;
+use crate::syscall::impls::Errno;
+use crate::task::current_user_token;
+use crate::timer::get_timeval;
+use core::cmp::min;
+use nix::time::RtcTime;
+use nix::RTC_RD_TIME;
+use spin::Mutex;
+
+/// `/dev/misc/rtc`: 通过 `RTC_RD_TIME` 读取当前时间
+pub struct Rtc;
+
+impl Device for Rtc {
+    fn rdev(&self) -> (u32, u32) {
+        (10, 135)
+    }
+    fn read(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize, Errno> {
+        Ok(0)
+    }
+    fn write(&self, _offset: usize, _buf: &[u8]) -> Result<usize, Errno> {
+        Ok(0)
+    }
+    fn ioctl(&self, request: usize, argp: usize) -> Result<isize, Errno> {
+        match request {
+            RTC_RD_TIME => {
+                // 内核没有读取硬件 RTC, 以开机时刻为 1970-01-01 00:00:00
+                let time = RtcTime::from_secs(get_timeval().sec);
+                copyout(current_user_token(), argp as *mut RtcTime, &time)?;
+                Ok(0)
+            }
+            _ => Err(Errno::ENOTTY),
+        }
+    }
+}
+
+/// `/dev/cpu_dma_latency`: 应用程序写入一个非负整数 (微秒) 来请求 CPU 的最大延迟, 读出当前请求的值
+///
+/// 内核不做电源管理, 只记录写入的值
+pub struct CpuDmaLatency {
+    latency: Mutex<i32>,
+}
+
+impl CpuDmaLatency {
+    pub fn new() -> Self {
+        // 与 Linux 的 PM_QOS_CPU_LATENCY_DEFAULT_VALUE 一致
+        Self {
+            latency: Mutex::new(2000 * 1000 * 1000),
+        }
+    }
+}
+
+impl Device for CpuDmaLatency {
+    fn rdev(&self) -> (u32, u32) {
+        (10, 62)
+    }
+    fn read(&self, _offset: usize, buf: &mut [u8]) -> Result<usize, Errno> {
+        let bytes = self.latency.lock().to_ne_bytes();
+        let len = min(buf.len(), bytes.len());
+        buf[..len].copy_from_slice(&bytes[..len]);
+        Ok(len)
+    }
+    /// 与 Linux 一样, 既可以写入 4 字节的整数, 也可以写入十进制字符串
+    fn write(&self, _offset: usize, buf: &[u8]) -> Result<usize, Errno> {
+        let value = if buf.len() == 4 {
+            Some(i32::from_ne_bytes([buf[0], buf[1], buf[2], buf[3]]))
+        } else {
+            core::str::from_utf8(buf)
+                .ok()
+                .and_then(|s| s.trim().parse().ok())
+        };
+        if let Some(value) = value.filter(|value| *value >= 0) {
+            *self.latency.lock() = value;
+        }
+        Ok(buf.len())
+    }
+}
@@ -0,0 +1,350 @@
+//! devfs
+//!
+//! `/dev` 下的设备文件不再是根文件系统中的占位文件, 而是直接对应内核中的设备:
+//! 每个设备实现 [`Device`], 打开后由 [`DevFile`] 负责偏移与打开标志等与设备无关的部分.
+//!
+//! 目录结构:
+//! - `/dev/{null,zero,random,urandom}`: 见 [`mem`]
+//! - `/dev/tty`: 控制台
+//! - `/dev/misc/rtc`: 实时时钟
+//! - `/dev/cpu_dma_latency`
+//! - `/dev/vda`: 根文件系统所在的块设备
+//! - `/dev/shm`: 空目录, 供挂载 tmpfs
+
+mod block;
+mod mem;
+mod misc;
+mod tty;
+
+pub use block::*;
+pub use mem::*;
+pub use misc::*;
+pub use tty::*;
+
+use super::{ino_alloc, Dentry, File, Inode, InodeType, SuperBlock};
+use crate::drivers::BLOCK_DEVICE;
+use crate::mm::UserBuffer;
+use crate::syscall::impls::Errno;
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::any::Any;
+use core::mem::size_of;
+use nix::{Dirent, Kstat, OpenFlags, NAME_LIMIT, S_IFBLK, S_IFCHR, S_IFDIR};
+use path::AbsolutePath;
+use spin::Mutex;
+
+/// 设备驱动向文件系统提供的接口
+pub trait Device: Send + Sync {
+    /// 主设备号与次设备号
+    fn rdev(&self) -> (u32, u32);
+    /// 是否为块设备
+    fn is_block(&self) -> bool {
+        false
+    }
+    /// 设备容量 (字节); 字符设备没有长度的概念, 与管道一样视为无限长
+    fn size(&self) -> usize {
+        usize::MAX
+    }
+    /// 从 `offset` 处读取数据到 `buf`, 返回实际读取的字节数; 字符设备通常忽略 `offset`
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, Errno>;
+    /// 将 `buf` 写入 `offset` 处, 返回实际写入的字节数
+    fn write(&self, offset: usize, buf: &[u8]) -> Result<usize, Errno>;
+    fn ioctl(&self, _request: usize, _argp: usize) -> Result<isize, Errno> {
+        Err(Errno::ENOTTY)
+    }
+}
+
+/// 与 Linux 的 `new_encode_dev` 一致的设备号编码
+pub fn makedev(major: u32, minor: u32) -> u64 {
+    let (major, minor) = (major as u64, minor as u64);
+    (minor & 0xff) | (major << 8) | ((minor & !0xff) << 12)
+}
+
+pub struct DevFs {
+    root: Arc<DevEntry>,
+}
+
+impl DevFs {
+    pub fn new() -> Self {
+        let dir = |name: &str, children: Vec<Arc<DevEntry>>| {
+            Arc::new(DevEntry {
+                name: String::from(name),
+                ino: ino_alloc(),
+                node: DevNode::Dir(children),
+            })
+        };
+        let dev = |name: &str, device: Arc<dyn Device>| {
+            Arc::new(DevEntry {
+                name: String::from(name),
+                ino: ino_alloc(),
+                node: DevNode::Device(device),
+            })
+        };
+        let root = dir(
+            "/",
+            Vec::from([
+                dev("null", Arc::new(Null)),
+                dev("zero", Arc::new(Zero)),
+                dev("random", Arc::new(Random::new(8))),
+                dev("urandom", Arc::new(Random::new(9))),
+                dev("tty", Arc::new(Tty)),
+                dev("cpu_dma_latency", Arc::new(CpuDmaLatency::new())),
+                dev("vda", Arc::new(Disk::new(BLOCK_DEVICE.clone()))),
+                dir("misc", Vec::from([dev("rtc", Arc::new(Rtc))])),
+                dir("shm", Vec::new()),
+            ]),
+        );
+        Self { root }
+    }
+}
+
+impl SuperBlock for DevFs {
+    fn fs_type(&self) -> &str {
+        "devtmpfs"
+    }
+    fn root(&self) -> Arc<dyn Dentry> {
+        self.root.clone()
+    }
+}
+
+enum DevNode {
+    Dir(Vec<Arc<DevEntry>>),
+    Device(Arc<dyn Device>),
+}
+
+/// devfs 的目录项, 同时也是 inode; 目录树在创建文件系统时确定, 之后不再改变
+pub struct DevEntry {
+    name: String,
+    /// 创建文件系统时分配, 之后不再改变
+    ino: u64,
+    node: DevNode,
+}
+
+impl DevEntry {
+    /// 是否为块设备文件
+    pub fn is_block(&self) -> bool {
+        match &self.node {
+            DevNode::Device(device) => device.is_block(),
+            DevNode::Dir(_) => false,
+        }
+    }
+}
+
+impl Dentry for DevEntry {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+    fn is_dir(&self) -> bool {
+        matches!(self.node, DevNode::Dir(_))
+    }
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Dentry>> {
+        match &self.node {
+            DevNode::Dir(children) => children
+                .iter()
+                .find(|child| child.name == name)
+                .map(|child| child.clone() as Arc<dyn Dentry>),
+            DevNode::Device(_) => None,
+        }
+    }
+    fn create(&self, _name: &str, _itype: InodeType) -> Result<Arc<dyn Dentry>, Errno> {
+        match self.node {
+            DevNode::Dir(_) => Err(Errno::EACCES),
+            DevNode::Device(_) => Err(Errno::ENOTDIR),
+        }
+    }
+    fn inode(self: Arc<Self>) -> Arc<dyn Inode> {
+        self
+    }
+    fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+impl Inode for DevEntry {
+    fn open(self: Arc<Self>, path: AbsolutePath, flags: OpenFlags) -> Result<Arc<dyn File>, Errno> {
+        Ok(Arc::new(DevFile {
+            entry: self,
+            path,
+            flags: Mutex::new(flags),
+            offset: Mutex::new(0),
+        }))
+    }
+}
+
+/// 打开的设备文件或 devfs 目录
+pub struct DevFile {
+    entry: Arc<DevEntry>,
+    path: AbsolutePath,
+    flags: Mutex<OpenFlags>,
+    /// 对设备是读写位置, 对目录是下一个目录项的下标
+    offset: Mutex<usize>,
+}
+
+impl DevFile {
+    fn device(&self) -> Option<&Arc<dyn Device>> {
+        match &self.entry.node {
+            DevNode::Device(device) => Some(device),
+            DevNode::Dir(_) => None,
+        }
+    }
+    fn read_at(&self, offset: usize, buf: &mut UserBuffer) -> Result<usize, Errno> {
+        let device = match self.device() {
+            Some(device) => device,
+            None => return Ok(0),
+        };
+        let mut total_read_size = 0;
+        for sub_buf in buf.buffers.iter_mut() {
+            let read_size = device.read(offset + total_read_size, sub_buf)?;
+            total_read_size += read_size;
+            if read_size < sub_buf.len() {
+                break;
+            }
+        }
+        Ok(total_read_size)
+    }
+    fn write_at(&self, offset: usize, buf: &UserBuffer) -> Result<usize, Errno> {
+        let device = match self.device() {
+            Some(device) => device,
+            None => return Ok(0),
+        };
+        let mut total_write_size = 0;
+        for sub_buf in buf.buffers.iter() {
+            // 已经写入了一部分时返回写入的字节数, 而不是错误
+            let write_size = match device.write(offset + total_write_size, sub_buf) {
+                Ok(write_size) => write_size,
+                Err(_) if total_write_size > 0 => break,
+                Err(errno) => return Err(errno),
+            };
+            total_write_size += write_size;
+            if write_size < sub_buf.len() {
+                break;
+            }
+        }
+        Ok(total_write_size)
+    }
+}
+
+impl File for DevFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        self.device().is_some()
+    }
+    fn available(&self) -> bool {
+        true
+    }
+    fn read_to_ubuf(&self, mut buf: UserBuffer) -> Result<usize, Errno> {
+        let offset = self.offset();
+        let read_size = self.read_at(offset, &mut buf)?;
+        self.seek(offset + read_size);
+        Ok(read_size)
+    }
+    fn write_from_ubuf(&self, buf: UserBuffer) -> Result<usize, Errno> {
+        let offset = self.offset();
+        let write_size = self.write_at(offset, &buf)?;
+        self.seek(offset + write_size);
+        Ok(write_size)
+    }
+    fn pread(&self, mut buf: UserBuffer, offset: usize) -> Result<usize, Errno> {
+        self.read_at(offset, &mut buf)
+    }
+    fn pwrite(&self, buf: UserBuffer, offset: usize) -> Result<usize, Errno> {
+        self.write_at(offset, &buf)
+    }
+    fn kernel_read_with_offset(&self, offset: usize, len: usize) -> Result<Vec<u8>, Errno> {
+        let mut buf = vec![0; len];
+        let read_size = match self.device() {
+            Some(device) => device.read(offset, &mut buf)?,
+            None => 0,
+        };
+        buf.truncate(read_size);
+        Ok(buf)
+    }
+    fn write_from_kspace(&self, data: &Vec<u8>) -> Result<usize, Errno> {
+        let offset = self.offset();
+        let write_size = match self.device() {
+            Some(device) => device.write(offset, data)?,
+            None => 0,
+        };
+        self.seek(offset + write_size);
+        Ok(write_size)
+    }
+    fn ioctl(&self, request: usize, argp: usize) -> Result<isize, Errno> {
+        match self.device() {
+            Some(device) => device.ioctl(request, argp),
+            None => Err(Errno::ENOTTY),
+        }
+    }
+    fn seek(&self, pos: usize) {
+        *self.offset.lock() = pos;
+    }
+    fn offset(&self) -> usize {
+        *self.offset.lock()
+    }
+    fn name(&self) -> String {
+        self.entry.name.clone()
+    }
+    fn fstat(&self, kstat: &mut Kstat) -> Result<(), Errno> {
+        let (st_mode, st_rdev) = match self.device() {
+            Some(device) => {
+                let (major, minor) = device.rdev();
+                let mode = if device.is_block() { S_IFBLK } else { S_IFCHR };
+                (mode | 0o666, makedev(major, minor))
+            }
+            None => (S_IFDIR | 0o755, 0),
+        };
+        kstat.st_dev = 0;
+        kstat.st_ino = self.entry.ino;
+        kstat.st_mode = st_mode;
+        kstat.st_nlink = 1;
+        kstat.st_rdev = st_rdev;
+        kstat.st_size = 0;
+        kstat.st_blksize = 512;
+        kstat.st_blocks = 0;
+        Ok(())
+    }
+    fn dirent(&self, dirent: &mut Dirent) -> isize {
+        let children = match &self.entry.node {
+            DevNode::Dir(children) => children,
+            DevNode::Device(_) => return 0,
+        };
+        let idx = self.offset();
+        if idx >= children.len() {
+            return 0;
+        }
+        let file_bytes = children[idx].name.as_bytes();
+        dirent.d_ino = children[idx].ino as usize;
+        dirent.d_off =
+            size_of::<Dirent>() as isize - NAME_LIMIT as isize + file_bytes.len() as isize;
+        dirent.d_reclen = size_of::<Dirent>() as u16;
+        dirent.d_type = 0; // 0 d_type is file
+        dirent.d_name[..file_bytes.len()].copy_from_slice(file_bytes);
+        dirent.d_name[file_bytes.len()] = b'\0';
+        self.seek(idx + 1);
+        size_of::<Dirent>() as isize
+    }
+    fn file_size(&self) -> usize {
+        self.device().map_or(0, |device| device.size())
+    }
+    fn set_flags(&self, flag: OpenFlags) {
+        *self.flags.lock() = flag;
+    }
+    fn flags(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+    fn set_cloexec(&self) {
+        *self.flags.lock() |= OpenFlags::O_CLOEXEC;
+    }
+    fn path(&self) -> AbsolutePath {
+        self.path.clone()
+    }
+    fn is_under(&self, dir: &AbsolutePath) -> bool {
+        self.path.start_with(dir)
+    }
+    fn truncate(&self, _new_length: usize) -> Result<(), Errno> {
+        Ok(())
+    }
+    fn is_dir(&self) -> bool {
+        self.device().is_none()
+    }
+}
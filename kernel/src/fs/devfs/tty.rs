@@ -0,0 +1,38 @@
+use super::Device;
+use crate::console::write_bytes;
+use crate::fs::{check_tty_read, console_getc, tty_ioctl};
+use crate::syscall::impls::Errno;
+use crate::task::{current_task, suspend_current_and_run_next};
+
+/// `/dev/tty`: 与标准输入输出一样, 读写都落到控制台上
+pub struct Tty;
+
+impl Device for Tty {
+    fn rdev(&self) -> (u32, u32) {
+        (5, 0)
+    }
+    /// 阻塞直到读到一个字符, 每次只返回一个字符
+    fn read(&self, _offset: usize, buf: &mut [u8]) -> Result<usize, Errno> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        check_tty_read()?;
+        buf[0] = loop {
+            if let Some(c) = console_getc() {
+                break c;
+            }
+            if current_task().unwrap().has_unmasked_signal() {
+                return Err(Errno::ERESTARTSYS);
+            }
+            suspend_current_and_run_next();
+        };
+        Ok(1)
+    }
+    fn write(&self, _offset: usize, buf: &[u8]) -> Result<usize, Errno> {
+        write_bytes(buf);
+        Ok(buf.len())
+    }
+    fn ioctl(&self, request: usize, argp: usize) -> Result<isize, Errno> {
+        tty_ioctl(request, argp)
+    }
+}
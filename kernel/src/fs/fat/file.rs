@@ -1,6 +1,6 @@
 //! Kernel file system implementation for FAT32.
 //!
-//! Design considerations for [`Inode`]:
+//! Design considerations for [`FatInode`]:
 //! 1. Enhance lookup efficiency by utilizing InodeCache for file caching.
 //! 2. Regarding the fields of file and page cache:
 //!     - Due to time constraints after the first stage of the national competition,
@@ -17,7 +17,7 @@
 //!       which results in data being synchronized to the disk every time it is written.
 //!       However, after studying TitanixOS's PageCache design, we introduced a page caching mechanism for kernel files,
 //!       effectively creating a virtual tempfs and significantly improving execution efficiency.
-//! 3. Regarding the file_size field (storing the file size in the Inode):
+//! 3. Regarding the file_size field (storing the file size in the FatInode):
 //!     - During kernel execution, files created are often memory-mapped, treating them as files managed by a virtual tempfs.
 //!     - The read and write operations on these files created during kernel execution are actually performed in memory using the Page Cache
 //!       and are often not directly written back to the file system.
@@ -30,20 +30,23 @@
 #[cfg(not(feature = "no-page-cache"))]
 mod feature_no_page_cache {
     pub use crate::consts::PAGE_SIZE;
-    pub use crate::fs::PageCache;
+    pub use crate::fs::{mark_inode_dirty, PageCache};
+    pub use crate::mm::FrameTracker;
     pub use alloc::collections::BTreeMap;
+    pub use core::sync::atomic::{AtomicBool, Ordering};
     pub use spin::RwLock;
 }
 #[cfg(not(feature = "no-page-cache"))]
 use feature_no_page_cache::*;
 
-use crate::fs::open;
+use crate::fs::{ino_alloc, lookup, open};
 use crate::fs::{File, OpenFlags};
 use crate::mm::UserBuffer;
+use crate::syscall::impls::Errno;
 use alloc::{string::String, sync::Arc, vec::Vec};
 use fat32::VirtFile;
 
-use nix::{CreateMode, Dirent, InodeTime, Kstat, S_IFCHR, S_IFDIR, S_IFREG};
+use nix::{CreateMode, Dirent, InodeTime, Kstat, S_IFDIR, S_IFLNK, S_IFREG};
 use path::AbsolutePath;
 use spin::lazy::Lazy;
 use spin::{Mutex, MutexGuard};
@@ -53,10 +56,10 @@ pub const INODE_CACHE_LIMIT: usize = 1024;
 
 /// InodeCache is used to cache the Inode of the file. Mainly used for the Open syscall.
 #[cfg(all(not(feature = "no-page-cache"), not(feature = "hash-inode-cache")))]
-pub struct InodeCache(pub RwLock<BTreeMap<AbsolutePath, Arc<Inode>>>);
-// pub struct InodeCache(pub SpinLock<BTreeMap<AbsolutePath, Arc<Inode>>>);
+pub struct InodeCache(pub RwLock<BTreeMap<AbsolutePath, Arc<FatInode>>>);
+// pub struct InodeCache(pub SpinLock<BTreeMap<AbsolutePath, Arc<FatInode>>>);
 #[cfg(all(not(feature = "no-page-cache"), feature = "hash-inode-cache"))]
-pub struct InodeCache(pub RwLock<hashbrown::HashMap<AbsolutePath, Arc<Inode>>>);
+pub struct InodeCache(pub RwLock<hashbrown::HashMap<AbsolutePath, Arc<FatInode>>>);
 #[cfg(all(not(feature = "no-page-cache"), not(feature = "hash-inode-cache")))]
 // pub static INODE_CACHE: InodeCache = InodeCache(RwLock::new(BTreeMap::new()));
 pub static INODE_CACHE: Lazy<InodeCache> = Lazy::new(|| {
@@ -77,11 +80,11 @@ pub static INODE_CACHE: Lazy<InodeCache> =
 #[cfg(not(feature = "no-page-cache"))]
 #[allow(unused)]
 impl InodeCache {
-    pub fn get(&self, path: &AbsolutePath) -> Option<Arc<Inode>> {
+    pub fn get(&self, path: &AbsolutePath) -> Option<Arc<FatInode>> {
         self.0.read().get(path).cloned()
         // self.0.lock().get(path).cloned()
     }
-    pub fn insert(&self, path: AbsolutePath, inode: Arc<Inode>) {
+    pub fn insert(&self, path: AbsolutePath, inode: Arc<FatInode>) {
         self.0.write().insert(path, inode);
         if self.0.read().len() > INODE_CACHE_LIMIT {
             self.shrink();
@@ -100,7 +103,7 @@ impl InodeCache {
         // self.0.lock().clear();
     }
     pub fn shrink(&self) {
-        // remove the item whose Inode strong reference count is 1
+        // remove the item whose FatInode strong reference count is 1
         let mut map = self.0.write();
         // let mut map = self.0.lock();
         let mut remove_list = Vec::new();
@@ -130,26 +133,90 @@ pub struct KFile {
     pub available: Mutex<bool>,
 
     // shared by the same file (with page cache)
-    pub inode: Arc<Inode>,
+    pub inode: Arc<FatInode>,
 }
 
 // You can see the introduction at the beginning of this file.
-pub struct Inode {
+pub struct FatInode {
     pub file: Mutex<Arc<VirtFile>>,
     pub fid: u64,
     #[cfg(not(feature = "no-page-cache"))]
     pub page_cache: Mutex<Option<Arc<PageCache>>>,
     #[cfg(not(feature = "no-page-cache"))]
     pub file_size: Mutex<usize>,
+    /// Whether the inode is on the dirty list, see [`super::writeback`]
+    #[cfg(not(feature = "no-page-cache"))]
+    pub dirty: AtomicBool,
+}
+
+#[cfg(not(feature = "no-page-cache"))]
+impl FatInode {
+    /// Write back the pages dirtied at or before `dirtied_before` (ms), or all dirty pages
+    /// if it is None, then the file size. Returns whether dirty pages are left.
+    pub fn writeback(&self, dirtied_before: Option<usize>) -> Result<bool, Errno> {
+        let page_cache = match self.page_cache.lock().clone() {
+            Some(page_cache) => page_cache,
+            None => return Ok(false),
+        };
+        let file = self.file.lock().clone();
+        // The file has been unlinked, its data has nowhere to go
+        if file.is_deleted()? {
+            return Ok(false);
+        }
+        let file_size = *self.file_size.lock();
+        let dirty_left = page_cache.writeback(file_size, dirtied_before)?;
+        // The size kept in the inode is the one seen by the kernel
+        if !file.is_dir() && file.file_size()? != file_size {
+            file.modify_size(file_size)?;
+        }
+        Ok(dirty_left)
+    }
+
+    /// Evict up to `max` cached pages that have not been used recently, see
+    /// [`PageCache::reclaim`]. Returns the number of pages evicted.
+    pub fn reclaim(&self, max: usize) -> usize {
+        let page_cache = match self.page_cache.lock().clone() {
+            Some(page_cache) => page_cache,
+            None => return 0,
+        };
+        let file = self.file.lock().clone();
+        let file_size = *self.file_size.lock();
+        // Dirty pages of an unlinked file can simply be dropped
+        let writeback = !file.is_deleted().unwrap_or(false);
+        let (evicted, written) = page_cache.reclaim(file_size, max, writeback);
+        if written && !file.is_dir() && file.file_size() != Ok(file_size) {
+            if let Err(err) = file.modify_size(file_size) {
+                warn!("[FatInode::reclaim] failed to update file size: {:?}", err);
+            }
+        }
+        evicted
+    }
+}
+
+/// FAT32 has no inode numbers, so every directory entry position gets a fixed one
+/// from `ino_alloc()`. This keeps `st_ino` stable across reopens and inode cache shrinking.
+static FAT_INO: Mutex<alloc::collections::BTreeMap<(u32, usize), u64>> =
+    Mutex::new(alloc::collections::BTreeMap::new());
+
+fn fat_ino(file: &VirtFile) -> u64 {
+    *FAT_INO
+        .lock()
+        .entry(file.dirent_id())
+        .or_insert_with(ino_alloc)
 }
 
 #[cfg(feature = "inode-drop")]
-impl Drop for Inode {
+impl Drop for FatInode {
     // Actually, all the tests create files in memory, read and write files,
     // and do not need to be written back to the file system.
     // TODO 实现 ramfs 将 page cache 转移到 ramfs
     fn drop(&mut self) {
-        self.page_cache.lock().as_mut().unwrap().sync().unwrap();
+        if let Err(errno) = self.writeback(None) {
+            warn!(
+                "[FatInode::drop] failed to write back inode {}: {:?}",
+                self.fid, errno
+            );
+        }
     }
 }
 
@@ -157,7 +224,7 @@ impl KFile {
     pub fn new(
         readable: bool,
         writable: bool,
-        inode: Arc<Inode>,
+        inode: Arc<FatInode>,
         path: AbsolutePath,
         name: String,
     ) -> Self {
@@ -174,6 +241,47 @@ impl KFile {
             time_info: Mutex::new(InodeTime::empty()),
         }
     }
+    /// Open a FAT32 file as a kernel file, sharing the cached inode (and its page cache)
+    /// with other opened instances of the same path.
+    pub fn open(
+        file: Arc<VirtFile>,
+        path: AbsolutePath,
+        flags: OpenFlags,
+    ) -> Result<Arc<Self>, Errno> {
+        let (readable, writable) = flags.read_write();
+        #[cfg(not(feature = "no-page-cache"))]
+        let inode = match INODE_CACHE.get(&path) {
+            Some(inode) => inode,
+            None => {
+                let file_size = file.file_size()?;
+                // 以 O_PATH 打开的符号链接不能进入缓存, 否则之后跟随该链接的打开会命中链接本身
+                let is_symlink = file.is_symlink()?;
+                let inode = Arc::new(FatInode {
+                    fid: fat_ino(&file),
+                    file: Mutex::new(file),
+                    page_cache: Mutex::new(None),
+                    file_size: Mutex::new(file_size),
+                    dirty: AtomicBool::new(false),
+                });
+                if !is_symlink {
+                    INODE_CACHE.insert(path.clone(), inode.clone());
+                }
+                inode
+            }
+        };
+        #[cfg(feature = "no-page-cache")]
+        let inode = Arc::new(FatInode {
+            fid: fat_ino(&file),
+            file: Mutex::new(file),
+        });
+        let name = path.last();
+        let res = Arc::new(KFile::new(readable, writable, inode, path, name));
+        // create page cache
+        #[cfg(not(feature = "no-page-cache"))]
+        res.create_page_cache_if_needed();
+        res.set_flags(flags);
+        Ok(res)
+    }
     pub fn file(&self) -> MutexGuard<'_, Arc<VirtFile>> {
         self.inode.file.lock()
     }
@@ -190,7 +298,7 @@ impl KFile {
         }
     }
     #[cfg(not(feature = "no-page-cache"))]
-    pub fn write_all(&self, data: &Vec<u8>) -> usize {
+    pub fn write_all(&self, data: &Vec<u8>) -> Result<usize, Errno> {
         let mut total_write_size = 0usize;
         let page_cache = self.page_cache().as_ref().cloned().unwrap();
         let mut offset = if self.flags().contains(OpenFlags::O_APPEND) {
@@ -202,15 +310,20 @@ impl KFile {
         let slice_end = data.len();
         while slice_offset < slice_end {
             // to avoid slice's length spread page boundary
-            let page = page_cache.get_page(offset, None).expect("get page error");
             let page_offset = offset % PAGE_SIZE;
             let mut slice_offset_end = slice_offset + (PAGE_SIZE - page_offset);
             if slice_offset_end > slice_end {
                 slice_offset_end = slice_end;
             }
-            let write_size = page
-                .write(page_offset, &data[slice_offset..slice_offset_end])
-                .expect("read page error");
+            let write_size = match page_cache
+                .get_page(offset, None)
+                .and_then(|page| page.write(page_offset, &data[slice_offset..slice_offset_end]))
+            {
+                Ok(write_size) => write_size,
+                // the part already written is kept and reported as a short write
+                Err(_) if total_write_size > 0 => break,
+                Err(errno) => return Err(errno),
+            };
             offset += write_size;
             self.seek(offset);
             slice_offset += write_size;
@@ -219,17 +332,22 @@ impl KFile {
         if self.file_size() < offset {
             self.set_file_size(offset);
         }
-        total_write_size
+        mark_inode_dirty(&self.inode);
+        Ok(total_write_size)
     }
     #[cfg(feature = "no-page-cache")]
-    pub fn write_all(&self, data: &Vec<u8>) -> usize {
+    pub fn write_all(&self, data: &Vec<u8>) -> Result<usize, Errno> {
         let file = self.file();
         let mut remain = data.len();
         let mut index = 0;
         loop {
             let len = remain.min(512);
             let offset = self.offset();
-            file.write_at(offset, &data.as_slice()[index..index + len]);
+            match file.write_at(offset, &data.as_slice()[index..index + len]) {
+                Ok(_) => {}
+                Err(_) if index > 0 => break,
+                Err(err) => return Err(err.into()),
+            }
             self.seek(offset + len);
             index += len;
             remain -= len;
@@ -237,7 +355,7 @@ impl KFile {
                 break;
             }
         }
-        index
+        Ok(index)
     }
     pub fn is_dir(&self) -> bool {
         let file = self.file();
@@ -246,17 +364,28 @@ impl KFile {
     pub fn name(&self) -> String {
         self.name.clone()
     }
-    pub fn delete(&self) -> usize {
+    pub fn delete(&self) -> Result<(), Errno> {
         let file = self.file();
         #[cfg(not(feature = "no-page-cache"))]
         let path = self.path.clone();
         #[cfg(not(feature = "no-page-cache"))]
         INODE_CACHE.remove(&path);
-        file.clear()
+        match file.clear() {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                warn!("[KFile::delete] failed to free clusters: {:?}", err);
+                Err(Errno::EIO)
+            }
+        }
     }
     pub fn delete_direntry(&self) {
         let file = self.file();
-        file.clear_direntry();
+        if let Err(err) = file.clear_direntry() {
+            warn!(
+                "[KFile::delete_direntry] failed to clear direntry: {:?}",
+                err
+            );
+        }
     }
     #[cfg(not(feature = "no-page-cache"))]
     pub fn file_size(&self) -> usize {
@@ -265,7 +394,7 @@ impl KFile {
     #[cfg(feature = "no-page-cache")]
     pub fn file_size(&self) -> usize {
         let file = self.file();
-        file.file_size()
+        file.file_size().unwrap_or(0)
     }
     #[cfg(not(feature = "no-page-cache"))]
     pub fn set_file_size(&self, file_size: usize) {
@@ -274,21 +403,61 @@ impl KFile {
     #[cfg(feature = "no-page-cache")]
     pub fn set_file_size(&self, file_size: usize) {
         let file = self.file();
-        file.set_file_size(file_size);
+        if let Err(err) = file.set_file_size(file_size) {
+            warn!(
+                "[KFile::set_file_size] failed to update file size: {:?}",
+                err
+            );
+        }
     }
-    pub fn rename(&self, new_path: AbsolutePath, flags: OpenFlags) {
+    pub fn rename(&self, new_path: AbsolutePath, flags: OpenFlags) -> Result<(), Errno> {
+        if new_path == self.path {
+            return Ok(());
+        }
+        // the new direntry takes the clusters and size on disk, so flush the page cache first
+        #[cfg(not(feature = "no-page-cache"))]
+        if let Err(errno) = self.inode.writeback(None) {
+            warn!(
+                "[KFile::rename] failed to write back {:?}: {:?}",
+                self.path, errno
+            );
+            return Err(errno);
+        }
         // duplicate a new file, and set file cluster and file size
+        drop(open(new_path.clone(), flags, CreateMode::empty())?);
+        let new_inner = lookup(&new_path)?
+            .as_any()
+            .downcast::<VirtFile>()
+            .map_err(|_| Errno::EXDEV)?;
         let inner = self.file();
-        // check file exits
-        let new_file = open(new_path, flags, CreateMode::empty()).unwrap();
-        let new_inner = new_file.file();
-        let first_cluster = inner.first_cluster();
-        let file_size = inner.file_size();
-        new_inner.set_first_cluster(first_cluster);
-        new_inner.set_file_size(file_size);
+        // a replaced target is dropped from the inode cache, and its clusters are freed
+        // before the new direntry takes over ours
+        #[cfg(not(feature = "no-page-cache"))]
+        INODE_CACHE.remove(&new_path);
+        let moved = inner.first_cluster().and_then(|first_cluster| {
+            new_inner.clear_content()?;
+            new_inner.set_first_cluster(first_cluster)?;
+            new_inner.set_file_size(inner.file_size()?)?;
+            if inner.is_symlink()? {
+                new_inner.set_symlink()?;
+            }
+            Ok(())
+        });
         drop(inner);
-        // clear old direntry
-        self.delete_direntry();
+        match moved {
+            // clear old direntry, later opens of the old path must not find our inode
+            Ok(()) => {
+                #[cfg(not(feature = "no-page-cache"))]
+                INODE_CACHE.remove(&self.path);
+                self.delete_direntry();
+                Ok(())
+            }
+            // keep the old direntry, it still owns the clusters
+            Err(err) => {
+                warn!("[KFile::rename] failed to move {:?}: {:?}", self.path, err);
+                Err(Errno::EIO)
+            }
+        }
     }
     pub fn fid(&self) -> u64 {
         self.inode.fid
@@ -298,7 +467,7 @@ impl KFile {
 impl File for KFile {
     //  No change file offset
     #[cfg(not(feature = "no-page-cache"))]
-    fn kernel_read_with_offset(&self, offset: usize, len: usize) -> Vec<u8> {
+    fn kernel_read_with_offset(&self, offset: usize, len: usize) -> Result<Vec<u8>, Errno> {
         let page_cache = self.page_cache().as_ref().cloned().unwrap();
         let mut offset = offset;
         let mut buf: Vec<u8> = vec![0; len];
@@ -306,24 +475,22 @@ impl File for KFile {
         let buf_end = len;
 
         while buf_offset < buf_end {
-            let page = page_cache.get_page(offset, None).expect("get page error");
+            let page = page_cache.get_page(offset, None)?;
             let page_offset = offset % PAGE_SIZE;
             let mut buf_offset_end = buf_offset + (PAGE_SIZE - page_offset);
             if buf_offset_end > buf_end {
                 buf_offset_end = buf_end;
             }
             let slice = buf.as_mut_slice();
-            let read_size = page
-                .read(page_offset, &mut slice[buf_offset..buf_offset_end])
-                .expect("read page error");
+            let read_size = page.read(page_offset, &mut slice[buf_offset..buf_offset_end])?;
             offset += read_size;
             buf_offset += read_size;
         }
 
-        buf
+        Ok(buf)
     }
     #[cfg(feature = "no-page-cache")]
-    fn kernel_read_with_offset(&self, offset: usize, len: usize) -> Vec<u8> {
+    fn kernel_read_with_offset(&self, offset: usize, len: usize) -> Result<Vec<u8>, Errno> {
         let file = self.file();
         let mut len = len;
         let mut offset = offset;
@@ -334,7 +501,7 @@ impl File for KFile {
             ret.reserve(96 * 4096);
         }
         loop {
-            let read_size = file.read_at(offset, &mut buffer);
+            let read_size = file.read_at(offset, &mut buffer)?;
             if read_size == 0 {
                 break;
             }
@@ -347,19 +514,19 @@ impl File for KFile {
                 break;
             }
         }
-        ret
+        Ok(ret)
     }
     // change file offset
-    fn read_to_kspace(&self) -> Vec<u8> {
+    fn read_to_kspace(&self) -> Result<Vec<u8>, Errno> {
         let file_size = self.file_size();
         let offset = self.offset();
         let len = file_size - offset;
-        let res = self.kernel_read_with_offset(offset, len);
+        let res = self.kernel_read_with_offset(offset, len)?;
         self.seek(offset + res.len());
-        res
+        Ok(res)
     }
     #[cfg(not(feature = "no-page-cache"))]
-    fn read_to_ubuf(&self, mut buf: UserBuffer) -> usize {
+    fn read_to_ubuf(&self, mut buf: UserBuffer) -> Result<usize, Errno> {
         // with page cache
         #[cfg(feature = "time_trace")]
         time_trace!("read");
@@ -367,13 +534,10 @@ impl File for KFile {
         let file_size = self.file_size();
         let mut total_read_size = 0usize;
         if file_size == 0 {
-            if self.name == "zero" {
-                buf.write_zeros();
-            }
-            return 0;
+            return Ok(0);
         }
         if offset >= file_size {
-            return 0;
+            return Ok(0);
         }
         let page_cache = self.page_cache().as_ref().cloned().unwrap();
         for slice in buf.buffers.iter_mut() {
@@ -382,24 +546,23 @@ impl File for KFile {
             while slice_offset < slice_end {
                 // to avoid slice's length spread page boundary
                 let offset = self.offset();
-                let page = page_cache.get_page(offset, None).expect("get page error");
+                let page = page_cache.get_page(offset, None)?;
                 let page_offset = offset % PAGE_SIZE;
                 let mut slice_offset_end = slice_offset + (PAGE_SIZE - page_offset);
                 if slice_offset_end > slice_end {
                     slice_offset_end = slice_end;
                 }
-                let read_size = page
-                    .read(page_offset, &mut slice[slice_offset..slice_offset_end])
-                    .expect("read page error");
+                let read_size =
+                    page.read(page_offset, &mut slice[slice_offset..slice_offset_end])?;
                 self.seek(offset + read_size);
                 slice_offset += read_size;
                 total_read_size += read_size;
             }
         }
-        total_read_size
+        Ok(total_read_size)
     }
     #[cfg(feature = "no-page-cache")]
-    fn read_to_ubuf(&self, mut buf: UserBuffer) -> usize {
+    fn read_to_ubuf(&self, mut buf: UserBuffer) -> Result<usize, Errno> {
         #[cfg(feature = "time_trace")]
         time_trace!("read");
         let offset = self.offset();
@@ -408,41 +571,35 @@ impl File for KFile {
         let mut total_read_size = 0usize;
 
         if file_size == 0 {
-            if self.name == "zero" {
-                buf.write_zeros();
-            }
-            return 0;
+            return Ok(0);
         }
         if offset >= file_size {
-            return 0;
+            return Ok(0);
         }
 
         for slice in buf.buffers.iter_mut() {
-            let read_size = file.read_at(offset, *slice);
+            let read_size = file.read_at(offset, *slice)?;
             if read_size == 0 {
                 break;
             }
             self.seek(offset + read_size);
             total_read_size += read_size;
         }
-        total_read_size
+        Ok(total_read_size)
     }
     // The same as read_to_ubuf, but will not change offset
     #[cfg(not(feature = "no-page-cache"))]
-    fn pread(&self, mut buf: UserBuffer, offset: usize) -> usize {
+    fn pread(&self, mut buf: UserBuffer, offset: usize) -> Result<usize, Errno> {
         #[cfg(feature = "time-tracer")]
         time_trace!("read");
         let mut offset = offset;
         let file_size = self.file_size();
         let mut total_read_size = 0usize;
         if file_size == 0 {
-            if self.name == "zero" {
-                buf.write_zeros();
-            }
-            return 0;
+            return Ok(0);
         }
         if offset >= file_size {
-            return 0;
+            return Ok(0);
         }
         let page_cache = self.page_cache().as_ref().cloned().unwrap();
         for slice in buf.buffers.iter_mut() {
@@ -450,24 +607,23 @@ impl File for KFile {
             let mut slice_offset = 0;
             while slice_offset < slice_end {
                 // to avoid slice's length spread page boundary
-                let page = page_cache.get_page(offset, None).expect("get page error");
+                let page = page_cache.get_page(offset, None)?;
                 let page_offset = offset % PAGE_SIZE;
                 let mut slice_offset_end = slice_offset + (PAGE_SIZE - page_offset);
                 if slice_offset_end > slice_end {
                     slice_offset_end = slice_end;
                 }
-                let read_size = page
-                    .read(page_offset, &mut slice[slice_offset..slice_offset_end])
-                    .expect("read page error");
+                let read_size =
+                    page.read(page_offset, &mut slice[slice_offset..slice_offset_end])?;
                 offset += read_size;
                 slice_offset += read_size;
                 total_read_size += read_size;
             }
         }
-        total_read_size
+        Ok(total_read_size)
     }
     #[cfg(feature = "no-page-cache")]
-    fn pread(&self, mut buf: UserBuffer, mut offset: usize) -> usize {
+    fn pread(&self, mut buf: UserBuffer, mut offset: usize) -> Result<usize, Errno> {
         #[cfg(feature = "time_trace")]
         time_trace!("read");
         let file_size = self.file_size();
@@ -475,27 +631,24 @@ impl File for KFile {
         let mut total_read_size = 0usize;
 
         if file_size == 0 {
-            if self.name == "zero" {
-                buf.write_zeros();
-            }
-            return 0;
+            return Ok(0);
         }
         if offset >= file_size {
-            return 0;
+            return Ok(0);
         }
 
         for slice in buf.buffers.iter_mut() {
-            let read_size = file.read_at(offset, *slice);
+            let read_size = file.read_at(offset, *slice)?;
             if read_size == 0 {
                 break;
             }
             offset += read_size;
             total_read_size += read_size;
         }
-        total_read_size
+        Ok(total_read_size)
     }
     #[cfg(not(feature = "no-page-cache"))]
-    fn write_from_kspace(&self, data: &Vec<u8>) -> usize {
+    fn write_from_kspace(&self, data: &Vec<u8>) -> Result<usize, Errno> {
         #[cfg(feature = "time-tracer")]
         time_trace!("write");
         let mut total_write_size = 0usize;
@@ -509,15 +662,20 @@ impl File for KFile {
         let slice_end = data.len();
         while slice_offset < slice_end {
             // to avoid slice's length spread page boundary
-            let page = page_cache.get_page(offset, None).expect("get page error");
             let page_offset = offset % PAGE_SIZE;
             let mut slice_offset_end = slice_offset + (PAGE_SIZE - page_offset);
             if slice_offset_end > slice_end {
                 slice_offset_end = slice_end;
             }
-            let write_size = page
-                .write(page_offset, &data[slice_offset..slice_offset_end])
-                .expect("read page error");
+            let write_size = match page_cache
+                .get_page(offset, None)
+                .and_then(|page| page.write(page_offset, &data[slice_offset..slice_offset_end]))
+            {
+                Ok(write_size) => write_size,
+                // the part already written is kept and reported as a short write
+                Err(_) if total_write_size > 0 => break,
+                Err(errno) => return Err(errno),
+            };
             offset += write_size;
             self.seek(offset);
             slice_offset += write_size;
@@ -526,10 +684,11 @@ impl File for KFile {
         if self.file_size() < offset {
             self.set_file_size(offset);
         }
-        total_write_size
+        mark_inode_dirty(&self.inode);
+        Ok(total_write_size)
     }
     #[cfg(feature = "no-page-cache")]
-    fn write_from_kspace(&self, data: &Vec<u8>) -> usize {
+    fn write_from_kspace(&self, data: &Vec<u8>) -> Result<usize, Errno> {
         #[cfg(feature = "time-tracer")]
         time_trace!("write");
         let file = self.file();
@@ -538,7 +697,11 @@ impl File for KFile {
         loop {
             let len = remain.min(512);
             let offset = self.offset();
-            file.write_at(offset, &data.as_slice()[base..base + len]);
+            match file.write_at(offset, &data.as_slice()[base..base + len]) {
+                Ok(_) => {}
+                Err(_) if base > 0 => break,
+                Err(err) => return Err(err.into()),
+            }
             self.seek(offset + len);
             base += len;
             remain -= len;
@@ -546,10 +709,10 @@ impl File for KFile {
                 break;
             }
         }
-        base
+        Ok(base)
     }
     #[cfg(not(feature = "no-page-cache"))]
-    fn write_from_ubuf(&self, buf: UserBuffer) -> usize {
+    fn write_from_ubuf(&self, buf: UserBuffer) -> Result<usize, Errno> {
         #[cfg(feature = "time-tracer")]
         time_trace!("write");
         let mut total_write_size = 0usize;
@@ -559,20 +722,24 @@ impl File for KFile {
         } else {
             self.offset()
         };
-        for slice in buf.buffers.iter() {
+        'write: for slice in buf.buffers.iter() {
             let slice_end = slice.len();
             let mut slice_offset = 0;
             while slice_offset < slice_end {
                 // to avoid slice's length spread page boundary
-                let page = page_cache.get_page(offset, None).expect("get page error");
                 let page_offset = offset % PAGE_SIZE;
                 let mut slice_offset_end = slice_offset + (PAGE_SIZE - page_offset);
                 if slice_offset_end > slice_end {
                     slice_offset_end = slice_end;
                 }
-                let write_size = page
-                    .write(page_offset, &slice[slice_offset..slice_offset_end])
-                    .expect("read page error");
+                let write_size = match page_cache.get_page(offset, None).and_then(|page| {
+                    page.write(page_offset, &slice[slice_offset..slice_offset_end])
+                }) {
+                    Ok(write_size) => write_size,
+                    // the part already written is kept and reported as a short write
+                    Err(_) if total_write_size > 0 => break 'write,
+                    Err(errno) => return Err(errno),
+                };
                 offset += write_size;
                 self.seek(offset);
                 slice_offset += write_size;
@@ -582,10 +749,11 @@ impl File for KFile {
         if self.file_size() < offset {
             self.set_file_size(offset);
         }
-        total_write_size
+        mark_inode_dirty(&self.inode);
+        Ok(total_write_size)
     }
     #[cfg(feature = "no-page-cache")]
-    fn write_from_ubuf(&self, buf: UserBuffer) -> usize {
+    fn write_from_ubuf(&self, buf: UserBuffer) -> Result<usize, Errno> {
         #[cfg(feature = "time-tracer")]
         time_trace!("write");
         let mut total_write_size = 0usize;
@@ -597,17 +765,17 @@ impl File for KFile {
             self.offset()
         };
         for slice in buf.buffers.iter() {
-            let write_size = file.write_at(offset, *slice);
+            let write_size = file.write_at(offset, *slice)?;
             assert_eq!(write_size, slice.len());
             offset += write_size;
             self.seek(offset);
             total_write_size += write_size;
         }
-        total_write_size
+        Ok(total_write_size)
     }
     #[cfg(not(feature = "no-page-cache"))]
     // The same as write_from_ubuf, but will not change offset
-    fn pwrite(&self, buf: UserBuffer, offset: usize) -> usize {
+    fn pwrite(&self, buf: UserBuffer, offset: usize) -> Result<usize, Errno> {
         #[cfg(feature = "time-tracer")]
         time_trace!("write");
         let mut total_write_size = 0usize;
@@ -617,20 +785,24 @@ impl File for KFile {
         } else {
             offset
         };
-        for slice in buf.buffers.iter() {
+        'write: for slice in buf.buffers.iter() {
             let slice_end = slice.len();
             let mut slice_offset = 0;
             while slice_offset < slice_end {
                 // to avoid slice's length spread page boundary (howerver, it's low probability)
-                let page = page_cache.get_page(offset, None).expect("get page error");
                 let page_offset = offset % PAGE_SIZE;
                 let mut slice_offset_end = slice_offset + (PAGE_SIZE - page_offset);
                 if slice_offset_end > slice_end {
                     slice_offset_end = slice_end;
                 }
-                let write_size = page
-                    .write(page_offset, &slice[slice_offset..slice_offset_end])
-                    .expect("read page error");
+                let write_size = match page_cache.get_page(offset, None).and_then(|page| {
+                    page.write(page_offset, &slice[slice_offset..slice_offset_end])
+                }) {
+                    Ok(write_size) => write_size,
+                    // the part already written is kept and reported as a short write
+                    Err(_) if total_write_size > 0 => break 'write,
+                    Err(errno) => return Err(errno),
+                };
                 offset += write_size;
                 slice_offset += write_size;
                 total_write_size += write_size;
@@ -639,10 +811,11 @@ impl File for KFile {
         if self.file_size() < offset {
             self.set_file_size(offset);
         }
-        total_write_size
+        mark_inode_dirty(&self.inode);
+        Ok(total_write_size)
     }
     #[cfg(feature = "no-page-cache")]
-    fn pwrite(&self, buf: UserBuffer, offset: usize) -> usize {
+    fn pwrite(&self, buf: UserBuffer, offset: usize) -> Result<usize, Errno> {
         #[cfg(feature = "time-tracer")]
         time_trace!("write");
         let mut total_write_size = 0usize;
@@ -654,12 +827,12 @@ impl File for KFile {
             offset
         };
         for slice in buf.buffers.iter() {
-            let write_size = file.write_at(offset, *slice);
+            let write_size = file.write_at(offset, *slice)?;
             assert_eq!(write_size, slice.len());
             offset += write_size;
             total_write_size += write_size;
         }
-        total_write_size
+        Ok(total_write_size)
     }
     // TODO
     fn set_time(&self, time_info: InodeTime) {
@@ -679,7 +852,7 @@ impl File for KFile {
         }
         let inner = self.file();
         let offset = self.offset();
-        if let Some((name, offset, first_cluster, _attr)) = inner.dir_info(offset) {
+        if let Ok(Some((name, offset, first_cluster, _attr))) = inner.dir_info(offset) {
             dirent.init(name.as_str(), offset as isize, first_cluster as usize);
             self.seek(offset as usize);
             // return size of Dirent as read size
@@ -688,31 +861,25 @@ impl File for KFile {
             -1
         }
     }
-    fn fstat(&self, kstat: &mut Kstat) {
-        let name = self.name();
+    fn fstat(&self, kstat: &mut Kstat) -> Result<(), Errno> {
         let inner = self.file();
         let vfile = inner.clone();
         let mut st_mode = 0;
         _ = st_mode;
         #[cfg(not(feature = "no-page-cache"))]
-        let (_, st_blksize, st_blocks, is_dir, _time) = vfile.stat();
+        let (_, st_blksize, st_blocks, is_dir, _time) = vfile.stat()?;
         #[cfg(not(feature = "no-page-cache"))]
         let st_size = self.file_size();
         #[cfg(feature = "no-page-cache")]
-        let (st_size, st_blksize, st_blocks, is_dir, _time) = vfile.stat();
+        let (st_size, st_blksize, st_blocks, is_dir, _time) = vfile.stat()?;
 
         if is_dir {
             st_mode = S_IFDIR;
+        } else if vfile.is_symlink()? {
+            st_mode = S_IFLNK;
         } else {
             st_mode = S_IFREG;
         }
-        // if &vfile.name() == "null"
-        // || &vfile.name() == "NULL"
-        // || &vfile.name() == "zero"
-        // || &vfile.name() == "ZERO"
-        if &name == "null" || &name == "NULL" || &name == "zero" || &name == "ZERO" {
-            st_mode = S_IFCHR;
-        }
         let time_info = self.time_info.lock();
         let atime = time_info.access_time;
         let mtime = time_info.modify_time;
@@ -728,6 +895,7 @@ impl File for KFile {
             mtime as i64,
             ctime as i64,
         );
+        Ok(())
     }
     fn name(&self) -> String {
         self.name()
@@ -750,6 +918,9 @@ impl File for KFile {
     fn path(&self) -> AbsolutePath {
         self.path.clone()
     }
+    fn is_under(&self, dir: &AbsolutePath) -> bool {
+        self.path().start_with(dir)
+    }
     fn readable(&self) -> bool {
         self.readable
     }
@@ -762,9 +933,58 @@ impl File for KFile {
     fn file_size(&self) -> usize {
         self.file_size()
     }
-    fn truncate(&self, new_length: usize) {
+    fn truncate(&self, new_length: usize) -> Result<(), Errno> {
         let inner = self.file();
-        inner.modify_size(new_length);
+        if let Err(err) = inner.modify_size(new_length) {
+            warn!(
+                "[KFile::truncate] failed to resize {:?}: {:?}",
+                self.path, err
+            );
+            return Err(err.into());
+        }
+        #[cfg(not(feature = "no-page-cache"))]
+        self.set_file_size(new_length);
+        Ok(())
+    }
+    #[cfg(not(feature = "no-page-cache"))]
+    fn fsync(&self) -> Result<(), Errno> {
+        self.inode.writeback(None)?;
+        self.file().sync()?;
+        Ok(())
+    }
+    #[cfg(feature = "no-page-cache")]
+    fn fsync(&self) -> Result<(), Errno> {
+        self.file().sync()?;
+        Ok(())
+    }
+    /// `MAP_SHARED` mappings map the frame of the page cache, so that writes through a mapping
+    /// are seen by read() and by the other mappings at once. Mapped frames are never evicted,
+    /// see [`PageCache::reclaim`].
+    #[cfg(not(feature = "no-page-cache"))]
+    fn shared_frame(&self, offset: usize) -> Option<FrameTracker> {
+        if offset >= self.file_size() {
+            return None;
+        }
+        let page_cache = self.page_cache().as_ref().cloned().unwrap();
+        let page = page_cache.get_page(offset, None).ok()?;
+        page.load_all_buffers().ok()?;
+        Some(page.data_frame.clone())
+    }
+    #[cfg(not(feature = "no-page-cache"))]
+    fn dirty_shared_frame(&self, offset: usize) {
+        let page_cache = self.page_cache().as_ref().cloned().unwrap();
+        let page = match page_cache.get_page(offset, None) {
+            Ok(page) => page,
+            Err(_) => return,
+        };
+        if let Err(err) = page.mark_dirty() {
+            warn!(
+                "[KFile::dirty_shared_frame] {:?} at {:#x}: {:?}",
+                self.path, offset, err
+            );
+            return;
+        }
+        mark_inode_dirty(&self.inode);
     }
     fn fid(&self) -> u64 {
         self.fid()
@@ -776,7 +996,8 @@ impl File for KFile {
     fn read_at_direct(&self, offset: usize, len: usize) -> Vec<u8> {
         let mut buf: Vec<u8> = vec![0; len];
         let inner = self.file();
-        inner.read_at(offset, &mut buf);
+        let read_size = inner.read_at(offset, &mut buf).unwrap_or(0);
+        buf.truncate(read_size);
         buf
     }
     // Currently not used in the kernel. Design problem, it can be used to design general
@@ -788,13 +1009,13 @@ impl File for KFile {
         if offset + data.len() > self.file_size() {
             self.set_file_size(offset + data.len());
         }
-        inner.write_at(offset, data)
+        inner.write_at(offset, data).unwrap_or(0)
     }
-    fn delete(&self) -> usize {
+    fn delete(&self) -> Result<(), Errno> {
         self.delete()
     }
-    fn rename(&self, new_path: AbsolutePath, flags: OpenFlags) {
-        self.rename(new_path, flags);
+    fn rename(&self, new_path: AbsolutePath, flags: OpenFlags) -> Result<(), Errno> {
+        self.rename(new_path, flags)
     }
     fn is_dir(&self) -> bool {
         self.is_dir()
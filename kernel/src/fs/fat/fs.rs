@@ -0,0 +1,144 @@
+//! VFS glue for FAT32: [`FatFs`] is the super block, and [`VirtFile`] serves as both
+//! the dentry and the inode of a FAT32 file.
+
+use super::KFile;
+#[cfg(not(feature = "no-page-cache"))]
+use super::INODE_CACHE;
+use crate::fs::{Dentry, File, Inode, InodeType, SuperBlock};
+use crate::syscall::impls::Errno;
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+};
+use core::any::Any;
+use fat32::{root, sync_all, BlockDevice, Dir, DirError, FileSystem, VirtFile, VirtFileType};
+use nix::OpenFlags;
+use path::AbsolutePath;
+use spin::RwLock;
+
+pub struct FatFs {
+    fs: Arc<RwLock<FileSystem>>,
+    root: Arc<VirtFile>,
+}
+
+impl FatFs {
+    pub fn open(device: Arc<dyn BlockDevice>) -> Self {
+        let fs = FileSystem::open(device).expect("failed to read the FAT32 boot sector");
+        let root = Arc::new(root(fs.clone()).expect("failed to read the FAT32 root directory"));
+        Self { fs, root }
+    }
+    pub fn root_file(&self) -> Arc<VirtFile> {
+        self.root.clone()
+    }
+}
+
+impl SuperBlock for FatFs {
+    fn fs_type(&self) -> &str {
+        "vfat"
+    }
+    fn root(&self) -> Arc<dyn Dentry> {
+        self.root.clone()
+    }
+    fn sync(&self) {
+        #[cfg(not(feature = "no-page-cache"))]
+        super::writeback_all();
+        if let Err(err) = sync_all() {
+            warn!("[FatFs::sync] failed to write back blocks: {:?}", err);
+        }
+    }
+    fn is_read_only(&self) -> bool {
+        self.fs.read().is_read_only()
+    }
+    #[cfg(not(feature = "no-page-cache"))]
+    fn open_cached(&self, path: &AbsolutePath, flags: OpenFlags) -> Option<Arc<dyn File>> {
+        let inode = INODE_CACHE.get(path)?;
+        let (readable, writable) = flags.read_write();
+        let res = Arc::new(KFile::new(
+            readable,
+            writable,
+            inode,
+            path.clone(),
+            path.last(),
+        ));
+        res.create_page_cache_if_needed();
+        res.set_flags(flags);
+        Some(res)
+    }
+}
+
+impl Dentry for VirtFile {
+    fn name(&self) -> String {
+        VirtFile::name(self).to_string()
+    }
+    fn is_dir(&self) -> bool {
+        VirtFile::is_dir(self)
+    }
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Dentry>> {
+        match name {
+            "" | "." => Some(Arc::new(self.clone())),
+            _ => self
+                .find_by_name(name)
+                .ok()
+                .flatten()
+                .map(|file| Arc::new(file) as Arc<dyn Dentry>),
+        }
+    }
+    fn create(&self, name: &str, itype: InodeType) -> Result<Arc<dyn Dentry>, Errno> {
+        let file_type = match itype {
+            InodeType::File => VirtFileType::File,
+            InodeType::Dir => VirtFileType::Dir,
+        };
+        match Dir::create(self, name, file_type) {
+            Ok(file) => Ok(Arc::new(file)),
+            Err(DirError::FileHasExist) | Err(DirError::DirHasExist) => Err(Errno::EEXIST),
+            Err(DirError::NotDir) => Err(Errno::ENOTDIR),
+            Err(DirError::Device(err)) => Err(err.into()),
+            Err(_) => Err(Errno::DISCARD),
+        }
+    }
+    fn is_symlink(&self) -> bool {
+        VirtFile::is_symlink(self).unwrap_or(false)
+    }
+    fn readlink(&self) -> Result<String, Errno> {
+        if !VirtFile::is_symlink(self)? {
+            return Err(Errno::EINVAL);
+        }
+        // 链接创建后内容不再改变, 直接从磁盘读取, 不经过页缓存
+        let mut target = vec![0u8; self.file_size()?];
+        self.read_at(0, &mut target)?;
+        String::from_utf8(target).map_err(|_| Errno::EINVAL)
+    }
+    fn symlink(&self, name: &str, target: &str) -> Result<Arc<dyn Dentry>, Errno> {
+        if !VirtFile::is_dir(self) {
+            return Err(Errno::ENOTDIR);
+        }
+        if self.find_by_name(name)?.is_some() {
+            return Err(Errno::EEXIST);
+        }
+        let link = Dentry::create(self, name, InodeType::File)?;
+        let file = link.as_any().downcast::<VirtFile>().unwrap();
+        file.write_at(0, target.as_bytes())?;
+        file.set_symlink()?;
+        Ok(file)
+    }
+    fn inode(self: Arc<Self>) -> Arc<dyn Inode> {
+        self
+    }
+    fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+impl Inode for VirtFile {
+    fn open(
+        self: Arc<Self>,
+        path: AbsolutePath,
+        flags: OpenFlags,
+    ) -> Result<Arc<dyn File>, Errno> {
+        // clear file if O_TRUNC
+        if flags.contains(OpenFlags::O_TRUNC) && !VirtFile::is_dir(&self) {
+            self.clear_content()?;
+        }
+        KFile::open(self, path, flags)
+    }
+}
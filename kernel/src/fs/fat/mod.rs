@@ -1,7 +1,17 @@
 mod file;
+mod fs;
 mod page;
 mod page_cache;
+#[cfg(not(feature = "no-page-cache"))]
+mod reclaim;
+#[cfg(not(feature = "no-page-cache"))]
+mod writeback;
 
 pub use file::*;
+pub use fs::*;
 pub use page::*;
 pub use page_cache::*;
+#[cfg(not(feature = "no-page-cache"))]
+pub use reclaim::*;
+#[cfg(not(feature = "no-page-cache"))]
+pub use writeback::*;
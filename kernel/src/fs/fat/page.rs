@@ -1,4 +1,5 @@
 use alloc::sync::{Arc, Weak};
+use core::sync::atomic::AtomicBool;
 
 use fat32::{VirtFile, BLOCK_SIZE};
 // use crate::fat32::{VirtFile, BLOCK_SIZE};
@@ -9,15 +10,18 @@ use crate::{
     consts::PAGE_SIZE,
     mm::{alloc_frame, FrameTracker, MapPermission},
     syscall::impls::Errno,
+    timer::get_time_ms,
 };
 
-// The difference compared to MmapPage is that it does not require a virtual address.
+// The difference compared to a page of a VmArea is that it does not require a virtual address.
 pub struct FilePage {
     /// Immutable page permission
     pub permission: MapPermission,
     /// Physical data frame
     pub data_frame: FrameTracker,
     pub file_info: Option<Mutex<FilePageInfo>>,
+    /// Set when the page is looked up, cleared by the reclaimer (CLOCK)
+    pub referenced: AtomicBool,
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -33,22 +37,27 @@ pub struct FilePageInfo {
     pub data_states: [DataState; PAGE_SIZE / BLOCK_SIZE],
     /// Inode that this page related to
     inode: Weak<VirtFile>,
+    /// Time (ms) when the page first became dirty since its last writeback, None if clean
+    dirtied_at: Option<usize>,
 }
 #[allow(unused)]
 impl FilePage {
-    pub fn new(perm: MapPermission, offset: usize, inode: Arc<VirtFile>) -> Self {
-        let data_frame = alloc_frame().expect("failed to alloc frame");
+    /// Returns ENOMEM if no frame is left even after reclaiming
+    pub fn new(perm: MapPermission, offset: usize, inode: Arc<VirtFile>) -> Result<Self, Errno> {
+        let data_frame = alloc_frame().ok_or(Errno::ENOMEM)?;
         assert!(offset % PAGE_SIZE == 0);
         let file_info = FilePageInfo {
             file_offset: offset,
             data_states: [DataState::Unload; PAGE_SIZE / BLOCK_SIZE],
             inode: Arc::downgrade(&inode),
+            dirtied_at: None,
         };
-        Self {
+        Ok(Self {
             permission: perm,
             data_frame,
             file_info: Some(Mutex::new(file_info)),
-        }
+            referenced: AtomicBool::new(true),
+        })
     }
     pub fn as_mut<T>(&self) -> &'static mut T {
         self.data_frame.ppn.as_mut()
@@ -94,10 +103,26 @@ impl FilePage {
         }
     }
 
-    /// Sync all buffers if needed
-    pub fn sync(&self) -> Result<(), Errno> {
+    /// Mark the whole page dirty, used when it is written through a `MAP_SHARED` mapping
+    pub fn mark_dirty(&self) -> Result<(), Errno> {
+        self.mark_buffer_dirty_if_needed(0, PAGE_SIZE)
+    }
+
+    /// Time (ms) when the page became dirty, None if the page is clean
+    pub fn dirtied_at(&self) -> Option<usize> {
+        self.file_info.as_ref().unwrap().lock().dirtied_at
+    }
+
+    /// Write the dirty buffers back to the file and mark them clean.
+    /// `file_size` is the size seen by the kernel; buffers beyond it are dropped
+    /// instead of growing the file on disk. If the device fails, the buffers not yet
+    /// written stay dirty.
+    pub fn sync(&self, file_size: usize) -> Result<(), Errno> {
         // let file_info = self.file_info.as_ref().unwrap().lock();
         let mut file_info = self.file_info.as_ref().unwrap().lock();
+        if file_info.dirtied_at.is_none() {
+            return Ok(());
+        }
         let inode = file_info.inode.upgrade().ok_or(Errno::EBADF)?;
         // let file_size = inode.file_size();
         // log::trace!("[Page::sync] sync page, file offset {:#x}",file_info.file_offset);
@@ -115,21 +140,22 @@ impl FilePage {
                         "[Page::sync] sync block of the page, file offset {:#x}",
                         file_offset
                     );
-                    // // In case of truncate
-                    // if file_size <= file_offset {
-                    //     // info!("[Page::sync] file has been truncated, now len {:#x}, page's file offset {:#x}", file_size, file_offset);
-                    //     return Ok(());
-                    // }
-                    let data = &self.data_frame.ppn.as_bytes_array()
-                        [page_offset..page_offset + BLOCK_SIZE]
-                        .to_vec();
-
-                    // inode.write_from_direct(file_offset, data);
-                    inode.write_at(file_offset, &data);
+                    // In case of truncate
+                    if file_offset < file_size {
+                        let len = BLOCK_SIZE.min(file_size - file_offset);
+                        let data = &self.data_frame.ppn.as_bytes_array()
+                            [page_offset..page_offset + len]
+                            .to_vec();
+
+                        // inode.write_from_direct(file_offset, data);
+                        inode.write_at(file_offset, &data)?;
+                    }
+                    file_info.data_states[idx] = DataState::Load;
                 }
                 _ => {}
             }
         }
+        file_info.dirtied_at = None;
         Ok(())
     }
 
@@ -141,8 +167,7 @@ impl FilePage {
             self.as_bytes_array_ptr() as usize
         );
         let len = PAGE_SIZE;
-        self.load_buffer_if_needed(0, len);
-        Ok(())
+        self.load_buffer_if_needed(0, len)
     }
 
     /// Get the raw pointer of this page
@@ -183,7 +208,7 @@ impl FilePage {
                     .inode
                     .upgrade()
                     .unwrap()
-                    .read_at(file_offset, &mut src);
+                    .read_at(file_offset, &mut src)?;
                 dst.copy_from_slice(&src);
                 file_info.data_states[idx] = DataState::Load;
             }
@@ -215,7 +240,7 @@ impl FilePage {
                     .inode
                     .upgrade()
                     .unwrap()
-                    .read_at(file_offset, &mut src);
+                    .read_at(file_offset, &mut src)?;
                 dst.copy_from_slice(src.as_slice());
                 // trace!("outdated block, idx {}, start_page_off {:#x}",idx,start_off);
                 trace!(
@@ -229,6 +254,7 @@ impl FilePage {
                 file_info.data_states[idx] = DataState::Dirty;
             }
         }
+        file_info.dirtied_at.get_or_insert_with(get_time_ms);
         Ok(())
     }
 }
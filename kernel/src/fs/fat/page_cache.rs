@@ -6,13 +6,18 @@ use alloc::{
     sync::{Arc, Weak},
     vec::Vec,
 };
+use core::sync::atomic::Ordering;
 
 use fat32::VirtFile;
 // use crate::fat32::VirtFile;
 
 use spin::RwLock;
 
-use crate::{consts::PAGE_SIZE, mm::MapPermission, syscall::impls::Errno};
+use crate::{
+    consts::PAGE_SIZE,
+    mm::{enquire_refcount, MapPermission},
+    syscall::impls::Errno,
+};
 
 use super::FilePage;
 
@@ -31,7 +36,9 @@ impl PageCache {
         }
     }
     fn lookup(&self, offset: usize) -> Option<Arc<FilePage>> {
-        self.pages.read().get(&(offset / PAGE_SIZE)).cloned()
+        let page = self.pages.read().get(&(offset / PAGE_SIZE)).cloned()?;
+        page.referenced.store(true, Ordering::Relaxed);
+        Some(page)
     }
     pub fn insert(&self, offset: usize, page: FilePage) {
         debug_assert!(self
@@ -56,21 +63,70 @@ impl PageCache {
                 map_perm.unwrap_or(MapPermission::R | MapPermission::W),
                 page_start_offset,
                 self.inode.as_ref().unwrap().upgrade().unwrap(),
-            ));
+            )?);
             self.pages.write().insert(offset / PAGE_SIZE, page.clone());
             Ok(page)
         }
     }
-    /// Flush all pages to disk if needed
-    pub fn sync(&self) -> Result<(), Errno> {
+    /// Flush all dirty pages to disk
+    pub fn sync(&self, file_size: usize) -> Result<(), Errno> {
+        self.writeback(file_size, None).map(|_| ())
+    }
+
+    /// Flush the dirty pages that became dirty at or before `dirtied_before` (ms),
+    /// or all dirty pages if it is None. Returns whether any dirty page is left.
+    pub fn writeback(
+        &self,
+        file_size: usize,
+        dirtied_before: Option<usize>,
+    ) -> Result<bool, Errno> {
         let mut page_set: Vec<Arc<FilePage>> = Vec::new();
+        let mut dirty_left = false;
         for (_, page) in self.pages.read().iter() {
-            page_set.push(page.clone());
+            match (page.dirtied_at(), dirtied_before) {
+                (None, _) => {}
+                (Some(dirtied_at), Some(before)) if dirtied_at > before => dirty_left = true,
+                _ => page_set.push(page.clone()),
+            }
         }
         for page in page_set {
-            page.sync()?;
+            page.sync(file_size)?;
+        }
+        Ok(dirty_left)
+    }
+
+    /// Evict up to `max` pages that have not been looked up since the last scan, and clear
+    /// the referenced bit of the others (CLOCK). Pages still held by someone, or whose frame
+    /// is also mapped elsewhere, are skipped. Dirty pages are written back first, or dropped
+    /// if `writeback` is false. Returns (pages evicted, whether a dirty page was written).
+    pub fn reclaim(&self, file_size: usize, max: usize, writeback: bool) -> (usize, bool) {
+        let mut pages = self.pages.write();
+        let mut victims = Vec::new();
+        for (&idx, page) in pages.iter() {
+            if victims.len() == max {
+                break;
+            }
+            if page.referenced.swap(false, Ordering::Relaxed) {
+                continue;
+            }
+            if Arc::strong_count(page) > 1 || enquire_refcount(page.data_frame.ppn) > 1 {
+                continue;
+            }
+            victims.push(idx);
+        }
+        let mut evicted = 0;
+        let mut written = false;
+        for idx in victims {
+            if writeback && pages[&idx].dirtied_at().is_some() {
+                if pages[&idx].sync(file_size).is_err() {
+                    continue;
+                }
+                written = true;
+            }
+            pages.remove(&idx);
+            evicted += 1;
         }
-        Ok(())
+        (evicted, written)
     }
 
     pub fn remove(&self, offset: usize) {
@@ -0,0 +1,56 @@
+//! Reclaim of page cache pages under memory pressure.
+//!
+//! Called by `alloc_frame()` when the frame allocator runs dry. Cached pages of all inodes
+//! in the inode cache are scanned with a CLOCK: a page looked up since the last scan gets a
+//! second chance, the others are written back if dirty and evicted. Pages that are still in
+//! use or whose frame is mapped elsewhere are never evicted. If that is not enough, unused
+//! inodes are dropped from the inode cache together with their pages.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use super::{FatInode, INODE_CACHE};
+use crate::mm::frame_usage;
+
+/// Only one reclaimer at a time. Also breaks recursion if reclaiming itself runs out of frames.
+static RECLAIM_LOCK: Mutex<()> = Mutex::new(());
+/// Index of the inode the next scan starts from
+static CLOCK_HAND: AtomicUsize = AtomicUsize::new(0);
+
+/// Try to free at least `target` frames, returns the number of frames actually freed
+pub fn reclaim_file_pages(target: usize) -> usize {
+    let _guard = match RECLAIM_LOCK.try_lock() {
+        Some(guard) => guard,
+        None => return 0,
+    };
+    let free_before = frame_usage().1;
+    let freed = || frame_usage().1.saturating_sub(free_before);
+
+    let inodes: Vec<Arc<FatInode>> = INODE_CACHE.0.read().values().cloned().collect();
+    if !inodes.is_empty() {
+        let start = CLOCK_HAND.load(Ordering::Relaxed) % inodes.len();
+        let mut evicted = 0;
+        // The first round may only clear referenced bits
+        'scan: for _ in 0..2 {
+            for i in 0..inodes.len() {
+                let idx = (start + i) % inodes.len();
+                evicted += inodes[idx].reclaim(target - evicted);
+                if evicted >= target {
+                    CLOCK_HAND.store(idx + 1, Ordering::Relaxed);
+                    break 'scan;
+                }
+            }
+        }
+    }
+    // Our references would keep every inode in the cache
+    drop(inodes);
+
+    if freed() < target {
+        INODE_CACHE.shrink();
+    }
+    let freed = freed();
+    debug!("[reclaim] freed {} frames (target {})", freed, target);
+    freed
+}
@@ -0,0 +1,99 @@
+//! Writeback of dirty page cache pages.
+//!
+//! Pages remember when they became dirty. Inodes with dirty pages are kept on a global
+//! dirty list, which also holds a reference to them so that shrinking the inode cache
+//! cannot drop data that has not reached the disk yet.
+//!
+//! - `fsync` writes back the pages of a single inode ([`FatInode::writeback`]).
+//! - `sync` writes back every inode on the dirty list ([`writeback_all`]).
+//! - The flusher ([`writeback_expired`]) is called from the scheduler loop. Every
+//!   `dirty_writeback_centisecs` it writes back the pages that have been dirty for longer
+//!   than `dirty_expire_centisecs`, so a crash loses at most about the sum of the two.
+//!
+//! An inode whose writeback fails stays on the dirty list to be retried, unless the file
+//! system has turned read-only after a device write error.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use fat32::sync_all;
+use spin::Mutex;
+
+use super::FatInode;
+use crate::syscall::impls::Errno;
+use crate::timer::get_time_ms;
+
+/// Age (in 1/100 s) after which a dirty page is written back by the flusher, as in Linux.
+/// Set through `/proc/sys/vm/dirty_expire_centisecs`.
+pub static DIRTY_EXPIRE_CENTISECS: AtomicUsize = AtomicUsize::new(3000);
+/// Interval (in 1/100 s) between two runs of the flusher, 0 disables it.
+/// Set through `/proc/sys/vm/dirty_writeback_centisecs`.
+pub static DIRTY_WRITEBACK_CENTISECS: AtomicUsize = AtomicUsize::new(500);
+
+static DIRTY_INODES: Mutex<Vec<Arc<FatInode>>> = Mutex::new(Vec::new());
+/// Time (ms) of the next flusher run
+static NEXT_WRITEBACK: AtomicUsize = AtomicUsize::new(0);
+
+/// Put the inode on the dirty list after its pages have been written
+pub fn mark_inode_dirty(inode: &Arc<FatInode>) {
+    if !inode.dirty.swap(true, Ordering::AcqRel) {
+        DIRTY_INODES.lock().push(inode.clone());
+    }
+}
+
+/// Take the inodes off the dirty list and write back the pages dirtied at or before
+/// `dirtied_before` (all pages if None). Inodes that still have dirty pages go back on the list.
+fn writeback_inodes(dirtied_before: Option<usize>) {
+    let inodes = core::mem::take(&mut *DIRTY_INODES.lock());
+    for inode in inodes {
+        // Cleared before writing back, so that concurrent writes put the inode back on the list
+        inode.dirty.store(false, Ordering::Release);
+        match inode.writeback(dirtied_before) {
+            Ok(false) => {}
+            Ok(true) => mark_inode_dirty(&inode),
+            // The file system has turned read-only, retrying cannot succeed
+            Err(Errno::EROFS) => {
+                warn!(
+                    "[writeback] dropping dirty pages of inode {} on a read-only fs",
+                    inode.fid
+                );
+            }
+            Err(errno) => {
+                warn!(
+                    "[writeback] failed to write back inode {}: {:?}",
+                    inode.fid, errno
+                );
+                mark_inode_dirty(&inode);
+            }
+        }
+    }
+    if let Err(err) = sync_all() {
+        warn!("[writeback] failed to write back blocks: {:?}", err);
+    }
+}
+
+/// Write back all dirty pages, used by `sync`
+pub fn writeback_all() {
+    writeback_inodes(None);
+}
+
+/// The periodic flusher. Does nothing until `dirty_writeback_centisecs` has passed since its
+/// last run, so it is cheap to call on every round of the scheduler.
+pub fn writeback_expired() {
+    let interval = DIRTY_WRITEBACK_CENTISECS.load(Ordering::Relaxed) * 10;
+    if interval == 0 {
+        return;
+    }
+    let now = get_time_ms();
+    let next = NEXT_WRITEBACK.load(Ordering::Relaxed);
+    // Only one hart runs the flusher in each interval
+    if now < next
+        || NEXT_WRITEBACK
+            .compare_exchange(next, now + interval, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+    {
+        return;
+    }
+    let expire = DIRTY_EXPIRE_CENTISECS.load(Ordering::Relaxed) * 10;
+    writeback_inodes(Some(now.saturating_sub(expire)));
+}
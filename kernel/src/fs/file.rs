@@ -1,332 +1,105 @@
-use crate::fs::ino_alloc;
-use crate::fs::File;
-#[cfg(feature = "fat32")]
-use crate::fs::Inode;
-#[cfg(feature = "fat32")]
-use crate::fs::KFile;
 #[cfg(feature = "fat32")]
-use crate::fs::INODE_CACHE;
+use crate::fs::FatFs;
+use crate::fs::File;
+#[cfg(not(feature = "fat32"))]
+use crate::fs::RamFs;
+use crate::fs::{find_mount, lookup, resolve, Dentry, InodeType};
 use crate::return_errno;
 use crate::syscall::impls::Errno;
+#[cfg(feature = "fat32")]
 use crate::BLOCK_DEVICE;
-use alloc::string::ToString;
 use alloc::sync::Arc;
-use fat32::{root, Dir, DirError, FileSystem, VirtFile, VirtFileType, ATTR_DIRECTORY};
+#[cfg(feature = "fat32")]
+use fat32::{VirtFile, ATTR_DIRECTORY};
 use nix::{CreateMode, OpenFlags};
 pub use path::*;
 use spin::lazy::Lazy;
-use spin::rwlock::RwLock;
-use spin::Mutex;
-
-#[cfg(feature = "ramfs")]
-use crate::fs::MountedInfo;
-#[cfg(feature = "ramfs")]
-use crate::fs::RamDir;
-#[cfg(feature = "ramfs")]
-use crate::fs::RamFs;
 
-// pub use mount::MNT_TABLE;
-// pub use pipe::{make_pipe, Pipe};
-// pub use stdio::{Stdin, Stdout};
-
-#[cfg(feature = "ramfs")]
-pub static FILE_SYSTEM: Lazy<Arc<RwLock<RamFs>>> =
-    Lazy::new(|| Arc::new(RwLock::new(RamFs::new())));
+/// 挂载在 `/` 上的文件系统
+#[cfg(feature = "fat32")]
+pub static ROOT_FS: Lazy<Arc<FatFs>> = Lazy::new(|| Arc::new(FatFs::open(BLOCK_DEVICE.clone())));
 
-#[cfg(feature = "ramfs")]
-pub static ROOT_INODE: Lazy<Arc<RamDir>> = Lazy::new(|| {
-    let fs = FILE_SYSTEM.read();
-    let root_dir = fs.root_dir(MountedInfo {
-        fs_id: 0,
-        path: AbsolutePath::from_str("/"),
-    });
-    root_dir
-});
+#[cfg(not(feature = "fat32"))]
+pub static ROOT_FS: Lazy<Arc<RamFs>> = Lazy::new(|| Arc::new(RamFs::new()));
 
-#[cfg(feature = "ramfs")]
+#[cfg(feature = "fat32")]
+pub static ROOT_INODE: Lazy<Arc<VirtFile>> = Lazy::new(|| ROOT_FS.root_file());
+
+/// 打开 `path` 对应的文件.
+///
+/// 先解析路径中的符号链接 (见 [`resolve`]), 再在解析后的路径所属的文件系统中打开; 若文件不存在且
+/// 带有 `O_CREAT`, 则在父目录中创建. 带有 `O_NOFOLLOW` 时不跟随最后一级的符号链接: 此时若同时
+/// 带有 `O_PATH` 则打开链接本身, 否则返回 ELOOP.
+///
+/// `flags` 的访问模式与用户态一致 (`O_RDONLY` 为 0), 内核内部只读打开时应传入 `OpenFlags::empty()`;
+/// nix 中的 `OpenFlags::O_RDONLY` 与用户态的 `O_RDWR` 数值相同, 会被当作写打开.
 pub fn open(
     path: AbsolutePath,
     flags: OpenFlags,
     _mode: CreateMode,
 ) -> Result<Arc<dyn File>, Errno> {
-    if path == AbsolutePath::from_str("/") {
-        return Ok(ROOT_INODE.clone());
-    }
-    let pathv = path.as_vec_str();
-    let target = pathv.last().unwrap();
-    match find_parent_dir(path.clone()) {
-        Some(parent) => match parent.open(target) {
-            Some(file) => {
-                let res = file.to_inode(MountedInfo { fs_id: 0, path });
-                res.set_flags(flags);
-                Ok(res)
-            }
-            None => {
-                if flags.contains(OpenFlags::O_CREAT) {
-                    if flags.contains(OpenFlags::O_DIRECTORY) {
-                        parent.mkdir(target, flags)
-                    } else {
-                        parent.touch(target, flags)
-                    }
-                } else {
-                    Err(Errno::ENOENT)
-                }
-            }
-        },
-        None => Err(Errno::ENOENT),
-    }
-}
-
-#[cfg(feature = "ramfs")]
-pub fn chdir(path: AbsolutePath) -> bool {
-    if path == AbsolutePath::from_str("/") {
-        return true;
-    }
-    let pathv = path.as_vec_str();
-    let target = pathv.last().unwrap();
-    match find_parent_dir(path.clone()) {
-        Some(parent) => parent.open(target).is_some(),
-        None => false,
-    }
-}
-
-#[cfg(feature = "ramfs")]
-pub fn find_parent_dir(path: AbsolutePath) -> Option<Arc<RamDir>> {
-    let mut current_dir = ROOT_INODE.clone();
-    if path == AbsolutePath::from_str("/") {
-        return Some(current_dir);
-    }
-    let pathv = path.as_vec_str();
-    // 找到父目录
-    for name in pathv.iter().take(pathv.len() - 1) {
-        match current_dir.open_dir(name) {
-            Some(dir) => {
-                current_dir = dir;
+    #[cfg(feature = "time-tracer")]
+    time_trace!("open");
+    // 缓存中只有普通文件, 且以解析后的路径为键
+    if let Some(mnt) = find_mount(&path) {
+        // bind 挂载的文件在缓存中的路径与 `path` 不同, 不走缓存
+        if mnt.root.is_root() && !mnt.is_rdonly() {
+            if let Some(file) = mnt.sb.open_cached(&path, flags) {
+                return Ok(file);
             }
-            None => return None,
         }
     }
-    Some(current_dir)
-}
-
-#[cfg(feature = "fat32")]
-pub static FILE_SYSTEM: Lazy<Arc<RwLock<FileSystem>>> = Lazy::new(|| {
-    let blk = BLOCK_DEVICE.clone();
-    FileSystem::open(blk)
-});
-
-#[cfg(feature = "fat32")]
-pub static ROOT_INODE: Lazy<Arc<VirtFile>> = Lazy::new(|| {
-    let fs = FILE_SYSTEM.clone();
-    Arc::new(root(fs))
-});
-
-#[cfg(feature = "fat32")]
-pub fn open(path: AbsolutePath, flags: OpenFlags, _mode: CreateMode) -> Result<Arc<KFile>, Errno> {
-    #[cfg(feature = "time-tracer")]
-    time_trace!("open");
-    let (readable, writable) = flags.read_write();
-    let mut pathv = path.as_vec_str();
-
-    // println!("open test 0.1");
-
-    #[cfg(not(feature = "no-page-cache"))]
-    if let Some(inode) = INODE_CACHE.get(&path) {
-        // println!("open test 0.1.1");
-        let name = if let Some(name_) = pathv.last() {
-            name_.to_string()
-        } else {
-            "/".to_string()
-        };
-        let res = Arc::new(KFile::new(
-            readable,
-            writable,
-            inode.clone(),
-            path.clone(),
-            name,
-        ));
-
-        // println!("open test 0.1.2");
 
-        res.create_page_cache_if_needed();
-
-        res.set_flags(flags);
-
-        // println!("open test 0.1.3");
-
-        return Ok(res);
+    let follow = !flags.contains(OpenFlags::O_NOFOLLOW);
+    let (path, dentry) = match resolve(&path, follow) {
+        Ok(resolved) => resolved,
+        Err(errno) => return_errno!(errno, "failed to resolve path:{:?}", path),
+    };
+    let mnt = match find_mount(&path) {
+        Some(mnt) => mnt,
+        None => return_errno!(Errno::ENOENT, "no filesystem mounted for path:{:?}", path),
+    };
+
+    if mnt.is_rdonly()
+        && flags.intersects(
+            OpenFlags::O_WRONLY | OpenFlags::O_RDWR | OpenFlags::O_CREAT | OpenFlags::O_TRUNC,
+        )
+    {
+        return_errno!(Errno::EROFS, "read-only filesystem, path:{:?}", path);
     }
 
-    // println!("open test 0.2");
-
-    if flags.contains(OpenFlags::O_CREAT) {
-        // Create File
-        let res = ROOT_INODE.find(pathv.clone());
-
-        // println!("open test 0.3");
-
-        match res {
-            Ok(file) => {
-                // println!("open test 0.4");
-
-                let name = if let Some(name_) = pathv.pop() {
-                    name_
-                } else {
-                    "/"
-                };
-                let fid = ino_alloc();
-                #[cfg(not(feature = "no-page-cache"))]
-                let file_size = file.file_size();
-                #[cfg(not(feature = "no-page-cache"))]
-                let inode = Arc::new(Inode {
-                    file: Mutex::new(file),
-                    fid,
-                    page_cache: Mutex::new(None),
-                    file_size: Mutex::new(file_size),
-                });
-                #[cfg(feature = "no-page-cache")]
-                let inode = Arc::new(Inode {
-                    fid,
-                    file: Mutex::new(file),
-                });
-
-                let res = Arc::new(KFile::new(
-                    readable,
-                    writable,
-                    inode.clone(),
-                    path.clone(),
-                    name.to_string(),
-                ));
-
-                // println!("open test 0.5");
-
-                // create page cache
-                #[cfg(not(feature = "no-page-cache"))]
-                res.create_page_cache_if_needed();
-
-                // println!("open test 0.6");
-
-                #[cfg(not(feature = "no-page-cache"))]
-                INODE_CACHE.insert(path.clone(), inode.clone());
-
-                // println!("open test 0.7");
-                Ok(res)
-            }
-            Err(_err) => {
-                // println!("open test 0.8");
-
-                if _err == DirError::NotDir {
-                    return Err(Errno::ENOTDIR);
-                }
-                let mut create_type = VirtFileType::File;
-                if flags.contains(OpenFlags::O_DIRECTORY) {
-                    create_type = VirtFileType::Dir;
-                }
-
-                // to find parent
-                let name = pathv.pop().unwrap();
-
-                // println!("open test 0.9");
-
-                match ROOT_INODE.find(pathv.clone()) {
-                    // find parent to create file
-                    Ok(parent) => match parent.create(name, create_type as VirtFileType) {
-                        Ok(file) => {
-                            // println!("open test 0.10");
-
-                            let fid = ino_alloc();
-                            #[cfg(not(feature = "no-page-cache"))]
-                            let file_size = file.file_size();
-                            #[cfg(not(feature = "no-page-cache"))]
-                            let inode = Arc::new(Inode {
-                                file: Mutex::new(Arc::new(file)),
-                                fid,
-                                page_cache: Mutex::new(None),
-                                file_size: Mutex::new(file_size),
-                            });
-                            #[cfg(feature = "no-page-cache")]
-                            let inode = Arc::new(Inode {
-                                fid,
-                                file: Mutex::new(Arc::new(file)),
-                            });
-                            let res = Arc::new(KFile::new(
-                                readable,
-                                writable,
-                                inode.clone(),
-                                path.clone(),
-                                name.to_string(),
-                            ));
-                            #[cfg(not(feature = "no-page-cache"))]
-                            res.create_page_cache_if_needed();
-                            #[cfg(not(feature = "no-page-cache"))]
-                            INODE_CACHE.insert(path.clone(), inode.clone());
-                            Ok(res)
-                        }
-                        Err(_err) => Err(Errno::DISCARD),
-                    },
-                    Err(_err) => {
-                        return_errno!(Errno::ENOENT, "parent path not exist path:{:?}", path)
-                    }
-                }
-            }
+    let dentry: Arc<dyn Dentry> = match dentry {
+        Some(dentry) if dentry.is_symlink() && !flags.contains(OpenFlags::PATH) => {
+            return_errno!(Errno::ELOOP, "refuse to follow symlink, path:{:?}", path)
         }
-    } else {
-        // Open File
-        match ROOT_INODE.find(pathv.clone()) {
-            Ok(file) => {
-                // println!("open test 0.11");
-
-                // clear file if O_TRUNC
-                if flags.contains(OpenFlags::O_TRUNC) {
-                    file.clear();
-                }
-                let name = file.name().to_string();
-                let fid = ino_alloc();
-                #[cfg(not(feature = "no-page-cache"))]
-                let file_size = file.file_size();
-                #[cfg(not(feature = "no-page-cache"))]
-                let inode = Arc::new(Inode {
-                    file: Mutex::new(file),
-                    fid,
-                    file_size: Mutex::new(file_size),
-                    page_cache: Mutex::new(None),
-                });
-                #[cfg(feature = "no-page-cache")]
-                let inode = Arc::new(Inode {
-                    fid,
-                    file: Mutex::new(file),
-                });
-                let res = Arc::new(KFile::new(
-                    readable,
-                    writable,
-                    inode.clone(),
-                    path.clone(),
-                    name,
-                ));
-                #[cfg(not(feature = "no-page-cache"))]
-                res.create_page_cache_if_needed();
-                #[cfg(not(feature = "no-page-cache"))]
-                INODE_CACHE.insert(path.clone(), inode.clone());
-                res.set_flags(flags);
-                Ok(res)
-            }
-            Err(_err) => return_errno!(Errno::ENOENT, "no such file or path:{:?}", path),
+        Some(dentry) => dentry,
+        None if flags.contains(OpenFlags::O_CREAT) => {
+            // 解析后的路径中不含符号链接, 父目录一定存在
+            let parent = lookup(&path.parent())?;
+            let itype = if flags.contains(OpenFlags::O_DIRECTORY) {
+                InodeType::Dir
+            } else {
+                InodeType::File
+            };
+            parent.create(&path.last(), itype)?
         }
-    }
+        None => return_errno!(Errno::ENOENT, "no such file or path:{:?}", path),
+    };
+    dentry.inode().open(path, flags)
 }
 
-// TODO This only used to check whether can cd to path
-#[cfg(feature = "fat32")]
-pub fn chdir(path: AbsolutePath) -> bool {
-    if let Ok(_) = ROOT_INODE.find(path.as_vec_str()) {
-        true
-    } else {
-        false
+/// Check whether can cd to path, return the path with symlinks resolved
+pub fn chdir(path: AbsolutePath) -> Result<AbsolutePath, Errno> {
+    match resolve(&path, true)? {
+        (path, Some(dentry)) if dentry.is_dir() => Ok(path),
+        (_, Some(_)) => Err(Errno::ENOTDIR),
+        (_, None) => Err(Errno::ENOENT),
     }
 }
 
 #[cfg(feature = "fat32")]
 pub fn list_apps(path: AbsolutePath) {
+    use fat32::Dir;
     let layer: usize = 0;
     fn ls(path: AbsolutePath, layer: usize) {
         let dir = ROOT_INODE.find(path.as_vec_str()).unwrap();
@@ -1,7 +1,12 @@
 //! Kernel file system
 //!
-//! The kernel uniformly borrows the VirtFile provided by the fat32 file system
-//! as the object for the kernel to operate files.
+//! All file systems are accessed through the VFS layer (see [`vfs`]): the FAT32 image
+//! is mounted on `/`, `/tmp` and `/dev/shm` are kept in memory by ramfs, the files under
+//! `/proc` are generated from kernel state by procfs, and `/dev` exposes the kernel's
+//! devices through devfs.
+//!
+//! Before the VFS layer was introduced, the kernel uniformly borrowed the VirtFile provided
+//! by the fat32 file system as the object for the kernel to operate files.
 //!
 //! Due to the fact that files within the kernel actually encapsulate the VirtFile
 //! provided by FAT32 into KFile, resulting in data being synchronized to the disk
@@ -21,22 +26,26 @@
 
 #[cfg(feature = "fat32")]
 mod fat;
+mod devfs;
 mod file;
 mod mount;
 mod pipe;
-#[cfg(feature = "ramfs")]
+mod procfs;
 mod ramfs;
 mod stdio;
+mod vfs;
 
 #[cfg(feature = "fat32")]
 pub use self::fat::*;
+pub use devfs::*;
 pub use file::*;
 pub use mount::*;
 pub use path::*;
 pub use pipe::*;
-#[cfg(feature = "ramfs")]
+pub use procfs::*;
 pub use ramfs::*;
 pub use stdio::*;
+pub use vfs::*;
 
 // use crate::return_errno;
 use crate::syscall::impls::Errno;
@@ -49,7 +58,7 @@ pub use path::*;
 // use spin::rwlock::RwLock;
 use spin::Mutex;
 
-use crate::mm::UserBuffer;
+use crate::mm::{FrameTracker, UserBuffer};
 use alloc::string::String;
 use alloc::vec::Vec;
 use core::fmt::Debug;
@@ -60,89 +69,66 @@ use path::AbsolutePath;
 // pub use pipe::{make_pipe, Pipe};
 // pub use stdio::{Stdin, Stdout};
 
-use nix::CreateMode;
+use alloc::sync::Arc;
+use nix::{CreateMode, MountFlags};
 
 pub fn init() {
-    open(
-        "/proc".into(),
-        OpenFlags::O_DIRECTORY | OpenFlags::O_CREAT,
-        CreateMode::empty(),
+    #[cfg(feature = "fat32")]
+    let (root_special, root_fstype) = ("/dev/vda", "vfat");
+    #[cfg(not(feature = "fat32"))]
+    let (root_special, root_fstype) = ("rootfs", "ramfs");
+    mount_fs(
+        root_special,
+        "/".into(),
+        root_fstype,
+        MountFlags::empty(),
+        ROOT_FS.clone(),
     )
     .unwrap();
-    open(
-        "/tmp".into(),
-        OpenFlags::O_DIRECTORY | OpenFlags::O_CREAT,
-        CreateMode::empty(),
-    )
-    .unwrap();
-    open(
-        "/dev".into(),
-        OpenFlags::O_DIRECTORY | OpenFlags::O_CREAT,
-        CreateMode::empty(),
+
+    // /proc, /tmp and /dev are kept in memory, the mount points themselves live on the root fs.
+    let mounts: [(&str, &str, Arc<dyn SuperBlock>); 3] = [
+        ("/proc", "proc", Arc::new(ProcFs::new())),
+        ("/tmp", "tmpfs", Arc::new(RamFs::new())),
+        ("/dev", "devtmpfs", Arc::new(DevFs::new())),
+    ];
+    for (mount_point, fstype, sb) in mounts {
+        open(
+            mount_point.into(),
+            OpenFlags::O_DIRECTORY | OpenFlags::O_CREAT,
+            CreateMode::empty(),
+        )
+        .unwrap();
+        mount_fs(fstype, mount_point.into(), fstype, MountFlags::empty(), sb).unwrap();
+    }
+    // devfs 中已有 /dev/shm 目录
+    mount_fs(
+        "tmpfs",
+        "/dev/shm".into(),
+        "tmpfs",
+        MountFlags::empty(),
+        Arc::new(RamFs::new()),
     )
     .unwrap();
+
     open(
         "/var".into(),
         OpenFlags::O_DIRECTORY | OpenFlags::O_CREAT,
         CreateMode::empty(),
     )
     .unwrap();
-    open(
-        "/dev/misc".into(),
-        OpenFlags::O_DIRECTORY | OpenFlags::O_CREAT,
-        CreateMode::empty(),
-    )
-    .unwrap();
-    open(
-        "/dev/shm".into(),
-        OpenFlags::O_DIRECTORY | OpenFlags::O_CREAT,
-        CreateMode::empty(),
-    )
-    .unwrap();
     open(
         "/var/tmp".into(),
         OpenFlags::O_DIRECTORY | OpenFlags::O_CREAT,
         CreateMode::empty(),
     )
     .unwrap();
-    open("/dev/null".into(), OpenFlags::O_CREAT, CreateMode::empty()).unwrap();
-    open("/dev/zero".into(), OpenFlags::O_CREAT, CreateMode::empty()).unwrap();
-    open(
-        "/proc/mounts".into(),
-        OpenFlags::O_CREAT,
-        CreateMode::empty(),
-    )
-    .unwrap();
-    open(
-        "/proc/meminfo".into(),
-        OpenFlags::O_CREAT,
-        CreateMode::empty(),
-    )
-    .unwrap();
-    open(
-        "/dev/misc/rtc".into(),
-        OpenFlags::O_CREAT,
-        CreateMode::empty(),
-    )
-    .unwrap();
     open(
         "/var/tmp/lmbench".into(),
         OpenFlags::O_CREAT,
         CreateMode::empty(),
     )
     .unwrap();
-
-    // sys_clock_getres
-    // 应用程序可以通过打开 /dev/cpu_dma_latency 设备文件, 并向其写入一个非负整数, 来请求将 CPU 切换到低延迟模式.
-    // 写入的整数值表示请求的最大延迟时间, 单位为微秒
-    open(
-        "/dev/cpu_dma_latency".into(),
-        OpenFlags::O_CREAT,
-        CreateMode::empty(),
-    )
-    .unwrap();
-
-    open("/dev/tty".into(), OpenFlags::O_CREAT, CreateMode::empty()).unwrap();
     open("/lat_sig".into(), OpenFlags::O_CREAT, CreateMode::empty()).unwrap();
 }
 
@@ -163,6 +149,24 @@ impl Allocator {
         self.fetch_add()
     }
 }
+/// 后台写回脏页, 由调度循环反复调用, 实际的写回间隔见 `DIRTY_WRITEBACK_CENTISECS`
+pub fn writeback_tick() {
+    #[cfg(all(feature = "fat32", not(feature = "no-page-cache")))]
+    writeback_expired();
+}
+/// 内存不足时回收页缓存, 返回实际释放的页帧数
+pub fn reclaim_memory(target: usize) -> usize {
+    #[cfg(all(feature = "fat32", not(feature = "no-page-cache")))]
+    {
+        reclaim_file_pages(target)
+    }
+    #[cfg(not(all(feature = "fat32", not(feature = "no-page-cache"))))]
+    {
+        let _ = target;
+        0
+    }
+}
+
 pub fn ino_alloc() -> u64 {
     INO_ALLOCATOR.lock().alloc()
 }
@@ -171,14 +175,15 @@ pub trait File: Send + Sync {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
     fn available(&self) -> bool;
-    /// 从文件中读取数据放到缓冲区中, 最多将缓冲区填满, 并返回实际读取的字节数
-    fn read_to_ubuf(&self, buf: UserBuffer) -> usize;
+    /// 从文件中读取数据放到缓冲区中, 最多将缓冲区填满, 并返回实际读取的字节数;
+    /// 底层设备出错时返回 EIO
+    fn read_to_ubuf(&self, buf: UserBuffer) -> Result<usize, Errno>;
     /// 将缓冲区中的数据写入文件, 最多将缓冲区中的数据全部写入, 并返回直接写入的字节数
-    fn write_from_ubuf(&self, buf: UserBuffer) -> usize;
-    fn pread(&self, _buf: UserBuffer, _offset: usize) -> usize {
+    fn write_from_ubuf(&self, buf: UserBuffer) -> Result<usize, Errno>;
+    fn pread(&self, _buf: UserBuffer, _offset: usize) -> Result<usize, Errno> {
         panic!("{} not implement pread", self.name());
     }
-    fn pwrite(&self, _buf: UserBuffer, _offset: usize) -> usize {
+    fn pwrite(&self, _buf: UserBuffer, _offset: usize) -> Result<usize, Errno> {
         panic!("{} not implement pwrite", self.name());
     }
     fn read_at_direct(&self, _offset: usize, _len: usize) -> Vec<u8> {
@@ -187,14 +192,14 @@ pub trait File: Send + Sync {
     fn write_from_direct(&self, _offset: usize, _data: &Vec<u8>) -> usize {
         panic!("{} not implement write_from_direct", self.name());
     }
-    fn kernel_read_with_offset(&self, _offset: usize, _len: usize) -> Vec<u8> {
+    fn kernel_read_with_offset(&self, _offset: usize, _len: usize) -> Result<Vec<u8>, Errno> {
         panic!("{} not implement read_to_kspace_with_offset", self.name());
     }
     fn seek(&self, _pos: usize) {
         panic!("{} not implement seek", self.name());
     }
     fn name(&self) -> String;
-    fn fstat(&self, _kstat: &mut Kstat) {
+    fn fstat(&self, _kstat: &mut Kstat) -> Result<(), Errno> {
         panic!("{} not implement fstat", self.name());
     }
     fn set_time(&self, _xtime_info: InodeTime) {
@@ -221,10 +226,10 @@ pub trait File: Send + Sync {
     fn set_cloexec(&self) {
         panic!("{} not implement set_cloexec", self.name());
     }
-    fn read_to_kspace(&self) -> Vec<u8> {
+    fn read_to_kspace(&self) -> Result<Vec<u8>, Errno> {
         panic!("{} not implement read_kernel_space", self.name());
     }
-    fn write_from_kspace(&self, _data: &Vec<u8>) -> usize {
+    fn write_from_kspace(&self, _data: &Vec<u8>) -> Result<usize, Errno> {
         panic!("{} not implement write_kernel_space", self.name());
     }
     fn file_size(&self) -> usize {
@@ -239,21 +244,42 @@ pub trait File: Send + Sync {
     fn path(&self) -> AbsolutePath {
         unimplemented!("not implemente yet");
     }
-    fn truncate(&self, _new_length: usize) {
+    /// 文件是否位于 `dir` 之下, 用于判断挂载点是否繁忙; 不在目录树中的文件 (如管道) 返回 false
+    fn is_under(&self, _dir: &AbsolutePath) -> bool {
+        false
+    }
+    fn truncate(&self, _new_length: usize) -> Result<(), Errno> {
         unimplemented!("not implemente yet");
     }
     fn fid(&self) -> u64 {
         unimplemented!("not implemente yet");
     }
-    fn delete(&self) -> usize {
-        unimplemented!("not implemente yet");
+    /// 删除文件的目录项
+    fn delete(&self) -> Result<(), Errno> {
+        Err(Errno::EPERM)
     }
-    fn rename(&self, _new_name: AbsolutePath, _flags: OpenFlags) {
-        unimplemented!("not implemente yet");
+    /// 将文件移动到 `new_name`; 不支持重命名的文件返回 EPERM
+    fn rename(&self, _new_name: AbsolutePath, _flags: OpenFlags) -> Result<(), Errno> {
+        Err(Errno::EPERM)
     }
     fn is_dir(&self) -> bool {
         unimplemented!("not implemente yet");
     }
+    /// `MAP_SHARED` 映射 `offset` 处的页时, 与文件共享的页帧; 数据不在页帧中的文件返回 None,
+    /// 此时映射只能得到文件内容的副本
+    fn shared_frame(&self, _offset: usize) -> Option<FrameTracker> {
+        None
+    }
+    /// 通过 `MAP_SHARED` 映射写入了 `offset` 处与文件共享的页帧, 需要写回设备的文件将该页标记为脏页
+    fn dirty_shared_frame(&self, _offset: usize) {}
+    /// 设备相关的控制操作, 不是设备文件时返回 ENOTTY
+    fn ioctl(&self, _request: usize, _argp: usize) -> Result<isize, Errno> {
+        Err(Errno::ENOTTY)
+    }
+    /// 将文件在内存中的修改写回设备 (fsync/fdatasync); 数据只存在于内存中的文件无需写回
+    fn fsync(&self) -> Result<(), Errno> {
+        Ok(())
+    }
 }
 
 impl Debug for dyn File + Send + Sync {
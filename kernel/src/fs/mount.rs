@@ -1,46 +1,136 @@
-use alloc::{string::String, sync::Arc, vec::Vec};
+//! Mount table
+//!
+//! 每个挂载点记录挂载的文件系统 ([`SuperBlock`]) 以及被挂载的子树在该文件系统中的路径
+//! (非 bind 挂载时为 `/`). 路径解析 ([`super::lookup`]/[`super::open`]) 通过
+//! [`MountTable::find`] 找到路径所属的挂载点, 从而跨越挂载点.
+
+use super::{walk, SuperBlock};
+use crate::syscall::impls::Errno;
+use crate::task::PID2TCB;
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use nix::{MountFlags, UmountFlags};
+use path::AbsolutePath;
 use spin::Mutex;
 
 const MNT_MAXLEN: usize = 16;
 
+#[derive(Clone)]
+pub struct MountEntry {
+    pub special: String,
+    pub dir: AbsolutePath,
+    pub fstype: String,
+    pub flags: MountFlags,
+    pub sb: Arc<dyn SuperBlock>,
+    /// 被挂载的子树在 `sb` 中的路径
+    pub root: AbsolutePath,
+}
+
+impl MountEntry {
+    /// 以 `MS_RDONLY` 挂载, 或文件系统因设备写入失败已转为只读
+    pub fn is_rdonly(&self) -> bool {
+        self.flags.contains(MountFlags::MS_RDONLY) || self.sb.is_read_only()
+    }
+}
+
 pub struct MountTable {
-    mnt_list: Vec<(String, String, String)>, // special, dir, fstype
+    mnt_list: Vec<MountEntry>,
 }
 
 impl MountTable {
-    pub fn mount(&mut self, special: String, dir: String, fstype: String, _flags: u32) -> isize {
+    pub fn mount(&mut self, entry: MountEntry) -> Result<(), Errno> {
         if self.mnt_list.len() == MNT_MAXLEN {
-            return -1;
+            return Err(Errno::EMFILE);
         }
-        // if is mounted
-        if self.mnt_list.iter().find(|&(_, d, _)| *d == dir).is_some() {
-            return 0;
+        self.mnt_list.push(entry);
+        Ok(())
+    }
+    /// 修改挂载在 `dir` 上的文件系统的 flags
+    pub fn remount(&mut self, dir: &AbsolutePath, flags: MountFlags) -> Result<(), Errno> {
+        let entry = self
+            .mnt_list
+            .iter_mut()
+            .rev()
+            .find(|entry| entry.dir == *dir)
+            .ok_or(Errno::EINVAL)?;
+        entry.flags = flags & !MountFlags::MS_REMOUNT;
+        Ok(())
+    }
+    /// 找到 `target` 对应的挂载点目录
+    pub fn target_dir(&self, target: &AbsolutePath) -> Result<AbsolutePath, Errno> {
+        // according to the syscall specification, it should be dir == target
+        // however, the test program may pass special, so here is an or operation
+        self.mnt_list
+            .iter()
+            .rev()
+            .find(|entry| entry.dir == *target || AbsolutePath::from_str(&entry.special) == *target)
+            .map(|entry| entry.dir.clone())
+            .ok_or(Errno::EINVAL)
+    }
+    /// 卸载挂载在 `dir` 上的文件系统, 是否有进程在使用由调用者检查
+    pub fn umount(&mut self, dir: &AbsolutePath, flags: UmountFlags) -> Result<(), Errno> {
+        if dir.is_root() {
+            return Err(Errno::EBUSY);
         }
-        self.mnt_list.push((special, dir, fstype));
-        0
-    }
-    pub fn umount(&mut self, special: String, _flags: u32) -> isize {
-        let len = self.mnt_list.len();
-        for i in 0..len {
-            // according to the syscall specification, it should be self.mnt_list[i].0 == special
-            // however, the test program passes dir, so here is an or operation
-            if self.mnt_list[i].0 == special || self.mnt_list[i].1 == special {
-                self.mnt_list.remove(i);
-                return 0;
+        let idx = self
+            .mnt_list
+            .iter()
+            .rposition(|entry| entry.dir == *dir)
+            .ok_or(Errno::EINVAL)?;
+        // other filesystems mounted under it
+        let nested = |entry: &MountEntry| entry.dir.start_with(dir) && entry.dir.len() > dir.len();
+        if !flags.contains(UmountFlags::MNT_DETACH) {
+            if self.mnt_list[idx + 1..].iter().any(nested) {
+                return Err(Errno::EBUSY);
             }
+        } else {
+            // lazy unmount: detach the whole subtree
+            self.mnt_list.retain(|entry| !nested(entry));
         }
-        -1
+        let idx = self
+            .mnt_list
+            .iter()
+            .rposition(|entry| entry.dir == *dir)
+            .unwrap();
+        self.mnt_list.remove(idx);
+        Ok(())
+    }
+    /// 找到 `path` 所属的挂载点 (最长前缀匹配, 同一路径上后挂载的覆盖先挂载的)
+    pub fn find(&self, path: &AbsolutePath) -> Option<MountEntry> {
+        self.mnt_list
+            .iter()
+            .filter(|entry| path.start_with(&entry.dir))
+            .max_by_key(|entry| entry.dir.layer())
+            .cloned()
+    }
+    /// `/proc/mounts` 的内容
+    pub fn mounts_info(&self) -> String {
+        let mut info = String::new();
+        for entry in self.mnt_list.iter() {
+            let rw = if entry.is_rdonly() { "ro" } else { "rw" };
+            info += &format!(
+                "{} {:?} {} {} 0 0\n",
+                entry.special, entry.dir, entry.fstype, rw
+            );
+        }
+        info
     }
 }
 
-// lazy_static! {
-//     pub static ref MNT_TABLE: Arc<Mutex<MountTable>> = {
-//         let mnt_table = MountTable {
-//             mnt_list: Vec::new(),
-//         };
-//         Arc::new(Mutex::new(mnt_table))
-//     };
-// }
+/// 是否有任务的工作目录或打开的文件位于 `dir` 之下
+fn is_busy(dir: &AbsolutePath) -> bool {
+    // 不能在持有 PID2TCB 时获取任务的锁
+    let tasks: Vec<_> = PID2TCB.lock().values().cloned().collect();
+    tasks.iter().any(|task| {
+        if task.fs.read().cwd.start_with(dir) {
+            return true;
+        }
+        task.fd_table
+            .read()
+            .iter()
+            .flatten()
+            .any(|file| file.is_under(dir))
+    })
+}
 
 use spin::lazy::Lazy;
 pub static MNT_TABLE: Lazy<Arc<Mutex<MountTable>>> = Lazy::new(|| {
@@ -49,3 +139,67 @@ pub static MNT_TABLE: Lazy<Arc<Mutex<MountTable>>> = Lazy::new(|| {
     };
     Arc::new(Mutex::new(mnt_table))
 });
+
+/// 将文件系统 `sb` 挂载到 `dir` 上
+pub fn mount_fs(
+    special: &str,
+    dir: AbsolutePath,
+    fstype: &str,
+    flags: MountFlags,
+    sb: Arc<dyn SuperBlock>,
+) -> Result<(), Errno> {
+    MNT_TABLE.lock().mount(MountEntry {
+        special: String::from(special),
+        dir,
+        fstype: String::from(fstype),
+        flags,
+        sb,
+        root: AbsolutePath::from_str("/"),
+    })
+}
+
+/// 将 `src` 所在的目录树绑定到 `dir` 上
+pub fn bind_mount(src: &AbsolutePath, dir: AbsolutePath, flags: MountFlags) -> Result<(), Errno> {
+    let src_mnt = find_mount(src).ok_or(Errno::ENOENT)?;
+    let mut root = src_mnt.root.clone();
+    for name in src.remove_prefix(&src_mnt.dir).iter() {
+        root.push_back(name.clone());
+    }
+    if !walk(src_mnt.sb.root(), &root.as_vec_str())?.is_dir() {
+        return Err(Errno::ENOTDIR);
+    }
+    MNT_TABLE.lock().mount(MountEntry {
+        special: format!("{:?}", src),
+        dir,
+        fstype: src_mnt.fstype,
+        flags: flags & !MountFlags::MS_BIND,
+        sb: src_mnt.sb,
+        root,
+    })
+}
+
+/// 卸载 `target` (挂载点或设备名); 未指定 `MNT_DETACH` 时, 若仍有进程使用该文件系统则返回 EBUSY
+pub fn umount(target: &AbsolutePath, flags: UmountFlags) -> Result<(), Errno> {
+    // 检查繁忙时不持有挂载表的锁: 其他任务可能正持有自身的锁等待挂载表
+    let dir = MNT_TABLE.lock().target_dir(target)?;
+    if !flags.contains(UmountFlags::MNT_DETACH) && is_busy(&dir) {
+        return Err(Errno::EBUSY);
+    }
+    MNT_TABLE.lock().umount(&dir, flags)
+}
+
+pub fn find_mount(path: &AbsolutePath) -> Option<MountEntry> {
+    MNT_TABLE.lock().find(path)
+}
+
+/// 将所有挂载的文件系统在内存中的修改写回设备 (sync)
+pub fn sync_all_fs() {
+    // 写回时不持有挂载表的锁
+    let sbs: Vec<_> = MNT_TABLE
+        .lock()
+        .mnt_list
+        .iter()
+        .map(|entry| entry.sb.clone())
+        .collect();
+    sbs.iter().for_each(|sb| sb.sync());
+}
@@ -1,5 +1,5 @@
 use super::File;
-use crate::{mm::UserBuffer, task::suspend_current_and_run_next};
+use crate::{mm::UserBuffer, syscall::impls::Errno, task::suspend_current_and_run_next};
 use alloc::{
     string::{String, ToString},
     sync::{Arc, Weak},
@@ -131,7 +131,7 @@ impl File for Pipe {
     fn available(&self) -> bool {
         true
     }
-    fn read_to_ubuf(&self, buf: UserBuffer) -> usize {
+    fn read_to_ubuf(&self, buf: UserBuffer) -> Result<usize, Errno> {
         #[cfg(feature = "time-tracer")]
         time_trace!("pipe_read");
         assert_eq!(self.readable(), true);
@@ -142,11 +142,12 @@ impl File for Pipe {
             let loop_read = ring_buffer.available_read();
             if loop_read == 0 {
                 if ring_buffer.all_write_ends_closed() {
-                    return read_size;
+                    return Ok(read_size);
                 }
                 drop(ring_buffer);
                 if suspend_current_and_run_next() < 0 {
-                    return read_size;
+                    // 被信号打断, 处理完信号后重新读
+                    return Err(Errno::ERESTARTSYS);
                 }
                 continue;
             }
@@ -158,13 +159,13 @@ impl File for Pipe {
                     }
                     read_size += 1;
                 } else {
-                    return read_size;
+                    return Ok(read_size);
                 }
             }
-            return read_size;
+            return Ok(read_size);
         }
     }
-    fn write_from_ubuf(&self, buf: UserBuffer) -> usize {
+    fn write_from_ubuf(&self, buf: UserBuffer) -> Result<usize, Errno> {
         assert_eq!(self.writable(), true);
         let mut buf_iter = buf.into_iter();
         let mut write_size = 0usize;
@@ -174,7 +175,11 @@ impl File for Pipe {
             if loop_write == 0 {
                 drop(ring_buffer);
                 if suspend_current_and_run_next() < 0 {
-                    return write_size;
+                    // 被信号打断, 已经写入的部分不再重写
+                    if write_size == 0 {
+                        return Err(Errno::ERESTARTSYS);
+                    }
+                    return Ok(write_size);
                 }
                 continue;
             }
@@ -183,7 +188,7 @@ impl File for Pipe {
                     ring_buffer.write_byte(unsafe { *byte_ref });
                     write_size += 1;
                 } else {
-                    return write_size;
+                    return Ok(write_size);
                 }
             }
         }
@@ -197,7 +202,7 @@ impl File for Pipe {
     fn seek(&self, _offset: usize) {
         return;
     }
-    fn read_to_kspace(&self) -> Vec<u8> {
+    fn read_to_kspace(&self) -> Result<Vec<u8>, Errno> {
         assert_eq!(self.readable(), true);
         let mut buf: Vec<u8> = Vec::new();
         loop {
@@ -205,21 +210,21 @@ impl File for Pipe {
             let loop_read = ring_buffer.available_read();
             if loop_read == 0 {
                 if ring_buffer.all_write_ends_closed() {
-                    return buf;
+                    return Ok(buf);
                 }
                 drop(ring_buffer);
                 if suspend_current_and_run_next() < 0 {
-                    return buf;
+                    return Ok(buf);
                 }
                 continue;
             }
             for _ in 0..loop_read {
                 buf.push(ring_buffer.read_byte());
             }
-            return buf;
+            return Ok(buf);
         }
     }
-    fn write_from_kspace(&self, data: &Vec<u8>) -> usize {
+    fn write_from_kspace(&self, data: &Vec<u8>) -> Result<usize, Errno> {
         assert_eq!(self.writable(), true);
         let mut data_iter = data.into_iter();
         let mut write_size = 0usize;
@@ -229,7 +234,7 @@ impl File for Pipe {
             if loop_write == 0 {
                 drop(ring_buffer);
                 if suspend_current_and_run_next() < 0 {
-                    return write_size;
+                    return Ok(write_size);
                 }
                 continue;
             }
@@ -238,7 +243,7 @@ impl File for Pipe {
                     ring_buffer.write_byte(*data_ref);
                     write_size += 1;
                 } else {
-                    return write_size;
+                    return Ok(write_size);
                 }
             }
         }
@@ -256,8 +261,9 @@ impl File for Pipe {
         let loop_write = ring_buffer.available_write();
         loop_write > 0
     }
-    fn fstat(&self, _kstat: &mut Kstat) {
+    fn fstat(&self, _kstat: &mut Kstat) -> Result<(), Errno> {
         // TODO: if needed to implement?
+        Ok(())
     }
     fn set_cloexec(&self) {
         let pipe = unsafe { (self as *const _ as *mut Self).as_mut().unwrap() };
@@ -0,0 +1,276 @@
+//! procfs 文件内容的生成, 格式参考 proc(5)
+
+use crate::consts::PAGE_SIZE;
+use crate::fs::{File, MNT_TABLE};
+use crate::mm::{frame_usage, MapPermission, MemorySet, VirtAddr, VmArea, VmAreaType, VmBacking};
+use crate::task::{TaskControlBlock, TaskStatus, PID2TCB};
+use crate::timer::{get_timeval, USEC_PER_SEC};
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use nix::time::TimeVal;
+use path::AbsolutePath;
+
+/// 用户态看到的时钟频率 (`sysconf(_SC_CLK_TCK)`)
+const USER_HZ: usize = 100;
+
+fn to_ticks(time: TimeVal) -> usize {
+    time.sec * USER_HZ + time.usec / (USEC_PER_SEC / USER_HZ)
+}
+
+/// 取得所有任务的快照, 避免在持有 PID2TCB 时获取任务的锁
+fn all_tasks() -> Vec<Arc<TaskControlBlock>> {
+    PID2TCB.lock().values().cloned().collect()
+}
+
+/// fd 链接的目标: 目录树中的文件为其路径, 其余 (管道, 标准输入输出) 为其名字
+pub fn fd_target(file: &Arc<dyn File>) -> String {
+    if file.is_under(&AbsolutePath::from_str("/")) {
+        file.path().to_string()
+    } else {
+        file.name()
+    }
+}
+
+pub fn meminfo() -> String {
+    let (total, free) = frame_usage();
+    let total_kb = total * PAGE_SIZE / 1024;
+    let free_kb = free * PAGE_SIZE / 1024;
+    let mut info = String::new();
+    for (key, kb) in [
+        ("MemTotal", total_kb),
+        ("MemFree", free_kb),
+        ("MemAvailable", free_kb),
+        ("Buffers", 0),
+        ("Cached", 0),
+        ("SwapCached", 0),
+        ("Shmem", 0),
+        ("SReclaimable", 0),
+        ("SwapTotal", 0),
+        ("SwapFree", 0),
+    ] {
+        info += &format!("{:<16}{:>8} kB\n", format!("{}:", key), kb);
+    }
+    info
+}
+
+pub fn mounts() -> String {
+    MNT_TABLE.lock().mounts_info()
+}
+
+pub fn uptime() -> String {
+    let now = get_timeval();
+    let cpu_ticks: usize = all_tasks()
+        .iter()
+        .map(|task| {
+            let inner = task.inner_ref();
+            to_ticks(inner.utime) + to_ticks(inner.stime)
+        })
+        .sum();
+    let idle_ticks = to_ticks(now).saturating_sub(cpu_ticks);
+    format!(
+        "{}.{:02} {}.{:02}\n",
+        now.sec,
+        now.usec / (USEC_PER_SEC / USER_HZ),
+        idle_ticks / USER_HZ,
+        idle_ticks % USER_HZ
+    )
+}
+
+pub fn stat() -> String {
+    let tasks = all_tasks();
+    let (mut user, mut system, mut running) = (0, 0, 0);
+    for task in tasks.iter() {
+        let inner = task.inner_ref();
+        user += to_ticks(inner.utime);
+        system += to_ticks(inner.stime);
+        if inner.task_status == TaskStatus::Running || inner.task_status == TaskStatus::Ready {
+            running += 1;
+        }
+    }
+    let idle = to_ticks(get_timeval()).saturating_sub(user + system);
+    let cpu = format!("{} 0 {} {} 0 0 0 0 0 0", user, system, idle);
+    format!(
+        "cpu  {}\ncpu0 {}\nintr 0\nctxt 0\nbtime 0\nprocesses {}\nprocs_running {}\nprocs_blocked 0\n",
+        cpu,
+        cpu,
+        tasks.len(),
+        running
+    )
+}
+
+pub fn loadavg() -> String {
+    let tasks = all_tasks();
+    let running = tasks
+        .iter()
+        .filter(|task| task.inner_ref().task_status == TaskStatus::Running)
+        .count();
+    let last_pid = tasks.iter().map(|task| task.pid()).max().unwrap_or(0);
+    format!("0.00 0.00 0.00 {}/{} {}\n", running, tasks.len(), last_pid)
+}
+
+fn state(status: TaskStatus) -> (char, &'static str) {
+    match status {
+        TaskStatus::Ready | TaskStatus::Running => ('R', "R (running)"),
+        TaskStatus::Blocking | TaskStatus::Hanging => ('S', "S (sleeping)"),
+        TaskStatus::Stopped => ('T', "T (stopped)"),
+        TaskStatus::Zombie => ('Z', "Z (zombie)"),
+    }
+}
+
+/// 进程名, 取自 `argv[0]` 的最后一项, 与 Linux 一样最多 15 个字符
+fn comm(cmdline: &[String]) -> String {
+    let name = cmdline
+        .first()
+        .map(|arg0| arg0.rsplit('/').next().unwrap_or(arg0))
+        .unwrap_or("");
+    name.chars().take(15).collect()
+}
+
+fn threads(task: &TaskControlBlock) -> usize {
+    all_tasks().iter().filter(|t| t.tgid == task.tgid).count()
+}
+
+/// 地址空间的 (总页数, 驻留页数)
+fn vm_pages(ms: &MemorySet) -> (usize, usize) {
+    let (mut total, mut resident) = (0, 0);
+    for area in ms.areas.values() {
+        total += area.page_count();
+        resident += area.frame_map.len();
+    }
+    (total, resident)
+}
+
+pub fn task_stat(task: &TaskControlBlock) -> String {
+    // 先于 inner 取得, 避免同时持有多个锁
+    let threads = threads(task);
+    let (vm_pages, rss_pages) = vm_pages(&task.memory_set().read());
+    let ppid = task.ppid();
+    let inner = task.inner_ref();
+    let (state, _) = state(inner.task_status);
+    let fields = [
+        ppid,                       // ppid
+        task.thread_group.pgid(),   // pgrp
+        task.thread_group.sid(),    // session
+        0,                          // tty_nr
+        0,                          // tpgid
+        0,                          // flags
+        0,                          // minflt
+        0,                          // cminflt
+        0,                          // majflt
+        0,                          // cmajflt
+        to_ticks(inner.utime),      // utime
+        to_ticks(inner.stime),      // stime
+        0,                          // cutime
+        0,                          // cstime
+        20,                         // priority
+        0,                          // nice
+        threads,                    // num_threads
+        0,                          // itrealvalue
+        to_ticks(inner.start_time), // starttime
+        vm_pages * PAGE_SIZE,       // vsize
+        rss_pages,                  // rss
+    ];
+    let mut stat = format!("{} ({}) {}", task.pid(), comm(&inner.cmdline), state);
+    for field in fields {
+        stat += &format!(" {}", field);
+    }
+    // rsslim 到 exit_code 的剩余字段
+    stat += &" 0".repeat(52 - 24);
+    stat.push('\n');
+    stat
+}
+
+pub fn task_status(task: &TaskControlBlock) -> String {
+    // 先于 inner 取得, 避免同时持有多个锁
+    let threads = threads(task);
+    let (vm_pages, rss_pages) = vm_pages(&task.memory_set().read());
+    let ppid = task.ppid();
+    let inner = task.inner_ref();
+    let (_, state) = state(inner.task_status);
+    let shared_pending = task.thread_group.inner().pending_signals;
+    format!(
+        "Name:\t{}\nState:\t{}\nTgid:\t{}\nPid:\t{}\nPPid:\t{}\nUid:\t0\t0\t0\t0\nGid:\t0\t0\t0\t0\nVmSize:\t{:>8} kB\nVmRSS:\t{:>8} kB\nThreads:\t{}\nSigPnd:\t{:016x}\nShdPnd:\t{:016x}\nSigBlk:\t{:016x}\n",
+        comm(&inner.cmdline),
+        state,
+        task.tgid,
+        task.pid(),
+        ppid,
+        vm_pages * PAGE_SIZE / 1024,
+        rss_pages * PAGE_SIZE / 1024,
+        threads,
+        inner.pending_signals.bits(),
+        shared_pending.bits(),
+        inner.sigmask.bits(),
+    )
+}
+
+fn map_line(start: usize, end: usize, perm: [char; 4], offset: usize, name: &str) -> String {
+    let perm: String = perm.iter().collect();
+    format!(
+        "{:08x}-{:08x} {} {:08x} 00:00 0 {}\n",
+        start, end, perm, offset, name
+    )
+}
+
+fn area_line(area: &VmArea, exe: &str) -> String {
+    let perm = [
+        if area.permission.contains(MapPermission::R) {
+            'r'
+        } else {
+            '-'
+        },
+        if area.permission.contains(MapPermission::W) {
+            'w'
+        } else {
+            '-'
+        },
+        if area.permission.contains(MapPermission::X) {
+            'x'
+        } else {
+            '-'
+        },
+        if area.shared { 's' } else { 'p' },
+    ];
+    let (name, offset) = match (&area.backing, area.area_type) {
+        (VmBacking::File { file, offset, .. }, _) => (fd_target(file), *offset),
+        (_, VmAreaType::Elf) => (String::from(exe), 0),
+        (_, VmAreaType::UserStack) => (String::from("[stack]"), 0),
+        (_, VmAreaType::UserHeap) => (String::from("[heap]"), 0),
+        (_, _) => (String::new(), 0),
+    };
+    map_line(
+        VirtAddr::from(area.start_vpn()).0,
+        VirtAddr::from(area.end_vpn()).0,
+        perm,
+        offset,
+        &name,
+    )
+}
+
+pub fn task_maps(task: &TaskControlBlock) -> String {
+    let exe = task.inner_ref().exe.to_string();
+    let ms = task.memory_set();
+    let ms = ms.read();
+    let mut maps = String::new();
+    for area in ms.areas.values() {
+        // 用户不可访问的区域 (如 Trap 上下文) 不显示
+        if area.permission.contains(MapPermission::U) {
+            maps += &area_line(area, &exe);
+        }
+    }
+    maps
+}
+
+/// 以 `\0` 分隔的启动参数
+pub fn task_cmdline(task: &TaskControlBlock) -> Vec<u8> {
+    let mut cmdline = Vec::new();
+    for arg in task.inner_ref().cmdline.iter() {
+        cmdline.extend_from_slice(arg.as_bytes());
+        cmdline.push(0);
+    }
+    cmdline
+}
@@ -0,0 +1,476 @@
+//! procfs
+//!
+//! `/proc` 下的文件不保存任何数据, 而是在读取时根据内核当前的状态生成内容:
+//! 打开文件后第一次读取时生成一份快照, 之后的读取都基于这份快照, 保证同一次打开读到的内容一致.
+//!
+//! 目录结构:
+//! - `/proc/{meminfo,mounts,uptime,stat,loadavg}`
+//! - `/proc/self`: 指向当前任务的目录
+//! - `/proc/<pid>/{stat,status,maps,cmdline,exe,fd/}`
+//! - `/proc/sys/vm/{dirty_expire_centisecs,dirty_writeback_centisecs}`: 页缓存回写的参数, 可以写入
+
+mod content;
+
+use super::{open, Dentry, File, Inode, InodeType, SuperBlock};
+use crate::mm::UserBuffer;
+use crate::syscall::impls::Errno;
+use crate::task::{current_task, pid2task, PID2TCB};
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::any::Any;
+use core::cmp::min;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use nix::{CreateMode, Dirent, Kstat, OpenFlags, NAME_LIMIT, S_IFDIR, S_IFREG};
+use path::AbsolutePath;
+use spin::Mutex;
+
+pub struct ProcFs;
+
+impl ProcFs {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl SuperBlock for ProcFs {
+    fn fs_type(&self) -> &str {
+        "proc"
+    }
+    fn root(&self) -> Arc<dyn Dentry> {
+        Arc::new(ProcEntry {
+            kind: ProcKind::Root,
+        })
+    }
+}
+
+/// `/proc/sys/vm` 下可以读写的参数
+#[cfg(all(feature = "fat32", not(feature = "no-page-cache")))]
+static VM_SYSCTLS: &[(&str, &AtomicUsize)] = &[
+    ("dirty_expire_centisecs", &super::DIRTY_EXPIRE_CENTISECS),
+    (
+        "dirty_writeback_centisecs",
+        &super::DIRTY_WRITEBACK_CENTISECS,
+    ),
+];
+#[cfg(not(all(feature = "fat32", not(feature = "no-page-cache"))))]
+static VM_SYSCTLS: &[(&str, &AtomicUsize)] = &[];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProcKind {
+    Root,
+    Meminfo,
+    Mounts,
+    Uptime,
+    Stat,
+    Loadavg,
+    SelfLink,
+    SysDir,
+    SysVmDir,
+    /// `VM_SYSCTLS` 中的第几个参数
+    VmSysctl(usize),
+    PidDir(usize),
+    PidStat(usize),
+    PidStatus(usize),
+    PidMaps(usize),
+    PidCmdline(usize),
+    PidExe(usize),
+    FdDir(usize),
+    Fd(usize, usize),
+}
+
+impl ProcKind {
+    fn is_dir(&self) -> bool {
+        matches!(
+            self,
+            ProcKind::Root
+                | ProcKind::SelfLink
+                | ProcKind::SysDir
+                | ProcKind::SysVmDir
+                | ProcKind::PidDir(_)
+                | ProcKind::FdDir(_)
+        )
+    }
+    /// `/proc/self` 在路径解析时被当作当前任务的目录
+    fn follow(self) -> Self {
+        match self {
+            ProcKind::SelfLink => ProcKind::PidDir(current_task().unwrap().pid()),
+            kind => kind,
+        }
+    }
+    fn name(&self) -> String {
+        match self {
+            ProcKind::Root => String::from("/"),
+            ProcKind::Meminfo => String::from("meminfo"),
+            ProcKind::Mounts => String::from("mounts"),
+            ProcKind::Uptime => String::from("uptime"),
+            ProcKind::Stat | ProcKind::PidStat(_) => String::from("stat"),
+            ProcKind::Loadavg => String::from("loadavg"),
+            ProcKind::SelfLink => String::from("self"),
+            ProcKind::SysDir => String::from("sys"),
+            ProcKind::SysVmDir => String::from("vm"),
+            ProcKind::VmSysctl(idx) => String::from(VM_SYSCTLS[*idx].0),
+            ProcKind::PidDir(pid) => pid.to_string(),
+            ProcKind::PidStatus(_) => String::from("status"),
+            ProcKind::PidMaps(_) => String::from("maps"),
+            ProcKind::PidCmdline(_) => String::from("cmdline"),
+            ProcKind::PidExe(_) => String::from("exe"),
+            ProcKind::FdDir(_) => String::from("fd"),
+            ProcKind::Fd(_, fd) => fd.to_string(),
+        }
+    }
+    /// 节点的 inode 号, 同一个节点总是相同, 不同节点互不相同.
+    /// 与进程有关的节点高 32 位为 pid + 1, 低 32 位区分节点种类和 fd
+    fn ino(&self) -> u64 {
+        let (pid, idx) = match *self {
+            ProcKind::Root => return 1,
+            ProcKind::Meminfo => return 2,
+            ProcKind::Mounts => return 3,
+            ProcKind::Uptime => return 4,
+            ProcKind::Stat => return 5,
+            ProcKind::Loadavg => return 6,
+            ProcKind::SelfLink => return 7,
+            ProcKind::SysDir => return 8,
+            ProcKind::SysVmDir => return 9,
+            ProcKind::VmSysctl(idx) => return 16 + idx as u64,
+            ProcKind::PidDir(pid) => (pid, 0),
+            ProcKind::PidStat(pid) => (pid, 1),
+            ProcKind::PidStatus(pid) => (pid, 2),
+            ProcKind::PidMaps(pid) => (pid, 3),
+            ProcKind::PidCmdline(pid) => (pid, 4),
+            ProcKind::PidExe(pid) => (pid, 5),
+            ProcKind::FdDir(pid) => (pid, 6),
+            ProcKind::Fd(pid, fd) => (pid, 16 + fd),
+        };
+        ((pid as u64 + 1) << 32) | idx as u64
+    }
+    /// 目录下的所有子节点
+    fn children(&self) -> Vec<ProcKind> {
+        match self.follow() {
+            ProcKind::Root => {
+                let mut children = Vec::from([
+                    ProcKind::Meminfo,
+                    ProcKind::Mounts,
+                    ProcKind::Uptime,
+                    ProcKind::Stat,
+                    ProcKind::Loadavg,
+                    ProcKind::SelfLink,
+                    ProcKind::SysDir,
+                ]);
+                // 与 Linux 一致, 只列出进程, 不列出线程
+                PID2TCB
+                    .lock()
+                    .values()
+                    .filter(|task| !task.is_child_thread())
+                    .for_each(|task| children.push(ProcKind::PidDir(task.pid())));
+                children
+            }
+            ProcKind::SysDir => Vec::from([ProcKind::SysVmDir]),
+            ProcKind::SysVmDir => (0..VM_SYSCTLS.len()).map(ProcKind::VmSysctl).collect(),
+            ProcKind::PidDir(pid) => Vec::from([
+                ProcKind::PidStat(pid),
+                ProcKind::PidStatus(pid),
+                ProcKind::PidMaps(pid),
+                ProcKind::PidCmdline(pid),
+                ProcKind::PidExe(pid),
+                ProcKind::FdDir(pid),
+            ]),
+            ProcKind::FdDir(pid) => match pid2task(pid) {
+                Some(task) => task
+                    .fd_table
+                    .read()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, file)| file.is_some())
+                    .map(|(fd, _)| ProcKind::Fd(pid, fd))
+                    .collect(),
+                None => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+    fn lookup(&self, name: &str) -> Option<ProcKind> {
+        let dir = self.follow();
+        if let (ProcKind::Root, Ok(pid)) = (dir, name.parse::<usize>()) {
+            // 线程虽然不在 `/proc` 中列出, 但仍然可以通过 tid 访问
+            return pid2task(pid).map(|_| ProcKind::PidDir(pid));
+        }
+        dir.children().into_iter().find(|kind| kind.name() == name)
+    }
+    fn readlink(&self) -> Result<String, Errno> {
+        match self {
+            ProcKind::SelfLink => Ok(current_task().unwrap().pid().to_string()),
+            ProcKind::PidExe(pid) => {
+                let task = pid2task(*pid).ok_or(Errno::ENOENT)?;
+                let exe = task.inner_ref().exe.to_string();
+                Ok(exe)
+            }
+            ProcKind::Fd(pid, fd) => Ok(content::fd_target(&self.fd_file(*pid, *fd)?)),
+            _ => Err(Errno::EINVAL),
+        }
+    }
+    fn fd_file(&self, pid: usize, fd: usize) -> Result<Arc<dyn File>, Errno> {
+        let task = pid2task(pid).ok_or(Errno::ENOENT)?;
+        let fd_table = task.fd_table.read();
+        fd_table.get(fd).cloned().flatten().ok_or(Errno::ENOENT)
+    }
+    /// 生成文件内容
+    fn generate(&self) -> Vec<u8> {
+        match *self {
+            ProcKind::Meminfo => content::meminfo().into_bytes(),
+            ProcKind::Mounts => content::mounts().into_bytes(),
+            ProcKind::Uptime => content::uptime().into_bytes(),
+            ProcKind::Stat => content::stat().into_bytes(),
+            ProcKind::Loadavg => content::loadavg().into_bytes(),
+            ProcKind::VmSysctl(idx) => {
+                let value = VM_SYSCTLS[idx].1.load(Ordering::Relaxed);
+                format!("{}\n", value).into_bytes()
+            }
+            ProcKind::PidStat(pid) => pid2task(pid)
+                .map(|task| content::task_stat(&task).into_bytes())
+                .unwrap_or_default(),
+            ProcKind::PidStatus(pid) => pid2task(pid)
+                .map(|task| content::task_status(&task).into_bytes())
+                .unwrap_or_default(),
+            ProcKind::PidMaps(pid) => pid2task(pid)
+                .map(|task| content::task_maps(&task).into_bytes())
+                .unwrap_or_default(),
+            ProcKind::PidCmdline(pid) => pid2task(pid)
+                .map(|task| content::task_cmdline(&task))
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// procfs 的目录项, 同时也是 inode
+pub struct ProcEntry {
+    kind: ProcKind,
+}
+
+impl Dentry for ProcEntry {
+    fn name(&self) -> String {
+        self.kind.name()
+    }
+    fn is_dir(&self) -> bool {
+        self.kind.is_dir()
+    }
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Dentry>> {
+        match name {
+            "" | "." => Some(Arc::new(ProcEntry { kind: self.kind })),
+            _ => self
+                .kind
+                .lookup(name)
+                .map(|kind| Arc::new(ProcEntry { kind }) as Arc<dyn Dentry>),
+        }
+    }
+    fn create(&self, _name: &str, _itype: InodeType) -> Result<Arc<dyn Dentry>, Errno> {
+        Err(Errno::EACCES)
+    }
+    fn is_symlink(&self) -> bool {
+        // fd 链接可能指向管道等不在目录树中的文件, 打开时单独处理, 不参与路径解析
+        matches!(self.kind, ProcKind::SelfLink | ProcKind::PidExe(_))
+    }
+    fn readlink(&self) -> Result<String, Errno> {
+        self.kind.readlink()
+    }
+    fn inode(self: Arc<Self>) -> Arc<dyn Inode> {
+        self
+    }
+    fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+impl Inode for ProcEntry {
+    fn open(self: Arc<Self>, path: AbsolutePath, flags: OpenFlags) -> Result<Arc<dyn File>, Errno> {
+        if flags.intersects(OpenFlags::O_WRONLY | OpenFlags::O_RDWR | OpenFlags::O_TRUNC)
+            && !matches!(self.kind, ProcKind::VmSysctl(_))
+        {
+            return Err(Errno::EACCES);
+        }
+        let kind = match self.kind {
+            ProcKind::PidExe(_) => {
+                let exe = AbsolutePath::from_string(self.kind.readlink()?);
+                return open(exe, flags, CreateMode::empty());
+            }
+            // 打开 fd 链接得到的是对应的文件本身, 而不是链接
+            ProcKind::Fd(pid, fd) => {
+                let file = self.kind.fd_file(pid, fd)?;
+                if file.is_under(&AbsolutePath::from_str("/")) {
+                    return open(file.path(), flags, CreateMode::empty());
+                }
+                return Ok(file);
+            }
+            kind => kind.follow(),
+        };
+        Ok(Arc::new(ProcFile {
+            kind,
+            path,
+            flags: Mutex::new(flags),
+            offset: Mutex::new(0),
+            content: Mutex::new(None),
+        }))
+    }
+}
+
+/// 打开的 procfs 文件
+pub struct ProcFile {
+    kind: ProcKind,
+    path: AbsolutePath,
+    flags: Mutex<OpenFlags>,
+    /// 对普通文件是读写位置, 对目录是下一个目录项的下标
+    offset: Mutex<usize>,
+    /// 第一次读取时生成的内容快照
+    content: Mutex<Option<Vec<u8>>>,
+}
+
+impl ProcFile {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let mut content = self.content.lock();
+        let content = content.get_or_insert_with(|| self.kind.generate());
+        if offset >= content.len() {
+            return 0;
+        }
+        let len = min(buf.len(), content.len() - offset);
+        buf[..len].copy_from_slice(&content[offset..offset + len]);
+        len
+    }
+    /// 写入 `/proc/sys/vm` 下的参数, 内容为一个十进制整数
+    fn write_sysctl(&self, buf: UserBuffer) -> Result<usize, Errno> {
+        let idx = match self.kind {
+            ProcKind::VmSysctl(idx) => idx,
+            _ => return Ok(0),
+        };
+        let mut data = Vec::new();
+        for sub_buf in buf.buffers.iter() {
+            data.extend_from_slice(sub_buf);
+        }
+        let value = core::str::from_utf8(&data)
+            .ok()
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .ok_or(Errno::EINVAL)?;
+        VM_SYSCTLS[idx].1.store(value, Ordering::Relaxed);
+        Ok(data.len())
+    }
+}
+
+impl File for ProcFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        matches!(self.kind, ProcKind::VmSysctl(_))
+    }
+    fn available(&self) -> bool {
+        true
+    }
+    fn read_to_ubuf(&self, buf: UserBuffer) -> Result<usize, Errno> {
+        let offset = self.offset();
+        let read_size = self.pread(buf, offset)?;
+        self.seek(offset + read_size);
+        Ok(read_size)
+    }
+    fn write_from_ubuf(&self, buf: UserBuffer) -> Result<usize, Errno> {
+        self.write_sysctl(buf)
+    }
+    fn pwrite(&self, buf: UserBuffer, _offset: usize) -> Result<usize, Errno> {
+        self.write_sysctl(buf)
+    }
+    fn pread(&self, mut buf: UserBuffer, offset: usize) -> Result<usize, Errno> {
+        let mut total_read_size = 0;
+        for sub_buf in buf.buffers.iter_mut() {
+            let read_size = self.read_at(offset + total_read_size, sub_buf);
+            total_read_size += read_size;
+            if read_size < sub_buf.len() {
+                break;
+            }
+        }
+        Ok(total_read_size)
+    }
+    fn kernel_read_with_offset(&self, offset: usize, len: usize) -> Result<Vec<u8>, Errno> {
+        let mut buf = vec![0; len];
+        let read_size = self.read_at(offset, &mut buf);
+        buf.truncate(read_size);
+        Ok(buf)
+    }
+    fn read_to_kspace(&self) -> Result<Vec<u8>, Errno> {
+        let offset = self.offset();
+        let mut content = self.content.lock();
+        let content = content.get_or_insert_with(|| self.kind.generate());
+        let buf = content[min(offset, content.len())..].to_vec();
+        self.seek(offset + buf.len());
+        Ok(buf)
+    }
+    fn write_from_kspace(&self, _data: &Vec<u8>) -> Result<usize, Errno> {
+        Ok(0)
+    }
+    fn seek(&self, pos: usize) {
+        *self.offset.lock() = pos;
+    }
+    fn offset(&self) -> usize {
+        *self.offset.lock()
+    }
+    fn name(&self) -> String {
+        self.kind.name()
+    }
+    fn fstat(&self, kstat: &mut Kstat) -> Result<(), Errno> {
+        kstat.st_dev = 0;
+        kstat.st_ino = self.kind.ino();
+        kstat.st_mode = match self.kind {
+            kind if kind.is_dir() => S_IFDIR | 0o555,
+            ProcKind::VmSysctl(_) => S_IFREG | 0o644,
+            _ => S_IFREG | 0o444,
+        };
+        kstat.st_nlink = 1;
+        // 与 Linux 一致, procfs 中的文件大小为 0
+        kstat.st_size = 0;
+        kstat.st_blksize = 512;
+        kstat.st_blocks = 0;
+        Ok(())
+    }
+    fn dirent(&self, dirent: &mut Dirent) -> isize {
+        let children = self.kind.children();
+        let idx = self.offset();
+        if idx >= children.len() {
+            return 0;
+        }
+        let filename = children[idx].name();
+        let file_bytes = filename.as_bytes();
+        dirent.d_ino = children[idx].ino() as usize;
+        dirent.d_off =
+            size_of::<Dirent>() as isize - NAME_LIMIT as isize + file_bytes.len() as isize;
+        dirent.d_reclen = size_of::<Dirent>() as u16;
+        dirent.d_type = 0; // 0 d_type is file
+        dirent.d_name[..file_bytes.len()].copy_from_slice(file_bytes);
+        dirent.d_name[file_bytes.len()] = b'\0';
+        self.seek(idx + 1);
+        size_of::<Dirent>() as isize
+    }
+    fn file_size(&self) -> usize {
+        let mut content = self.content.lock();
+        content.get_or_insert_with(|| self.kind.generate()).len()
+    }
+    fn set_flags(&self, flag: OpenFlags) {
+        *self.flags.lock() = flag;
+    }
+    fn flags(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+    fn set_cloexec(&self) {
+        *self.flags.lock() |= OpenFlags::O_CLOEXEC;
+    }
+    fn path(&self) -> AbsolutePath {
+        self.path.clone()
+    }
+    fn is_under(&self, dir: &AbsolutePath) -> bool {
+        self.path.start_with(dir)
+    }
+    fn is_dir(&self) -> bool {
+        self.kind.is_dir()
+    }
+}
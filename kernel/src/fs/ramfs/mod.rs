@@ -1,6 +1,11 @@
+mod pages;
+
+pub use pages::*;
+
 use core::{
-    cmp::{self, min},
+    any::Any,
     mem::size_of,
+    sync::atomic::{AtomicUsize, Ordering},
     task::RawWaker,
 };
 
@@ -13,32 +18,72 @@ use alloc::{
 use bitflags::Flag;
 use nix::{
     CreateMode, Dirent, InodeTime, Kstat, OpenFlags, SeekFlags, StatMode, Statfs, TimeSpec,
-    NAME_LIMIT, S_IFCHR, S_IFDIR, S_IFREG, UTIME_OMIT,
+    NAME_LIMIT, S_IFDIR, S_IFLNK, S_IFREG, UTIME_OMIT,
 };
 use path::AbsolutePath;
 use spin::{Mutex, RwLock};
 
-use crate::{mm::UserBuffer, syscall::impls::Errno};
+use crate::{
+    consts::PAGE_SIZE,
+    mm::{FrameTracker, UserBuffer},
+    syscall::impls::Errno,
+};
+
+use super::{ino_alloc, lookup, File, InodeType};
 
-use super::{file, find_parent_dir, open, File};
+/// tmpfs 的 magic number, 见 statfs(2)
+const TMPFS_MAGIC: u64 = 0x01021994;
 
 pub struct RamFs {
     root: Arc<RamDirInner>,
+    usage: Arc<RamFsUsage>,
 }
 
 impl RamFs {
     pub fn new() -> Self {
+        Self::with_size_limit(None)
+    }
+    /// `limit` 为文件系统的容量, 以字节为单位, 向上取整到页
+    pub fn with_size_limit(limit: Option<usize>) -> Self {
+        let usage = Arc::new(RamFsUsage::new(
+            limit.map(|limit| (limit + PAGE_SIZE - 1) / PAGE_SIZE),
+        ));
         let inner = Arc::new(RamDirInner {
+            ino: ino_alloc(),
             name: Mutex::new(String::from("/")),
             flags: Mutex::new(OpenFlags::O_DIRECTORY),
             rw: Mutex::new(RWablity::ReadWrite),
             children: Mutex::new(Vec::new()),
             dir_path: Mutex::new(AbsolutePath::from_str("/")),
+            usage: usage.clone(),
         });
-        Self { root: inner }
+        Self { root: inner, usage }
     }
 }
 
+/// 解析挂载参数中的 `size=`, 支持 k/m/g 后缀与物理内存的百分比, 没有该参数时返回 None
+pub fn parse_size_option(data: &str) -> Result<Option<usize>, Errno> {
+    let size = match data
+        .split(',')
+        .find_map(|option| option.strip_prefix("size="))
+    {
+        Some(size) => size,
+        None => return Ok(None),
+    };
+    let (number, unit) = match size.as_bytes().last() {
+        Some(b'k' | b'K') => (&size[..size.len() - 1], 1 << 10),
+        Some(b'm' | b'M') => (&size[..size.len() - 1], 1 << 20),
+        Some(b'g' | b'G') => (&size[..size.len() - 1], 1 << 30),
+        Some(b'%') => (
+            &size[..size.len() - 1],
+            crate::mm::frame_usage().0 * PAGE_SIZE / 100,
+        ),
+        _ => (size, 1),
+    };
+    let number: usize = number.parse().map_err(|_| Errno::EINVAL)?;
+    number.checked_mul(unit).map(Some).ok_or(Errno::EINVAL)
+}
+
 impl RamFs {
     pub fn root_dir(&self, mi: MountedInfo) -> Arc<RamDir> {
         Arc::new(RamDir {
@@ -53,12 +98,45 @@ impl RamFs {
     }
 }
 
+impl super::SuperBlock for RamFs {
+    fn fs_type(&self) -> &str {
+        self.name()
+    }
+    fn root(&self) -> Arc<dyn super::Dentry> {
+        self.root_dir(MountedInfo {
+            fs_id: 0,
+            path: AbsolutePath::from_str("/"),
+        })
+    }
+    fn statfs(&self, statfs: &mut Statfs) {
+        let free = self.usage.limit().saturating_sub(self.usage.used());
+        statfs.f_type = TMPFS_MAGIC;
+        statfs.f_bsize = PAGE_SIZE as u64;
+        statfs.f_frsize = PAGE_SIZE as u64;
+        statfs.f_blocks = self.usage.limit() as u64;
+        statfs.f_bfree = free as u64;
+        statfs.f_bavail = free as u64;
+        statfs.f_namelen = NAME_LIMIT as u64;
+    }
+}
+
+/// 找到 `path` 的父目录, 父目录必须同样位于 ramfs 中
+fn find_parent_dir(path: &AbsolutePath) -> Option<Arc<RamDir>> {
+    lookup(&path.parent())
+        .ok()?
+        .as_any()
+        .downcast::<RamDir>()
+        .ok()
+}
+
 pub struct RamDirInner {
+    ino: u64,
     name: Mutex<String>,
     rw: Mutex<RWablity>,
     flags: Mutex<OpenFlags>,
     children: Mutex<Vec<FileContainer>>,
     dir_path: Mutex<AbsolutePath>,
+    usage: Arc<RamFsUsage>,
 }
 
 impl RamDirInner {
@@ -97,30 +175,20 @@ pub enum RWablity {
     ReadWrite,
 }
 
-// TODO: use frame insteads of Vec.
+/// 普通文件的 inode, 文件名保存在目录项 (`FileContainer`) 中,
+/// 硬链接的多个目录项共享同一个 `RamFileInner`
 pub struct RamFileInner {
-    name: Mutex<String>,
+    ino: u64,
+    /// 指向该 inode 的目录项个数
+    nlink: AtomicUsize,
     rw: Mutex<RWablity>,
-    content: Mutex<Vec<u8>>,
+    content: Mutex<FilePages>,
     flags: Mutex<OpenFlags>,
-    dir_path: Mutex<AbsolutePath>,
     // times: Mutex<[TimeSpec; 3]>, // ctime, atime, mtime.
     times: Mutex<InodeTime>,
 }
 
 impl RamFileInner {
-    fn name(&self) -> String {
-        self.name.lock().clone()
-    }
-    fn rename(&self, new_name: String) {
-        *self.name.lock() = new_name;
-    }
-    fn set_path(&self, path: AbsolutePath) {
-        *self.dir_path.lock() = path;
-    }
-    fn get_path(&self) -> AbsolutePath {
-        self.dir_path.lock().clone()
-    }
     fn rw(&self) -> RWablity {
         let flags = *self.flags.lock();
         let rw = if flags.contains(OpenFlags::O_RDONLY) {
@@ -135,16 +203,27 @@ impl RamFileInner {
     }
 }
 
+/// 符号链接节点, 链接的目标直接保存在节点中
+pub struct RamLinkInner {
+    ino: u64,
+    nlink: AtomicUsize,
+    target: String,
+}
+
+/// 目录中的一项. 普通文件与符号链接的名字保存在这里而不是 inode 中,
+/// 这样硬链接才能以不同的名字指向同一个 inode
 pub enum FileContainer {
-    File(Arc<RamFileInner>),
+    File(String, Arc<RamFileInner>),
     Dir(Arc<RamDirInner>),
+    Link(String, Arc<RamLinkInner>),
 }
 
 impl Clone for FileContainer {
     fn clone(&self) -> Self {
         match self {
-            FileContainer::File(file) => FileContainer::File(file.clone()),
+            FileContainer::File(name, file) => FileContainer::File(name.clone(), file.clone()),
             FileContainer::Dir(dir) => FileContainer::Dir(dir.clone()),
+            FileContainer::Link(name, link) => FileContainer::Link(name.clone(), link.clone()),
         }
     }
 }
@@ -153,8 +232,24 @@ impl FileContainer {
     #[inline]
     pub fn filename(&self) -> String {
         match self {
-            FileContainer::File(file) => file.name(),
+            FileContainer::File(name, _) => name.clone(),
             FileContainer::Dir(dir) => dir.name(),
+            FileContainer::Link(name, _) => name.clone(),
+        }
+    }
+    pub fn ino(&self) -> u64 {
+        match self {
+            FileContainer::File(_, file) => file.ino,
+            FileContainer::Dir(dir) => dir.ino,
+            FileContainer::Link(_, link) => link.ino,
+        }
+    }
+    /// 以新的名字指向同一个 inode, 用于 rename 与 link
+    fn with_name(&self, name: String) -> Self {
+        match self {
+            FileContainer::File(_, file) => FileContainer::File(name, file.clone()),
+            FileContainer::Dir(dir) => FileContainer::Dir(dir.clone()),
+            FileContainer::Link(_, link) => FileContainer::Link(name, link.clone()),
         }
     }
     pub fn to_dir(&self, mi: MountedInfo) -> Arc<RamDir> {
@@ -169,7 +264,7 @@ impl FileContainer {
     }
     pub fn to_file(&self, mi: MountedInfo) -> Arc<RamFile> {
         match self {
-            FileContainer::File(file) => Arc::new(RamFile {
+            FileContainer::File(_, file) => Arc::new(RamFile {
                 inner: file.clone(),
                 offset: Mutex::new(0),
                 mi,
@@ -178,9 +273,19 @@ impl FileContainer {
         }
     }
     #[inline]
+    pub fn to_dentry(&self, mi: MountedInfo) -> Arc<dyn super::Dentry> {
+        match self {
+            FileContainer::File(..) => self.to_file(mi) as Arc<dyn super::Dentry>,
+            FileContainer::Dir(_) => self.to_dir(mi) as Arc<dyn super::Dentry>,
+            FileContainer::Link(_, link) => {
+                RamLink::new(link.clone(), mi) as Arc<dyn super::Dentry>
+            }
+        }
+    }
+    #[inline]
     pub fn to_inode(&self, mi: MountedInfo) -> Arc<dyn File> {
         match self {
-            FileContainer::File(file) => Arc::new(RamFile {
+            FileContainer::File(_, file) => Arc::new(RamFile {
                 inner: file.clone(),
                 offset: Mutex::new(0),
                 mi,
@@ -190,6 +295,7 @@ impl FileContainer {
                 dents_off: Mutex::new(0),
                 mi,
             }),
+            FileContainer::Link(_, link) => RamLink::new(link.clone(), mi),
         }
     }
 }
@@ -237,7 +343,7 @@ impl File for RamDir {
         self.inner.name()
     }
     fn fid(&self) -> u64 {
-        self.mi.fs_id as u64
+        self.inner.ino
     }
     fn file_size(&self) -> usize {
         unimplemented!()
@@ -248,14 +354,17 @@ impl File for RamDir {
     fn set_flags(&self, flag: OpenFlags) {
         *self.inner.flags.lock() = flag;
     }
-    fn fstat(&self, _stat: &mut Kstat) {
-        self.stat(_stat).unwrap();
+    fn fstat(&self, _stat: &mut Kstat) -> Result<(), Errno> {
+        self.stat(_stat).map_err(|_| Errno::EIO)
     }
     fn offset(&self) -> usize {
         *self.dents_off.lock()
     }
     fn path(&self) -> AbsolutePath {
-        self.inner.dir_path.lock().clone()
+        self.mi.path.clone()
+    }
+    fn is_under(&self, dir: &AbsolutePath) -> bool {
+        self.path().start_with(dir)
     }
     fn set_cloexec(&self) {
         let mut flags = self.inner.flags.lock();
@@ -267,28 +376,28 @@ impl File for RamDir {
     fn time(&self) -> InodeTime {
         unimplemented!()
     }
-    fn kernel_read_with_offset(&self, _offset: usize, _len: usize) -> Vec<u8> {
+    fn kernel_read_with_offset(&self, _offset: usize, _len: usize) -> Result<Vec<u8>, Errno> {
         unimplemented!()
     }
     fn read_at_direct(&self, _offset: usize, _len: usize) -> Vec<u8> {
         unimplemented!()
     }
-    fn pread(&self, _buf: UserBuffer, _offset: usize) -> usize {
+    fn pread(&self, _buf: UserBuffer, _offset: usize) -> Result<usize, Errno> {
         unimplemented!()
     }
-    fn pwrite(&self, _buf: UserBuffer, _offset: usize) -> usize {
+    fn pwrite(&self, _buf: UserBuffer, _offset: usize) -> Result<usize, Errno> {
         unimplemented!()
     }
-    fn read_to_kspace(&self) -> Vec<u8> {
+    fn read_to_kspace(&self) -> Result<Vec<u8>, Errno> {
         unimplemented!()
     }
     fn write_from_direct(&self, _offset: usize, _data: &Vec<u8>) -> usize {
         unimplemented!()
     }
-    fn write_from_kspace(&self, _data: &Vec<u8>) -> usize {
+    fn write_from_kspace(&self, _data: &Vec<u8>) -> Result<usize, Errno> {
         unimplemented!()
     }
-    fn write_from_ubuf(&self, buf: UserBuffer) -> usize {
+    fn write_from_ubuf(&self, buf: UserBuffer) -> Result<usize, Errno> {
         unimplemented!()
     }
     fn w_ready(&self) -> bool {
@@ -297,10 +406,10 @@ impl File for RamDir {
     fn r_ready(&self) -> bool {
         unimplemented!()
     }
-    fn read_to_ubuf(&self, buf: UserBuffer) -> usize {
+    fn read_to_ubuf(&self, buf: UserBuffer) -> Result<usize, Errno> {
         unimplemented!()
     }
-    fn truncate(&self, _new_length: usize) {
+    fn truncate(&self, _new_length: usize) -> Result<(), Errno> {
         unimplemented!()
     }
     fn readable(&self) -> bool {
@@ -311,22 +420,29 @@ impl File for RamDir {
         let rw = *self.inner.rw.lock();
         rw == RWablity::WriteOnly || rw == RWablity::ReadWrite
     }
-    fn delete(&self) -> usize {
-        let path = self.path();
-        let parent = find_parent_dir(path.clone()).unwrap();
-        parent.remove(self.name()).unwrap();
-        0
+    fn delete(&self) -> Result<(), Errno> {
+        let parent = find_parent_dir(&self.path()).ok_or(Errno::ENOENT)?;
+        parent.remove(self.name()).map_err(|_| Errno::ENOENT)
     }
-    fn rename(&self, new_path: AbsolutePath, _flag: OpenFlags) {
-        let new_parent = find_parent_dir(new_path.clone()).unwrap();
-        let old_parent = find_parent_dir(self.path()).unwrap();
+    fn rename(&self, new_path: AbsolutePath, _flag: OpenFlags) -> Result<(), Errno> {
+        if new_path == self.path() {
+            return Ok(());
+        }
+        // 不能把目录移动到它自己之下, 否则整棵子树会脱离文件系统
+        if new_path.start_with(&self.path()) {
+            return Err(Errno::EINVAL);
+        }
+        let new_parent = find_parent_dir(&new_path).ok_or(Errno::ENOENT)?;
+        let old_parent = find_parent_dir(&self.path()).ok_or(Errno::ENOENT)?;
         let inner = self.inner.clone();
         let old_name = self.name();
         let new_name = new_path.last();
-        old_parent.remove(old_name).unwrap();
+        new_parent.remove_target(&new_name, true)?;
+        old_parent.detach(&old_name).ok_or(Errno::ENOENT)?;
         inner.set_path(new_path);
         inner.rename(new_name);
         new_parent.add_from_container(FileContainer::Dir(inner));
+        Ok(())
     }
 }
 
@@ -351,8 +467,7 @@ impl RamDir {
         self.inner.children.lock().push(container);
     }
 
-    // pub fn touch(&self, name: &str, flags: OpenFlags) -> VfsResult<Arc<dyn File>> {
-    pub fn touch(&self, name: &str, flags: OpenFlags) -> Result<Arc<dyn File>, Errno> {
+    pub fn touch(&self, name: &str, flags: OpenFlags) -> Result<FileContainer, Errno> {
         // Find file, return VfsError::AlreadyExists if file exists
         self.inner
             .children
@@ -370,34 +485,20 @@ impl RamDir {
         };
 
         let new_inner = Arc::new(RamFileInner {
-            name: Mutex::new(String::from(name)),
+            ino: ino_alloc(),
+            nlink: AtomicUsize::new(1),
             rw: Mutex::new(rw),
             flags: Mutex::new(flags),
-            content: Mutex::new(Vec::new()),
-            dir_path: {
-                let path = self.inner.get_path().to_string();
-                let new_path = format!("{}/{}", path, self.inner.name());
-                Mutex::new(AbsolutePath::from_string(new_path))
-            },
+            content: Mutex::new(FilePages::new(self.inner.usage.clone())),
             times: Mutex::new(InodeTime::empty()),
         });
 
-        let new_file = Arc::new(RamFile {
-            inner: new_inner.clone(),
-            offset: Mutex::new(0),
-            mi: self.mi.clone(),
-        });
-
-        self.inner
-            .children
-            .lock()
-            .push(FileContainer::File(new_inner));
-
+        let new_file = FileContainer::File(String::from(name), new_inner);
+        self.inner.children.lock().push(new_file.clone());
         Ok(new_file)
     }
 
-    // pub fn mkdir(&self, name: &str, flags: OpenFlags) -> VfsResult<Arc<dyn File>> {
-    pub fn mkdir(&self, name: &str, flags: OpenFlags) -> Result<Arc<dyn File>, Errno> {
+    pub fn mkdir(&self, name: &str, flags: OpenFlags) -> Result<FileContainer, Errno> {
         // Find file, return VfsError::AlreadyExists if file exists
         self.inner
             .children
@@ -415,10 +516,12 @@ impl RamDir {
             RWablity::ReadWrite
         };
         let new_inner = Arc::new(RamDirInner {
+            ino: ino_alloc(),
             name: Mutex::new(String::from(name)),
             flags: Mutex::new(flags),
             rw: Mutex::new(rw),
             children: Mutex::new(Vec::new()),
+            usage: self.inner.usage.clone(),
             dir_path: {
                 let path = self.inner.get_path().to_string();
                 let new_path = format!("{}/{}", path, self.inner.name());
@@ -426,18 +529,45 @@ impl RamDir {
             },
         });
 
-        let new_dir = Arc::new(RamDir {
-            inner: new_inner.clone(),
-            dents_off: Mutex::new(0),
-            mi: self.mi.clone(),
-        });
+        let new_dir = FileContainer::Dir(new_inner);
+        self.inner.children.lock().push(new_dir.clone());
+        Ok(new_dir)
+    }
 
+    pub fn symlink(&self, name: &str, target: &str) -> Result<FileContainer, Errno> {
         self.inner
             .children
             .lock()
-            .push(FileContainer::Dir(new_inner));
+            .iter()
+            .find(|x| x.filename() == name)
+            .map_or(Ok(()), |_| Err(Errno::EEXIST))?;
 
-        Ok(new_dir)
+        let new_link = FileContainer::Link(
+            String::from(name),
+            Arc::new(RamLinkInner {
+                ino: ino_alloc(),
+                nlink: AtomicUsize::new(1),
+                target: String::from(target),
+            }),
+        );
+        self.inner.children.lock().push(new_link.clone());
+        Ok(new_link)
+    }
+
+    /// 创建指向 `target` 的 inode 的硬链接 `name`, 目录不能有硬链接
+    pub fn link(&self, name: &str, target: &FileContainer) -> Result<FileContainer, Errno> {
+        let mut children = self.inner.children.lock();
+        if children.iter().any(|x| x.filename() == name) {
+            return Err(Errno::EEXIST);
+        }
+        match target {
+            FileContainer::File(_, file) => file.nlink.fetch_add(1, Ordering::Relaxed),
+            FileContainer::Link(_, link) => link.nlink.fetch_add(1, Ordering::Relaxed),
+            FileContainer::Dir(_) => return Err(Errno::EPERM),
+        };
+        let new_entry = target.with_name(String::from(name));
+        children.push(new_entry.clone());
+        Ok(new_entry)
     }
 
     pub fn rmdir(&self, name: &str) -> VfsResult<()> {
@@ -465,8 +595,8 @@ impl RamDir {
             .lock()
             .iter()
             .map(|x| match x {
-                FileContainer::File(file) => DirEntry {
-                    filename: file.name().to_string(),
+                FileContainer::File(name, file) => DirEntry {
+                    filename: name.clone(),
                     len: file.content.lock().len(),
                     file_type: FileType::File,
                 },
@@ -475,24 +605,53 @@ impl RamDir {
                     len: 0,
                     file_type: FileType::Directory,
                 },
+                FileContainer::Link(name, link) => DirEntry {
+                    filename: name.clone(),
+                    len: link.target.len(),
+                    file_type: FileType::SymLink,
+                },
             })
             .collect())
     }
 
+    /// 删除目录项 `name`, 其 inode 的链接数减一; 最后一个目录项被删除后,
+    /// inode 在最后一个打开它的文件关闭时随 `Arc` 一起释放
     pub fn remove(&self, name: String) -> VfsResult<()> {
-        let len = self
-            .inner
-            .children
-            .lock()
-            .drain_filter(|x| match x {
-                FileContainer::File(x) => x.name() == name,
-                FileContainer::Dir(x) => x.name() == name,
-            })
-            .count();
-        match len > 0 {
-            true => Ok(()),
-            false => Err(VfsError::FileNotFound),
+        let entry = self.detach(&name).ok_or(VfsError::FileNotFound)?;
+        match entry {
+            FileContainer::File(_, file) => file.nlink.fetch_sub(1, Ordering::Relaxed),
+            FileContainer::Link(_, link) => link.nlink.fetch_sub(1, Ordering::Relaxed),
+            FileContainer::Dir(_) => 0,
+        };
+        Ok(())
+    }
+
+    /// rename 的目标 `name` 已经存在时先删除它; 目录只能替换空目录, 其他文件不能替换目录
+    fn remove_target(&self, name: &str, is_dir: bool) -> Result<(), Errno> {
+        let children = self.inner.children.lock();
+        let target = match children.iter().find(|x| x.filename() == name) {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+        match target {
+            FileContainer::Dir(_) if !is_dir => return Err(Errno::EISDIR),
+            FileContainer::Dir(dir) if !dir.children.lock().is_empty() => {
+                return Err(Errno::ENOTEMPTY)
+            }
+            FileContainer::File(..) | FileContainer::Link(..) if is_dir => {
+                return Err(Errno::ENOTDIR)
+            }
+            _ => {}
         }
+        drop(children);
+        self.remove(String::from(name)).map_err(|_| Errno::ENOENT)
+    }
+
+    /// 从目录中取出目录项 `name`, 不改变链接数, 供 rename 使用
+    fn detach(&self, name: &str) -> Option<FileContainer> {
+        let mut children = self.inner.children.lock();
+        let idx = children.iter().position(|x| x.filename() == name)?;
+        Some(children.remove(idx))
     }
 
     pub fn metadata(&self) -> VfsResult<Metadata> {
@@ -507,9 +666,16 @@ impl RamDir {
 
     pub fn stat(&self, stat: &mut Kstat) -> VfsResult<()> {
         stat.st_dev = self.mi.fs_id as u64;
-        stat.st_ino = 1;
+        stat.st_ino = self.inner.ino;
         stat.st_mode = S_IFDIR;
-        stat.st_nlink = 1;
+        // "." 与父目录中的目录项, 以及每个子目录中的 ".."
+        stat.st_nlink = 2 + self
+            .inner
+            .children
+            .lock()
+            .iter()
+            .filter(|x| matches!(x, FileContainer::Dir(_)))
+            .count() as u32;
         stat.st_uid = 0;
         stat.st_gid = 0;
         stat.st_rdev = 0;
@@ -543,7 +709,7 @@ impl RamDir {
             // let dirent = c2rust_ref(ptr as *mut Dirent);
             let dirent: &mut Dirent = unsafe { (ptr as *mut Dirent).as_mut() }.unwrap();
 
-            dirent.d_ino = 0;
+            dirent.d_ino = x.ino() as usize;
             dirent.d_off = current_len as isize;
             dirent.d_reclen = current_len as u16;
             dirent.d_type = 0; // 0 d_type is file
@@ -561,6 +727,81 @@ impl RamDir {
     }
 }
 
+impl super::Dentry for RamDir {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+    fn is_dir(&self) -> bool {
+        true
+    }
+    fn lookup(&self, name: &str) -> Option<Arc<dyn super::Dentry>> {
+        let mi = MountedInfo {
+            fs_id: self.mi.fs_id,
+            path: self.mi.path.cd(String::from(name)),
+        };
+        self.open(name).map(|container| container.to_dentry(mi))
+    }
+    fn create(&self, name: &str, itype: InodeType) -> Result<Arc<dyn super::Dentry>, Errno> {
+        let container = match itype {
+            InodeType::File => self.touch(name, OpenFlags::O_RDWR)?,
+            InodeType::Dir => self.mkdir(name, OpenFlags::O_DIRECTORY)?,
+        };
+        let mi = MountedInfo {
+            fs_id: self.mi.fs_id,
+            path: self.mi.path.cd(String::from(name)),
+        };
+        Ok(container.to_dentry(mi))
+    }
+    fn symlink(&self, name: &str, target: &str) -> Result<Arc<dyn super::Dentry>, Errno> {
+        let mi = MountedInfo {
+            fs_id: self.mi.fs_id,
+            path: self.mi.path.cd(String::from(name)),
+        };
+        Ok(self.symlink(name, target)?.to_dentry(mi))
+    }
+    fn link(
+        &self,
+        name: &str,
+        target: Arc<dyn super::Dentry>,
+    ) -> Result<Arc<dyn super::Dentry>, Errno> {
+        let any = target.as_any();
+        let entry = if let Some(file) = any.downcast_ref::<RamFile>() {
+            FileContainer::File(file.name(), file.inner.clone())
+        } else if let Some(link) = any.downcast_ref::<RamLink>() {
+            FileContainer::Link(link.name(), link.inner.clone())
+        } else {
+            return Err(Errno::EPERM);
+        };
+        let mi = MountedInfo {
+            fs_id: self.mi.fs_id,
+            path: self.mi.path.cd(String::from(name)),
+        };
+        Ok(self.link(name, &entry)?.to_dentry(mi))
+    }
+    fn inode(self: Arc<Self>) -> Arc<dyn super::Inode> {
+        self
+    }
+    fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+impl super::Inode for RamDir {
+    fn open(
+        self: Arc<Self>,
+        path: AbsolutePath,
+        flags: OpenFlags,
+    ) -> Result<Arc<dyn File>, Errno> {
+        let mi = MountedInfo {
+            fs_id: self.mi.fs_id,
+            path,
+        };
+        let res = FileContainer::Dir(self.inner.clone()).to_inode(mi);
+        res.set_flags(flags);
+        Ok(res)
+    }
+}
+
 pub struct RamFile {
     inner: Arc<RamFileInner>,
     mi: MountedInfo,
@@ -570,32 +811,19 @@ pub struct RamFile {
 impl RamFile {
     fn read(&self, buffer: &mut [u8]) -> VfsResult<usize> {
         let offset = self.offset();
-        let file_size = self.inner.content.lock().len();
-        match offset >= file_size {
-            true => Ok(0),
-            false => {
-                let read_len = min(buffer.len(), file_size - offset);
-                let content = self.inner.content.lock();
-                buffer[..read_len].copy_from_slice(&content[offset..(offset + read_len)]);
-                self.seek(offset + read_len);
-                Ok(read_len)
-            }
-        }
+        let read_len = self.inner.content.lock().read_at(offset, buffer);
+        self.seek(offset + read_len);
+        Ok(read_len)
     }
 
     fn write(&self, buffer: &[u8]) -> VfsResult<usize> {
         let offset = self.offset();
-        let file_size = self.inner.content.lock().len();
-        let wsize = buffer.len();
-
-        let part1 = cmp::min(file_size - offset, wsize);
-        let mut content = self.inner.content.lock();
-        content[offset..offset + part1].copy_from_slice(&buffer[..part1]);
-        // extend content if offset + buffer > content.len()
-        content.extend_from_slice(&buffer[part1..]);
-
+        let wsize = self.inner.content.lock().write_at(offset, buffer);
         self.seek(offset + wsize);
-        Ok(wsize)
+        match wsize == 0 && !buffer.is_empty() {
+            true => Err(VfsError::StorageFull),
+            false => Ok(wsize),
+        }
     }
 
     fn seek(&self, new_off: usize) {
@@ -603,14 +831,14 @@ impl RamFile {
     }
 
     fn truncate(&self, size: usize) -> VfsResult<()> {
-        self.inner.content.lock().drain(size..);
+        self.inner.content.lock().truncate(size);
         Ok(())
     }
 
     fn metadata(&self) -> VfsResult<Metadata> {
         Ok(Metadata {
-            filename: self.inner.name().to_string(),
-            inode: 0,
+            filename: self.mi.path.last(),
+            inode: self.inner.ino as usize,
             file_type: FileType::File,
             size: self.inner.content.lock().len(),
             childrens: 0,
@@ -618,26 +846,19 @@ impl RamFile {
     }
 
     fn stat(&self, stat: &mut Kstat) -> VfsResult<()> {
-        let st_mode = if self.name() == "zero"
-            || self.name() == "null"
-            || self.name() == "NULL"
-            || self.name() == "ZERO"
-        {
-            // StatMode::CHAR
-            S_IFCHR
-        } else {
-            S_IFREG
-        };
+        let st_mode = S_IFREG;
         stat.st_dev = self.mi.fs_id as u64;
-        stat.st_ino = 1;
+        stat.st_ino = self.inner.ino;
         stat.st_mode = st_mode as u32;
-        stat.st_nlink = 1;
+        stat.st_nlink = self.inner.nlink.load(Ordering::Relaxed) as u32;
         stat.st_uid = 0;
         stat.st_gid = 0;
         stat.st_rdev = 0;
-        stat.st_size = self.inner.content.lock().len() as i64;
-        stat.st_blksize = 512;
-        stat.st_blocks = 0;
+        let content = self.inner.content.lock();
+        stat.st_size = content.len() as i64;
+        stat.st_blksize = PAGE_SIZE as i32;
+        stat.st_blocks = (content.resident_pages() * PAGE_SIZE / 512) as u64;
+        drop(content);
         // stat.st_atime_sec = self.inner.times.lock()[1].tv_sec;
         // stat.st_atime_nsec = self.inner.times.lock()[1].tv_nsec;
         // stat.st_mtime_sec = self.inner.times.lock()[2].tv_sec;
@@ -670,93 +891,68 @@ impl RamFile {
 }
 
 impl File for RamFile {
-    fn kernel_read_with_offset(&self, offset: usize, len: usize) -> Vec<u8> {
+    fn kernel_read_with_offset(&self, offset: usize, len: usize) -> Result<Vec<u8>, Errno> {
         let mut buffer = vec![0; len];
-        assert!(offset + len <= self.file_size());
-        let content = self.inner.content.lock();
-        buffer.copy_from_slice(&content[offset..(offset + len)]);
-        buffer
+        let read_size = self.inner.content.lock().read_at(offset, &mut buffer);
+        buffer.truncate(read_size);
+        Ok(buffer)
     }
-    // TODO
-    fn read_to_kspace(&self) -> Vec<u8> {
-        let file_size = self.file_size();
+    fn read_to_kspace(&self) -> Result<Vec<u8>, Errno> {
         let offset = self.offset();
-        assert!(offset <= file_size);
-        let len = file_size - offset;
-        let mut buffer = vec![0; len];
-        let content = self.inner.content.lock();
-        buffer.copy_from_slice(&content[offset..(offset + len)]);
-        self.seek(file_size);
-        buffer
+        let len = self.file_size().saturating_sub(offset);
+        let buffer = self.kernel_read_with_offset(offset, len)?;
+        self.seek(offset + buffer.len());
+        Ok(buffer)
     }
-    // TODO
-    fn read_to_ubuf(&self, mut buf: UserBuffer) -> usize {
+    fn read_to_ubuf(&self, buf: UserBuffer) -> Result<usize, Errno> {
         let offset = self.offset();
-        self.pread(buf, offset)
+        let read_size = self.pread(buf, offset)?;
+        self.seek(offset + read_size);
+        Ok(read_size)
     }
-    fn pread(&self, mut buf: UserBuffer, offset: usize) -> usize {
-        let mut offset = offset;
-        let file_size = self.file_size();
-        let mut total_read_size = 0usize;
-        if file_size == 0 {
-            if self.name() == "zero" || self.name() == "ZERO" {
-                buf.write_zeros();
-            }
-            return 0;
-        }
-        if offset >= file_size {
-            return 0;
-        }
+    fn pread(&self, mut buf: UserBuffer, offset: usize) -> Result<usize, Errno> {
         let content = self.inner.content.lock();
-        for sub_buff in buf.buffers.iter_mut() {
-            let sub_buf_end = sub_buff.len();
-            let offset = self.offset();
-            let read_size = min(sub_buf_end, file_size - offset);
-            sub_buff.copy_from_slice(&content[offset..offset + read_size]);
-            self.seek(offset + read_size);
+        let mut total_read_size = 0usize;
+        for sub_buf in buf.buffers.iter_mut() {
+            let read_size = content.read_at(offset + total_read_size, sub_buf);
             total_read_size += read_size;
+            if read_size < sub_buf.len() {
+                break;
+            }
         }
-        total_read_size
+        Ok(total_read_size)
     }
-    fn write_from_kspace(&self, buffer: &Vec<u8>) -> usize {
-        let offset = self.offset();
-        let file_size = self.file_size();
-        let wsize = buffer.len();
-
-        let part1 = cmp::min(file_size - offset, wsize);
-        let mut content = self.inner.content.lock();
-        content[offset..offset + part1].copy_from_slice(&&buffer[..part1]);
-        // extend content if offset + buffer > content.len()
-        content.extend_from_slice(&buffer[part1..]);
-
-        self.seek(offset + wsize);
-        wsize
+    fn write_from_kspace(&self, buffer: &Vec<u8>) -> Result<usize, Errno> {
+        self.write(buffer).map_err(|_| Errno::ENOSPC)
     }
-    fn pwrite(&self, buf: UserBuffer, offset: usize) -> usize {
-        let mut offset = offset;
-        let mut total_write_size = 0usize;
+    fn pwrite(&self, buf: UserBuffer, offset: usize) -> Result<usize, Errno> {
         let mut content = self.inner.content.lock();
+        let mut total_write_size = 0usize;
         for sub_buf in buf.buffers.iter() {
-            // let file_size = self.file_size(); // lock
-            let file_size = content.len();
-            let write_size = sub_buf.len();
-            let part1 = min(write_size, file_size - offset);
-            content[offset..offset + part1].copy_from_slice(&sub_buf[..part1]);
-            content.extend_from_slice(&sub_buf[part1..]);
+            let write_size = content.write_at(offset + total_write_size, sub_buf);
             total_write_size += write_size;
-            offset += write_size;
-            self.seek(offset);
+            // 超出 tmpfs 的容量
+            if write_size < sub_buf.len() {
+                break;
+            }
+        }
+        if total_write_size == 0 && buf.len() > 0 {
+            return Err(Errno::ENOSPC);
         }
-        total_write_size
+        Ok(total_write_size)
     }
-    fn write_from_ubuf(&self, buf: UserBuffer) -> usize {
-        let mut offset = if self.flags().contains(OpenFlags::O_APPEND) {
+    fn write_from_ubuf(&self, buf: UserBuffer) -> Result<usize, Errno> {
+        let offset = if self.flags().contains(OpenFlags::O_APPEND) {
             self.file_size()
         } else {
             self.offset()
         };
-        // let offset = self.file_size();
-        self.pwrite(buf, offset)
+        let write_size = self.pwrite(buf, offset)?;
+        self.seek(offset + write_size);
+        Ok(write_size)
+    }
+    fn shared_frame(&self, offset: usize) -> Option<FrameTracker> {
+        self.inner.content.lock().shared_frame(offset)
     }
     // TODO
     fn set_time(&self, time_info: InodeTime) {
@@ -773,10 +969,10 @@ impl File for RamFile {
         -1
     }
     fn name(&self) -> String {
-        self.inner.name()
+        self.mi.path.last()
     }
-    fn fstat(&self, kstat: &mut Kstat) {
-        self.stat(kstat).unwrap();
+    fn fstat(&self, kstat: &mut Kstat) -> Result<(), Errno> {
+        self.stat(kstat).map_err(|_| Errno::EIO)
     }
     fn offset(&self) -> usize {
         self.offset()
@@ -795,16 +991,19 @@ impl File for RamFile {
         *flags |= OpenFlags::O_CLOEXEC;
     }
     fn path(&self) -> AbsolutePath {
-        self.inner.dir_path.lock().clone()
+        self.mi.path.clone()
+    }
+    fn is_under(&self, dir: &AbsolutePath) -> bool {
+        self.path().start_with(dir)
     }
     fn file_size(&self) -> usize {
         self.file_size()
     }
-    fn truncate(&self, new_length: usize) {
-        self.truncate(new_length);
+    fn truncate(&self, new_length: usize) -> Result<(), Errno> {
+        self.truncate(new_length).map_err(|_| Errno::EIO)
     }
     fn fid(&self) -> u64 {
-        self.mi.fs_id as u64
+        self.inner.ino
     }
     fn available(&self) -> bool {
         true
@@ -825,26 +1024,197 @@ impl File for RamFile {
     fn write_from_direct(&self, _offset: usize, _data: &Vec<u8>) -> usize {
         panic!("{} not implement write_from_direct", self.name());
     }
-    fn delete(&self) -> usize {
-        let path = self.path();
-        let parent = find_parent_dir(path.clone()).unwrap();
-        parent.remove(self.name()).unwrap();
+    fn delete(&self) -> Result<(), Errno> {
+        let parent = find_parent_dir(&self.path()).ok_or(Errno::ENOENT)?;
+        parent.remove(self.name()).map_err(|_| Errno::ENOENT)
+    }
+    fn rename(&self, new_path: AbsolutePath, _flag: OpenFlags) -> Result<(), Errno> {
+        if new_path == self.path() {
+            return Ok(());
+        }
+        let new_parent = find_parent_dir(&new_path).ok_or(Errno::ENOENT)?;
+        let old_parent = find_parent_dir(&self.path()).ok_or(Errno::ENOENT)?;
+        new_parent.remove_target(&new_path.last(), false)?;
+        let entry = old_parent.detach(&self.name()).ok_or(Errno::ENOENT)?;
+        new_parent.add_from_container(entry.with_name(new_path.last()));
+        Ok(())
+    }
+    fn is_dir(&self) -> bool {
+        false
+    }
+}
+
+impl super::Dentry for RamFile {
+    fn name(&self) -> String {
+        self.mi.path.last()
+    }
+    fn is_dir(&self) -> bool {
+        false
+    }
+    fn lookup(&self, _name: &str) -> Option<Arc<dyn super::Dentry>> {
+        None
+    }
+    fn create(&self, _name: &str, _itype: InodeType) -> Result<Arc<dyn super::Dentry>, Errno> {
+        Err(Errno::ENOTDIR)
+    }
+    fn inode(self: Arc<Self>) -> Arc<dyn super::Inode> {
+        self
+    }
+    fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+impl super::Inode for RamFile {
+    fn open(
+        self: Arc<Self>,
+        path: AbsolutePath,
+        flags: OpenFlags,
+    ) -> Result<Arc<dyn File>, Errno> {
+        if flags.contains(OpenFlags::O_TRUNC) {
+            self.inner.content.lock().truncate(0);
+        }
+        let mi = MountedInfo {
+            fs_id: self.mi.fs_id,
+            path,
+        };
+        let res = FileContainer::File(self.name(), self.inner.clone()).to_inode(mi);
+        res.set_flags(flags);
+        Ok(res)
+    }
+}
+
+/// 以 `O_PATH | O_NOFOLLOW` 打开的符号链接, 只能用于 fstat/unlink/rename
+pub struct RamLink {
+    inner: Arc<RamLinkInner>,
+    mi: MountedInfo,
+    flags: Mutex<OpenFlags>,
+}
+
+impl RamLink {
+    fn new(inner: Arc<RamLinkInner>, mi: MountedInfo) -> Arc<Self> {
+        Arc::new(Self {
+            inner,
+            mi,
+            flags: Mutex::new(OpenFlags::PATH),
+        })
+    }
+}
+
+impl File for RamLink {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn available(&self) -> bool {
+        true
+    }
+    fn read_to_ubuf(&self, _buf: UserBuffer) -> Result<usize, Errno> {
+        Ok(0)
+    }
+    fn write_from_ubuf(&self, _buf: UserBuffer) -> Result<usize, Errno> {
+        Ok(0)
+    }
+    fn name(&self) -> String {
+        self.mi.path.last()
+    }
+    fn fstat(&self, stat: &mut Kstat) -> Result<(), Errno> {
+        stat.st_dev = self.mi.fs_id as u64;
+        stat.st_ino = self.inner.ino;
+        stat.st_mode = S_IFLNK;
+        stat.st_nlink = self.inner.nlink.load(Ordering::Relaxed) as u32;
+        stat.st_size = self.inner.target.len() as i64;
+        stat.st_blksize = PAGE_SIZE as i32;
+        stat.st_blocks = 0;
+        Ok(())
+    }
+    fn offset(&self) -> usize {
         0
     }
-    fn rename(&self, new_path: AbsolutePath, _flag: OpenFlags) {
-        let new_parent = find_parent_dir(new_path.clone()).unwrap();
-        let old_parent = find_parent_dir(self.path()).unwrap();
-        let inner = self.inner.clone();
-        let old_name = self.name();
-        let new_name = new_path.last();
-        old_parent.remove(old_name).unwrap();
-        inner.set_path(new_path);
-        inner.rename(new_name);
-        new_parent.add_from_container(FileContainer::File(inner));
+    fn file_size(&self) -> usize {
+        self.inner.target.len()
+    }
+    fn flags(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+    fn set_flags(&self, flag: OpenFlags) {
+        *self.flags.lock() = flag;
+    }
+    fn set_cloexec(&self) {
+        *self.flags.lock() |= OpenFlags::O_CLOEXEC;
+    }
+    fn path(&self) -> AbsolutePath {
+        self.mi.path.clone()
+    }
+    fn is_under(&self, dir: &AbsolutePath) -> bool {
+        self.path().start_with(dir)
+    }
+    fn fid(&self) -> u64 {
+        self.inner.ino
     }
     fn is_dir(&self) -> bool {
         false
     }
+    fn delete(&self) -> Result<(), Errno> {
+        let parent = find_parent_dir(&self.path()).ok_or(Errno::ENOENT)?;
+        parent.remove(self.name()).map_err(|_| Errno::ENOENT)
+    }
+    fn rename(&self, new_path: AbsolutePath, _flag: OpenFlags) -> Result<(), Errno> {
+        if new_path == self.path() {
+            return Ok(());
+        }
+        let new_parent = find_parent_dir(&new_path).ok_or(Errno::ENOENT)?;
+        let old_parent = find_parent_dir(&self.path()).ok_or(Errno::ENOENT)?;
+        new_parent.remove_target(&new_path.last(), false)?;
+        let entry = old_parent.detach(&self.name()).ok_or(Errno::ENOENT)?;
+        new_parent.add_from_container(entry.with_name(new_path.last()));
+        Ok(())
+    }
+}
+
+impl super::Dentry for RamLink {
+    fn name(&self) -> String {
+        self.mi.path.last()
+    }
+    fn is_dir(&self) -> bool {
+        false
+    }
+    fn is_symlink(&self) -> bool {
+        true
+    }
+    fn readlink(&self) -> Result<String, Errno> {
+        Ok(self.inner.target.clone())
+    }
+    fn lookup(&self, _name: &str) -> Option<Arc<dyn super::Dentry>> {
+        None
+    }
+    fn create(&self, _name: &str, _itype: InodeType) -> Result<Arc<dyn super::Dentry>, Errno> {
+        Err(Errno::ENOTDIR)
+    }
+    fn inode(self: Arc<Self>) -> Arc<dyn super::Inode> {
+        self
+    }
+    fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+impl super::Inode for RamLink {
+    fn open(
+        self: Arc<Self>,
+        path: AbsolutePath,
+        flags: OpenFlags,
+    ) -> Result<Arc<dyn File>, Errno> {
+        let mi = MountedInfo {
+            fs_id: self.mi.fs_id,
+            path,
+        };
+        let res = RamLink::new(self.inner.clone(), mi);
+        res.set_flags(flags);
+        Ok(res)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -900,4 +1270,5 @@ pub enum VfsError {
 pub enum FileType {
     File,
     Directory,
+    SymLink,
 }
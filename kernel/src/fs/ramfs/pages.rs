@@ -0,0 +1,138 @@
+use crate::consts::PAGE_SIZE;
+use crate::mm::{alloc_frame, frame_usage, FrameTracker};
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::cmp::min;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// 一个 tmpfs 实例的容量, 以页为单位, 由其中的所有文件共享
+pub struct RamFsUsage {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl RamFsUsage {
+    /// `limit` 为 None 时与 Linux 一样, 默认使用一半的物理内存
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit: limit.unwrap_or_else(|| frame_usage().0 / 2),
+            used: AtomicUsize::new(0),
+        }
+    }
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+    /// 在容量限制内分配一个页帧
+    fn alloc(&self) -> Option<FrameTracker> {
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                (used < self.limit).then_some(used + 1)
+            })
+            .ok()?;
+        let frame = alloc_frame();
+        if frame.is_none() {
+            self.release(1);
+        }
+        frame
+    }
+    fn release(&self, pages: usize) {
+        self.used.fetch_sub(pages, Ordering::Relaxed);
+    }
+}
+
+/// 文件数据, 按页号保存在物理页帧中; 从未写入过的页 (空洞) 不占用页帧, 读出来是 0
+pub struct FilePages {
+    size: usize,
+    pages: BTreeMap<usize, FrameTracker>,
+    usage: Arc<RamFsUsage>,
+}
+
+impl FilePages {
+    pub fn new(usage: Arc<RamFsUsage>) -> Self {
+        Self {
+            size: 0,
+            pages: BTreeMap::new(),
+            usage,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.size
+    }
+    /// 实际占用的页帧数
+    pub fn resident_pages(&self) -> usize {
+        self.pages.len()
+    }
+    /// 取得 `page` 号页的页帧, 是空洞时分配一个新的页帧; 超出容量时返回 None
+    fn frame_or_alloc(&mut self, page: usize) -> Option<&FrameTracker> {
+        if !self.pages.contains_key(&page) {
+            self.pages.insert(page, self.usage.alloc()?);
+        }
+        self.pages.get(&page)
+    }
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        if offset >= self.size {
+            return 0;
+        }
+        let len = min(buf.len(), self.size - offset);
+        let mut read_size = 0;
+        while read_size < len {
+            let pos = offset + read_size;
+            let page_offset = pos % PAGE_SIZE;
+            let n = min(PAGE_SIZE - page_offset, len - read_size);
+            let dst = &mut buf[read_size..read_size + n];
+            match self.pages.get(&(pos / PAGE_SIZE)) {
+                Some(frame) => {
+                    dst.copy_from_slice(&frame.ppn.as_bytes_array()[page_offset..page_offset + n])
+                }
+                None => dst.fill(0),
+            }
+            read_size += n;
+        }
+        read_size
+    }
+    /// 返回实际写入的字节数, 超出容量时写入的字节数少于 `buf.len()`
+    pub fn write_at(&mut self, offset: usize, buf: &[u8]) -> usize {
+        let mut write_size = 0;
+        while write_size < buf.len() {
+            let pos = offset + write_size;
+            let page_offset = pos % PAGE_SIZE;
+            let n = min(PAGE_SIZE - page_offset, buf.len() - write_size);
+            let frame = match self.frame_or_alloc(pos / PAGE_SIZE) {
+                Some(frame) => frame,
+                None => break,
+            };
+            frame.ppn.as_bytes_array()[page_offset..page_offset + n]
+                .copy_from_slice(&buf[write_size..write_size + n]);
+            write_size += n;
+        }
+        self.size = self.size.max(offset + write_size);
+        write_size
+    }
+    /// 改变文件大小; 变大时只留下空洞, 变小时释放多余的页帧并将最后一页的剩余部分清零
+    pub fn truncate(&mut self, size: usize) {
+        if size < self.size {
+            let keep = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+            let released = self.pages.split_off(&keep);
+            self.usage.release(released.len());
+            if let Some(frame) = self.pages.get(&(size / PAGE_SIZE)) {
+                frame.ppn.as_bytes_array()[size % PAGE_SIZE..].fill(0);
+            }
+        }
+        self.size = size;
+    }
+    /// 供 `MAP_SHARED` 映射使用的页帧, 映射与文件共享同一个页帧
+    pub fn shared_frame(&mut self, offset: usize) -> Option<FrameTracker> {
+        if offset >= self.size {
+            return None;
+        }
+        self.frame_or_alloc(offset / PAGE_SIZE).cloned()
+    }
+}
+
+impl Drop for FilePages {
+    fn drop(&mut self) {
+        self.usage.release(self.resident_pages());
+    }
+}
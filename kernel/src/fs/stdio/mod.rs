@@ -1,5 +1,7 @@
 pub mod stdin;
 pub mod stdout;
+pub mod tty;
 
 pub use stdin::Stdin;
 pub use stdout::Stdout;
+pub use tty::*;
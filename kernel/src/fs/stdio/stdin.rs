@@ -1,9 +1,10 @@
 use alloc::string::{String, ToString};
 
+use super::{check_tty_read, console_getc, tty_ioctl};
 use crate::fs::File;
 use crate::mm::UserBuffer;
-use crate::sbi::console_getchar;
-use crate::task::suspend_current_and_run_next;
+use crate::syscall::impls::Errno;
+use crate::task::{current_task, suspend_current_and_run_next};
 
 pub struct Stdin;
 
@@ -18,25 +19,24 @@ impl File for Stdin {
         true
     }
 
-    fn read_to_ubuf(&self, mut user_buf: UserBuffer) -> usize {
+    fn read_to_ubuf(&self, mut user_buf: UserBuffer) -> Result<usize, Errno> {
         assert_eq!(user_buf.len(), 1);
-        let mut c: i32;
-        loop {
-            c = console_getchar() as i32;
-            if c <= 0 {
-                suspend_current_and_run_next();
-                continue;
-            } else {
-                break;
+        check_tty_read()?;
+        let ch = loop {
+            if let Some(c) = console_getc() {
+                break c;
             }
-        }
-        let ch = c as u8;
+            if current_task().unwrap().has_unmasked_signal() {
+                return Err(Errno::ERESTARTSYS);
+            }
+            suspend_current_and_run_next();
+        };
         unsafe { user_buf.buffers[0].as_mut_ptr().write_volatile(ch) }
 
-        1
+        Ok(1)
     }
 
-    fn write_from_ubuf(&self, _user_buf: UserBuffer) -> usize {
+    fn write_from_ubuf(&self, _user_buf: UserBuffer) -> Result<usize, Errno> {
         panic!("Cannot write to stdin!");
     }
 
@@ -53,7 +53,11 @@ impl File for Stdin {
     fn file_size(&self) -> usize {
         usize::MAX
     }
-    fn truncate(&self, _new_length: usize) {
+    fn truncate(&self, _new_length: usize) -> Result<(), Errno> {
         warn!("Fake truncate for Stdin");
+        Ok(())
+    }
+    fn ioctl(&self, request: usize, argp: usize) -> Result<isize, Errno> {
+        tty_ioctl(request, argp)
     }
 }
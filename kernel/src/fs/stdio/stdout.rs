@@ -4,7 +4,8 @@ use alloc::{
 };
 use nix::Kstat;
 
-use crate::{fs::File, mm::UserBuffer};
+use super::tty_ioctl;
+use crate::{fs::File, mm::UserBuffer, syscall::impls::Errno};
 
 pub struct Stdout;
 
@@ -18,28 +19,32 @@ impl File for Stdout {
     fn available(&self) -> bool {
         true
     }
-    fn read_to_ubuf(&self, _user_buf: UserBuffer) -> usize {
+    fn read_to_ubuf(&self, _user_buf: UserBuffer) -> Result<usize, Errno> {
         panic!("Cannot read from stdout!");
     }
-    fn write_from_ubuf(&self, user_buf: UserBuffer) -> usize {
+    fn write_from_ubuf(&self, user_buf: UserBuffer) -> Result<usize, Errno> {
         for buffer in user_buf.buffers.iter() {
             print!("{}", core::str::from_utf8(*buffer).unwrap());
         }
-        user_buf.len()
+        Ok(user_buf.len())
     }
 
     fn name(&self) -> String {
         "Stdout".to_string()
     }
 
-    fn write_from_kspace(&self, data: &Vec<u8>) -> usize {
+    fn write_from_kspace(&self, data: &Vec<u8>) -> Result<usize, Errno> {
         let buffer = data.as_slice();
         print!("{}", core::str::from_utf8(buffer).unwrap());
 
-        data.len()
+        Ok(data.len())
     }
     fn set_cloexec(&self) {}
-    fn fstat(&self, _kstat: &mut Kstat) {
+    fn fstat(&self, _kstat: &mut Kstat) -> Result<(), Errno> {
         warn!("Fake fstat for Stdout");
+        Ok(())
+    }
+    fn ioctl(&self, request: usize, argp: usize) -> Result<isize, Errno> {
+        tty_ioctl(request, argp)
     }
 }
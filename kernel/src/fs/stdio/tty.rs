@@ -0,0 +1,192 @@
+//! 控制台终端
+//!
+//! 内核只有 SBI 控制台一个终端, 标准输入输出与 `/dev/tty` 都落到它上面. 这里记录作业控制所需的状态:
+//! - 以控制台为控制终端的会话, 开机时为 initproc 所在的会话;
+//! - 前台进程组, 由 shell 通过 `tcsetpgrp` 设置;
+//! - 尚未被读取的输入. 调度循环不断轮询控制台 ([`console_poll`]), 将 Ctrl-C/Ctrl-\/Ctrl-Z
+//!   转换为发给前台进程组的信号, 因此前台作业不读终端时也能被打断. 输入的时间戳同时作为熵样本.
+//!
+//! 后台进程组读终端时收到 SIGTTIN, 设置终端时收到 SIGTTOU. 没有实现 termios, 因此也不支持 TOSTOP,
+//! 与 Linux 的默认设置一样, 后台进程组可以直接写终端.
+
+use alloc::collections::VecDeque;
+use nix::{
+    SigSet, Signal, TCGETS, TCSETS, TIOCGPGRP, TIOCGSID, TIOCGWINSZ, TIOCNOTTY, TIOCSCTTY,
+    TIOCSPGRP,
+};
+use spin::{lazy::Lazy, Mutex};
+
+use crate::mm::{copyin, copyout};
+use crate::random::add_randomness;
+use crate::return_errno;
+use crate::sbi::console_getchar;
+use crate::syscall::impls::Errno;
+use crate::task::{current_task, current_user_token, kill_process_group, process_group};
+
+struct Terminal {
+    /// 以控制台为控制终端的会话
+    session: Option<usize>,
+    /// 前台进程组
+    foreground: usize,
+    /// 尚未被读取的输入
+    input: VecDeque<u8>,
+}
+
+static CONSOLE: Lazy<Mutex<Terminal>> = Lazy::new(|| {
+    Mutex::new(Terminal {
+        session: Some(0),
+        foreground: 0,
+        input: VecDeque::new(),
+    })
+});
+
+/// 产生信号的控制字符, 即 termios 默认的 VINTR/VQUIT/VSUSP
+fn control_signal(c: u8) -> Option<SigSet> {
+    match c {
+        0x03 => Some(SigSet::SIGINT),
+        0x1c => Some(SigSet::SIGQUIT),
+        0x1a => Some(SigSet::SIGTSTP),
+        _ => None,
+    }
+}
+
+/// 读取控制台上已有的输入, 控制字符转换为发给前台进程组的信号. 由调度循环反复调用
+pub fn console_poll() {
+    // 其他核正在读取时跳过
+    let mut console = match CONSOLE.try_lock() {
+        Some(console) => console,
+        None => return,
+    };
+    let mut signals = SigSet::empty();
+    loop {
+        let c = console_getchar();
+        if c <= 0 {
+            break;
+        }
+        add_randomness(c as usize);
+        match control_signal(c as u8) {
+            Some(signal) => signals |= signal,
+            None => console.input.push_back(c as u8),
+        }
+    }
+    let foreground = console.foreground;
+    drop(console);
+    if !signals.is_empty() {
+        kill_process_group(foreground, signals);
+    }
+}
+
+/// 从控制台读取一个字符, 没有输入时返回 None
+pub fn console_getc() -> Option<u8> {
+    console_poll();
+    CONSOLE.lock().input.pop_front()
+}
+
+/// 当前进程是否属于控制台的后台进程组. 不以控制台为控制终端的进程不受作业控制的限制
+fn in_background() -> bool {
+    let group = current_task().unwrap().thread_group.clone();
+    let (pgid, sid) = (group.pgid(), group.sid());
+    let console = CONSOLE.lock();
+    console.session == Some(sid) && console.foreground != pgid
+}
+
+/// 后台进程组读终端时, 向其发送 SIGTTIN, 被恢复到前台后重新读. SIGTTIN 被忽略或屏蔽时返回 EIO
+pub fn check_tty_read() -> Result<(), Errno> {
+    if !in_background() {
+        return Ok(());
+    }
+    let task = current_task().unwrap();
+    if task.ignores_signal(Signal::SIGTTIN as u32) {
+        return_errno!(Errno::EIO, "background read with SIGTTIN ignored");
+    }
+    kill_process_group(task.thread_group.pgid(), SigSet::SIGTTIN);
+    return_errno!(Errno::ERESTARTSYS);
+}
+
+/// 后台进程组设置终端时, 向其发送 SIGTTOU, 被恢复后重新设置. SIGTTOU 被忽略或屏蔽时允许设置,
+/// shell 正是这样把自己设为前台进程组的
+fn check_tty_write() -> Result<(), Errno> {
+    if !in_background() {
+        return Ok(());
+    }
+    let task = current_task().unwrap();
+    if task.ignores_signal(Signal::SIGTTOU as u32) {
+        return Ok(());
+    }
+    kill_process_group(task.thread_group.pgid(), SigSet::SIGTTOU);
+    return_errno!(Errno::ERESTARTSYS);
+}
+
+/// 会话首进程退出时, 向前台进程组发送 SIGHUP 与 SIGCONT, 会话失去控制终端
+pub fn tty_session_exit(sid: usize) {
+    let mut console = CONSOLE.lock();
+    if console.session != Some(sid) {
+        return;
+    }
+    console.session = None;
+    let foreground = console.foreground;
+    drop(console);
+    kill_process_group(foreground, SigSet::SIGHUP | SigSet::SIGCONT);
+}
+
+/// 终端的 ioctl, 标准输入输出与 `/dev/tty` 的 ioctl 都由它处理, 其他文件不是终端
+pub fn tty_ioctl(request: usize, argp: usize) -> Result<isize, Errno> {
+    let group = current_task().unwrap().thread_group.clone();
+    let (tgid, pgid, sid) = (group.tgid, group.pgid(), group.sid());
+    drop(group);
+    let token = current_user_token();
+    if request == TCGETS {
+        return Ok(0);
+    }
+    if request == TIOCGWINSZ {
+        copyout(token, argp as *mut u8, &0u8)?;
+        return Ok(0);
+    }
+    if request == TCSETS {
+        // 没有实现 termios, 只做作业控制的检查
+        return check_tty_write().map(|_| 0);
+    }
+    if request == TIOCSCTTY {
+        // 只有没有控制终端的会话首进程可以获取控制终端, 我们总是以 root 运行, 可以从其他会话抢占
+        if tgid != sid {
+            return_errno!(Errno::EPERM, "{} is not a session leader", tgid);
+        }
+        let mut console = CONSOLE.lock();
+        if console.session.is_some() && console.session != Some(sid) && argp != 1 {
+            return_errno!(Errno::EPERM, "owned by session {:?}", console.session);
+        }
+        console.session = Some(sid);
+        console.foreground = pgid;
+        return Ok(0);
+    }
+    if CONSOLE.lock().session != Some(sid) {
+        return_errno!(Errno::ENOTTY, "no controlling tty in session {}", sid);
+    }
+    match request {
+        TIOCGPGRP => {
+            let foreground = CONSOLE.lock().foreground as i32;
+            copyout(token, argp as *mut i32, &foreground)?;
+        }
+        TIOCSPGRP => {
+            check_tty_write()?;
+            let mut foreground = 0i32;
+            copyin(token, &mut foreground, argp as *const i32)?;
+            if foreground < 0 {
+                return_errno!(Errno::EINVAL, "invalid process group {}", foreground);
+            }
+            let foreground = foreground as usize;
+            if !process_group(foreground).iter().any(|p| p.sid() == sid) {
+                return_errno!(Errno::EPERM, "no process group {} here", foreground);
+            }
+            CONSOLE.lock().foreground = foreground;
+        }
+        TIOCGSID => copyout(token, argp as *mut i32, &(sid as i32))?,
+        TIOCNOTTY => {
+            if tgid == sid {
+                tty_session_exit(sid);
+            }
+        }
+        _ => return_errno!(Errno::ENOTTY, "unsupported tty request {:#x}", request),
+    }
+    Ok(0)
+}
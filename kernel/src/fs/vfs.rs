@@ -0,0 +1,170 @@
+//! Virtual File System
+//!
+//! 内核通过 [`SuperBlock`] / [`Dentry`] / [`Inode`] 三个 trait 统一访问各个具体文件系统:
+//! - [`SuperBlock`]: 一个文件系统实例, 提供根目录项;
+//! - [`Dentry`]: 目录树中的一个节点, 负责按名字查找与创建子节点;
+//! - [`Inode`]: 文件本体, 打开后得到内核的 [`File`] 对象.
+//!
+//! 路径解析时, 先在挂载表中找到路径所属的文件系统 (最长前缀匹配), 再从该文件系统的根目录项
+//! 逐级查找, 因此 FAT32 与 ramfs 可以同时存在于同一个目录树中.
+//!
+//! 逐级查找时会跟随途经的符号链接 (见 [`resolve`]), 链接的目标可以位于另一个文件系统中.
+
+use super::{find_mount, File};
+use crate::syscall::impls::Errno;
+use alloc::{collections::VecDeque, string::String, sync::Arc};
+use core::any::Any;
+use nix::{OpenFlags, Statfs};
+use path::AbsolutePath;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InodeType {
+    File,
+    Dir,
+}
+
+/// 文件系统实例
+pub trait SuperBlock: Send + Sync {
+    /// 文件系统类型, 如 "vfat", "ramfs"
+    fn fs_type(&self) -> &str;
+    /// 文件系统的根目录项
+    fn root(&self) -> Arc<dyn Dentry>;
+    /// 将文件系统的数据写回设备
+    fn sync(&self) {}
+    /// 文件系统是否已变为只读, 如块设备写入失败后 (类似 `errors=remount-ro`)
+    fn is_read_only(&self) -> bool {
+        false
+    }
+    /// 填写 statfs(2) 返回的文件系统信息, 默认保留调用者给出的值
+    fn statfs(&self, _statfs: &mut Statfs) {}
+    /// 按完整路径查询文件系统自身的缓存, 命中时可跳过逐级查找
+    fn open_cached(&self, _path: &AbsolutePath, _flags: OpenFlags) -> Option<Arc<dyn File>> {
+        None
+    }
+}
+
+/// 目录项
+pub trait Dentry: Send + Sync {
+    fn name(&self) -> String;
+    fn is_dir(&self) -> bool;
+    /// 在当前目录下查找名为 `name` 的子节点
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Dentry>>;
+    /// 在当前目录下创建名为 `name` 的子节点
+    fn create(&self, name: &str, itype: InodeType) -> Result<Arc<dyn Dentry>, Errno>;
+    /// 是否为符号链接
+    fn is_symlink(&self) -> bool {
+        false
+    }
+    /// 符号链接指向的路径, 不是符号链接时返回 EINVAL
+    fn readlink(&self) -> Result<String, Errno> {
+        Err(Errno::EINVAL)
+    }
+    /// 在当前目录下创建指向 `target` 的符号链接 `name`, 文件系统不支持符号链接时返回 EPERM
+    fn symlink(&self, _name: &str, _target: &str) -> Result<Arc<dyn Dentry>, Errno> {
+        Err(Errno::EPERM)
+    }
+    /// 在当前目录下创建指向 `target` 的 inode 的硬链接 `name`, `target` 与当前目录位于同一文件系统;
+    /// 文件系统不支持硬链接 (如 FAT32) 时返回 EPERM
+    fn link(&self, _name: &str, _target: Arc<dyn Dentry>) -> Result<Arc<dyn Dentry>, Errno> {
+        Err(Errno::EPERM)
+    }
+    /// 目录项对应的 inode
+    fn inode(self: Arc<Self>) -> Arc<dyn Inode>;
+    /// 取回具体类型, 仅用于同一文件系统内部的操作 (如 rename)
+    fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
+}
+
+/// 索引节点
+pub trait Inode: Send + Sync {
+    /// 以 `flags` 打开该 inode, `path` 为打开时使用的完整路径
+    fn open(self: Arc<Self>, path: AbsolutePath, flags: OpenFlags) -> Result<Arc<dyn File>, Errno>;
+}
+
+/// 从 `dentry` 开始逐级查找 `names`
+pub fn walk(mut dentry: Arc<dyn Dentry>, names: &[&str]) -> Result<Arc<dyn Dentry>, Errno> {
+    for name in names {
+        if !dentry.is_dir() {
+            return Err(Errno::ENOTDIR);
+        }
+        dentry = dentry.lookup(name).ok_or(Errno::ENOENT)?;
+    }
+    Ok(dentry)
+}
+
+/// 路径解析时最多跟随的符号链接数, 超过时返回 ELOOP
+pub const SYMLOOP_MAX: usize = 40;
+
+/// 不跟随符号链接, 直接按挂载表查找 `path` 对应的目录项
+fn walk_mounted(path: &AbsolutePath) -> Result<Arc<dyn Dentry>, Errno> {
+    let mnt = find_mount(path).ok_or(Errno::ENOENT)?;
+    let dentry = walk(mnt.sb.root(), &mnt.root.as_vec_str())?;
+    let rel_path = path.remove_prefix(&mnt.dir);
+    walk(dentry, &rel_path.as_vec_str())
+}
+
+/// 解析 `path` 中的符号链接, 返回不含符号链接的路径及其目录项.
+///
+/// 中间各级的符号链接总是被跟随, 最后一级仅在 `follow` 为 true 时跟随
+/// (`O_NOFOLLOW`/`AT_SYMLINK_NOFOLLOW` 时为 false). 最后一级不存在时目录项为 None,
+/// 返回的路径即为应当创建该文件的位置.
+pub fn resolve(
+    path: &AbsolutePath,
+    follow: bool,
+) -> Result<(AbsolutePath, Option<Arc<dyn Dentry>>), Errno> {
+    let root = AbsolutePath::from_str("/");
+    let mut resolved = root.clone();
+    let mut dentry = walk_mounted(&resolved)?;
+    let mut rest: VecDeque<String> = path.components.clone();
+    let mut links = 0;
+    while let Some(name) = rest.pop_front() {
+        if !dentry.is_dir() {
+            return Err(Errno::ENOTDIR);
+        }
+        // 链接目标中的 ".." 在这里才能确定指向哪个目录
+        if name == ".." {
+            resolved.pop_back();
+            dentry = walk_mounted(&resolved)?;
+            continue;
+        }
+        let child_path = resolved.cd(name.clone());
+        // 子目录是挂载点时进入挂载的文件系统
+        let child = match find_mount(&child_path) {
+            Some(mnt) if mnt.dir == child_path => {
+                Some(walk(mnt.sb.root(), &mnt.root.as_vec_str())?)
+            }
+            _ => dentry.lookup(&name),
+        };
+        let child = match child {
+            Some(child) => child,
+            None if rest.is_empty() => return Ok((child_path, None)),
+            None => return Err(Errno::ENOENT),
+        };
+        if child.is_symlink() && (follow || !rest.is_empty()) {
+            links += 1;
+            if links > SYMLOOP_MAX {
+                return Err(Errno::ELOOP);
+            }
+            // 相对路径的链接相对于链接所在的目录, 从根目录重新解析
+            let target = resolved.cd(child.readlink()?);
+            for name in target.components.into_iter().rev() {
+                rest.push_front(name);
+            }
+            resolved = root.clone();
+            dentry = walk_mounted(&resolved)?;
+            continue;
+        }
+        resolved = child_path;
+        dentry = child;
+    }
+    Ok((resolved, Some(dentry)))
+}
+
+/// 查找 `path` 对应的目录项, 跟随路径中的符号链接
+pub fn lookup(path: &AbsolutePath) -> Result<Arc<dyn Dentry>, Errno> {
+    resolve(path, true)?.1.ok_or(Errno::ENOENT)
+}
+
+/// 查找 `path` 对应的目录项, 最后一级是符号链接时返回链接本身
+pub fn lookup_nofollow(path: &AbsolutePath) -> Result<Arc<dyn Dentry>, Errno> {
+    resolve(path, false)?.1.ok_or(Errno::ENOENT)
+}
@@ -34,6 +34,7 @@ mod fs;
 mod logging;
 mod mm;
 mod panic;
+mod random;
 mod sbi;
 mod syscall;
 mod task;
@@ -94,6 +95,7 @@ pub fn kernel_main(_hartid: usize, _device_tree: usize) -> ! {
     trap::init();
     trap::enable_stimer_interrupt();
     timer::set_next_trigger();
+    random::init();
 
     fs::init();
     task::add_initproc();
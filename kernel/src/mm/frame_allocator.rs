@@ -171,7 +171,18 @@ pub fn init() {
     );
 }
 
+/// 页帧耗尽时一次尝试回收的页数
+const RECLAIM_BATCH: usize = 32;
+
 pub fn alloc_frame() -> Option<FrameTracker> {
+    let ppn = FRAME_ALLOCATOR.lock().alloc();
+    if let Some(ppn) = ppn {
+        return Some(FrameTracker::new(ppn));
+    }
+    // 回收页缓存 (不能持有 FRAME_ALLOCATOR, 写回和释放页帧都要用到它) 后重试
+    if crate::fs::reclaim_memory(RECLAIM_BATCH) == 0 {
+        return None;
+    }
     let ppn = FRAME_ALLOCATOR.lock().alloc()?;
     Some(FrameTracker::new(ppn))
 }
@@ -184,3 +195,8 @@ pub fn frame_add_ref(ppn: PhysPageNum) {
 pub fn enquire_refcount(ppn: PhysPageNum) -> usize {
     FRAME_ALLOCATOR.lock().enquire_ref(ppn)
 }
+/// 返回 (总页帧数, 空闲页帧数)
+pub fn frame_usage() -> (usize, usize) {
+    let (current, recycled, end, base) = FRAME_ALLOCATOR.lock().usage();
+    (end - base, end - current + recycled)
+}
@@ -7,7 +7,7 @@ use crate::boards::{MemRegion, MMIO, PHYSICAL_MEM_END};
 use crate::boards::{MMIO, PHYSICAL_MEM_END};
 
 use crate::mm::{
-    vm_area::{VmArea, VmAreaType},
+    vm_area::{VmArea, VmAreaType, VmBacking},
     MapPermission, MapType, MemorySet,
 };
 
@@ -27,10 +27,12 @@ extern "C" {
 
 use spin::lazy::Lazy;
 pub static KERNEL_VMM: Lazy<Arc<Mutex<MemorySet>>> = Lazy::new(|| {
-    let mut memory_set = MemorySet::new_bare();
-    memory_set.map_trampoline();
+    let mut memory_set = MemorySet::new_bare().expect("failed to create kernel space");
+    memory_set
+        .map_trampoline()
+        .expect("failed to map kernel space");
     macro_rules! insert_kernel_vm_areas {
-        ($kvmm:ident,$($start:expr, $end:expr, $permission:expr, $file:expr, $page_offset:expr)*) => {
+        ($kvmm:ident,$($start:expr, $end:expr, $permission:expr)*) => {
             $(
                 $kvmm.insert_and_map(
                     VmArea::new(
@@ -39,11 +41,11 @@ pub static KERNEL_VMM: Lazy<Arc<Mutex<MemorySet>>> = Lazy::new(|| {
                         MapType::Identical,
                         VmAreaType::KernelSpace,
                         $permission,
-                        $file,
-                        $page_offset,
+                        VmBacking::Anonymous,
+                        false,
                     ),
                     None
-                );
+                ).expect("failed to map kernel space");
             )*
         };
     }
@@ -52,12 +54,12 @@ pub static KERNEL_VMM: Lazy<Arc<Mutex<MemorySet>>> = Lazy::new(|| {
     // 相当于 vpn1(最高页面指定映射, map_trampoline) -> ppn1, vpn2(ppn1恒等映射, insert_kernel_vm_area) -> ppn1
     // page_table map 时是传入的 vpn, 通过 vpn 查找 ppn, 不同 vpn 映射同一个 ppn 并没有问题
     insert_kernel_vm_areas! { memory_set,
-        stext,   etext,    MapPermission::R | MapPermission::X, None, 0
-        srodata, erodata,  MapPermission::R, None, 0
-        sdata,   edata,    MapPermission::R | MapPermission::W, None, 0
-        sbss,    ebss,     MapPermission::R | MapPermission::W, None, 0
+        stext,   etext,    MapPermission::R | MapPermission::X
+        srodata, erodata,  MapPermission::R
+        sdata,   edata,    MapPermission::R | MapPermission::W
+        sbss,    ebss,     MapPermission::R | MapPermission::W
         ekernel, PHYSICAL_MEM_END,
-            MapPermission::R | MapPermission::W, None, 0
+            MapPermission::R | MapPermission::W
     }
 
     // For MMIO(Memory mapped IO).
@@ -67,9 +69,7 @@ pub static KERNEL_VMM: Lazy<Arc<Mutex<MemorySet>>> = Lazy::new(|| {
             memory_set,
             pair.0,
             pair.0 + pair.1,
-            MapPermission::R | MapPermission::W,
-            None,
-            0
+            MapPermission::R | MapPermission::W
         );
     }
 
@@ -137,9 +137,7 @@ pub static KERNEL_VMM: Lazy<Arc<Mutex<MemorySet>>> = Lazy::new(|| {
                 memory_set,
                 pair.start,
                 pair.end,
-                MapPermission::R | MapPermission::W,
-                None,
-                0
+                MapPermission::R | MapPermission::W
             );
         }
         // println!("except mapped_region, mapped mmio finnished");
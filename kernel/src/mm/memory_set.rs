@@ -2,77 +2,53 @@ use alloc::collections::BTreeMap;
 use alloc::{sync::Arc, vec::Vec};
 use path::AbsolutePath;
 
-use super::{MapPermission, MapType, VmArea, VmAreaType};
+use super::{MapPermission, MapType, VmArea, VmAreaType, VmBacking};
 use crate::boards::CLOCK_FREQ;
 use crate::consts::{
     LINK_BASE, MMAP_BASE, PAGE_SIZE, SHM_BASE, SIGNAL_TRAMPOLINE, THREAD_LIMIT, TRAMPOLINE,
-    TRAP_CONTEXT_BASE, USER_HEAP_SIZE, USER_STACK_BASE, USER_STACK_SIZE,
+    TRAP_CONTEXT_BASE, USER_SPACE_END, USER_STACK_BASE, USER_STACK_SIZE,
 };
 use crate::fs::{open, File};
 use crate::mm::{
     alloc_frame, enquire_refcount, shm_get_address_and_size, shm_get_nattch, FrameTracker,
-    MmapManager, PTEFlags, PageTable, PageTableEntry, PhysAddr, PhysPageNum, SharedMemoryTracker,
+    PTEFlags, PageTable, PageTableEntry, PhysAddr, PhysPageNum, SharedMemoryTracker, VPNRange,
     VirtAddr, VirtPageNum,
 };
+use crate::return_errno;
+use crate::syscall::impls::Errno;
 use crate::task::trap_context_position;
 
 use nix::{
     AuxEntry, CreateMode, OpenFlags, AT_BASE, AT_CLKTCK, AT_EGID, AT_ENTRY, AT_EUID, AT_FLAGS,
-    AT_GID, AT_HWCAP, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM, AT_RANDOM, AT_SECURE, AT_UID,
+    AT_GID, AT_HWCAP, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM, AT_SECURE, AT_UID,
 };
 
 pub struct MemorySet {
     pub page_table: PageTable,
-    pub map_areas: Vec<VmArea>,
-    // 用于处理 mmap
-    pub mmap_manager: MmapManager,
-    // 用于处理 heap
-    pub heap_areas: VmArea,
+    // 地址空间中的所有区域, 以起始页号为键, 互不重叠
+    pub areas: BTreeMap<VirtPageNum, VmArea>,
     // 用于处理 shm
-    pub shm_areas: Vec<VmArea>,
-    pub shm_trackers: BTreeMap<VirtAddr, SharedMemoryTracker>,
     pub shm_top: usize,
-    // 用于处理 brk
+    // 用于处理 brk, 堆区域覆盖 [brk_start, brk) 所在的页
     pub brk_start: usize,
     pub brk: usize,
     // 用于处理用户栈
-    pub user_stack_areas: VmArea,
     pub user_stack_start: usize,
     pub user_stack_end: usize,
 }
 
 impl MemorySet {
-    pub fn new_bare() -> Self {
-        Self {
-            page_table: PageTable::new(),
-            map_areas: Vec::new(),
-            heap_areas: VmArea::new(
-                0.into(),
-                0.into(),
-                MapType::Framed,
-                VmAreaType::UserHeap,
-                MapPermission::R | MapPermission::W | MapPermission::U,
-                None,
-                0,
-            ),
-            mmap_manager: MmapManager::new(VirtAddr::from(MMAP_BASE), VirtAddr::from(MMAP_BASE)),
-            shm_areas: Vec::new(),
-            shm_trackers: BTreeMap::new(),
+    /// 新建一个空的地址空间, 页帧耗尽时返回 ENOMEM
+    pub fn new_bare() -> Result<Self, Errno> {
+        Ok(Self {
+            page_table: PageTable::new().ok_or(Errno::ENOMEM)?,
+            areas: BTreeMap::new(),
             shm_top: SHM_BASE,
             brk_start: 0,
             brk: 0,
-            user_stack_areas: VmArea::new(
-                0.into(),
-                0.into(),
-                MapType::Framed,
-                VmAreaType::UserStack,
-                MapPermission::R | MapPermission::W | MapPermission::U,
-                None,
-                0,
-            ),
             user_stack_start: 0,
             user_stack_end: 0,
-        }
+        })
     }
 
     pub fn token(&self) -> usize {
@@ -86,7 +62,7 @@ impl MemorySet {
         end_va: VirtAddr,
         permission: MapPermission,
         area_tpye: VmAreaType,
-    ) {
+    ) -> Result<(), Errno> {
         self.insert_and_map(
             VmArea::new(
                 start_va,
@@ -94,24 +70,17 @@ impl MemorySet {
                 MapType::Framed,
                 area_tpye,
                 permission,
-                None,
-                0,
+                VmBacking::Anonymous,
+                false,
             ),
             None,
-        );
+        )
     }
 
-    /// Remove the corresponding logical segments (including both contiguous and
-    /// non-contiguous segments) based on the starting virtual page number.
+    /// Remove the logical segment that starts at the given virtual page number.
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
-        if let Some((idx, vm_area)) = self
-            .map_areas
-            .iter_mut()
-            .enumerate()
-            .find(|(_, area)| area.vpn_range.get_start() == start_vpn)
-        {
+        if let Some(mut vm_area) = self.areas.remove(&start_vpn) {
             vm_area.erase_pagetable(&mut self.page_table);
-            self.map_areas.remove(idx);
         }
     }
 
@@ -121,13 +90,150 @@ impl MemorySet {
     /// - If the mapping is done in the Framed mode to physical memory,
     ///   it is optional to write some initialization data on the mapped physical page frames.
     /// - data: (osinode, offset, len, page_offset)
-    pub fn insert_and_map(&mut self, mut map_area: VmArea, data: Option<(usize, usize, usize)>) {
-        map_area.inflate_pagetable(&mut self.page_table);
-        if let Some(data) = data {
-            map_area.copy_data(&mut self.page_table, data.0, data.1, data.2);
+    /// - If frames run out or reading the initialization data fails, the area is unmapped
+    ///   and the error is returned.
+    pub fn insert_and_map(
+        &mut self,
+        mut map_area: VmArea,
+        data: Option<(usize, usize, usize)>,
+    ) -> Result<(), Errno> {
+        let result = map_area
+            .inflate_pagetable(&mut self.page_table)
+            .and_then(|_| match data {
+                Some(data) => map_area.copy_data(&mut self.page_table, data.0, data.1, data.2),
+                None => Ok(()),
+            });
+        if let Err(errno) = result {
+            map_area.erase_pagetable(&mut self.page_table);
+            return Err(errno);
         }
         // Push the generated data segment into areas to have its lifecycle controlled by areas.
-        self.map_areas.push(map_area);
+        self.areas.insert(map_area.start_vpn(), map_area);
+        Ok(())
+    }
+
+    /// 插入一个按需映射的区域, 并与相邻的区域合并. 调用者保证它不与已有的区域重叠
+    pub fn insert_area(&mut self, area: VmArea) {
+        let start_vpn = area.start_vpn();
+        self.areas.insert(start_vpn, area);
+        self.try_merge(start_vpn);
+    }
+
+    /// 包含 `vpn` 的区域
+    pub fn find_area(&self, vpn: VirtPageNum) -> Option<&VmArea> {
+        self.areas
+            .range(..=vpn)
+            .next_back()
+            .map(|(_, area)| area)
+            .filter(|area| area.contains(vpn))
+    }
+
+    /// [start_vpn, end_vpn) 是否不与任何区域重叠
+    pub fn is_range_free(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        match self.areas.range(..end_vpn).next_back() {
+            Some((_, area)) => area.end_vpn() <= start_vpn,
+            None => true,
+        }
+    }
+
+    /// [start_vpn, end_vpn) 中的区域是否都是用户可访问的.
+    /// 用户不可访问的区域 (如 Trap 上下文) 由内核管理, 不能被用户修改或替换
+    pub fn is_user_range(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        self.areas
+            .range(..end_vpn)
+            .rev()
+            .take_while(|(_, area)| area.end_vpn() > start_vpn)
+            .all(|(_, area)| area.permission.contains(MapPermission::U))
+    }
+
+    /// 检查 MAP_FIXED 能否用新的映射替换 [start, start + len) 中原有的映射: 范围超出用户地址空间时
+    /// 返回 ENOMEM, 与用户不可访问的区域重叠时返回 EINVAL
+    pub fn check_fixed_range(&self, start: usize, len: usize) -> Result<(), Errno> {
+        match start.checked_add(len) {
+            Some(end) if end <= USER_SPACE_END => {}
+            _ => return_errno!(Errno::ENOMEM, "out of user space, addr: {:#x}", start),
+        }
+        let start_vpn = VirtAddr::from(start).floor();
+        let end_vpn = VirtAddr::from(start + len).ceil();
+        if !self.is_user_range(start_vpn, end_vpn) {
+            return_errno!(Errno::EINVAL, "overlaps kernel areas, addr: {:#x}", start);
+        }
+        Ok(())
+    }
+
+    /// 从 `MMAP_BASE` 开始, 在用户栈之下寻找能容纳 `page_count` 页的空闲区间
+    pub fn find_free_area(&self, page_count: usize) -> Option<VirtPageNum> {
+        let mut start_vpn = VirtAddr::from(MMAP_BASE).floor();
+        for area in self.areas.values() {
+            if area.end_vpn() <= start_vpn {
+                continue;
+            }
+            if area.start_vpn().0 >= start_vpn.0 + page_count {
+                break;
+            }
+            start_vpn = area.end_vpn();
+        }
+        let limit = VirtAddr::from(self.user_stack_start).floor();
+        if start_vpn.0 + page_count <= limit.0 {
+            Some(start_vpn)
+        } else {
+            None
+        }
+    }
+
+    /// 若 `vpn` 落在某个区域的内部, 在此将该区域一分为二
+    fn split_at(&mut self, vpn: VirtPageNum) {
+        let area = match self.areas.range_mut(..vpn).next_back() {
+            Some((_, area)) if area.contains(vpn) => area,
+            _ => return,
+        };
+        let tail = area.split_off(vpn);
+        self.areas.insert(vpn, tail);
+    }
+
+    /// 将起始于 `start_vpn` 的区域与前后相邻且属性相同的区域合并
+    fn try_merge(&mut self, start_vpn: VirtPageNum) {
+        let end_vpn = match self.areas.get(&start_vpn) {
+            Some(area) => area.end_vpn(),
+            None => return,
+        };
+        let next_mergeable = match self.areas.get(&end_vpn) {
+            Some(next) => self.areas[&start_vpn].can_merge(next),
+            None => false,
+        };
+        if next_mergeable {
+            let next = self.areas.remove(&end_vpn).unwrap();
+            self.areas.get_mut(&start_vpn).unwrap().merge(next);
+        }
+        let prev_vpn = self
+            .areas
+            .range(..start_vpn)
+            .next_back()
+            .filter(|(_, prev)| prev.can_merge(&self.areas[&start_vpn]))
+            .map(|(vpn, _)| *vpn);
+        if let Some(prev_vpn) = prev_vpn {
+            let area = self.areas.remove(&start_vpn).unwrap();
+            self.areas.get_mut(&prev_vpn).unwrap().merge(area);
+        }
+    }
+
+    /// 解除 [start_vpn, end_vpn) 中用户区域的映射, 只有部分落在其中的区域会被拆分.
+    /// 共享文件映射中写入的数据先交给文件
+    pub fn unmap_range(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) {
+        self.split_at(start_vpn);
+        self.split_at(end_vpn);
+        let starts: Vec<VirtPageNum> = self
+            .areas
+            .range(start_vpn..end_vpn)
+            // 用户不可访问的区域 (如 Trap 上下文) 由内核管理
+            .filter(|(_, area)| area.permission.contains(MapPermission::U))
+            .map(|(vpn, _)| *vpn)
+            .collect();
+        for vpn in starts {
+            let mut area = self.areas.remove(&vpn).unwrap();
+            area.write_back(area.vpn_range, &self.page_table, true);
+            area.erase_pagetable(&mut self.page_table);
+        }
     }
 
     /// Insert a contiguous logical segment into the current address space
@@ -137,10 +243,10 @@ impl MemorySet {
     /// the child process's address space during COW creation, where specific
     /// physical page numbers need to be specified.
     pub fn push_mapped_area(&mut self, map_area: VmArea) {
-        self.map_areas.push(map_area);
+        self.areas.insert(map_area.start_vpn(), map_area);
     }
 
-    pub fn map_trampoline(&mut self) {
+    pub fn map_trampoline(&mut self) -> Result<(), Errno> {
         extern "C" {
             fn strampoline();
         }
@@ -148,10 +254,10 @@ impl MemorySet {
             VirtAddr::from(TRAMPOLINE).into(),
             PhysAddr::from(strampoline as usize).into(),
             PTEFlags::R | PTEFlags::X,
-        );
+        )
     }
 
-    pub fn map_signal_trampoline(&mut self) {
+    pub fn map_signal_trampoline(&mut self) -> Result<(), Errno> {
         extern "C" {
             fn user_sigreturn();
         }
@@ -159,10 +265,10 @@ impl MemorySet {
             VirtAddr::from(SIGNAL_TRAMPOLINE).into(),
             PhysAddr::from(user_sigreturn as usize).into(),
             PTEFlags::R | PTEFlags::X | PTEFlags::U,
-        );
+        )
     }
 
-    pub fn map_trap_context(&mut self) {
+    pub fn map_trap_context(&mut self) -> Result<(), Errno> {
         self.insert_and_map(
             VmArea::new(
                 TRAP_CONTEXT_BASE.into(),
@@ -172,13 +278,13 @@ impl MemorySet {
                 // 不包括 U, 虽然 trap context 位于应用地址空间的次高页面, 但仅 S 态的内核可访问
                 // 虽然不在同一地址空间, 内核可通过 Task->MemorySet->PageTable 查询并获取应用的 TrapContext
                 MapPermission::R | MapPermission::W,
-                None,
-                0,
+                VmBacking::Anonymous,
+                false,
             ),
             None,
-        );
+        )
     }
-    pub fn map_thread_trap_context(&mut self, tid: usize) {
+    pub fn map_thread_trap_context(&mut self, tid: usize) -> Result<(), Errno> {
         assert!(tid > 0 && tid < THREAD_LIMIT);
         let start_va = trap_context_position(tid);
         let end_va = VirtAddr::from(start_va.0 + PAGE_SIZE);
@@ -189,14 +295,15 @@ impl MemorySet {
                 MapType::Framed,
                 VmAreaType::TrapContext,
                 MapPermission::R | MapPermission::W,
-                None,
-                0,
+                VmBacking::Anonymous,
+                false,
             ),
             None,
-        );
+        )
     }
 
-    pub fn load_elf(elf_file: Arc<dyn File>) -> LoadedELF {
+    /// 从 ELF 文件构造新的地址空间. 读取文件出错时返回相应的错误, 文件不是合法的 ELF 时返回 ENOEXEC
+    pub fn load_elf(elf_file: Arc<dyn File>) -> Result<LoadedELF, Errno> {
         #[cfg(feature = "static-busybox")]
         {
             const BB: &str = "BUSYBOX";
@@ -204,17 +311,17 @@ impl MemorySet {
                 return hijack_busybox_load_elf();
             }
         }
-        let mut memory_set = Self::new_bare();
+        let mut memory_set = Self::new_bare()?;
         let mut auxs = Vec::new();
 
-        memory_set.map_trampoline();
-        memory_set.map_signal_trampoline();
-        memory_set.map_trap_context();
+        memory_set.map_trampoline()?;
+        memory_set.map_signal_trampoline()?;
+        memory_set.map_trap_context()?;
 
         // Read the first 64 bytes to determine the position and size of the program table.
-        let elf_head_data = elf_file.kernel_read_with_offset(0, 64);
+        let elf_head_data = elf_file.kernel_read_with_offset(0, 64)?;
         let elf_head_data_slice = elf_head_data.as_slice();
-        let elf = xmas_elf::ElfFile::new(elf_head_data_slice).unwrap();
+        let elf = xmas_elf::ElfFile::new(elf_head_data_slice).map_err(|_| Errno::ENOEXEC)?;
 
         let ph_entry_size = elf.header.pt2.ph_entry_size() as usize;
         let ph_offset = elf.header.pt2.ph_offset() as usize;
@@ -223,8 +330,8 @@ impl MemorySet {
         // Perform a second read so that the ELF object can correctly parse the
         // information in the program header.
         let elf_head_data =
-            elf_file.kernel_read_with_offset(0, ph_offset + ph_count * ph_entry_size);
-        let elf = xmas_elf::ElfFile::new(elf_head_data.as_slice()).unwrap();
+            elf_file.kernel_read_with_offset(0, ph_offset + ph_count * ph_entry_size)?;
+        let elf = xmas_elf::ElfFile::new(elf_head_data.as_slice()).map_err(|_| Errno::ENOEXEC)?;
 
         let mut head_va = None; // top va of ELF which points to ELF header
 
@@ -234,8 +341,8 @@ impl MemorySet {
         let mut entry_point = elf.header.pt2.entry_point() as usize;
         // Iterate through the program segments for loading.
         for i in 0..ph_count as u16 {
-            let ph = elf.program_header(i).unwrap();
-            match ph.get_type().unwrap() {
+            let ph = elf.program_header(i).map_err(|_| Errno::ENOEXEC)?;
+            match ph.get_type().map_err(|_| Errno::ENOEXEC)? {
                 xmas_elf::program::Type::Load => {
                     let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
                     let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
@@ -260,8 +367,12 @@ impl MemorySet {
                         MapType::Framed,
                         VmAreaType::Elf,
                         map_perm,
-                        Some(Arc::clone(&elf_file)),
-                        start_va.page_offset(),
+                        VmBacking::File {
+                            file: Arc::clone(&elf_file),
+                            offset: ph.offset() as usize - start_va.page_offset(),
+                            len: start_va.page_offset() + ph.file_size() as usize,
+                        },
+                        false,
                     );
                     brk_start_va = end_va;
                     memory_set.insert_and_map(
@@ -271,7 +382,7 @@ impl MemorySet {
                             ph.file_size() as usize,
                             start_va.page_offset(),
                         )),
-                    );
+                    )?;
                 }
                 xmas_elf::program::Type::Phdr => {
                     // auxs.push(AuxEntry(AT_PHDR, ph.virtual_addr() as usize));
@@ -285,26 +396,27 @@ impl MemorySet {
         }
         if dynamic_link {
             let path = AbsolutePath::from_str("/libc.so");
-            let interpreter_file = open(path, OpenFlags::O_RDONLY, CreateMode::empty())
-                .expect("can't find interpreter file");
+            let interpreter_file = open(path, OpenFlags::empty(), CreateMode::empty())?;
 
-            let interpreter_head_data = interpreter_file.kernel_read_with_offset(0, 64);
-            let interp_elf = xmas_elf::ElfFile::new(interpreter_head_data.as_slice()).unwrap();
+            let interpreter_head_data = interpreter_file.kernel_read_with_offset(0, 64)?;
+            let interp_elf = xmas_elf::ElfFile::new(interpreter_head_data.as_slice())
+                .map_err(|_| Errno::ENOEXEC)?;
 
             let ph_entry_size = interp_elf.header.pt2.ph_entry_size() as usize;
             let ph_offset = interp_elf.header.pt2.ph_offset() as usize;
             let ph_count = interp_elf.header.pt2.ph_count() as usize;
 
-            let interpreter_head_data =
-                interpreter_file.kernel_read_with_offset(0, ph_offset + ph_count * ph_entry_size);
-            let interp_elf = xmas_elf::ElfFile::new(interpreter_head_data.as_slice()).unwrap();
+            let interpreter_head_data = interpreter_file
+                .kernel_read_with_offset(0, ph_offset + ph_count * ph_entry_size)?;
+            let interp_elf = xmas_elf::ElfFile::new(interpreter_head_data.as_slice())
+                .map_err(|_| Errno::ENOEXEC)?;
             auxs.push(AuxEntry(AT_BASE, LINK_BASE));
             entry_point = LINK_BASE + interp_elf.header.pt2.entry_point() as usize;
             // get the number of program header
             let ph_count = interp_elf.header.pt2.ph_count();
             for i in 0..ph_count {
-                let ph = interp_elf.program_header(i).unwrap();
-                if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
+                let ph = interp_elf.program_header(i).map_err(|_| Errno::ENOEXEC)?;
+                if ph.get_type().map_err(|_| Errno::ENOEXEC)? == xmas_elf::program::Type::Load {
                     let start_va: VirtAddr = (ph.virtual_addr() as usize + LINK_BASE).into();
                     let end_va: VirtAddr =
                         (ph.virtual_addr() as usize + ph.mem_size() as usize + LINK_BASE).into();
@@ -317,8 +429,12 @@ impl MemorySet {
                         MapType::Framed,
                         VmAreaType::Elf,
                         map_perm,
-                        Some(interpreter_file.clone()),
-                        0,
+                        VmBacking::File {
+                            file: interpreter_file.clone(),
+                            offset: ph.offset() as usize - start_va.page_offset(),
+                            len: start_va.page_offset() + ph.file_size() as usize,
+                        },
+                        false,
                     );
                     memory_set.insert_and_map(
                         map_area,
@@ -327,7 +443,7 @@ impl MemorySet {
                             ph.file_size() as usize,
                             start_va.page_offset(),
                         )),
-                    );
+                    )?;
                 }
             }
         } else {
@@ -337,7 +453,7 @@ impl MemorySet {
         let user_stack_top = USER_STACK_BASE;
         let user_stack_bottom = user_stack_top - USER_STACK_SIZE;
 
-        let ph_head_addr = head_va.unwrap() + elf.header.pt2.ph_offset() as usize;
+        let ph_head_addr = head_va.ok_or(Errno::ENOEXEC)? + elf.header.pt2.ph_offset() as usize;
 
         // get auxv vector
         auxs.push(AuxEntry(0x21, 0 as usize)); //no vdso
@@ -362,212 +478,138 @@ impl MemorySet {
         auxs.push(AuxEntry(AT_GID, 0 as usize));
         auxs.push(AuxEntry(AT_EGID, 0 as usize));
         auxs.push(AuxEntry(AT_SECURE, 0 as usize));
-        // do not add this line, program will run additional check?
-        auxs.push(AuxEntry(
-            AT_RANDOM,
-            user_stack_top - 2 * core::mem::size_of::<usize>(),
-        ));
+        // AT_RANDOM 在 init_ustack 中填入
         // auxs.push(AuxEntry(AT_EXECFN, 32));
         // do not add this line, too wide
         // auxs.push(AuxEntry(AT_NULL, 0));
 
         memory_set.user_stack_start = user_stack_bottom;
         memory_set.user_stack_end = user_stack_top;
-        memory_set.user_stack_areas = VmArea::new(
+        memory_set.insert_area(VmArea::new(
             user_stack_bottom.into(),
             user_stack_top.into(),
             MapType::Framed,
             VmAreaType::UserStack,
             MapPermission::R | MapPermission::W | MapPermission::U,
-            None,
-            0,
-        );
-
-        let user_heap_bottom: usize = usize::from(brk_start_va) + PAGE_SIZE;
-        let user_heap_top: usize = user_heap_bottom + USER_HEAP_SIZE;
-        // println!("[DEBUG] user heap:0x{:x?},0x{:x?}",user_heap_bottom,user_heap_top);
-        memory_set.heap_areas = VmArea::new(
-            user_heap_bottom.into(),
-            user_heap_top.into(),
-            MapType::Framed,
-            VmAreaType::UserHeap,
-            MapPermission::R | MapPermission::W | MapPermission::U,
-            Some(Arc::clone(&elf_file)),
-            VirtAddr(user_heap_bottom).page_offset(),
-        );
+            VmBacking::Anonymous,
+            false,
+        ));
 
+        // 堆从 ELF 之后的第一页开始, 随 brk 增长时再插入区域
+        let user_heap_bottom: usize = VirtAddr::from(brk_start_va.ceil()).0;
         memory_set.brk = user_heap_bottom;
         memory_set.brk_start = user_heap_bottom;
 
-        LoadedELF {
+        Ok(LoadedELF {
             memory_set,
             user_stack_top,
             elf_entry: entry_point,
             auxs,
-        }
+        })
     }
 
     /// Copy an address space using the Copy-On-Write (COW) technique.
-    pub fn from_copy_on_write(user_space: &mut MemorySet) -> MemorySet {
-        let mut new_memory_set = Self::new_bare(); // use 1 page (page_table root)
+    ///
+    /// Returns ENOMEM when frames for the page table or the trap contexts run out.
+    /// Pages already shared with the dropped copy become writable again on the next
+    /// write fault in the parent, since their reference count falls back to 1.
+    pub fn from_copy_on_write(user_space: &mut MemorySet) -> Result<MemorySet, Errno> {
+        let mut new_memory_set = Self::new_bare()?; // use 1 page (page_table root)
 
         // This part is not for Copy on Write.
         // Including:   Trampoline
         //              Trap_Context
-        new_memory_set.map_trampoline();
-        new_memory_set.map_signal_trampoline();
+        new_memory_set.map_trampoline()?;
+        new_memory_set.map_signal_trampoline()?;
 
-        // This part is for copy on write
-        let parent_areas = &user_space.map_areas;
+        // 同一个共享内存段被拆分为多个区域时, 子进程中也只 attach 一次
+        let mut shm_trackers: Vec<(*const SharedMemoryTracker, Arc<SharedMemoryTracker>)> =
+            Vec::new();
         let parent_page_table = &mut user_space.page_table;
-        for area in parent_areas.iter() {
-            match area.area_type {
-                VmAreaType::TrapContext => {
+        for area in user_space.areas.values() {
+            let mut new_area = VmArea::from_another(area);
+            match (area.area_type, &area.backing) {
+                (VmAreaType::TrapContext, _) => {
                     // 对于 TrapContext, 不采用 COW, 直接复制
-                    let new_area = VmArea::from_another(area);
-                    new_memory_set.insert_and_map(new_area, None);
+                    new_area.inflate_pagetable(&mut new_memory_set.page_table)?;
                     for vpn in area.vpn_range {
                         let src_ppn = parent_page_table.translate(vpn).unwrap().ppn();
                         let dst_ppn = new_memory_set.translate(vpn).unwrap().ppn();
-                        // println!{"[COW TRAP_CONTEXT] mapping {:?} --- {:?}, src: {:?}", vpn, dst_ppn, src_ppn};
                         dst_ppn
                             .as_bytes_array()
                             .copy_from_slice(src_ppn.as_bytes_array());
-
-                        // TODO 在 insert_and_map 中已经添加过
-                        // new_area
-                        //     .frame_map
-                        //     .insert(vpn, FrameTracker::from_ppn(dst_ppn));
                     }
                 }
-                VmAreaType::UserStack | VmAreaType::Elf => {
-                    let mut new_area = VmArea::from_another(area);
-                    // map the former physical address
+                (_, VmBacking::Shm(tracker)) => {
+                    let key = Arc::as_ptr(tracker);
+                    let new_tracker = match shm_trackers.iter().find(|(k, _)| *k == key) {
+                        Some((_, new_tracker)) => new_tracker.clone(),
+                        None => {
+                            let new_tracker = Arc::new(SharedMemoryTracker::new(tracker.key));
+                            shm_trackers.push((key, new_tracker.clone()));
+                            new_tracker
+                        }
+                    };
+                    new_area.backing = VmBacking::Shm(new_tracker);
                     for vpn in area.vpn_range {
+                        if let Some(pte) = parent_page_table.translate(vpn) {
+                            if pte.is_valid() {
+                                new_memory_set.page_table.map(vpn, pte.ppn(), pte.flags())?;
+                            }
+                        }
+                    }
+                }
+                _ if area.shared => {
+                    // 共享映射在父子进程间共享同一个页帧, 不做写时复制
+                    for (vpn, frame) in area.frame_map.iter() {
+                        let pte = parent_page_table.translate(*vpn).unwrap();
+                        new_memory_set
+                            .page_table
+                            .map(*vpn, frame.ppn, pte.flags())?;
+                        new_area.frame_map.insert(*vpn, frame.clone());
+                    }
+                }
+                _ => {
+                    // Only perform copy-on-write on the already mapped pages.
+                    for vpn in area.frame_map.keys() {
                         // change the map permission of both pagetable
                         // get the former flags and ppn
-                        let pte = parent_page_table.translate(vpn).unwrap();
+                        let pte = parent_page_table.translate(*vpn).unwrap();
                         let pte_flags = pte.flags() & !PTEFlags::W;
                         let src_ppn = pte.ppn();
-                        // frame_add_ref(src_ppn);
                         // change the flags of the src_pte
-                        parent_page_table.set_flags(vpn, pte_flags);
-                        parent_page_table.set_cow(vpn);
+                        parent_page_table.set_flags(*vpn, pte_flags);
+                        parent_page_table.set_cow(*vpn);
                         // map the cow page table to src_ppn
-                        new_memory_set.page_table.map(vpn, src_ppn, pte_flags);
-                        new_memory_set.page_table.set_cow(vpn);
+                        new_memory_set.page_table.map(*vpn, src_ppn, pte_flags)?;
+                        new_memory_set.page_table.set_cow(*vpn);
                         new_area
                             .frame_map
-                            .insert(vpn, FrameTracker::from_ppn(src_ppn));
+                            .insert(*vpn, FrameTracker::from_ppn(src_ppn));
                     }
-                    new_memory_set.push_mapped_area(new_area);
-                }
-                _ => {
-                    unreachable!()
-                }
-            }
-        }
-        new_memory_set.mmap_manager = user_space.mmap_manager.clone();
-        for (vpn, mmap_page) in user_space.mmap_manager.mmap_map.iter() {
-            // Only perform copy-on-write on the already mapped segments.
-            if mmap_page.valid {
-                let vpn = vpn.clone();
-                if let Some(pte) = parent_page_table.translate(vpn) {
-                    // change the map permission of both pagetable
-                    // get the former flags and ppn
-
-                    let pte_flags = pte.flags() & !PTEFlags::W;
-                    let src_ppn = pte.ppn();
-                    // frame_add_ref(src_ppn);
-                    // change the flags of the src_pte
-                    parent_page_table.set_flags(vpn, pte_flags);
-                    parent_page_table.set_cow(vpn);
-                    // map the cow page table to src_ppn
-                    new_memory_set.page_table.map(vpn, src_ppn, pte_flags);
-                    new_memory_set.page_table.set_cow(vpn);
                 }
             }
+            new_memory_set.push_mapped_area(new_area);
         }
-        new_memory_set.user_stack_areas = VmArea::from_another(&user_space.user_stack_areas);
-        for vpn in user_space.user_stack_areas.vpn_range.into_iter() {
-            // change the map permission of both pagetable
-            // get the former flags and ppn
-
-            // Only perform copy-on-write on the already mapped segments.
-            if let Some(pte) = parent_page_table.translate(vpn) {
-                let pte_flags = pte.flags() & !PTEFlags::W;
-                let src_ppn = pte.ppn();
-                // frame_add_ref(src_ppn);
-                // change the flags of the src_pte
-                parent_page_table.set_flags(vpn, pte_flags);
-                parent_page_table.set_cow(vpn);
-                // map the cow page table to src_ppn
-                new_memory_set.page_table.map(vpn, src_ppn, pte_flags);
-                new_memory_set.page_table.set_cow(vpn);
-
-                new_memory_set
-                    .user_stack_areas
-                    .frame_map
-                    .insert(vpn, FrameTracker::from_ppn(src_ppn));
-            }
-        }
-        new_memory_set.user_stack_start = user_space.user_stack_start;
-        new_memory_set.user_stack_end = user_space.user_stack_end;
-
-        new_memory_set.heap_areas = VmArea::from_another(&user_space.heap_areas);
-        for vpn in user_space.heap_areas.vpn_range.into_iter() {
-            // change the map permission of both pagetable
-            // get the former flags and ppn
-
-            // Only perform copy-on-write on the already mapped segments.
-            if let Some(pte) = parent_page_table.translate(vpn) {
-                let pte_flags = pte.flags() & !PTEFlags::W;
-                let src_ppn = pte.ppn();
-                // frame_add_ref(src_ppn);
-                // change the flags of the src_pte
-                parent_page_table.set_flags(vpn, pte_flags);
-                parent_page_table.set_cow(vpn);
-                // map the cow page table to src_ppn
-                new_memory_set.page_table.map(vpn, src_ppn, pte_flags);
-                new_memory_set.page_table.set_cow(vpn);
-
-                new_memory_set
-                    .heap_areas
-                    .frame_map
-                    .insert(vpn, FrameTracker::from_ppn(src_ppn));
-            }
-        }
+        new_memory_set.shm_top = user_space.shm_top;
         new_memory_set.brk_start = user_space.brk_start;
         new_memory_set.brk = user_space.brk;
+        new_memory_set.user_stack_start = user_space.user_stack_start;
+        new_memory_set.user_stack_end = user_space.user_stack_end;
 
-        for shm_area in user_space.shm_areas.iter() {
-            let new_shm_area = VmArea::from_another(shm_area);
-
-            for vpn in shm_area.vpn_range.into_iter() {
-                // change the map permission of both pagetable
-                // get the former flags and ppn
-
-                if let Some(pte) = parent_page_table.translate(vpn) {
-                    let pte_flags = pte.flags();
-                    let src_ppn = pte.ppn();
-                    new_memory_set.page_table.map(vpn, src_ppn, pte_flags);
-                }
-            }
-            new_memory_set.shm_areas.push(new_shm_area);
-        }
-        new_memory_set.shm_top = user_space.shm_top;
-        for (va, shm_tracker) in user_space.shm_trackers.iter() {
-            let new_shm_tracker = SharedMemoryTracker::new(shm_tracker.key);
-            new_memory_set
-                .shm_trackers
-                .insert(va.clone(), new_shm_tracker);
-        }
-
-        new_memory_set
+        Ok(new_memory_set)
     }
 
     #[no_mangle]
     pub fn cow_alloc(&mut self, vpn: VirtPageNum, former_ppn: PhysPageNum) -> isize {
+        let area = match self.areas.range_mut(..=vpn).next_back() {
+            Some((_, area)) if area.contains(vpn) => area,
+            _ => panic!("cow of of range"),
+        };
+        // 只读区域中的页 (如代码段) 也以写时复制的方式共享, 写入它们是非法的
+        if !area.permission.contains(MapPermission::W) {
+            return -4;
+        }
         // If there is only one reference, change it back to writable instead of reallocating ppn.
         if enquire_refcount(former_ppn) == 1 {
             self.page_table.reset_cow(vpn);
@@ -579,58 +621,35 @@ impl MemorySet {
             return 0;
         }
         // If there are multiple references, allocate a new physical page and copy the content to it.
-        let frame = alloc_frame().unwrap();
-        let ppn = frame.ppn;
-        self.remap_cow(vpn, ppn, former_ppn);
-
-        // Note: Here, the reference count is reduced using the insert() method of BTreeMap.
-        for area in self.map_areas.iter_mut() {
-            let head_vpn = area.vpn_range.get_start();
-            let tail_vpn = area.vpn_range.get_end();
-            if vpn < tail_vpn && vpn >= head_vpn {
-                // Before the BTreeMap insert, enqueue_refcount(former_ppn) > 1
-                area.frame_map.insert(vpn, frame);
-                // After the BTreeMap insert, since vpn key has already been inserted in
-                // frame_map during from_copy_on_write(), the insert will return the old value,
-                // the original FrameTracker will be dropped, reducing the reference count of former_ppn.
-                return 0;
-            }
-        }
-        if vpn >= VirtPageNum::from(self.mmap_manager.mmap_start)
-            && vpn < VirtPageNum::from(self.mmap_manager.mmap_top)
-        {
-            self.mmap_manager.frame_map.insert(vpn, frame);
-            return 0;
-        }
-        if vpn >= self.user_stack_areas.vpn_range.get_start()
-            && vpn < self.user_stack_areas.vpn_range.get_end()
-        {
-            self.user_stack_areas.frame_map.insert(vpn, frame);
-            return 0;
-        }
-
-        if vpn >= self.heap_areas.vpn_range.get_start() && vpn < self.heap_areas.vpn_range.get_end()
-        {
-            self.heap_areas.frame_map.insert(vpn, frame);
-            return 0;
-        }
-        panic!("cow of of range");
-    }
-
-    fn remap_cow(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, former_ppn: PhysPageNum) {
-        self.page_table.remap_cow(vpn, ppn, former_ppn);
-    }
-
-    pub fn lazy_alloc_heap(&mut self, vpn: VirtPageNum) -> isize {
-        self.heap_areas.lazy_map_vpn(vpn, &mut self.page_table);
-
+        let frame = match alloc_frame() {
+            Some(frame) => frame,
+            None => return -(Errno::ENOMEM as isize),
+        };
+        self.page_table.remap_cow(vpn, frame.ppn, former_ppn);
+        // Before the BTreeMap insert, enqueue_refcount(former_ppn) > 1
+        // The insert returns the old FrameTracker inserted during from_copy_on_write(),
+        // which is dropped, reducing the reference count of former_ppn.
+        area.frame_map.insert(vpn, frame);
         0
     }
-    pub fn lazy_alloc_stack(&mut self, vpn: VirtPageNum) -> isize {
-        self.user_stack_areas
-            .lazy_map_vpn(vpn, &mut self.page_table);
 
-        0
+    /// 缺页时在 `vpn` 所在的区域中按需建立映射. 成功时返回 0;
+    /// `vpn` 不属于任何可按需映射的区域, 或所在区域为 `PROT_NONE` 时返回 -2;
+    /// 页帧耗尽时返回 -ENOMEM, 读取文件出错时返回 -EIO 等相应的错误码
+    pub fn lazy_map(&mut self, vpn: VirtPageNum) -> isize {
+        match self.areas.range_mut(..=vpn).next_back() {
+            Some((_, area))
+                if area.contains(vpn)
+                    && area.accessible()
+                    && !matches!(area.backing, VmBacking::Shm(_)) =>
+            {
+                match area.lazy_map_vpn(vpn, &mut self.page_table) {
+                    Ok(()) => 0,
+                    Err(errno) => -(errno as isize),
+                }
+            }
+            _ => -2,
+        }
     }
 
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
@@ -639,53 +658,88 @@ impl MemorySet {
 
     /// Reclaim the application address space.
     ///
-    /// Clear the list of logical segments areas (i.e., perform a clear
-    /// operation on the Vec vector).
+    /// Remove the user areas of the address space, the trap contexts are kept.
     /// This will result in the application address space being reclaimed
     /// (i.e., the physical page frames corresponding to the process's data
     /// and code will be reclaimed).
     /// However, the physical page frames used to store the page tables will
     /// not be reclaimed at this point (they will be reclaimed by the parent
     /// process as it cleans up the remaining resources of the child process).
-    #[allow(unused)]
     pub fn recycle_data_pages(&mut self) {
-        //*self = Self::new_bare();
-        self.map_areas.clear();
+        let starts: Vec<VirtPageNum> = self
+            .areas
+            .iter()
+            .filter(|(_, area)| area.permission.contains(MapPermission::U))
+            .map(|(vpn, _)| *vpn)
+            .collect();
+        for vpn in starts {
+            let mut area = self.areas.remove(&vpn).unwrap();
+            area.write_back(area.vpn_range, &self.page_table, false);
+            area.erase_pagetable(&mut self.page_table);
+        }
     }
 
-    pub fn check_va_range(&self, start_va: VirtAddr, len: usize) -> bool {
-        let end_va = VirtAddr::from(start_va.0 + len);
-        for area in self.map_areas.iter() {
-            if area.vpn_range.get_start() <= start_va.floor()
-                && end_va.ceil() <= area.vpn_range.get_end()
-            {
-                return true;
-            }
-        }
-        if self.mmap_manager.mmap_start <= start_va && end_va < self.mmap_manager.mmap_top {
-            return true;
+    /// exec 时按 RLIMIT_STACK 缩小用户栈, 栈底之下的访问产生 SIGSEGV.
+    /// 超出 `USER_STACK_SIZE` 的限制不起作用
+    pub fn limit_stack(&mut self, limit: usize) {
+        let stack_start = VirtAddr::from(self.user_stack_start).floor();
+        let stack_end = VirtAddr::from(self.user_stack_end).floor();
+        let stack_pages = (limit / PAGE_SIZE).max(1);
+        if stack_end.0 - stack_start.0 <= stack_pages {
+            return;
         }
+        let new_start = VirtPageNum(stack_end.0 - stack_pages);
+        self.unmap_range(stack_start, new_start);
+        self.user_stack_start = VirtAddr::from(new_start).0;
+    }
 
-        // TODO boundary check
-        if self.heap_areas.vpn_range.get_start() <= start_va.floor()
-            && end_va.ceil() <= self.heap_areas.vpn_range.get_end()
-        {
-            return true;
-        }
-        if self.user_stack_areas.vpn_range.get_start() <= start_va.floor()
-            && end_va.ceil() <= self.user_stack_areas.vpn_range.get_end()
-        {
-            return true;
-        }
+    /// 用户区域的总页数, 对应 RLIMIT_AS
+    pub fn total_pages(&self) -> usize {
+        self.user_areas().map(|area| area.page_count()).sum()
+    }
+
+    /// 私有可写且不是栈的用户区域的总页数, 即堆, 数据段与私有的可写映射, 对应 RLIMIT_DATA
+    pub fn data_pages(&self) -> usize {
+        self.user_areas()
+            .filter(|area| {
+                !area.shared
+                    && area.permission.contains(MapPermission::W)
+                    && area.area_type != VmAreaType::UserStack
+            })
+            .map(|area| area.page_count())
+            .sum()
+    }
 
-        return false;
+    /// 用户区域中已经映射到页帧的页数, OOM killer 以此选择进程
+    pub fn resident_pages(&self) -> usize {
+        self.user_areas().map(|area| area.frame_map.len()).sum()
+    }
+
+    fn user_areas(&self) -> impl Iterator<Item = &VmArea> {
+        self.areas
+            .values()
+            .filter(|area| area.permission.contains(MapPermission::U))
+    }
+
+    /// [start_va, start_va + len) 是否完全被区域覆盖
+    pub fn check_va_range(&self, start_va: VirtAddr, len: usize) -> bool {
+        let end_vpn = VirtAddr::from(start_va.0 + len).ceil();
+        let mut vpn = start_va.floor();
+        while vpn < end_vpn {
+            match self.find_area(vpn) {
+                Some(area) => vpn = area.end_vpn(),
+                None => return false,
+            }
+        }
+        true
     }
 
     #[allow(unused)]
     pub fn is_lazy_mapped(&self, addr_vpn: VirtPageNum) -> bool {
         self.page_table.find_pte(addr_vpn).is_some()
     }
-    pub fn attach_shm(&mut self, key: usize, start_va: VirtAddr) {
+    /// 将共享内存段 `key` attach 到 `start_va`. 页表的页帧耗尽时撤销已经建立的映射并返回 ENOMEM
+    pub fn attach_shm(&mut self, key: usize, start_va: VirtAddr) -> Result<(), Errno> {
         let (start_pa, size) = shm_get_address_and_size(key);
         let flags = PTEFlags::V | PTEFlags::U | PTEFlags::W | PTEFlags::R;
         let mut offset = 0;
@@ -694,53 +748,236 @@ impl MemorySet {
             let va: VirtAddr = (start_va.0 + offset).into();
             let pa: PhysAddr = (start_pa.0 + offset).into();
             // println!("attach map va:{:x?} to pa{:x?}",va,pa);
-            self.page_table.map(va.into(), pa.into(), flags);
+            if let Err(errno) = self.page_table.map(va.into(), pa.into(), flags) {
+                for mapped in (0..offset).step_by(PAGE_SIZE) {
+                    self.page_table
+                        .unmap(VirtAddr::from(start_va.0 + mapped).into());
+                }
+                return Err(errno);
+            }
             offset += PAGE_SIZE;
         }
         self.shm_top = self.shm_top.max(start_va.0 + size);
-        let shm_tracker = SharedMemoryTracker::new(key);
-
-        self.shm_trackers.insert(start_va, shm_tracker);
-        let vma = VmArea::new(
+        let shm_tracker = Arc::new(SharedMemoryTracker::new(key));
+        self.push_mapped_area(VmArea::new(
             start_va,
             (start_va.0 + size).into(),
             MapType::Framed,
-            VmAreaType::Shared,
-            MapPermission::R | MapPermission::W,
-            None,
-            0,
-        );
-        self.shm_areas.push(vma);
-    }
-    pub fn detach_shm(&mut self, start_va: VirtAddr) -> usize {
-        // println!("detach start_va:{:?}",start_va);
-        let key = self.shm_trackers.get(&start_va).unwrap().key;
-        let (_, size) = shm_get_address_and_size(key);
-        // println!("detach size:{:?}",size);
-        let mut offset = 0;
-        while offset < size {
-            let va: VirtAddr = (start_va.0 + offset).into();
-            // println!("detach va:{:?}",va);
-            self.page_table.unmap(va.into());
-            offset += PAGE_SIZE
-        }
-        self.shm_trackers.remove(&start_va);
-        let vpn: VirtPageNum = start_va.into();
-        self.shm_areas.retain(|x| x.start_vpn() != vpn);
-        shm_get_nattch(key)
-    }
-    pub fn lazy_mmap(&mut self, vpn: VirtPageNum) {
-        if let Some(frame) = alloc_frame() {
-            let ppn = frame.ppn;
-            self.mmap_manager.frame_map.insert(vpn, frame);
-            let mmap_page = self.mmap_manager.mmap_map.get_mut(&vpn).unwrap();
-            // let pte_flags = PTEFlags::from_bits((mmap_page.prot.bits() << 1 & 0xf) as u64).unwrap(); // TODO
-            let pte_flags = PTEFlags::from_map_ports(mmap_page.prot);
-            let pte_flags = pte_flags | PTEFlags::U;
-            self.page_table.map(vpn, ppn, pte_flags);
-            mmap_page.lazy_map_page(self.page_table.token());
-        } else {
-            panic!("No more memory!");
+            VmAreaType::Shm,
+            MapPermission::R | MapPermission::W | MapPermission::U,
+            VmBacking::Shm(shm_tracker),
+            true,
+        ));
+        Ok(())
+    }
+    /// 解除 attach 在 `start_va` 处的共享内存段, 返回其剩余的 attach 次数.
+    /// `start_va` 处没有共享内存段时返回 None
+    pub fn detach_shm(&mut self, start_va: VirtAddr) -> Option<usize> {
+        let start_vpn = start_va.floor();
+        let tracker = match self.areas.get(&start_vpn).map(|area| &area.backing) {
+            Some(VmBacking::Shm(tracker)) => tracker.clone(),
+            _ => return None,
+        };
+        // 段可能已被 mprotect 拆分为多个区域
+        let starts: Vec<VirtPageNum> = self
+            .areas
+            .range(start_vpn..)
+            .take_while(
+                |(_, area)| matches!(&area.backing, VmBacking::Shm(t) if Arc::ptr_eq(t, &tracker)),
+            )
+            .map(|(vpn, _)| *vpn)
+            .collect();
+        for vpn in starts {
+            let mut area = self.areas.remove(&vpn).unwrap();
+            area.erase_pagetable(&mut self.page_table);
+        }
+        let key = tracker.key;
+        drop(tracker);
+        Some(shm_get_nattch(key))
+    }
+    /// 写入可写的 `MAP_SHARED` 文件映射中只读映射的页: 通知文件该页变脏, 然后恢复写权限.
+    /// 不是这种情况时返回 false
+    pub fn shared_write_fault(&mut self, vpn: VirtPageNum) -> bool {
+        let area = match self.areas.range(..=vpn).next_back() {
+            Some((_, area)) if area.contains(vpn) => area,
+            _ => return false,
+        };
+        if !area.shared || !area.permission.contains(MapPermission::W) {
+            return false;
+        }
+        let (file, offset, _) = match area.file_page(vpn) {
+            Some(file_page) => file_page,
+            None => return false,
+        };
+        let pte = match self.page_table.translate(vpn) {
+            Some(pte) if pte.is_valid() && !pte.writable() => pte,
+            _ => return false,
+        };
+        file.dirty_shared_frame(offset);
+        self.page_table.set_flags(vpn, pte.flags() | PTEFlags::W);
+        true
+    }
+    /// 将 [start_va, start_va + len) 中共享文件映射写入的数据交给文件, 并返回涉及的文件.
+    /// 范围中有未映射的部分时返回 None
+    pub fn write_back(&self, start_va: VirtAddr, len: usize) -> Option<Vec<Arc<dyn File>>> {
+        if !self.check_va_range(start_va, len) {
+            return None;
+        }
+        let start_vpn = start_va.floor();
+        let end_vpn = VirtAddr::from(start_va.0 + len).ceil();
+        let mut files: Vec<Arc<dyn File>> = Vec::new();
+        for area in self.areas.values() {
+            if area.end_vpn() <= start_vpn || area.start_vpn() >= end_vpn || !area.shared {
+                continue;
+            }
+            let file = match &area.backing {
+                VmBacking::File { file, .. } => file,
+                _ => continue,
+            };
+            let range =
+                VPNRange::from_vpn(area.start_vpn().max(start_vpn), area.end_vpn().min(end_vpn));
+            area.write_back(range, &self.page_table, true);
+            if !files
+                .iter()
+                .any(|f| Arc::as_ptr(f) as *const () == Arc::as_ptr(file) as *const ())
+            {
+                files.push(file.clone());
+            }
+        }
+        Some(files)
+    }
+    /// 修改 [start_va, start_va + len) 的权限, 只有部分落在其中的区域会被拆分,
+    /// 修改后与相邻的区域合并. 范围中有未映射或用户不可访问的部分时返回 false
+    pub fn mprotect(&mut self, start_va: VirtAddr, len: usize, permission: MapPermission) -> bool {
+        if !self.check_va_range(start_va, len) {
+            return false;
+        }
+        let start_vpn = start_va.floor();
+        let end_vpn = VirtAddr::from(start_va.0 + len).ceil();
+        if !self.is_user_range(start_vpn, end_vpn) {
+            return false;
+        }
+        self.split_at(start_vpn);
+        self.split_at(end_vpn);
+        let starts: Vec<VirtPageNum> = self
+            .areas
+            .range(start_vpn..end_vpn)
+            .map(|(vpn, _)| *vpn)
+            .collect();
+        for vpn in starts.iter() {
+            let area = self.areas.get_mut(vpn).unwrap();
+            area.set_permission(permission, &mut self.page_table);
+        }
+        for vpn in starts.iter() {
+            self.try_merge(*vpn);
+        }
+        true
+    }
+    /// 解除 [start_va, start_va + len) 中的映射, 共享映射写入的数据先交给文件
+    pub fn munmap(&mut self, start_va: VirtAddr, len: usize) {
+        let end_va = VirtAddr::from(start_va.0 + len);
+        self.unmap_range(start_va.floor(), end_va.ceil());
+    }
+    /// 将 [old_start, old_start + old_pages) 移动到 `new_start`, 并调整为 `new_pages` 页,
+    /// 增长的部分与原区域的属性相同. 移动时只移动页表项, 不复制页中的数据.
+    /// 调用者保证原范围位于同一个区域中, 新的范围除原范围外没有映射.
+    /// 无法为新的位置建立页表时返回 ENOMEM, 原范围保持不变
+    pub fn remap(
+        &mut self,
+        old_start: VirtPageNum,
+        old_pages: usize,
+        new_start: VirtPageNum,
+        new_pages: usize,
+    ) -> Result<(), Errno> {
+        self.split_at(old_start);
+        self.split_at(VirtPageNum(old_start.0 + old_pages));
+        let mut area = self.areas.remove(&old_start).unwrap();
+        if new_start != old_start {
+            if let Err(errno) = area.move_to(new_start, &mut self.page_table) {
+                self.insert_area(area);
+                return Err(errno);
+            }
+        }
+        area.vpn_range = VPNRange::from_vpn(new_start, VirtPageNum(new_start.0 + new_pages));
+        self.insert_area(area);
+        Ok(())
+    }
+    /// 释放 [start_vpn, end_vpn) 中已经映射的页, 共享内存段及用户不可访问的区域除外
+    pub fn discard(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) {
+        for area in self.areas.values_mut() {
+            if area.end_vpn() <= start_vpn
+                || area.start_vpn() >= end_vpn
+                || !area.permission.contains(MapPermission::U)
+                || matches!(area.backing, VmBacking::Shm(_))
+            {
+                continue;
+            }
+            let range =
+                VPNRange::from_vpn(area.start_vpn().max(start_vpn), area.end_vpn().min(end_vpn));
+            area.discard(range, &mut self.page_table);
+        }
+    }
+    /// 将 [start_vpn, end_vpn) 中文件映射的页预先读入页缓存
+    pub fn prefetch(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) {
+        for area in self.areas.values() {
+            if area.end_vpn() <= start_vpn || area.start_vpn() >= end_vpn {
+                continue;
+            }
+            let range =
+                VPNRange::from_vpn(area.start_vpn().max(start_vpn), area.end_vpn().min(end_vpn));
+            for vpn in range {
+                let cached = area
+                    .file_page(vpn)
+                    .and_then(|(file, offset, _)| file.shared_frame(offset));
+                // 到达文件末尾, 或文件没有页缓存
+                if cached.is_none() {
+                    break;
+                }
+            }
+        }
+    }
+    /// [start_vpn, end_vpn) 中的每一页是否已经映射到页帧
+    pub fn residency(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> Vec<bool> {
+        VPNRange::from_vpn(start_vpn, end_vpn)
+            .into_iter()
+            .map(|vpn| {
+                self.page_table
+                    .translate(vpn)
+                    .map_or(false, |pte| pte.is_valid())
+            })
+            .collect()
+    }
+    /// 将 brk 移动到 `new_brk`, 堆区域随之伸缩, 缩小时释放多出的页.
+    /// 堆需要扩展到已被占用的地址时返回 false
+    pub fn set_brk(&mut self, new_brk: usize) -> bool {
+        let old_end = VirtAddr::from(self.brk).ceil();
+        let new_end = VirtAddr::from(new_brk).ceil();
+        if new_end > old_end {
+            if !self.is_range_free(old_end, new_end) {
+                return false;
+            }
+            self.insert_area(VmArea::new(
+                old_end.into(),
+                new_end.into(),
+                MapType::Framed,
+                VmAreaType::UserHeap,
+                MapPermission::R | MapPermission::W | MapPermission::U,
+                VmBacking::Anonymous,
+                false,
+            ));
+        } else if new_end < old_end {
+            self.unmap_range(new_end, old_end);
+        }
+        self.brk = new_brk;
+        true
+    }
+}
+
+impl Drop for MemorySet {
+    /// 进程退出或 exec 时, 共享映射写入的数据交给文件的页缓存, 之后由后台写回
+    fn drop(&mut self) {
+        for area in self.areas.values() {
+            area.write_back(area.vpn_range, &self.page_table, false);
         }
     }
 }
@@ -748,18 +985,18 @@ impl MemorySet {
 #[cfg(feature = "static-busybox")]
 use crate::task::BUSYBOX;
 #[cfg(feature = "static-busybox")]
-fn hijack_busybox_load_elf() -> LoadedELF {
+fn hijack_busybox_load_elf() -> Result<LoadedELF, Errno> {
     let bb = BUSYBOX.read();
-    let memory_set = bb.memory_set();
+    let memory_set = bb.memory_set()?;
     let user_stack_top = memory_set.user_stack_end;
     let elf_entry = bb.elf_entry_point();
     let auxs = bb.aux();
-    LoadedELF {
+    Ok(LoadedELF {
         memory_set,
         user_stack_top,
         elf_entry,
         auxs,
-    }
+    })
 }
 
 pub struct LoadedELF {
@@ -3,7 +3,6 @@ mod frame_allocator;
 mod kernel_heap_allocator;
 mod kvmm;
 mod memory_set;
-mod mmap;
 mod page_table;
 mod permission;
 mod shared_memory;
@@ -13,7 +12,6 @@ pub use address::*;
 pub use frame_allocator::*;
 pub use kvmm::*;
 pub use memory_set::*;
-pub use mmap::*;
 pub use page_table::*;
 pub use permission::*;
 pub use shared_memory::*;
@@ -23,7 +21,7 @@ pub use vm_area::*;
 #[cfg(feature = "cvitex")]
 use crate::boards::init_mmio;
 
-use crate::{consts::PAGE_SIZE, task::current_task};
+use crate::{consts::PAGE_SIZE, syscall::impls::Errno, task::current_task};
 
 use alloc::{string::String, vec::Vec};
 use core::{cmp::min, mem::size_of};
@@ -53,7 +51,14 @@ pub fn enable_mmu() {
 }
 
 /// Get a bytes buffer [`Vec`] from user's memory set.
-pub fn translated_bytes_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
+///
+/// Returns ENOMEM when frames for on-demand pages run out, and EFAULT when the
+/// range is not mapped in user's memory set.
+pub fn translated_bytes_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+) -> Result<Vec<&'static mut [u8]>, Errno> {
     let page_table = PageTable::from_token(token);
     let mut start = VirtAddr::from(ptr as usize);
     let end = VirtAddr::from(start.0 + len);
@@ -63,13 +68,14 @@ pub fn translated_bytes_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<
         let mut vpn = start.floor();
         let ppn = match page_table.translate(vpn) {
             Some(pte) => pte.ppn(),
-            None => {
-                if task.check_lazy(start) != 0 {
-                    info!("hart {} check lazy failed", hartid!());
-                    panic!("check lazy error");
+            None => match task.check_lazy(start) {
+                0 => page_table.translate(vpn).unwrap().ppn(),
+                result if result == -(Errno::ENOMEM as isize) => return Err(Errno::ENOMEM),
+                _ => {
+                    info!("hart {} check lazy failed at {:x?}", hartid!(), start);
+                    return Err(Errno::EFAULT);
                 }
-                page_table.translate(vpn).unwrap().ppn()
-            }
+            },
         };
         vpn.step();
 
@@ -82,7 +88,7 @@ pub fn translated_bytes_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<
         }
         start = in_page_end_va.into();
     }
-    v
+    Ok(v)
 }
 
 /// Get a translated [`String`] from user's memory set.
@@ -138,8 +144,8 @@ pub fn translated_mut<T>(token: usize, ptr: *mut T) -> &'static mut T {
 }
 
 /// Copy data from `src` from memory set indicated by the given token into `dst` in kernel's memory set.
-pub fn copyin<T>(token: usize, dst: &mut T, src: *const T) {
-    let src_buffer = translated_bytes_buffer(token, src as *const u8, core::mem::size_of::<T>());
+pub fn copyin<T>(token: usize, dst: &mut T, src: *const T) -> Result<(), Errno> {
+    let src_buffer = translated_bytes_buffer(token, src as *const u8, core::mem::size_of::<T>())?;
 
     let dst_slice = unsafe {
         core::slice::from_raw_parts_mut(dst as *mut T as *mut u8, core::mem::size_of::<T>())
@@ -157,13 +163,14 @@ pub fn copyin<T>(token: usize, dst: &mut T, src: *const T) {
             break;
         }
     }
+    Ok(())
 }
 
 /// Copy data from `src` out of kernel memory set into `dst` which lives in the given
 /// memory set indicated by the given `token`.
-pub fn copyout<T>(token: usize, dst: *mut T, src: &T) {
+pub fn copyout<T>(token: usize, dst: *mut T, src: &T) -> Result<(), Errno> {
     let mut dst_buffer =
-        translated_bytes_buffer(token, dst as *const u8, core::mem::size_of::<T>());
+        translated_bytes_buffer(token, dst as *const u8, core::mem::size_of::<T>())?;
 
     let src_slice = unsafe {
         core::slice::from_raw_parts(src as *const T as *const u8, core::mem::size_of::<T>())
@@ -181,4 +188,5 @@ pub fn copyout<T>(token: usize, dst: *mut T, src: &T) {
             break;
         }
     }
+    Ok(())
 }
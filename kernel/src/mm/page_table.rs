@@ -22,6 +22,7 @@
 use super::address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::permission::MapPermission;
 use super::{alloc_frame, FrameTracker};
+use crate::syscall::impls::Errno;
 use alloc::vec::Vec;
 use bitflags::*;
 use nix::MmapProts;
@@ -34,13 +35,13 @@ pub struct PageTable {
 }
 
 impl PageTable {
-    /// 新建一个 `PageTable`
-    pub fn new() -> Self {
-        let frame = alloc_frame().unwrap();
-        PageTable {
+    /// 新建一个 `PageTable`, 页帧耗尽时返回 None
+    pub fn new() -> Option<Self> {
+        let frame = alloc_frame()?;
+        Some(PageTable {
             root_ppn: frame.ppn,
             frames: vec![frame], // 将新获取到的物理页帧存入向量
-        }
+        })
     }
 
     /// 通过 `satp` 获取对应的多级页表
@@ -64,7 +65,7 @@ impl PageTable {
         }
     }
 
-    /// 根据vpn查找对应页表项, 如果在查找过程中发现无效页表则新建页表
+    /// 根据vpn查找对应页表项, 如果在查找过程中发现无效页表则新建页表, 页帧耗尽时返回 None
     pub fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
         let idxs = vpn.indexes();
         // 当前节点的物理页号, 最开始指向多级页表的根节点
@@ -81,7 +82,7 @@ impl PageTable {
             if !pte.is_valid() {
                 // 发现页表项是无效的状态
                 // 获取一个物理页帧
-                let frame = alloc_frame().unwrap();
+                let frame = alloc_frame()?;
                 // 用获取到的物理页帧生成新的页表项
                 // *pte = PageTableEntry::new(frame.ppn, "VAD".into());
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
@@ -115,12 +116,19 @@ impl PageTable {
 
     /// 建立一个虚拟页号到物理页号的映射
     ///
-    /// 根据VPN找到第三级页表中的对应项, 将 `PPN` 和 `flags` 写入到页表项
-    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.find_pte_create(vpn).unwrap();
+    /// 根据VPN找到第三级页表中的对应项, 将 `PPN` 和 `flags` 写入到页表项.
+    /// 无法为中间级页表分配页帧时返回 ENOMEM
+    pub fn map(
+        &mut self,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+        flags: PTEFlags,
+    ) -> Result<(), Errno> {
+        let pte = self.find_pte_create(vpn).ok_or(Errno::ENOMEM)?;
         // 断言, 保证新获取到的PTE是无效的(不是已分配的)
         assert!(!pte.is_valid(), "{:#x?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        Ok(())
     }
 
     /// 删除一个虚拟页号到物理页号的映射
@@ -1,23 +1,40 @@
-use alloc::{collections::BTreeMap, sync::Arc};
+use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
 
 use crate::{
     consts::PAGE_SIZE,
     fs::File,
     mm::{
         address::Step, alloc_frame, page_table::PTEFlags, FrameTracker, PageTable, PhysPageNum,
-        VPNRange, VirtAddr, VirtPageNum,
+        SharedMemoryTracker, UserBuffer, VPNRange, VirtAddr, VirtPageNum,
     },
+    syscall::impls::Errno,
 };
 
 use super::{MapPermission, MapType};
 
+/// 区域中数据的来源
+#[derive(Clone)]
+pub enum VmBacking {
+    /// 匿名内存, 第一次访问时分配清零的页帧
+    Anonymous,
+    /// 文件中从 `offset` 开始的 `len` 字节, 对应区域的开头, 之后的部分读到 0.
+    /// ELF 段的 `len` 不超过段在文件中的长度, 文件映射的 `len` 为 `usize::MAX`
+    File {
+        file: Arc<dyn File>,
+        offset: usize,
+        len: usize,
+    },
+    /// System V 共享内存段, attach 时即映射全部页
+    Shm(Arc<SharedMemoryTracker>),
+}
+
 pub struct VmArea {
     pub area_type: VmAreaType,
     pub vpn_range: VPNRange,
     pub map_type: MapType,
     pub permission: MapPermission,
-    pub file: Option<Arc<dyn File>>, // Mapped file.
-    pub file_offset: usize,          // Offset of the mapped file in the file.
+    pub backing: VmBacking,
+    pub shared: bool, // 写入对同一来源的其他映射可见, fork 时不做写时复制
     pub frame_map: BTreeMap<VirtPageNum, FrameTracker>, // vpn -> frame_tracker
 }
 
@@ -27,7 +44,8 @@ pub enum VmAreaType {
     UserStack,
     Elf,
     TrapContext,
-    Shared, // Mmap
+    Mmap,
+    Shm,
     KernelStack,
     KernelSpace,
 }
@@ -39,8 +57,8 @@ impl VmArea {
         map_type: MapType,
         area_type: VmAreaType,
         permission: MapPermission,
-        file: Option<Arc<dyn File>>,
-        file_offset: usize,
+        backing: VmBacking,
+        shared: bool,
     ) -> Self {
         Self {
             vpn_range: VPNRange::from_va(start_va, end_va),
@@ -48,8 +66,8 @@ impl VmArea {
             area_type,
             permission,
             frame_map: BTreeMap::new(),
-            file,
-            file_offset,
+            backing,
+            shared,
         }
     }
 
@@ -60,8 +78,8 @@ impl VmArea {
             map_type: another.map_type,
             area_type: another.area_type,
             permission: another.permission,
-            file: another.file.clone(),
-            file_offset: another.file_offset,
+            backing: another.backing.clone(),
+            shared: another.shared,
         }
     }
 
@@ -69,83 +87,271 @@ impl VmArea {
         self.vpn_range.get_start()
     }
 
-    #[allow(unused)]
     pub fn end_vpn(&self) -> VirtPageNum {
         self.vpn_range.get_end()
     }
 
-    pub fn inflate_pagetable(&mut self, page_table: &mut PageTable) {
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.start_vpn() <= vpn && vpn < self.end_vpn()
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.end_vpn().0 - self.start_vpn().0
+    }
+
+    /// `vpn` 所在页对应的文件, 该页在文件中的偏移, 及页中来自文件的字节数
+    pub fn file_page(&self, vpn: VirtPageNum) -> Option<(&Arc<dyn File>, usize, usize)> {
+        match &self.backing {
+            VmBacking::File { file, offset, len } => {
+                let skip = (vpn.0 - self.start_vpn().0) * PAGE_SIZE;
+                Some((file, offset + skip, len.saturating_sub(skip).min(PAGE_SIZE)))
+            }
+            _ => None,
+        }
+    }
+
+    /// 在 `at` 处将区域一分为二: 自身保留 [start, at), 返回 [at, end)
+    pub fn split_off(&mut self, at: VirtPageNum) -> Self {
+        assert!(self.start_vpn() < at && at < self.end_vpn());
+        let skip = (at.0 - self.start_vpn().0) * PAGE_SIZE;
+        let backing = match &mut self.backing {
+            VmBacking::File { file, offset, len } => {
+                let tail_len = len.saturating_sub(skip);
+                *len = (*len).min(skip);
+                VmBacking::File {
+                    file: file.clone(),
+                    offset: *offset + skip,
+                    len: tail_len,
+                }
+            }
+            backing => backing.clone(),
+        };
+        let tail = Self {
+            vpn_range: VPNRange::from_vpn(at, self.end_vpn()),
+            frame_map: self.frame_map.split_off(&at),
+            map_type: self.map_type,
+            area_type: self.area_type,
+            permission: self.permission,
+            backing,
+            shared: self.shared,
+        };
+        self.vpn_range = VPNRange::from_vpn(self.start_vpn(), at);
+        tail
+    }
+
+    /// 紧随其后的 `next` 能否与自身合并为一个区域.
+    /// 只合并用户的堆, 栈, ELF 段与 mmap 区域, 它们的来源也要连续
+    pub fn can_merge(&self, next: &Self) -> bool {
+        let mergeable_type = matches!(
+            self.area_type,
+            VmAreaType::UserHeap | VmAreaType::UserStack | VmAreaType::Elf | VmAreaType::Mmap
+        );
+        let contiguous_backing = match (&self.backing, &next.backing) {
+            (VmBacking::Anonymous, VmBacking::Anonymous) => true,
+            (
+                VmBacking::File { file, offset, len },
+                VmBacking::File {
+                    file: next_file,
+                    offset: next_offset,
+                    ..
+                },
+            ) => {
+                Arc::as_ptr(file) as *const () == Arc::as_ptr(next_file) as *const ()
+                    && offset + self.page_count() * PAGE_SIZE == *next_offset
+                    && *len >= self.page_count() * PAGE_SIZE
+            }
+            _ => false,
+        };
+        mergeable_type
+            && contiguous_backing
+            && self.end_vpn() == next.start_vpn()
+            && self.area_type == next.area_type
+            && self.map_type == next.map_type
+            && self.permission.bits() == next.permission.bits()
+            && self.shared == next.shared
+    }
+
+    /// 将紧随其后的 `next` 并入自身, 调用前应由 [`VmArea::can_merge`] 检查
+    pub fn merge(&mut self, mut next: Self) {
+        let span = self.page_count() * PAGE_SIZE;
+        if let (VmBacking::File { len, .. }, VmBacking::File { len: next_len, .. }) =
+            (&mut self.backing, &next.backing)
+        {
+            *len = span.saturating_add(*next_len);
+        }
+        self.vpn_range = VPNRange::from_vpn(self.start_vpn(), next.end_vpn());
+        self.frame_map.append(&mut next.frame_map);
+    }
+
+    /// 用户态能否访问区域中的页, `PROT_NONE` 的区域不能访问
+    pub fn accessible(&self) -> bool {
+        self.permission
+            .intersects(MapPermission::R | MapPermission::W | MapPermission::X)
+    }
+
+    /// 区域中的页在页表中的标志位. 没有 R, W, X 的页表项会被当作指向下一级页表,
+    /// 因此 `PROT_NONE` 的页映射为不带 U 的只读页, 用户态访问时产生缺页
+    fn pte_flags(&self) -> PTEFlags {
+        if self.accessible() {
+            PTEFlags::from_map_permission(self.permission)
+        } else {
+            PTEFlags::from_map_permission(MapPermission::R)
+        }
+    }
+
+    /// 为区域中的所有页建立映射. 页帧耗尽时返回 ENOMEM, 已经建立的映射由调用者通过
+    /// [`VmArea::erase_pagetable`] 撤销
+    pub fn inflate_pagetable(&mut self, page_table: &mut PageTable) -> Result<(), Errno> {
         match self.map_type {
             MapType::Identical => {
-                self.vpn_range.into_iter().for_each(|vpn| {
+                for vpn in self.vpn_range {
                     let ppn = PhysPageNum(vpn.0);
-                    let flags = PTEFlags::from_map_permission(self.permission);
-                    page_table.map(vpn, ppn, flags);
-                });
+                    let flags = self.pte_flags();
+                    page_table.map(vpn, ppn, flags)?;
+                }
             }
             MapType::Framed => {
-                self.vpn_range.into_iter().for_each(|vpn| {
-                    let frame = alloc_frame().expect("out of memory");
+                for vpn in self.vpn_range {
+                    let frame = alloc_frame().ok_or(Errno::ENOMEM)?;
                     let ppn = frame.ppn;
                     if self.frame_map.contains_key(&vpn) {
                         panic!("vm area overlap");
                     }
-                    self.frame_map.insert(vpn, frame);
-                    let flags = PTEFlags::from_map_permission(self.permission);
+                    let flags = self.pte_flags();
                     // TODO premission and flags 统一
-                    page_table.map(vpn, ppn, flags);
-                });
+                    page_table.map(vpn, ppn, flags)?;
+                    self.frame_map.insert(vpn, frame);
+                }
             }
         }
+        Ok(())
     }
 
     /// Remove the mapping from the current logical segment to physical
     /// memory from the multi-level page table of the address space to
     /// which the incoming logical segment belongs.
-    /// Write back to file (if map_perm includes the W permission).
-    #[allow(unused)]
-    pub fn write_back(&self, page_table: &mut PageTable) -> Result<(), ()> {
-        if !self.permission.contains(MapPermission::W) {
-            return Ok(());
-        }
-        if self.file.is_none() {
-            return Ok(());
-        }
-        let file = self.file.as_ref().unwrap();
-        if !file.writable() {
-            return Ok(());
-        }
-        for vpn in self.vpn_range {
-            match page_table.translate(vpn) {
-                None => {}
-                Some(pte) => {
-                    if !pte.is_valid() {
-                        continue;
-                    }
-                    let data = pte.ppn().as_bytes_array();
-                    let offset =
-                        (vpn.0 - self.vpn_range.get_start().0) * PAGE_SIZE + self.file_offset;
-                    file.seek(offset);
-                    file.write_from_kspace(&data.to_vec());
+    ///
+    /// Framed areas backed by anonymous memory or files are mapped on demand,
+    /// so only the pages holding a frame are unmapped.
+    pub fn erase_pagetable(&mut self, page_table: &mut PageTable) {
+        match (self.map_type, &self.backing) {
+            (MapType::Framed, VmBacking::Anonymous | VmBacking::File { .. }) => {
+                // NOTICE: We should remove the frame from the frame allocator
+                for vpn in self.frame_map.keys() {
+                    page_table.unmap(*vpn);
                 }
+                self.frame_map.clear();
             }
+            _ => {
+                for vpn in self.vpn_range {
+                    page_table.unmap(vpn);
+                }
+            }
+        }
+    }
+
+    /// 将区域连同已经映射的页移动到 `new_start` 开始的位置, 只移动页表项, 不复制页中的数据.
+    /// 调用者保证目标位置没有映射. 先为目标位置建立各级页表, 页帧耗尽时返回 ENOMEM,
+    /// 此时不移动任何页
+    pub fn move_to(
+        &mut self,
+        new_start: VirtPageNum,
+        page_table: &mut PageTable,
+    ) -> Result<(), Errno> {
+        let old_start = self.start_vpn();
+        let shift = |vpn: VirtPageNum| VirtPageNum(vpn.0 - old_start.0 + new_start.0);
+        let mapped: Vec<VirtPageNum> = match self.backing {
+            VmBacking::Shm(_) => self.vpn_range.into_iter().collect(),
+            _ => self.frame_map.keys().copied().collect(),
+        };
+        for vpn in mapped.iter() {
+            page_table
+                .find_pte_create(shift(*vpn))
+                .ok_or(Errno::ENOMEM)?;
         }
+        for vpn in mapped {
+            let pte = page_table.translate(vpn).unwrap();
+            page_table.unmap(vpn);
+            page_table
+                .map(shift(vpn), pte.ppn(), pte.flags())
+                .expect("page table of the target is created");
+        }
+        self.frame_map = core::mem::take(&mut self.frame_map)
+            .into_iter()
+            .map(|(vpn, frame)| (shift(vpn), frame))
+            .collect();
+        self.vpn_range = VPNRange::from_vpn(new_start, shift(self.end_vpn()));
         Ok(())
     }
 
-    /// Remove the mapping from the current logical segment to physical
-    /// memory from the multi-level page table of the address space to
-    /// which the incoming logical segment belongs.
-    pub fn erase_pagetable(&mut self, page_table: &mut PageTable) {
-        for vpn in self.vpn_range {
-            match self.map_type {
-                MapType::Framed => {
-                    // NOTICE: We should remove the frame from the frame allocator
-                    self.frame_map.remove(&vpn);
+    /// 释放 `range` 中已经映射的页, 之后再访问时重新按需映射:
+    /// 匿名页读到 0, 文件页读到文件的内容
+    pub fn discard(&mut self, range: VPNRange, page_table: &mut PageTable) {
+        self.write_back(range, page_table, true);
+        let mapped: Vec<VirtPageNum> = self
+            .frame_map
+            .range(range.get_start()..range.get_end())
+            .map(|(vpn, _)| *vpn)
+            .collect();
+        for vpn in mapped {
+            page_table.unmap(vpn);
+            self.frame_map.remove(&vpn);
+        }
+    }
+
+    /// 修改区域的权限, 并更新已经映射的页.
+    /// 写时复制的页仍然只读, 由写入时的缺页完成复制; 共享文件页也重新以只读映射,
+    /// 以便再次写入时在 shared_write_fault 中标记为脏页
+    pub fn set_permission(&mut self, permission: MapPermission, page_table: &mut PageTable) {
+        let shared_file = self.shared && matches!(self.backing, VmBacking::File { .. });
+        if shared_file {
+            // 已经写入的页失去写权限之前先标记为脏页
+            self.write_back(self.vpn_range, page_table, false);
+        }
+        self.permission = permission;
+        let flags = self.pte_flags();
+        let update = |vpn: VirtPageNum| {
+            if let Some(pte) = page_table.find_pte(vpn) {
+                if !pte.is_valid() {
+                    return;
+                }
+                if pte.is_cow() {
+                    pte.set_flags((flags & !PTEFlags::W) | PTEFlags::COW);
+                } else if shared_file {
+                    pte.set_flags(flags & !PTEFlags::W);
+                } else {
+                    pte.set_flags(flags);
                 }
-                _ => {}
             }
-            page_table.unmap(vpn);
+        };
+        match self.backing {
+            VmBacking::Shm(_) => self.vpn_range.into_iter().for_each(update),
+            _ => self.frame_map.keys().copied().for_each(update),
+        }
+    }
+
+    /// 将 `range` 中已访问的共享文件页交给文件.
+    /// 与文件共享页帧的页已经在页缓存中, 但变脏之后可能已被写回, 因此映射为可写时再次标记为脏页;
+    /// 没有页缓存时页中是文件数据的副本, 仅在 `write_copies` 时写入文件, 且不会使文件变长
+    pub fn write_back(&self, range: VPNRange, page_table: &PageTable, write_copies: bool) {
+        if !self.shared {
+            return;
+        }
+        for (vpn, frame) in self.frame_map.range(range.get_start()..range.get_end()) {
+            let (file, offset, _) = match self.file_page(*vpn) {
+                Some(file_page) => file_page,
+                None => return,
+            };
+            if file.shared_frame(offset).map(|shared| shared.ppn) == Some(frame.ppn) {
+                if page_table
+                    .translate(*vpn)
+                    .map_or(false, |pte| pte.writable())
+                {
+                    file.dirty_shared_frame(offset);
+                }
+            } else if write_copies {
+                write_file_page(file, offset, frame.ppn);
+            }
         }
     }
 
@@ -155,13 +361,17 @@ impl VmArea {
         data_start: usize,
         mut data_len: usize,
         mut page_offset: usize,
-    ) {
+    ) -> Result<(), Errno> {
         assert_eq!(self.map_type, MapType::Framed);
         let mut offset: usize = 0;
         let mut current_vpn = self.vpn_range.get_start();
-        let file = self.file.as_ref().unwrap();
+        let file = match &self.backing {
+            VmBacking::File { file, .. } => file,
+            _ => panic!("copy data into an area without file"),
+        };
         loop {
-            let data = file.kernel_read_with_offset(data_start + offset, data_len.min(PAGE_SIZE));
+            let data =
+                file.kernel_read_with_offset(data_start + offset, data_len.min(PAGE_SIZE))?;
             let data_slice = data.as_slice();
 
             let src = &data_slice[0..data_len.min(PAGE_SIZE - page_offset)];
@@ -180,29 +390,68 @@ impl VmArea {
             }
             current_vpn.step();
         }
+        Ok(())
     }
 
-    pub fn lazy_map_vpn(&mut self, vpn: VirtPageNum, page_table: &mut PageTable) {
-        self.map_one(page_table, vpn);
-    }
-
-    fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        let ppn: PhysPageNum;
-        match self.map_type {
-            MapType::Identical => {
-                ppn = PhysPageNum(vpn.0);
-            }
-            MapType::Framed => {
-                if let Some(frame) = alloc_frame() {
-                    ppn = frame.ppn;
-                    self.frame_map.insert(vpn, frame);
-                } else {
-                    panic!("No more memory!");
-                }
+    /// 缺页时为 `vpn` 建立映射: 匿名页使用清零的页帧, 私有的文件页复制文件的内容,
+    /// 共享的文件页直接使用文件在页缓存中的页帧, 写入对文件本身及其他映射立即可见.
+    /// 页帧耗尽时返回 ENOMEM, 读取文件出错时返回相应的错误
+    pub fn lazy_map_vpn(
+        &mut self,
+        vpn: VirtPageNum,
+        page_table: &mut PageTable,
+    ) -> Result<(), Errno> {
+        let pte_flags = self.pte_flags();
+        if self.map_type == MapType::Identical {
+            return page_table.map(vpn, PhysPageNum(vpn.0), pte_flags);
+        }
+        if self.shared {
+            let frame = self
+                .file_page(vpn)
+                .and_then(|(file, offset, _)| file.shared_frame(offset));
+            if let Some(frame) = frame {
+                // 先以只读映射, 第一次写入时在 shared_write_fault 中将页标记为脏页
+                page_table.map(vpn, frame.ppn, pte_flags & !PTEFlags::W)?;
+                self.frame_map.insert(vpn, frame);
+                return Ok(());
             }
         }
-        // let pte_flags = PTEFlags::from_bits(self.permission.bits()).unwrap();
-        let pte_flags = PTEFlags::from_map_permission(self.permission);
-        page_table.map(vpn, ppn, pte_flags);
+        let frame = alloc_frame().ok_or(Errno::ENOMEM)?;
+        if let Some((file, offset, len)) = self.file_page(vpn) {
+            read_file_page(file, offset, len, frame.ppn)?;
+        }
+        page_table.map(vpn, frame.ppn, pte_flags)?;
+        self.frame_map.insert(vpn, frame);
+        Ok(())
+    }
+}
+
+/// 将文件 `offset` 处的 `len` 字节读入页帧 `ppn`, 其余部分及文件末尾之后的部分保持为 0.
+/// 按位置读取, 不改变文件的偏移量, 读取出错时返回相应的错误
+fn read_file_page(
+    file: &Arc<dyn File>,
+    offset: usize,
+    len: usize,
+    ppn: PhysPageNum,
+) -> Result<(), Errno> {
+    let file_size = file.file_size();
+    if !file.readable() || offset >= file_size || len == 0 {
+        return Ok(());
+    }
+    let data = file.kernel_read_with_offset(offset, len.min(file_size - offset))?;
+    ppn.as_bytes_array()[..data.len()].copy_from_slice(&data);
+    Ok(())
+}
+
+/// 将页帧 `ppn` 写入文件 `offset` 处, 不超过文件末尾
+fn write_file_page(file: &Arc<dyn File>, offset: usize, ppn: PhysPageNum) {
+    let file_size = file.file_size();
+    if !file.writable() || offset >= file_size {
+        return;
     }
+    let len = PAGE_SIZE.min(file_size - offset);
+    let _write_len = file.pwrite(
+        UserBuffer::wrap(vec![&mut ppn.as_bytes_array()[..len]]),
+        offset,
+    );
 }
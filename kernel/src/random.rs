@@ -0,0 +1,196 @@
+//! 内核随机数生成器
+//!
+//! 与 Linux 的 `drivers/char/random.c` 类似, 以 ChaCha20 为密码学安全的伪随机数生成器:
+//! - 熵来源: 开机时测量的时钟抖动, 每次陷入内核的时间与原因, 块设备读写与控制台输入的时间戳.
+//!   这些样本先被异或进输入缓冲区, 每积累一轮便以 ChaCha20 折叠进密钥;
+//! - 输出: 每次取随机数时先由密钥生成一个新密钥与一个一次性密钥, 再用一次性密钥生成输出
+//!   (fast key erasure), 之后泄露的内部状态无法推出已经输出的随机数;
+//! - 随机数池在收集到 [`READY_EVENTS`] 个熵样本之前没有初始化, 阻塞的读者等待它初始化.
+//!
+//! getrandom、`/dev/random`、`/dev/urandom` 与 exec 时的 AT_RANDOM 都使用同一个随机数池.
+
+use crate::return_errno;
+use crate::syscall::impls::Errno;
+use crate::task::suspend_current_and_run_next;
+use crate::timer::get_time;
+use spin::Mutex;
+
+/// 随机数池初始化所需的熵样本数, 每个样本按至多 1 bit 的熵计算
+const READY_EVENTS: usize = 256;
+/// 开机时测量时钟抖动的最大次数, 时钟过于规律时放弃等待
+const JITTER_ROUNDS: usize = READY_EVENTS * 64;
+
+/// "expand 32-byte k"
+const CHACHA_CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// 以 `key`、块计数 `counter` 与 `nonce` 生成一个 64 字节的 ChaCha20 块
+fn chacha20_block(key: &[u32; 8], counter: u64, nonce: u64) -> [u32; 16] {
+    let mut input = [0u32; 16];
+    input[..4].copy_from_slice(&CHACHA_CONSTANTS);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter as u32;
+    input[13] = (counter >> 32) as u32;
+    input[14] = nonce as u32;
+    input[15] = (nonce >> 32) as u32;
+    let mut state = input;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+    for (word, input) in state.iter_mut().zip(input.iter()) {
+        *word = word.wrapping_add(*input);
+    }
+    state
+}
+
+// 同一个密钥下生成输出与折叠熵输入使用不同的 nonce
+const NONCE_OUTPUT: u64 = 0;
+const NONCE_FOLD: u64 = 1;
+
+struct EntropyPool {
+    key: [u32; 8],
+    /// 尚未折叠进密钥的熵样本
+    input: [u32; 16],
+    /// 下一个样本写入 `input` 的位置
+    input_pos: usize,
+    /// `input` 中是否有尚未折叠的样本
+    input_dirty: bool,
+    /// 已收集的熵样本数
+    events: usize,
+}
+
+static POOL: Mutex<EntropyPool> = Mutex::new(EntropyPool {
+    key: [0; 8],
+    input: [0; 16],
+    input_pos: 0,
+    input_dirty: false,
+    events: 0,
+});
+
+impl EntropyPool {
+    /// 混入一个样本, `credit` 表示样本是否含有熵
+    fn mix(&mut self, sample: u64, credit: bool) {
+        let pos = self.input_pos;
+        self.input[pos] = self.input[pos].rotate_left(7) ^ sample as u32;
+        self.input[pos + 1] = self.input[pos + 1].rotate_left(7) ^ (sample >> 32) as u32;
+        self.input_pos = (pos + 2) % self.input.len();
+        self.input_dirty = true;
+        if credit {
+            self.events = self.events.saturating_add(1);
+        }
+        if self.input_pos == 0 {
+            self.fold();
+        }
+    }
+    /// 将输入缓冲区以 ChaCha20 折叠进密钥
+    fn fold(&mut self) {
+        let mut key = self.key;
+        for (i, word) in key.iter_mut().enumerate() {
+            *word ^= self.input[i] ^ self.input[i + 8].rotate_left(16);
+        }
+        let block = chacha20_block(&key, 0, NONCE_FOLD);
+        self.key.copy_from_slice(&block[..8]);
+        self.input = [0; 16];
+        self.input_pos = 0;
+        self.input_dirty = false;
+    }
+    /// 更换密钥, 返回一个只用于本次输出的一次性密钥
+    fn next_key(&mut self) -> [u32; 8] {
+        if self.input_dirty {
+            self.fold();
+        }
+        let block = chacha20_block(&self.key, 0, NONCE_OUTPUT);
+        self.key.copy_from_slice(&block[..8]);
+        let mut key = [0u32; 8];
+        key.copy_from_slice(&block[8..]);
+        key
+    }
+}
+
+/// 开机时以时钟抖动初始化随机数池: 反复执行一段计算并测量耗时, 耗时的变化作为熵样本
+pub fn init() {
+    let mut pool = POOL.lock();
+    let mut scratch = [0u32; 8];
+    let mut last_delta = 0;
+    for round in 0..JITTER_ROUNDS {
+        if pool.events >= READY_EVENTS {
+            break;
+        }
+        let start = get_time();
+        let block = chacha20_block(&scratch, round as u64, NONCE_FOLD);
+        scratch.copy_from_slice(&block[..8]);
+        let delta = get_time().wrapping_sub(start);
+        // 耗时与上一次相同的样本不计入熵
+        pool.mix(((delta as u64) << 32) | start as u64, delta != last_delta);
+        last_delta = delta;
+    }
+    if pool.events < READY_EVENTS {
+        // 不虚报熵, 随机数池等陷入内核与设备读写的样本积累够之后才初始化
+        warn!(
+            "random: only {} jitter samples at boot, not ready yet",
+            pool.events
+        );
+    }
+    pool.fold();
+}
+
+/// 混入一个带有当前时间戳的熵样本, 用于陷入内核与设备读写等不可预测的事件.
+/// 其他核正在使用随机数池时丢弃样本, 不会阻塞
+pub fn add_randomness(sample: usize) {
+    if let Some(mut pool) = POOL.try_lock() {
+        pool.mix(((get_time() as u64) << 32) ^ sample as u64, true);
+    }
+}
+
+/// 混入不计入熵的数据, 如写入 `/dev/random` 的内容
+pub fn add_device_data(buf: &[u8]) {
+    let mut pool = POOL.lock();
+    for chunk in buf.chunks(8) {
+        let mut bytes = [0u8; 8];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        pool.mix(u64::from_le_bytes(bytes), false);
+    }
+}
+
+/// 随机数池是否已经初始化
+pub fn random_ready() -> bool {
+    POOL.lock().events >= READY_EVENTS
+}
+
+/// 等待随机数池初始化, 被信号打断时返回 ERESTARTSYS
+pub fn wait_for_random() -> Result<(), Errno> {
+    while !random_ready() {
+        if suspend_current_and_run_next() < 0 {
+            return_errno!(Errno::ERESTARTSYS);
+        }
+    }
+    Ok(())
+}
+
+/// 以随机数填满 `buf`
+pub fn get_random_bytes(buf: &mut [u8]) {
+    let key = POOL.lock().next_key();
+    for (counter, chunk) in buf.chunks_mut(64).enumerate() {
+        let block = chacha20_block(&key, counter as u64, NONCE_OUTPUT);
+        for (bytes, word) in chunk.chunks_mut(4).zip(block.iter()) {
+            bytes.copy_from_slice(&word.to_le_bytes()[..bytes.len()]);
+        }
+    }
+}
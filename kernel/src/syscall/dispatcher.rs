@@ -1,7 +1,10 @@
 use super::impls::*;
 use super::*;
-use nix::{itimerval, time::TimeSpec};
-use nix::{RLimit, SchedParam, SigAction, SigMask};
+use nix::{itimerspec, itimerval, time::TimeSpec};
+use nix::{
+    CloneArgs, RLimit, RUsage, SchedParam, SigAction, SigEvent, SigInfo, SigMask, SigSet,
+    SignalStack,
+};
 
 /// Syscall dispatcher.
 pub fn syscall(id: usize, args: [usize; 6]) -> isize {
@@ -12,6 +15,7 @@ pub fn syscall(id: usize, args: [usize; 6]) -> isize {
     // }
     let ret = match syscall_id {
         SyscallId::SYS_CLONE => sys_do_fork(args[0], args[1], args[2], args[3], args[4]),
+        SyscallId::SYS_CLONE3 => sys_clone3(args[0] as *const CloneArgs, args[1]),
 
         SyscallId::SYS_TGKILL => sys_tgkill(args[0] as isize, args[1], args[2] as isize),
 
@@ -29,6 +33,12 @@ pub fn syscall(id: usize, args: [usize; 6]) -> isize {
             args[4] as u32,
         ),
 
+        SyscallId::SYS_SYMLINKAT => sys_symlinkat(
+            args[0] as *const u8,
+            args[1] as isize,
+            args[2] as *const u8,
+        ),
+
         SyscallId::SYS_OPENAT => sys_openat(
             args[0] as i32,
             args[1] as *const u8,
@@ -76,7 +86,19 @@ pub fn syscall(id: usize, args: [usize; 6]) -> isize {
             args[5],
         ),
         SyscallId::SYS_MUNMAP => sys_munmap(args[0], args[1]),
-        SyscallId::SYS_WAIT4 => sys_wait4(args[0] as isize, args[1] as *mut i32),
+        SyscallId::SYS_WAIT4 => sys_wait4(
+            args[0] as isize,
+            args[1] as *mut i32,
+            args[2] as u32,
+            args[3] as *mut RUsage,
+        ),
+        SyscallId::SYS_WAITID => sys_waitid(
+            args[0],
+            args[1],
+            args[2] as *mut SigInfo,
+            args[3] as u32,
+            args[4] as *mut RUsage,
+        ),
         SyscallId::SYS_SET_TID_ADDRESS => sys_set_tid_address(args[0] as *mut usize),
         SyscallId::SYS_READV => sys_readv(args[0], args[1] as *const usize, args[2]),
         SyscallId::SYS_WRITEV => sys_writev(args[0], args[1] as *const usize, args[2]),
@@ -107,7 +129,7 @@ pub fn syscall(id: usize, args: [usize; 6]) -> isize {
         SyscallId::SYS_SYSLOG => Ok(0),
         SyscallId::SYS_FACCESSAT => Ok(0),
         SyscallId::SYS_SYSINFO => Ok(0),
-        SyscallId::SYS_KILL => sys_kill(args[0], args[1] as u32),
+        SyscallId::SYS_KILL => sys_kill(args[0] as isize, args[1]),
         SyscallId::SYS_UTIMENSAT => sys_utimensat(
             args[0] as isize,
             args[1] as *const u8,
@@ -140,9 +162,11 @@ pub fn syscall(id: usize, args: [usize; 6]) -> isize {
             args[2] as *const u8,
             args[3],
         ),
-        SyscallId::SYS_GETRANDOM => sys_getrandom(args[0] as *const u8, args[1], args[2]),
-        SyscallId::SYS_GETPGID => Ok(0),
-        SyscallId::SYS_SETPGID => Ok(0),
+        SyscallId::SYS_GETRANDOM => sys_getrandom(args[0] as *mut u8, args[1], args[2]),
+        SyscallId::SYS_GETPGID => sys_getpgid(args[0]),
+        SyscallId::SYS_SETPGID => sys_setpgid(args[0], args[1] as isize),
+        SyscallId::SYS_GETSID => sys_getsid(args[0]),
+        SyscallId::SYS_SETSID => sys_setsid(),
         SyscallId::SYS_SYNC => sys_sync(),
         SyscallId::SYS_FTRUNCATE64 => sys_ftruncate64(args[0], args[1]),
         SyscallId::SYS_PSELECT6 => sys_pselect6(
@@ -159,9 +183,12 @@ pub fn syscall(id: usize, args: [usize; 6]) -> isize {
             args[2] as *mut itimerval,
         ),
         SyscallId::SYS_GETITIMER => sys_getitimer(args[0] as i32, args[1] as *mut itimerval),
-        SyscallId::SYS_UMASK => Ok(0),
-        SyscallId::SYS_FSYNC => Ok(0),
-        SyscallId::SYS_MSYNC => Ok(0),
+        SyscallId::SYS_UMASK => sys_umask(args[0] as u32),
+        SyscallId::SYS_FSYNC => sys_fsync(args[0]),
+        SyscallId::SYS_FDATASYNC => sys_fdatasync(args[0]),
+        SyscallId::SYS_MSYNC => sys_msync(args[0], args[1], args[2]),
+        SyscallId::SYS_MREMAP => sys_mremap(args[0], args[1], args[2], args[3], args[4]),
+        SyscallId::SYS_MINCORE => sys_mincore(args[0], args[1], args[2] as *mut u8),
         SyscallId::SYS_SHMGET => sys_shmget(args[0], args[1], args[2]),
         SyscallId::SYS_SHMCTL => sys_shmctl(args[0], args[1], args[2] as *const u8),
         SyscallId::SYS_SHMAT => sys_shmat(args[0], args[1], args[2]),
@@ -171,7 +198,16 @@ pub fn syscall(id: usize, args: [usize; 6]) -> isize {
             sys_pwrite64(args[0] as i32, args[1] as *const u8, args[2], args[3])
         }
         SyscallId::SYS_STATFS => sys_statfs(args[0] as *const u8, args[1] as *const u8),
-        SyscallId::SYS_SIGTIMEDWAIT => Ok(0),
+        SyscallId::SYS_SIGPENDING => sys_rt_sigpending(args[0] as *mut SigSet, args[1]),
+        SyscallId::SYS_SIGTIMEDWAIT => sys_rt_sigtimedwait(
+            args[0] as *const SigSet,
+            args[1] as *mut SigInfo,
+            args[2] as *const TimeSpec,
+            args[3],
+        ),
+        SyscallId::SYS_SIGQUEUEINFO => {
+            sys_rt_sigqueueinfo(args[0] as isize, args[1], args[2] as *const SigInfo)
+        }
         SyscallId::SYS_MPROTECT => sys_mprotect(args[0], args[1], args[2]),
         SyscallId::SYS_MEMBARRIER => Ok(0),
         SyscallId::SYS_SCHED_GETAFFINITY => {
@@ -206,6 +242,9 @@ pub fn syscall(id: usize, args: [usize; 6]) -> isize {
             args[3],
         ),
         SyscallId::SYS_SIGRETURN => sys_sigreturn(),
+        SyscallId::SYS_SIGALTSTACK => {
+            sys_sigaltstack(args[0] as *const SignalStack, args[1] as *mut SignalStack)
+        }
         SyscallId::SYS_FUTEX => sys_futex(
             args[0] as *const u32,
             args[1] as usize,
@@ -231,7 +270,7 @@ pub fn syscall(id: usize, args: [usize; 6]) -> isize {
             args[5],
         ),
         SyscallId::SYS_SETSOCKOPT => Ok(0),
-        SyscallId::SYS_MADVISE => Ok(0),
+        SyscallId::SYS_MADVISE => sys_madvise(args[0], args[1], args[2]),
 
         SyscallId::SYS_SCHED_SETAFFINITY => {
             sys_sched_setaffinity(args[0] as usize, args[1] as usize, args[2] as *const u8)
@@ -243,13 +282,20 @@ pub fn syscall(id: usize, args: [usize; 6]) -> isize {
             args[3] as *mut TimeSpec,
         ),
 
+        SyscallId::SYS_TIMER_CREATE => sys_timer_create(
+            args[0] as usize,
+            args[1] as *const SigEvent,
+            args[2] as *mut i32,
+        ),
         SyscallId::SYS_TIMER_SETTIME => sys_timer_settime(
             args[0] as usize,
-            args[1] as isize,
-            args[2] as *const itimerval,
-            args[3] as *mut itimerval,
+            args[1] as usize,
+            args[2] as *const itimerspec,
+            args[3] as *mut itimerspec,
         ),
-        SyscallId::SYS_TIMER_GETOVERRUN => Ok(0),
+        SyscallId::SYS_TIMER_GETTIME => sys_timer_gettime(args[0], args[1] as *mut itimerspec),
+        SyscallId::SYS_TIMER_GETOVERRUN => sys_timer_getoverrun(args[0]),
+        SyscallId::SYS_TIMER_DELETE => sys_timer_delete(args[0]),
         SyscallId::SYS_COPY_FILE_RANGE => sys_copy_file_range(
             args[0] as i32,
             args[1] as *mut u64,
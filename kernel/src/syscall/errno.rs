@@ -2,6 +2,7 @@
 
 #![allow(unused)]
 
+use fat32::DevError;
 use thiserror::Error;
 
 pub type Result = core::result::Result<isize, Errno>;
@@ -152,7 +153,25 @@ pub enum Errno {
     #[error("[ERANGE] Math result not representable")]
     ERANGE = 34,
 
+    /// Too many symbolic links encountered
+    #[error("[ELOOP] Too many symbolic links encountered")]
+    ELOOP = 40,
+
     /// Connection timed out
     #[error("[ETIMEDOUT] Connection timed out")]
     ETIMEDOUT = 110,
+
+    /// Interrupted system call that should be restarted, never seen by user space.
+    /// It becomes EINTR if a signal handler without SA_RESTART runs.
+    #[error("[ERESTARTSYS] Interrupted system call to be restarted")]
+    ERESTARTSYS = 512,
+}
+
+impl From<DevError> for Errno {
+    fn from(err: DevError) -> Self {
+        match err {
+            DevError::ReadFailed(_) | DevError::WriteFailed(_) => Errno::EIO,
+            DevError::ReadOnly => Errno::EROFS,
+        }
+    }
 }
@@ -50,6 +50,21 @@ impl FutexQueue {
         let mut waiters = self.waiters.write();
         *waiters -= 1;
     }
+    /// Remove the waiter of `task`, used when it is woken up by a signal
+    pub fn remove_waiter(&self, task: &Arc<TaskControlBlock>) -> bool {
+        let mut chain_lock = self.chain.write();
+        match chain_lock
+            .iter()
+            .position(|waiter| Arc::ptr_eq(&waiter.task, task))
+        {
+            Some(index) => {
+                chain_lock.remove(index);
+                self.waiters_decrease();
+                true
+            }
+            None => false,
+        }
+    }
     pub fn pop_expire_waiter(&mut self) -> Option<Arc<TaskControlBlock>> {
         let mut chain_lock = self.chain.write();
         let mut expire_task = None;
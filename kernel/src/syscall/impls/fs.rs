@@ -1,7 +1,11 @@
 //! About syscall detail: https://man7.org/linux/man-pages/dir_section_2.html
 
 use super::super::errno::*;
-use crate::fs::{chdir, make_pipe, open, File, Stdin, MNT_TABLE};
+use crate::fs::{
+    bind_mount, chdir, find_mount, lookup, lookup_nofollow, make_pipe, mount_fs, open,
+    parse_size_option, resolve, sync_all_fs, umount, AbsolutePath, DevFs, File, ProcFs, RamFs,
+    Stdin, SuperBlock, MNT_TABLE,
+};
 use crate::mm::{
     translated_bytes_buffer, translated_mut, translated_ref, translated_str, UserBuffer, VirtAddr,
 };
@@ -10,16 +14,13 @@ use crate::task::{current_task, current_user_token};
 use crate::task::{suspend_current_and_run_next, TaskControlBlock};
 use crate::timer::{get_time, get_timeval};
 
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{string::String, sync::Arc, vec::Vec};
 use core::mem::size_of;
 
-use fat32::sync_all;
-// use crate::fat32::sync_all;
-
 use nix::time::{TimeSpec, TimeVal};
 use nix::{
-    CreateMode, Dirent, FcntlFlags, InodeTime, Kstat, OpenFlags, SeekFlags, Statfs, AT_FDCWD,
-    RTC_RD_TIME, TCGETS, TCSETS, TIOCGPGRP, TIOCGWINSZ, TIOCSPGRP, UTIME_NOW, UTIME_OMIT,
+    CreateMode, Dirent, FcntlFlags, InodeTime, Kstat, MountFlags, OpenFlags, SeekFlags, Statfs,
+    UmountFlags, AT_FDCWD, AT_SYMLINK_FOLLOW, AT_SYMLINK_NOFOLLOW, UTIME_NOW, UTIME_OMIT,
 };
 use nix::{FdSet, Iovec};
 
@@ -37,11 +38,10 @@ pub fn sys_getcwd(buf: *mut u8, size: usize) -> Result {
     }
     let token = current_user_token();
     let task = current_task().unwrap();
-    let inner = task.inner_mut();
 
-    let buf_vec = translated_bytes_buffer(token, buf, size);
+    let buf_vec = translated_bytes_buffer(token, buf, size)?;
     let mut userbuf = UserBuffer::wrap(buf_vec);
-    let cwd = inner.cwd.to_string();
+    let cwd = task.get_work_path().to_string();
     let cwd_str = cwd.as_bytes();
     userbuf.write(cwd_str);
     userbuf.write_at(cwd_str.len(), &[0]); // 添加字符串末尾的\0
@@ -132,16 +132,22 @@ pub fn sys_dup3(old_fd: usize, new_fd: usize) -> Result {
 pub fn sys_chdir(path: *const u8) -> Result {
     let token = current_user_token();
     let task = current_task().unwrap();
-    let mut inner = task.inner_mut();
     let path = translated_str(token, path);
-    let current_path = inner.cwd.clone();
+    let current_path = task.get_work_path();
     let new_path = current_path.cd(path.clone());
-    if chdir(new_path.clone()) {
-        inner.cwd = new_path.clone();
-        Ok(0)
-    } else {
-        return_errno!(Errno::ENOENT);
-    }
+    // 解析路径时可能进入 procfs, 不能持有当前任务的锁
+    let new_path = chdir(new_path)?;
+    task.fs.write().cwd = new_path;
+    Ok(0)
+}
+
+// umask 166
+pub fn sys_umask(mask: u32) -> Result {
+    let task = current_task().unwrap();
+    let mut fs = task.fs.write();
+    let old = fs.umask;
+    fs.umask = mask & 0o777;
+    Ok(old as isize)
 }
 
 // openat 56
@@ -151,24 +157,16 @@ pub fn sys_openat(fd: i32, filename: *const u8, flags: u32, mode: u32) -> Result
     let task = current_task().unwrap();
     let token = current_user_token();
     let inner = task.inner_mut();
-    let mut fd_table = task.fd_table.write();
+    let fd_table = task.fd_table.read();
 
     let path = translated_str(token, filename);
+    // 新建文件的权限去掉 umask 中的位
+    let mode = mode & !task.fs.read().umask;
     let mode = CreateMode::from_bits(mode).unwrap_or(CreateMode::empty());
     let flags = OpenFlags::from_bits(flags).unwrap_or(OpenFlags::empty());
     let fd_limit = inner.rlimit_nofile.rlim_cur;
-    if fd as isize == AT_FDCWD {
-        let open_path = inner.get_work_path().cd(path);
-        let inode = open(open_path.clone(), flags, mode)?;
-        let fd = TaskControlBlock::alloc_fd(&mut fd_table, fd_limit);
-        if fd >= fd_limit {
-            return_errno!(Errno::EMFILE);
-        }
-        fd_table[fd] = Some(inode);
-        Ok(fd as isize)
-        // } else {
-        //     return_errno!(Errno::ENOENT, "try open path {:?}", open_path);
-        // }
+    let open_path = if fd as isize == AT_FDCWD {
+        task.get_work_path().cd(path)
     } else {
         let dirfd = fd as usize;
         // dirfd 不合法
@@ -179,23 +177,24 @@ pub fn sys_openat(fd: i32, filename: *const u8, flags: u32, mode: u32) -> Result
             return_errno!(Errno::EMFILE);
         }
         if let Some(file) = &fd_table[dirfd] {
-            let open_path = file.path().cd(path.clone());
-            // target file 存在
-            let tar_file = open(open_path.clone(), flags, mode)?;
-            let fd = TaskControlBlock::alloc_fd(&mut fd_table, fd_limit);
-            if fd >= fd_limit {
-                return_errno!(Errno::EMFILE);
-            }
-            fd_table[fd] = Some(tar_file);
-            Ok(fd as isize)
-            // } else {
-            //     return_errno!(Errno::ENOENT, "try to open {:?}", path);
-            // }
+            file.path().cd(path.clone())
         } else {
             // dirfd 对应条目为 None
             return_errno!(Errno::ENOENT, "no such a file, fd: {}", dirfd);
         }
+    };
+    // procfs 在路径解析时会读取任务的 fd 表, 打开文件时不能持有当前任务的锁
+    drop(fd_table);
+    drop(inner);
+
+    let inode = open(open_path, flags, mode)?;
+    let mut fd_table = task.fd_table.write();
+    let fd = TaskControlBlock::alloc_fd(&mut fd_table, fd_limit);
+    if fd >= fd_limit {
+        return_errno!(Errno::EMFILE);
     }
+    fd_table[fd] = Some(inode);
+    Ok(fd as isize)
 }
 
 // sys_close 57
@@ -214,13 +213,11 @@ pub fn sys_close(fd: usize) -> Result {
 }
 
 // getdents64 61
-#[cfg(feature = "fat32")]
 pub fn sys_getdents64(fd: isize, buf: *mut u8, len: usize) -> Result {
     let token = current_user_token();
     let task = current_task().unwrap();
-    let inner = task.inner_mut();
-    let work_path = inner.cwd.clone();
-    let buf_vec = translated_bytes_buffer(token, buf, len);
+    let work_path = task.get_work_path();
+    let buf_vec = translated_bytes_buffer(token, buf, len)?;
     let mut userbuf = UserBuffer::wrap(buf_vec);
     let mut dirent = Dirent::new();
     let dent_len = size_of::<Dirent>();
@@ -228,7 +225,7 @@ pub fn sys_getdents64(fd: isize, buf: *mut u8, len: usize) -> Result {
     let fd_table = task.fd_table.read();
 
     if fd == AT_FDCWD {
-        let file = open(work_path.clone(), OpenFlags::O_RDONLY, CreateMode::empty())?;
+        let file = open(work_path.clone(), OpenFlags::empty(), CreateMode::empty())?;
         loop {
             if total_len + dent_len >= len {
                 break;
@@ -265,29 +262,6 @@ pub fn sys_getdents64(fd: isize, buf: *mut u8, len: usize) -> Result {
     }
 }
 
-#[cfg(feature = "ramfs")]
-pub fn sys_getdents64(fd: isize, buf: *mut u8, len: usize) -> Result {
-    let token = current_user_token();
-    let task = current_task().unwrap();
-    let inner = task.inner_mut();
-    let work_path = inner.cwd.clone();
-    let fd_table = task.fd_table.read();
-    let buf_ptr = translated_mut(token, buf);
-    let buf = unsafe { core::slice::from_raw_parts_mut(buf_ptr as *mut u8, len) };
-
-    if fd == AT_FDCWD {
-        let file = open(work_path.clone(), OpenFlags::O_RDONLY, CreateMode::empty())?;
-        return file.getdents(buf);
-    } else {
-        let fd = fd as usize;
-        if let Some(file) = &fd_table[fd] {
-            return file.getdents(buf);
-        } else {
-            return_errno!(Errno::EBADF, "could not find fd {}", fd);
-        }
-    }
-}
-
 // read 63
 pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> Result {
     #[cfg(feature = "time-tracer")]
@@ -317,14 +291,6 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> Result {
         drop(fd_table); // 释放以避免死锁
         drop(task); // 需要及时释放减少引用数
 
-        // 对 /dev/zero 的处理, 暂时先加在这里
-        if &file.name() == "zero" || &file.name() == "ZERO" {
-            let mut userbuffer = UserBuffer::wrap(translated_bytes_buffer(token, buf, len));
-            let zero: Vec<u8> = (0..userbuffer.buffers.len()).map(|_| 0).collect();
-            userbuffer.write(zero.as_slice());
-            return Ok(userbuffer.buffers.len() as isize);
-        }
-
         #[cfg(feature = "time-tracer")]
         time_trace!("sys_read_3");
         let file_size = file.file_size();
@@ -333,9 +299,10 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> Result {
         if file_size == 0 {
             warn!("sys_read: {} file_size is zero!", file.name());
         }
-        let len = len.min(file_size - file_offset);
-        let readsize =
-            file.read_to_ubuf(UserBuffer::wrap(translated_bytes_buffer(token, buf, len))) as isize;
+        let len = len.min(file_size.saturating_sub(file_offset));
+        let readsize = file
+            .read_to_ubuf(UserBuffer::wrap(translated_bytes_buffer(token, buf, len)?))?
+            as isize;
         Ok(readsize as isize)
     } else {
         return_errno!(Errno::EBADF, "fd is not exist, fd: {}", fd);
@@ -369,23 +336,15 @@ pub fn sys_pread64(fd: usize, buf: *const u8, len: usize, offset: usize) -> Resu
         drop(fd_table); // 释放以避免死锁
         drop(task); // 需要及时释放减少引用数
 
-        // 对 /dev/zero 的处理, 暂时先加在这里
-        if &file.name() == "zero" || &file.name() == "ZERO" {
-            let mut userbuffer = UserBuffer::wrap(translated_bytes_buffer(token, buf, len));
-            let zero: Vec<u8> = (0..userbuffer.buffers.len()).map(|_| 0).collect();
-            userbuffer.write(zero.as_slice());
-            return Ok(userbuffer.buffers.len() as isize);
-        }
-
         let file_size = file.file_size();
         if file_size == 0 {
             warn!("sys_read: file_size is zero!");
         }
-        let len = len.min(file_size - offset);
+        let len = len.min(file_size.saturating_sub(offset));
         let readsize = file.pread(
-            UserBuffer::wrap(translated_bytes_buffer(token, buf, len)),
+            UserBuffer::wrap(translated_bytes_buffer(token, buf, len)?),
             offset,
-        ) as isize;
+        )? as isize;
         Ok(readsize)
     } else {
         return_errno!(Errno::EBADF, "couldn't find fd: {}", fd);
@@ -399,7 +358,8 @@ pub fn sys_write(fd: i32, buf: *const u8, len: usize) -> Result {
     let token = current_user_token();
     let task = current_task().unwrap();
     let fd_table = task.fd_table.read();
-    let memory_set = task.memory_set.read();
+    let memory_set = task.memory_set();
+    let memory_set = memory_set.read();
 
     // 文件描述符不合法
     if fd as usize >= fd_table.len() {
@@ -435,7 +395,7 @@ pub fn sys_write(fd: i32, buf: *const u8, len: usize) -> Result {
         drop(memory_set);
 
         let write_size = file
-            .write_from_ubuf(UserBuffer::wrap(translated_bytes_buffer(token, buf, len)))
+            .write_from_ubuf(UserBuffer::wrap(translated_bytes_buffer(token, buf, len)?))?
             as isize;
         Ok(write_size)
     } else {
@@ -450,7 +410,8 @@ pub fn sys_pwrite64(fd: i32, buf: *const u8, len: usize, offset: usize) -> Resul
     let token = current_user_token();
     let task = current_task().unwrap();
     let fd_table = task.fd_table.read();
-    let memory_set = task.memory_set.read();
+    let memory_set = task.memory_set();
+    let memory_set = memory_set.read();
 
     // 文件描述符不合法
     if fd as usize >= fd_table.len() {
@@ -486,9 +447,9 @@ pub fn sys_pwrite64(fd: i32, buf: *const u8, len: usize, offset: usize) -> Resul
         drop(memory_set);
 
         let write_size = file.pwrite(
-            UserBuffer::wrap(translated_bytes_buffer(token, buf, len)),
+            UserBuffer::wrap(translated_bytes_buffer(token, buf, len)?),
             offset,
-        ) as isize;
+        )? as isize;
         Ok(write_size)
     } else {
         return_errno!(Errno::EBADF, "could not find fd: {}", fd);
@@ -497,93 +458,89 @@ pub fn sys_pwrite64(fd: i32, buf: *const u8, len: usize, offset: usize) -> Resul
 
 // linkat 37
 pub fn sys_linkat(
-    _old_dirfd: isize,
-    _old_path: *const u8,
-    _new_dirfd: isize,
-    _new_path: *const u8,
-    _flags: u32,
+    old_dirfd: isize,
+    old_path: *const u8,
+    new_dirfd: isize,
+    new_path: *const u8,
+    flags: u32,
 ) -> Result {
-    todo!()
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let old_path = at_base(&task, old_dirfd)?.cd(translated_str(token, old_path));
+    let new_path = at_base(&task, new_dirfd)?.cd(translated_str(token, new_path));
+    // 与 Linux 一样, 默认链接到符号链接本身
+    let follow = flags as usize & AT_SYMLINK_FOLLOW != 0;
+    let (old_path, target) = resolve(&old_path, follow)?;
+    let target = target.ok_or(Errno::ENOENT)?;
+    if target.is_dir() {
+        return_errno!(Errno::EPERM, "hard link to directory: {:?}", old_path);
+    }
+    let (new_path, dentry) = resolve(&new_path, false)?;
+    if dentry.is_some() {
+        return_errno!(Errno::EEXIST, "link path exists: {:?}", new_path);
+    }
+    let old_mnt = find_mount(&old_path).ok_or(Errno::ENOENT)?;
+    let new_mnt = find_mount(&new_path).ok_or(Errno::ENOENT)?;
+    if old_mnt.dir != new_mnt.dir {
+        return_errno!(
+            Errno::EXDEV,
+            "link across mounts: {:?} -> {:?}",
+            old_path,
+            new_path
+        );
+    }
+    if new_mnt.is_rdonly() {
+        return_errno!(Errno::EROFS, "read-only filesystem, path:{:?}", new_path);
+    }
+    lookup(&new_path.parent())?.link(&new_path.last(), target)?;
+    Ok(0)
 }
 
 // unlinkat 35
 pub fn sys_unlinkat(fd: isize, path: *const u8, flags: u32) -> Result {
     let task = current_task().unwrap();
     let token = current_user_token();
-    let inner = task.inner_mut();
 
     _ = flags;
 
-    let path = translated_str(token, path);
-    let open_path = inner.get_work_path().cd(path);
-
-    if fd == AT_FDCWD {
-        let file = open(open_path.clone(), OpenFlags::O_RDWR, CreateMode::empty())?;
-        file.delete();
-        Ok(0)
-        // } else {
-        //     return_errno!(Errno::ENOENT, "could not open: {:?}", open_path);
-        // }
-    } else {
-        unimplemented!("in sys_unlinkat");
+    let open_path = at_base(&task, fd)?.cd(translated_str(token, path));
+    if find_mount(&open_path).ok_or(Errno::ENOENT)?.is_rdonly() {
+        return_errno!(Errno::EROFS, "read-only filesystem, path:{:?}", open_path);
     }
+    // 删除的是链接本身, 而不是链接指向的文件
+    let file = open(
+        open_path,
+        OpenFlags::O_RDWR | OpenFlags::O_NOFOLLOW | OpenFlags::PATH,
+        CreateMode::empty(),
+    )?;
+    file.delete()?;
+    Ok(0)
 }
 
 // mkdirat 34
-pub fn sys_mkdirat(dirfd: i32, path: *const u8, _mode: u32) -> Result {
+pub fn sys_mkdirat(dirfd: i32, path: *const u8, mode: u32) -> Result {
     let token = current_user_token();
     let task = current_task().unwrap();
-    let inner = task.inner_mut();
-    let fd_table = task.fd_table.read();
-    let path = translated_str(token, path);
-    let fd_limit = inner.rlimit_nofile.rlim_cur;
-    if dirfd as isize == AT_FDCWD {
-        let open_path = inner.get_work_path().cd(path);
-        let _ = open(
-            open_path.clone(),
-            OpenFlags::O_DIRECTORY | OpenFlags::O_CREAT,
-            CreateMode::empty(),
-        );
-        // ) {
-        //     Ok(0)
-        // } else {
-        //     return_errno!(Errno::ENOENT, "could not open: {:?}", open_path);
-        // }
-        Ok(0)
-    } else {
-        let dirfd = dirfd as usize;
-        if dirfd >= fd_table.len() && dirfd >= fd_limit {
-            return_errno!(Errno::EBADF, "fd {} is out of range or reach limit", dirfd);
-        }
-        if let Some(file) = &fd_table[dirfd] {
-            let open_path = file.path().cd(path);
-
-            let _ = open(
-                open_path.clone(),
-                OpenFlags::O_DIRECTORY | OpenFlags::O_CREAT,
-                CreateMode::empty(),
-            );
-            Ok(0)
-        // else {
-        //     return_errno!(Errno::ENOENT, "could not open: {:?}", open_path);
-        // }
-        } else {
-            return_errno!(Errno::EBADF, "could not find fd: {}", dirfd);
-        }
+    let open_path = at_base(&task, dirfd as isize)?.cd(translated_str(token, path));
+    if find_mount(&open_path).ok_or(Errno::ENOENT)?.is_rdonly() {
+        return_errno!(Errno::EROFS, "read-only filesystem, path:{:?}", open_path);
     }
+    // 与 openat 一样, 新建目录的权限去掉 umask 中的位
+    let mode = mode & 0o1777 & !task.fs.read().umask;
+    let mode = CreateMode::from_bits(mode).unwrap_or(CreateMode::empty());
+    open(open_path, OpenFlags::O_DIRECTORY | OpenFlags::O_CREAT, mode)?;
+    Ok(0)
 }
 
 // umount2 39
 pub fn sys_umount2(p_special: *const u8, flags: usize) -> Result {
     let token = current_user_token();
     let special = translated_str(token, p_special);
+    let target = current_task().unwrap().get_work_path().cd(special);
+    let flags = UmountFlags::from_bits_truncate(flags as u32);
 
-    match MNT_TABLE.lock().umount(special, flags as u32) {
-        0 => Ok(0),
-        -1 => return_errno!(Errno::EINVAL),
-
-        _ => unreachable!(),
-    }
+    umount(&target, flags)?;
+    Ok(0)
 }
 
 // mount 40
@@ -598,21 +555,55 @@ pub fn sys_mount(
     let special = translated_str(token, special);
     let dir = translated_str(token, dir);
     let fstype = translated_str(token, fstype);
+    let flags = MountFlags::from_bits_truncate(flags as u32);
+    // 以逗号分隔的文件系统参数, 如 tmpfs 的 `size=64m`
+    let data = match data as usize {
+        0 => String::new(),
+        _ => translated_str(token, data),
+    };
 
-    _ = data;
+    let cwd = current_task().unwrap().get_work_path();
+    let dir = cwd.cd(dir);
+    match lookup(&dir) {
+        Ok(dentry) if dentry.is_dir() => {}
+        Ok(_) => return_errno!(Errno::ENOTDIR, "mount point is not a directory: {:?}", dir),
+        Err(errno) => return_errno!(errno, "mount point not exist: {:?}", dir),
+    }
 
-    match MNT_TABLE.lock().mount(special, dir, fstype, flags as u32) {
-        0 => Ok(0),
-        -1 => return_errno!(Errno::EMFILE, "mount too many"),
-        _ => unreachable!(),
+    if flags.contains(MountFlags::MS_REMOUNT) {
+        MNT_TABLE.lock().remount(&dir, flags)?;
+    } else if flags.contains(MountFlags::MS_BIND) {
+        bind_mount(&cwd.cd(special), dir, flags)?;
+    } else {
+        let sb: Arc<dyn SuperBlock> = match fstype.as_str() {
+            // 目前只有根文件系统所在的一个块设备, 挂载它时复用根文件系统
+            #[cfg(feature = "fat32")]
+            "vfat" | "fat32" => {
+                let device = lookup(&cwd.cd(special.clone()))?;
+                let is_block = device
+                    .as_any()
+                    .downcast::<crate::fs::DevEntry>()
+                    .map_or(false, |device| device.is_block());
+                if !is_block {
+                    return_errno!(Errno::ENOTBLK, "not a block device: {}", special);
+                }
+                crate::fs::ROOT_FS.clone()
+            }
+            "proc" => Arc::new(ProcFs::new()),
+            "tmpfs" | "ramfs" => Arc::new(RamFs::with_size_limit(parse_size_option(&data)?)),
+            "devtmpfs" => Arc::new(DevFs::new()),
+            _ => return_errno!(Errno::ENODEV, "unsupported filesystem type: {}", fstype),
+        };
+        mount_fs(&special, dir, &fstype, flags, sb)?;
     }
+    Ok(0)
 }
 
 // fstat 80
 pub fn sys_fstat(fd: i32, buf: *mut u8) -> Result {
     let token = current_user_token();
     let task = current_task().unwrap();
-    let buf_vec = translated_bytes_buffer(token, buf, size_of::<Kstat>());
+    let buf_vec = translated_bytes_buffer(token, buf, size_of::<Kstat>())?;
     let fd_table = task.fd_table.read();
 
     let mut userbuf = UserBuffer::wrap(buf_vec);
@@ -631,7 +622,7 @@ pub fn sys_fstat(fd: i32, buf: *mut u8) -> Result {
         return_errno!(Errno::EBADF, "fd {} reached limit", dirfd);
     }
     if let Some(file) = &fd_table[dirfd] {
-        file.fstat(&mut kstat);
+        file.fstat(&mut kstat)?;
         userbuf.write(kstat.as_bytes());
         Ok(0)
     } else {
@@ -672,13 +663,15 @@ pub fn sys_readv(fd: usize, iovp: *const usize, iovcnt: usize) -> Result {
         for _ in 0..iovcnt {
             let iov = translated_ref(token, addr as *const Iovec);
 
-            let len = iov.iov_len.min(file_size - file_offset - total_read_len);
+            let len = iov
+                .iov_len
+                .min(file_size.saturating_sub(file_offset + total_read_len));
             // println!("[DEBUG] sys_readv iov_addr:{:x?} len:{:?},buffer_len:{:?}",iov.iov_base,iov.iov_len,len);
             total_read_len += file.read_to_ubuf(UserBuffer::wrap(translated_bytes_buffer(
                 token,
                 iov.iov_base as *const u8,
                 len,
-            )));
+            )?))?;
             addr += size_of::<Iovec>();
         }
         Ok(total_read_len as isize)
@@ -722,7 +715,7 @@ pub fn sys_writev(fd: usize, iovp: *const usize, iovcnt: usize) -> Result {
                 token,
                 iov.iov_base as *const u8,
                 iov.iov_len,
-            )));
+            )?))?;
         }
 
         Ok(total_write_len as isize)
@@ -733,7 +726,6 @@ pub fn sys_writev(fd: usize, iovp: *const usize, iovcnt: usize) -> Result {
 
 // ioctl 29
 pub fn sys_ioctl(fd: i32, request: usize, argp: *mut u8) -> Result {
-    let token = current_user_token();
     let task = current_task().unwrap();
     let fd_table = task.fd_table.read();
     // 文件描述符不合法
@@ -745,16 +737,13 @@ pub fn sys_ioctl(fd: i32, request: usize, argp: *mut u8) -> Result {
             fd_table.len()
         );
     }
-    match request {
-        TCGETS => {}
-        TCSETS => {}
-        TIOCGPGRP => *translated_mut(token, argp) = 0 as u8,
-        TIOCSPGRP => {}
-        TIOCGWINSZ => *translated_mut(token, argp) = 0 as u8,
-        RTC_RD_TIME => {}
-        _ => return_errno!(Errno::EINVAL, "request {} is not supported", request),
-    }
-    Ok(0)
+    let file = match &fd_table[fd as usize] {
+        Some(file) => file.clone(),
+        None => return_errno!(Errno::EBADF, "fd {} is not opened", fd),
+    };
+    drop(fd_table);
+    // 终端请求由标准输入输出与 /dev/tty 处理, 其余文件返回 ENOTTY
+    file.ioctl(request, argp as usize)
 }
 
 // fcmtl 25
@@ -831,7 +820,7 @@ pub fn sys_newfstatat(
     dirfd: isize,
     pathname: *const u8,
     satabuf: *const usize,
-    _flags: usize,
+    flags: usize,
 ) -> Result {
     let token = current_user_token();
     let task = current_task().unwrap();
@@ -839,15 +828,21 @@ pub fn sys_newfstatat(
     let fd_table = task.fd_table.read();
     let path = translated_str(token, pathname);
 
-    let buf_vec = translated_bytes_buffer(token, satabuf as *const u8, size_of::<Kstat>());
+    let buf_vec = translated_bytes_buffer(token, satabuf as *const u8, size_of::<Kstat>())?;
     let mut userbuf = UserBuffer::wrap(buf_vec);
     let mut kstat = Kstat::new();
     let fd_limit = inner.rlimit_nofile.rlim_cur;
+    // AT_SYMLINK_NOFOLLOW: 返回链接本身的信息
+    let open_flags = if flags & AT_SYMLINK_NOFOLLOW != 0 {
+        OpenFlags::O_NOFOLLOW | OpenFlags::PATH
+    } else {
+        OpenFlags::empty()
+    };
     // 相对路径, 在当前工作目录
     if dirfd == AT_FDCWD {
-        let open_path = inner.get_work_path().cd(path);
-        let inode = open(open_path.clone(), OpenFlags::O_RDONLY, CreateMode::empty())?;
-        inode.fstat(&mut kstat);
+        let open_path = task.get_work_path().cd(path);
+        let inode = open(open_path.clone(), open_flags, CreateMode::empty())?;
+        inode.fstat(&mut kstat)?;
         userbuf.write(kstat.as_bytes());
         Ok(0)
         // } else {
@@ -863,9 +858,9 @@ pub fn sys_newfstatat(
         }
 
         if let Some(_file) = &fd_table[dirfd] {
-            let open_path = inner.get_work_path().cd(path);
-            let inode = open(open_path, OpenFlags::O_RDONLY, CreateMode::empty())?;
-            inode.fstat(&mut kstat);
+            let open_path = task.get_work_path().cd(path);
+            let inode = open(open_path, open_flags, CreateMode::empty())?;
+            inode.fstat(&mut kstat)?;
             userbuf.write(kstat.as_bytes());
             Ok(0)
         } else {
@@ -886,13 +881,11 @@ pub fn sys_sendfile(out_fd: i32, in_fd: i32, offset: usize, _count: usize) -> Re
         let out_file = fd_table[out_fd as usize].as_ref().unwrap();
         let mut data_buffer;
         loop {
-            data_buffer = in_file.read_to_kspace();
-            let len = data_buffer.len();
-            if len == 0 {
+            data_buffer = in_file.read_to_kspace()?;
+            if data_buffer.is_empty() {
                 break;
             } else {
-                out_file.write_from_kspace(&data_buffer);
-                total_write_size += len;
+                total_write_size += out_file.write_from_kspace(&data_buffer)?;
             }
         }
         Ok(total_write_size as isize)
@@ -953,7 +946,7 @@ pub fn sys_utimensat(
             unimplemented!();
         } else {
             let pathname = translated_str(token, pathname);
-            let path = inner.get_work_path().cd(pathname);
+            let path = task.get_work_path().cd(pathname);
             let _ = open(
                 path,
                 OpenFlags::O_RDWR | OpenFlags::O_CREAT,
@@ -995,35 +988,43 @@ pub fn sys_renameat2(
 ) -> Result {
     let task = current_task().unwrap();
     let token = current_user_token();
-    let inner = task.inner_mut();
-    let old_path = translated_str(token, old_path);
-    let new_path = translated_str(token, new_path);
-
-    let old_path = inner.get_work_path().cd(old_path);
-
-    if old_dirfd == AT_FDCWD {
-        let old_file = open(old_path, OpenFlags::O_RDWR, CreateMode::empty())?;
-        let flag = {
-            if old_file.is_dir() {
-                OpenFlags::O_RDWR | OpenFlags::O_CREAT | OpenFlags::O_DIRECTORY
-            } else {
-                OpenFlags::O_RDWR | OpenFlags::O_CREAT
-            }
-        };
-        if new_dirfd == AT_FDCWD {
-            let new_path = inner.get_work_path().cd(new_path);
-            println!("new path:{:?}", new_path);
-            old_file.rename(new_path, flag);
-            Ok(0)
+    let old_path = at_base(&task, old_dirfd)?.cd(translated_str(token, old_path));
+    let new_path = at_base(&task, new_dirfd)?.cd(translated_str(token, new_path));
+    let old_mnt = find_mount(&old_path).ok_or(Errno::ENOENT)?;
+    let new_mnt = find_mount(&new_path).ok_or(Errno::ENOENT)?;
+    if old_mnt.dir != new_mnt.dir {
+        return_errno!(
+            Errno::EXDEV,
+            "rename across mounts: {:?} -> {:?}",
+            old_path,
+            new_path
+        );
+    }
+    if new_mnt.is_rdonly() {
+        return_errno!(Errno::EROFS, "read-only filesystem, path:{:?}", new_path);
+    }
+    let old_file = open(
+        old_path.clone(),
+        OpenFlags::O_RDWR | OpenFlags::O_NOFOLLOW | OpenFlags::PATH,
+        CreateMode::empty(),
+    )?;
+    if old_file.is_dir() && new_path != old_path && new_path.start_with(&old_path) {
+        return_errno!(
+            Errno::EINVAL,
+            "move {:?} into itself: {:?}",
+            old_path,
+            new_path
+        );
+    }
+    let flag = {
+        if old_file.is_dir() {
+            OpenFlags::O_RDWR | OpenFlags::O_CREAT | OpenFlags::O_DIRECTORY
         } else {
-            unimplemented!();
+            OpenFlags::O_RDWR | OpenFlags::O_CREAT
         }
-        // } else {
-        //     panic!("can't find old file");
-        // }
-    } else {
-        unimplemented!();
-    }
+    };
+    old_file.rename(new_path, flag)?;
+    Ok(0)
 }
 
 // lseek 62
@@ -1073,37 +1074,81 @@ pub fn sys_lseek(fd: usize, off_t: isize, whence: usize) -> Result {
     }
 }
 
-// readlinkat 78
-pub fn sys_readlinkat(dirfd: isize, pathname: *const u8, buf: *const u8, bufsiz: usize) -> Result {
+/// `*at` 系列系统调用中相对路径的起点: `AT_FDCWD` 为当前工作目录, 否则为 `dirfd` 对应的目录
+fn at_base(task: &TaskControlBlock, dirfd: isize) -> core::result::Result<AbsolutePath, Errno> {
     if dirfd == AT_FDCWD {
-        let token = current_user_token();
-        let path = translated_str(token, pathname);
-        // println!("readlinkat path:{:?}", path);
-        if path.as_str() != "/proc/self/exe" {
-            panic!("sys_readlinkat: pathname not support");
-        }
-        let mut userbuf = UserBuffer::wrap(translated_bytes_buffer(token, buf, bufsiz));
-        let procinfo = "/lua\0";
-        userbuf.write(procinfo.as_bytes());
-        let len = procinfo.len() - 1;
-        return Ok(len as isize);
-    } else {
-        panic!("sys_readlinkat: fd not support");
+        return Ok(task.get_work_path());
+    }
+    match task.fd_table.read().get(dirfd as usize) {
+        Some(Some(file)) => Ok(file.path()),
+        _ => return_errno!(Errno::EBADF, "could not find fd: {}", dirfd),
+    }
+}
+
+// symlinkat 36
+pub fn sys_symlinkat(target: *const u8, new_dirfd: isize, linkpath: *const u8) -> Result {
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let target = translated_str(token, target);
+    if target.is_empty() {
+        return_errno!(Errno::ENOENT, "symlink target is empty");
+    }
+    let path = at_base(&task, new_dirfd)?.cd(translated_str(token, linkpath));
+    // 链接路径的最后一级不能已经存在, 即使它是一个悬空的链接
+    let (path, dentry) = resolve(&path, false)?;
+    if dentry.is_some() {
+        return_errno!(Errno::EEXIST, "symlink path exists: {:?}", path);
     }
+    if find_mount(&path).ok_or(Errno::ENOENT)?.is_rdonly() {
+        return_errno!(Errno::EROFS, "read-only filesystem, path:{:?}", path);
+    }
+    lookup(&path.parent())?.symlink(&path.last(), &target)?;
+    Ok(0)
+}
+
+// readlinkat 78
+pub fn sys_readlinkat(dirfd: isize, pathname: *const u8, buf: *const u8, bufsiz: usize) -> Result {
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let path = translated_str(token, pathname);
+    let path = at_base(&task, dirfd)?.cd(path);
+    let target = lookup_nofollow(&path)?.readlink()?;
+    // readlink 不会在末尾添加 '\0'
+    let len = target.len().min(bufsiz);
+    let mut userbuf = UserBuffer::wrap(translated_bytes_buffer(token, buf, len)?);
+    userbuf.write(&target.as_bytes()[..len]);
+    Ok(len as isize)
 }
 
 // sync 81
 pub fn sys_sync() -> Result {
-    sync_all();
+    sync_all_fs();
+    Ok(0)
+}
+
+// fsync 82
+pub fn sys_fsync(fd: usize) -> Result {
+    let task = current_task().unwrap();
+    let file = match task.fd_table.read().get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return_errno!(Errno::EBADF, "could not find fd: {}", fd),
+    };
+    file.fsync()?;
     Ok(0)
 }
 
+// fdatasync 83
+pub fn sys_fdatasync(fd: usize) -> Result {
+    // 文件的元数据中只有大小会写回磁盘, 而大小也是 fdatasync 必须写回的, 两者没有区别
+    sys_fsync(fd)
+}
+
 // ftruncate64 46
 pub fn sys_ftruncate64(fd: usize, length: usize) -> Result {
     let task = current_task().unwrap();
     let fd_table = task.fd_table.read();
     if let Some(file) = &fd_table[fd] {
-        file.truncate(length);
+        file.truncate(length)?;
         Ok(0)
     } else {
         return_errno!(Errno::DISCARD);
@@ -1137,7 +1182,7 @@ pub fn sys_pselect6(
 
     let mut ubuf_rfds = {
         if readfds as usize != 0 {
-            UserBuffer::wrap(translated_bytes_buffer(token, readfds, size_of::<FdSet>()))
+            UserBuffer::wrap(translated_bytes_buffer(token, readfds, size_of::<FdSet>())?)
         } else {
             UserBuffer::empty()
         }
@@ -1146,7 +1191,11 @@ pub fn sys_pselect6(
 
     let mut ubuf_wfds = {
         if writefds as usize != 0 {
-            UserBuffer::wrap(translated_bytes_buffer(token, writefds, size_of::<FdSet>()))
+            UserBuffer::wrap(translated_bytes_buffer(
+                token,
+                writefds,
+                size_of::<FdSet>(),
+            )?)
         } else {
             UserBuffer::empty()
         }
@@ -1159,7 +1208,7 @@ pub fn sys_pselect6(
                 token,
                 exceptfds,
                 size_of::<FdSet>(),
-            ))
+            )?)
         } else {
             UserBuffer::empty()
         }
@@ -1270,7 +1319,10 @@ pub fn sys_pselect6(
                 drop(fd_table);
                 drop(task);
                 // suspend may nerver end, pipe read, timer
-                suspend_current_and_run_next();
+                if suspend_current_and_run_next() < 0 {
+                    // 与 Linux 一样, 被信号打断的 pselect 不会重新执行
+                    return_errno!(Errno::EINTR);
+                }
             } else {
                 ubuf_rfds.write(rfd_set.as_bytes());
                 ubuf_wfds.write(wfd_set.as_bytes());
@@ -1285,10 +1337,18 @@ pub fn sys_pselect6(
 
 // statfs 43
 // TODO
-pub fn sys_statfs(_path: *const u8, buf: *const u8) -> Result {
+pub fn sys_statfs(path: *const u8, buf: *const u8) -> Result {
     let token = current_user_token();
-    let mut userbuf = UserBuffer::wrap(translated_bytes_buffer(token, buf, size_of::<Statfs>()));
-    userbuf.write(Statfs::new().as_bytes());
+    let path = current_task()
+        .unwrap()
+        .get_work_path()
+        .cd(translated_str(token, path));
+    lookup(&path)?;
+    let mnt = find_mount(&path).ok_or(Errno::ENOENT)?;
+    let mut statfs = Statfs::new();
+    mnt.sb.statfs(&mut statfs);
+    let mut userbuf = UserBuffer::wrap(translated_bytes_buffer(token, buf, size_of::<Statfs>())?);
+    userbuf.write(statfs.as_bytes());
     Ok(0)
 }
 
@@ -55,7 +55,7 @@ pub fn sys_futex(
             // val2 is a timespec
             let time = if val2 as usize != 0 {
                 let mut ts = TimeSpec::empty();
-                copyin(token, &mut ts, val2 as *const TimeSpec);
+                copyin(token, &mut ts, val2 as *const TimeSpec)?;
                 ts.into_ns()
             } else {
                 usize::MAX // inf
@@ -116,10 +116,20 @@ pub fn futex_wait(uaddr: usize, val: u32, timeout: usize) -> Result {
         return_errno!(Errno::ETIMEDOUT);
     }
     let task = current_task().unwrap();
-    let inner = task.inner_ref();
     // woke by signal
-    if !inner.pending_signals.difference(inner.sigmask).is_empty() {
-        return_errno!(Errno::EINTR);
+    if task.has_unmasked_signal() {
+        // the waiter is still queued, drop it so that the task can be released when it exits
+        let mut fq_writer = FUTEX_QUEUE.write();
+        if let Some(fq) = fq_writer.get(&uaddr) {
+            if fq.remove_waiter(&task) && fq.waiters() == 0 {
+                fq_writer.remove(&uaddr);
+            }
+        }
+        // 有超时的等待不重新执行, 否则会从头开始计时
+        if timeout != usize::MAX {
+            return_errno!(Errno::EINTR);
+        }
+        return_errno!(Errno::ERESTARTSYS);
     }
 
     Ok(0)
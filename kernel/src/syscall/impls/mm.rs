@@ -1,31 +1,32 @@
 //! About syscall detail: https://man7.org/linux/man-pages/dir_section_2.html
 
+use crate::consts::{PAGE_SIZE, USER_SPACE_END};
 use crate::mm::create_shm;
+use crate::mm::translated_bytes_buffer;
 use crate::mm::MapPermission;
-use crate::mm::PTEFlags;
-use crate::mm::PageTable;
-use crate::mm::{VPNRange, VirtAddr};
+use crate::mm::{VirtAddr, VirtPageNum, VmBacking};
 use crate::return_errno;
-use crate::{
-    consts::PAGE_SIZE,
-    mm::remove_shm,
-    task::{current_task, current_user_token},
-};
+use crate::task::current_user_token;
+use crate::{mm::remove_shm, task::current_task};
 
 use nix::ipc::{IPC_PRIVATE, IPC_RMID};
 use nix::MmapFlags;
 use nix::MmapProts;
+use nix::MremapFlags;
+use nix::MsyncFlags;
+use nix::{MADV_DONTNEED, MADV_FREE, MADV_WILLNEED};
 
 use super::*;
 
 // brk 214
+// 与 Linux 一样, 无法移动 brk 时返回原来的 brk 而不是错误码
 pub fn sys_brk(brk: usize) -> Result {
     let task = current_task().unwrap();
     if brk == 0 {
         Ok(task.grow_proc(0) as isize)
     } else {
         let former_addr = task.grow_proc(0);
-        let grow_size: isize = (brk - former_addr) as isize;
+        let grow_size = brk as isize - former_addr as isize;
         Ok(current_task().unwrap().grow_proc(grow_size) as isize)
     }
 }
@@ -36,6 +37,102 @@ pub fn sys_munmap(addr: usize, length: usize) -> Result {
     Ok(task.munmap(addr, length) as isize)
 }
 
+// mremap 216
+pub fn sys_mremap(
+    old_address: usize,
+    old_size: usize,
+    new_size: usize,
+    flags: usize,
+    new_address: usize,
+) -> Result {
+    let flags = match MremapFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return_errno!(Errno::EINVAL, "invalid mremap flags: {:#x}", flags),
+    };
+    if old_address % PAGE_SIZE != 0 || old_size == 0 || new_size == 0 {
+        return_errno!(
+            Errno::EINVAL,
+            "old_address: {:#x}, old_size: {:#x}, new_size: {:#x}",
+            old_address,
+            old_size,
+            new_size
+        );
+    }
+    if flags.contains(MremapFlags::MREMAP_FIXED) && !flags.contains(MremapFlags::MREMAP_MAYMOVE) {
+        return_errno!(Errno::EINVAL, "MREMAP_FIXED without MREMAP_MAYMOVE");
+    }
+    if flags.contains(MremapFlags::MREMAP_DONTUNMAP) {
+        return_errno!(Errno::EINVAL, "MREMAP_DONTUNMAP is not supported");
+    }
+    if old_size > USER_SPACE_END || new_size > USER_SPACE_END {
+        return_errno!(Errno::EINVAL, "size is out of user space");
+    }
+    let old_pages = (old_size + PAGE_SIZE - 1) / PAGE_SIZE;
+    let new_pages = (new_size + PAGE_SIZE - 1) / PAGE_SIZE;
+    let old_start = VirtAddr::from(old_address).floor();
+    let old_end = VirtPageNum(old_start.0 + old_pages);
+
+    let task = current_task().unwrap();
+    let as_limit = task.inner_ref().rlimit_as.rlim_cur;
+    let memory_set = task.memory_set();
+    let mut memory_set = memory_set.write();
+    if new_pages > old_pages
+        && memory_set.total_pages() + new_pages - old_pages > as_limit / PAGE_SIZE
+    {
+        return_errno!(
+            Errno::ENOMEM,
+            "growing {:#x} exceeds RLIMIT_AS",
+            old_address
+        );
+    }
+    // 原范围必须位于同一个用户区域中
+    match memory_set.find_area(old_start) {
+        Some(area) if area.end_vpn() >= old_end && area.permission.contains(MapPermission::U) => {}
+        _ => return_errno!(
+            Errno::EFAULT,
+            "{:#x}..{:#x} is not within one mapping",
+            old_address,
+            old_address + old_size
+        ),
+    }
+    let new_start = if flags.contains(MremapFlags::MREMAP_FIXED) {
+        if new_address % PAGE_SIZE != 0 {
+            return_errno!(Errno::EINVAL, "new_address: {:#x}", new_address);
+        }
+        // 目标范围超出用户地址空间或与内核管理的区域重叠时, 在解除任何映射之前返回 EINVAL
+        if memory_set
+            .check_fixed_range(new_address, new_pages * PAGE_SIZE)
+            .is_err()
+        {
+            return_errno!(Errno::EINVAL, "cannot move to {:#x}", new_address);
+        }
+        let new_start = VirtAddr::from(new_address).floor();
+        let new_end = VirtPageNum(new_start.0 + new_pages);
+        if new_start < old_end && old_start < new_end {
+            return_errno!(Errno::EINVAL, "old and new ranges overlap");
+        }
+        memory_set.unmap_range(new_start, new_end);
+        new_start
+    } else if new_pages <= old_pages
+        || old_address + new_pages * PAGE_SIZE <= USER_SPACE_END
+            && memory_set.is_range_free(old_end, VirtPageNum(old_start.0 + new_pages))
+    {
+        old_start
+    } else if flags.contains(MremapFlags::MREMAP_MAYMOVE) {
+        match memory_set.find_free_area(new_pages) {
+            Some(new_start) => new_start,
+            None => return_errno!(Errno::ENOMEM, "no free area for {:#x} pages", new_pages),
+        }
+    } else {
+        return_errno!(Errno::ENOMEM, "cannot grow {:#x} in place", old_address);
+    };
+    if new_pages < old_pages {
+        memory_set.unmap_range(VirtPageNum(old_start.0 + new_pages), old_end);
+    }
+    memory_set.remap(old_start, old_pages.min(new_pages), new_start, new_pages)?;
+    Ok(VirtAddr::from(new_start).0 as isize)
+}
+
 // mmap 222
 pub fn sys_mmap(
     addr: usize,
@@ -53,11 +150,23 @@ pub fn sys_mmap(
     if padding == PAGE_SIZE {
         padding = 0;
     }
-    let length = length + padding;
+    let length = match length.checked_add(padding) {
+        Some(length) => length,
+        None => return_errno!(Errno::ENOMEM, "length {:#x} is too large", length),
+    };
     let task = current_task().unwrap();
     let fd_table = task.fd_table.read();
-    let prot = MmapProts::from_bits(prot).expect("unsupported mmap prot");
-    let flags = MmapFlags::from_bits(flags).expect("unsupported mmap flags");
+    let prot = match MmapProts::from_bits(prot) {
+        Some(prot) => prot,
+        None => return_errno!(Errno::EINVAL, "invalid mmap prot: {:#x}", prot),
+    };
+    let flags = match MmapFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return_errno!(Errno::EINVAL, "invalid mmap flags: {:#x}", flags),
+    };
+    if flags.contains(MmapFlags::MAP_FIXED) && addr % PAGE_SIZE != 0 {
+        return_errno!(Errno::EINVAL, "MAP_FIXED with unaligned addr: {:#x}", addr);
+    }
     if !flags.contains(MmapFlags::MAP_ANONYMOUS)
         && (fd as usize >= fd_table.len() || fd_table[fd as usize].is_none())
     {
@@ -66,8 +175,7 @@ pub fn sys_mmap(
 
     drop(fd_table);
 
-    let result_addr = task.mmap(addr, length, prot, flags, fd, offset);
-
+    let result_addr = task.mmap(addr, length, prot, flags, fd, offset)?;
     Ok(result_addr as isize)
 }
 
@@ -99,13 +207,14 @@ pub fn sys_shmctl(key: usize, cmd: usize, _buf: *const u8) -> Result {
 // TODO
 pub fn sys_shmat(key: usize, address: usize, _shmflg: usize) -> Result {
     let task = current_task().unwrap();
-    let mut memory_set = task.memory_set.write();
+    let memory_set = task.memory_set();
+    let mut memory_set = memory_set.write();
     let address = if address == 0 {
         memory_set.shm_top
     } else {
         address
     };
-    memory_set.attach_shm(key, address.into());
+    memory_set.attach_shm(key, address.into())?;
     drop(memory_set);
     Ok(address as isize)
 }
@@ -113,49 +222,143 @@ pub fn sys_shmat(key: usize, address: usize, _shmflg: usize) -> Result {
 // shmdt 197
 pub fn sys_shmdt(address: usize) -> Result {
     let task = current_task().unwrap();
-    let mut memory_set = task.memory_set.write();
+    let memory_set = task.memory_set();
+    let mut memory_set = memory_set.write();
     let nattch = memory_set.detach_shm(address.into());
     drop(memory_set);
     // detach_shm called when drop SharedMemoryTracker
-
-    Ok(nattch as isize)
+    match nattch {
+        Some(nattch) => Ok(nattch as isize),
+        None => return_errno!(Errno::EINVAL, "no shared memory at {:#x}", address),
+    }
 }
 
 // mprotect 226
 pub fn sys_mprotect(addr: usize, length: usize, prot: usize) -> Result {
-    let token = current_user_token();
-    let page_table = PageTable::from_token(token);
-
-    let map_flags = (((prot & 0b111) << 1) + (1 << 4)) as u64;
-    let map_perm = MapPermission::from_bits(map_flags).unwrap();
-    // let pte_flags = PTEFlags::from_bits(map_perm.bits()).unwrap() | PTEFlags::V;
-    let pte_flags = PTEFlags::from_map_permission(map_perm) | PTEFlags::V;
-
-    let start_va = VirtAddr::from(addr);
-    let end_va = VirtAddr::from(addr + length);
-    let vpn_range = VPNRange::from_va(start_va, end_va);
-
-    for vpn in vpn_range {
-        if let Some(pte) = page_table.find_pte(vpn) {
-            pte.set_flags(pte_flags);
-        } else {
-            let task = current_task().unwrap();
-            let mut memory_set = task.memory_set.write();
-            let mmap_start = memory_set.mmap_manager.mmap_start;
-            let mmap_top = memory_set.mmap_manager.mmap_top;
-            let mmap_perm = MmapProts::from_bits(prot).unwrap();
-            let va: VirtAddr = vpn.into();
-            if va >= mmap_start && va < mmap_top {
-                memory_set
-                    .mmap_manager
-                    .mmap_map
-                    .get_mut(&vpn)
-                    .unwrap()
-                    .set_prot(mmap_perm);
-                continue;
+    let prot = match MmapProts::from_bits(prot) {
+        Some(prot) => prot,
+        None => return_errno!(Errno::EINVAL, "invalid mprotect prot: {:#x}", prot),
+    };
+    if addr % PAGE_SIZE != 0 {
+        return_errno!(Errno::EINVAL, "addr: {:#x}", addr);
+    }
+    let map_perm = MapPermission::from_vm_prot(prot) | MapPermission::U;
+
+    let task = current_task().unwrap();
+    let memory_set = task.memory_set();
+    let mut memory_set = memory_set.write();
+    if !memory_set.mprotect(VirtAddr::from(addr), length, map_perm) {
+        return_errno!(
+            Errno::ENOMEM,
+            "range is not mapped, addr: {:#x}, length: {:#x}",
+            addr,
+            length
+        );
+    }
+    Ok(0)
+}
+
+// msync 227
+pub fn sys_msync(addr: usize, length: usize, flags: usize) -> Result {
+    let flags = match MsyncFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return_errno!(Errno::EINVAL, "invalid msync flags: {:#x}", flags),
+    };
+    if addr % PAGE_SIZE != 0 || flags.contains(MsyncFlags::MS_ASYNC | MsyncFlags::MS_SYNC) {
+        return_errno!(Errno::EINVAL, "addr: {:#x}, flags: {:?}", addr, flags);
+    }
+    let task = current_task().unwrap();
+    let memory_set = task.memory_set();
+    let files = memory_set.read().write_back(VirtAddr::from(addr), length);
+    let files = match files {
+        Some(files) => files,
+        None => return_errno!(
+            Errno::ENOMEM,
+            "range is not mapped, addr: {:#x}, length: {:#x}",
+            addr,
+            length
+        ),
+    };
+    // MS_ASYNC 只需将数据交给页缓存, 之后由后台写回
+    if flags.contains(MsyncFlags::MS_SYNC) {
+        for file in files {
+            file.fsync()?;
+        }
+    }
+    Ok(0)
+}
+
+// mincore 232
+pub fn sys_mincore(addr: usize, length: usize, vec: *mut u8) -> Result {
+    if addr % PAGE_SIZE != 0 {
+        return_errno!(Errno::EINVAL, "addr: {:#x}", addr);
+    }
+    let start_vpn = VirtAddr::from(addr).floor();
+    let end_vpn = VirtAddr::from(addr + length).ceil();
+    let task = current_task().unwrap();
+    let residency = {
+        let memory_set = task.memory_set();
+        let memory_set = memory_set.read();
+        if !memory_set.check_va_range(VirtAddr::from(addr), length) {
+            return_errno!(
+                Errno::ENOMEM,
+                "range is not mapped, addr: {:#x}, length: {:#x}",
+                addr,
+                length
+            );
+        }
+        if !memory_set.check_va_range(VirtAddr::from(vec as usize), end_vpn.0 - start_vpn.0) {
+            return_errno!(Errno::EFAULT, "invalid vec: {:#x}", vec as usize);
+        }
+        memory_set.residency(start_vpn, end_vpn)
+    };
+    // 写入 vec 时可能缺页, 此时不能持有地址空间的锁
+    let buffers = translated_bytes_buffer(current_user_token(), vec, residency.len())?;
+    for (byte, resident) in buffers.into_iter().flatten().zip(residency) {
+        *byte = resident as u8;
+    }
+    Ok(0)
+}
+
+// madvise 233
+pub fn sys_madvise(addr: usize, length: usize, advice: usize) -> Result {
+    if addr % PAGE_SIZE != 0 {
+        return_errno!(Errno::EINVAL, "addr: {:#x}", addr);
+    }
+    let start_vpn = VirtAddr::from(addr).floor();
+    let end_vpn = VirtAddr::from(addr + length).ceil();
+    let task = current_task().unwrap();
+    let memory_set = task.memory_set();
+    let mut memory_set = memory_set.write();
+    if !memory_set.check_va_range(VirtAddr::from(addr), length) {
+        return_errno!(
+            Errno::ENOMEM,
+            "range is not mapped, addr: {:#x}, length: {:#x}",
+            addr,
+            length
+        );
+    }
+    match advice {
+        MADV_DONTNEED => memory_set.discard(start_vpn, end_vpn),
+        MADV_FREE => {
+            // 只能用于私有的匿名映射, 这里直接释放页帧而不等到内存紧张
+            let private_anonymous = memory_set
+                .areas
+                .range(..end_vpn)
+                .rev()
+                .take_while(|(_, area)| area.end_vpn() > start_vpn)
+                .all(|(_, area)| !area.shared && matches!(area.backing, VmBacking::Anonymous));
+            if !private_anonymous {
+                return_errno!(
+                    Errno::EINVAL,
+                    "MADV_FREE on a non private anonymous mapping"
+                );
             }
-            return_errno!(Errno::EINVAL, "invalid address: {:?}", va);
+            memory_set.discard(start_vpn, end_vpn);
         }
+        MADV_WILLNEED => memory_set.prefetch(start_vpn, end_vpn),
+        // 其余的建议只影响性能, 忽略
+        _ => {}
     }
     Ok(0)
 }
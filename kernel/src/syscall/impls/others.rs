@@ -1,14 +1,19 @@
 //! About syscall detail: https://man7.org/linux/man-pages/dir_section_2.html
 
+use alloc::sync::Arc;
 use nix::info::Utsname;
-use nix::{itimerval, tms, IntervalTimer, IntervalTimerType, TimeSpec, TimeVal};
+use nix::{
+    itimerspec, itimerval, tms, GetRandomFlags, IntervalTimerType, SigEvent, Signal, TimeSpec,
+    TimeVal, CLOCK_MONOTONIC, CLOCK_REALTIME, MAX_SIGNUM, SIGEV_NONE, SIGEV_SIGNAL, SIGEV_THREAD,
+    SIGEV_THREAD_ID, TIMER_ABSTIME,
+};
 
-use crate::mm::translated_mut;
+use crate::random::{get_random_bytes, random_ready, wait_for_random};
 use crate::return_errno;
-use crate::task::{current_task, hanging_current_and_run_next};
-use crate::timer::get_time_ns;
+use crate::task::{current_task, hanging_current_and_run_next, pid2task, PosixTimer, TimerNotify};
+use crate::timer::{get_time_ns, NSEC_PER_SEC, USEC_PER_SEC};
 use crate::{
-    mm::{translated_bytes_buffer, translated_ref, UserBuffer},
+    mm::{copyin, copyout, translated_bytes_buffer, translated_ref, UserBuffer},
     task::{current_user_token, suspend_current_and_run_next},
     timer::{get_time_ms, get_timeval},
 };
@@ -19,7 +24,7 @@ use super::*;
 pub fn sys_times(buf: *const u8) -> Result {
     let sec = get_time_ms() as isize * 1000;
     let token = current_user_token();
-    let buffers = translated_bytes_buffer(token, buf, core::mem::size_of::<tms>());
+    let buffers = translated_bytes_buffer(token, buf, core::mem::size_of::<tms>())?;
     let mut userbuf = UserBuffer::wrap(buffers);
     // TODO tms rusage
     userbuf.write(
@@ -41,7 +46,7 @@ pub fn sys_uname(buf: *const u8) -> Result {
         token,
         buf,
         core::mem::size_of::<Utsname>(),
-    ));
+    )?);
     userbuf.write(Utsname::get().as_bytes());
     Ok(0)
 }
@@ -55,7 +60,7 @@ pub fn sys_sched_yield() -> Result {
 // gettimeofday 169
 pub fn sys_gettimeofday(buf: *const u8) -> Result {
     let token = current_user_token();
-    let buffers = translated_bytes_buffer(token, buf, core::mem::size_of::<TimeVal>());
+    let buffers = translated_bytes_buffer(token, buf, core::mem::size_of::<TimeVal>())?;
     let mut userbuf = UserBuffer::wrap(buffers);
     userbuf.write(get_timeval().as_bytes());
     Ok(0)
@@ -96,122 +101,201 @@ pub fn sys_clock_nanosleep(
 }
 
 // getrandom 278
-// TODO
-pub fn sys_getrandom(_buf: *const u8, buf_size: usize, _flags: usize) -> Result {
+pub fn sys_getrandom(buf: *mut u8, buf_size: usize, flags: usize) -> Result {
+    let flags = match GetRandomFlags::from_bits(flags as u32) {
+        Some(valid) if valid.bits() as usize == flags => valid,
+        _ => return_errno!(Errno::EINVAL, "invalid getrandom flags {:#x}", flags),
+    };
+    if flags.contains(GetRandomFlags::GRND_INSECURE | GetRandomFlags::GRND_RANDOM) {
+        return_errno!(Errno::EINVAL, "GRND_INSECURE with GRND_RANDOM");
+    }
+    if !flags.contains(GetRandomFlags::GRND_INSECURE) && !random_ready() {
+        if flags.contains(GetRandomFlags::GRND_NONBLOCK) {
+            return_errno!(Errno::EAGAIN, "entropy pool is not initialized yet");
+        }
+        wait_for_random()?;
+    }
+    let buffers = translated_bytes_buffer(current_user_token(), buf, buf_size)?;
+    for buffer in buffers {
+        get_random_bytes(buffer);
+    }
     Ok(buf_size as isize)
 }
 
 // setitimer 103
 pub fn sys_setitimer(which: i32, new_value: *const itimerval, old_value: *mut itimerval) -> Result {
-    let nvp_usize = new_value as usize;
-    let ovp_usize = old_value as usize;
-    if nvp_usize == 0 {
-        return_errno!(Errno::EFAULT);
-    }
-    if let Ok(itimer_type) = IntervalTimerType::try_from(which) {
-        let task = current_task().unwrap();
-        if ovp_usize != 0 {
-            let inner = task.inner_ref();
-            if let Some(itimer) = &inner.interval_timer {
-                let ov = translated_mut(task.token(), old_value);
-                *ov = itimer.timer_value;
-            }
-        }
-        match itimer_type {
-            IntervalTimerType::Real => {
-                // 是否删除当前 itmer/新设置的 itmer 是否只触发一次
-                let nv = translated_ref(task.token(), new_value);
-                let zero = TimeVal::zero();
-                let mut inner = task.inner_mut();
-                if nv.it_interval == zero && nv.it_value == zero {
-                    inner.interval_timer = None;
-                    return Ok(0);
-                }
-                inner.interval_timer = Some(IntervalTimer::new(*nv, get_timeval()));
-            }
-            // TODO: 用到再写
-            IntervalTimerType::Virtual => {
-                unimplemented!("ITIMER_VIRTUAL")
-            }
-            IntervalTimerType::Profile => {
-                unimplemented!("ITIMER_PROF")
-            }
-        }
-    } else {
-        return_errno!(
+    let which = match IntervalTimerType::try_from(which) {
+        Ok(which) => which,
+        Err(_) => return_errno!(
             Errno::EINVAL,
             "which {} is not one of ITIMER_REAL, ITIMER_VIRTUAL, or ITIMER_PROF",
             which
-        );
+        ),
+    };
+    if new_value.is_null() {
+        return_errno!(Errno::EFAULT);
+    }
+    let token = current_user_token();
+    let mut new = itimerval::empty();
+    copyin(token, &mut new, new_value)?;
+    if new.it_value.usec >= USEC_PER_SEC || new.it_interval.usec >= USEC_PER_SEC {
+        return_errno!(Errno::EINVAL, "invalid itimerval {:?}", new);
+    }
+    let task = current_task().unwrap();
+    let old = task.thread_group.set_itimer(which, new)?;
+    if !old_value.is_null() {
+        copyout(token, old_value, &old)?;
     }
     Ok(0)
 }
 
 // getitimer 102
 pub fn sys_getitimer(which: i32, curr_value: *mut itimerval) -> Result {
-    let cv_usize = curr_value as usize;
-    if cv_usize == 0 {
-        return_errno!(Errno::EFAULT);
-    }
-    if let Ok(itimer_type) = IntervalTimerType::try_from(which) {
-        let task = current_task().unwrap();
-        match itimer_type {
-            IntervalTimerType::Real => {
-                let inner = task.inner_ref();
-                let cv = translated_mut(task.token(), curr_value);
-                *cv = if let Some(itimerval) = &inner.interval_timer {
-                    itimerval.timer_value
-                } else {
-                    itimerval::empty()
-                };
-            }
-            // TODO: 用到再写
-            IntervalTimerType::Virtual => {
-                unimplemented!("ITIMER_VIRTUAL")
-            }
-            IntervalTimerType::Profile => {
-                unimplemented!("ITIMER_PROF")
-            }
-        }
-    } else {
-        return_errno!(
+    let which = match IntervalTimerType::try_from(which) {
+        Ok(which) => which,
+        Err(_) => return_errno!(
             Errno::EINVAL,
             "which {} is not one of ITIMER_REAL, ITIMER_VIRTUAL, or ITIMER_PROF",
             which
-        );
+        ),
+    };
+    if curr_value.is_null() {
+        return_errno!(Errno::EFAULT);
     }
+    let task = current_task().unwrap();
+    let curr = task.thread_group.itimer(which);
+    copyout(current_user_token(), curr_value, &curr)?;
     Ok(0)
 }
 
+// timer_create 107
+pub fn sys_timer_create(clock_id: usize, sevp: *const SigEvent, timer_id: *mut i32) -> Result {
+    if clock_id != CLOCK_REALTIME && clock_id != CLOCK_MONOTONIC {
+        return_errno!(Errno::EINVAL, "unsupported clock: {}", clock_id);
+    }
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    // 默认以 SIGALRM 通知进程, sigev_value 为定时器 id
+    let mut event = SigEvent::empty();
+    event.sigev_signo = Signal::SIGALRM as i32;
+    let default_value = sevp.is_null();
+    if !default_value {
+        copyin(token, &mut event, sevp)?;
+    }
+    let (signo, value) = (event.sigev_signo, event.sigev_value);
+    if event.sigev_notify != SIGEV_NONE && (signo <= 0 || signo > MAX_SIGNUM as i32) {
+        return_errno!(Errno::EINVAL, "invalid signal: {}", signo);
+    }
+    let tid = event.sigev_notify_thread_id as usize;
+    let notify = match event.sigev_notify {
+        SIGEV_NONE => TimerNotify::None,
+        // SIGEV_THREAD 由 libc 实现, 内核只负责发送信号
+        SIGEV_SIGNAL | SIGEV_THREAD => TimerNotify::Process { signo, value },
+        SIGEV_THREAD_ID => {
+            if !pid2task(tid).map_or(false, |thread| thread.tgid == task.tgid) {
+                return_errno!(Errno::EINVAL, "thread {} is not in this process", tid);
+            }
+            TimerNotify::Thread { signo, value, tid }
+        }
+        notify => return_errno!(Errno::EINVAL, "invalid sigev_notify: {}", notify),
+    };
+    let id = task.thread_group.create_timer(|id| match notify {
+        TimerNotify::Process { signo, .. } if default_value => {
+            TimerNotify::Process { signo, value: id }
+        }
+        notify => notify,
+    });
+    copyout(token, timer_id, &(id as i32))?;
+    Ok(0)
+}
+
+/// 当前进程中 id 为 `timer_id` 的 POSIX 定时器
+fn current_timer(timer_id: usize) -> core::result::Result<Arc<PosixTimer>, Errno> {
+    match current_task().unwrap().thread_group.timer(timer_id) {
+        Some(timer) => Ok(timer),
+        None => return_errno!(Errno::EINVAL, "no timer {}", timer_id),
+    }
+}
+
+/// `TimeSpec` 转换为 ns, 溢出时返回 None
+fn timespec_to_ns(time: TimeSpec) -> Option<usize> {
+    (time.tv_sec as usize)
+        .checked_mul(NSEC_PER_SEC)?
+        .checked_add(time.tv_nsec as usize)
+}
+
+/// 剩余时间与周期 (ns) 转换为 itimerspec
+fn itimerspec_of((value, interval): (usize, usize)) -> itimerspec {
+    itimerspec {
+        it_interval: TimeSpec::from_ns(interval),
+        it_value: TimeSpec::from_ns(value),
+    }
+}
+
 // timer_settime 110
 pub fn sys_timer_settime(
-    _time_id: usize,
-    _flags: isize,
-    new_value: *const itimerval,
-    old_value: *mut itimerval,
+    timer_id: usize,
+    flags: usize,
+    new_value: *const itimerspec,
+    old_value: *mut itimerspec,
 ) -> Result {
-    let task = current_task().unwrap();
-    if new_value as usize != 0 {
-        let nv = translated_ref(task.token(), new_value);
-        let zero = TimeVal::zero();
-        let mut inner = task.inner_mut();
-        if nv.it_interval == zero && nv.it_value == zero {
-            inner.interval_timer = None;
-        }
-        inner.interval_timer = Some(IntervalTimer::new(*nv, get_timeval()));
+    let timer = current_timer(timer_id)?;
+    if new_value.is_null() {
+        return_errno!(Errno::EFAULT);
+    }
+    let token = current_user_token();
+    let mut new = itimerspec::empty();
+    copyin(token, &mut new, new_value)?;
+    if new.it_value.tv_nsec >= NSEC_PER_SEC as u64 || new.it_interval.tv_nsec >= NSEC_PER_SEC as u64
+    {
+        return_errno!(Errno::EINVAL, "invalid itimerspec: {:?}", new);
     }
-    if old_value as usize != 0 {
-        let inner = task.inner_ref();
-        if let Some(itimer) = &inner.interval_timer {
-            let ov = translated_mut(task.token(), old_value);
-            *ov = itimer.timer_value;
+    let (value, interval) = match (
+        timespec_to_ns(new.it_value),
+        timespec_to_ns(new.it_interval),
+    ) {
+        (Some(value), Some(interval)) => (value, interval),
+        _ => return_errno!(Errno::EINVAL, "itimerspec overflows: {:?}", new),
+    };
+    let deadline = if value == 0 {
+        None
+    } else if flags & TIMER_ABSTIME != 0 {
+        // CLOCK_REALTIME 与 CLOCK_MONOTONIC 都从开机时刻开始计时
+        Some(value)
+    } else {
+        match get_time_ns().checked_add(value) {
+            Some(deadline) => Some(deadline),
+            None => return_errno!(Errno::EINVAL, "it_value overflows: {:?}", new.it_value),
         }
+    };
+    let old = timer.set(deadline, interval);
+    if !old_value.is_null() {
+        copyout(token, old_value, &itimerspec_of(old))?;
     }
     Ok(0)
 }
 
+// timer_gettime 108
+pub fn sys_timer_gettime(timer_id: usize, curr_value: *mut itimerspec) -> Result {
+    let timer = current_timer(timer_id)?;
+    copyout(
+        current_user_token(),
+        curr_value,
+        &itimerspec_of(timer.get()),
+    )?;
+    Ok(0)
+}
+
 // timer_getoverrun 109
-pub fn sys_timer_getoverrun(_timerid: usize) -> Result {
+pub fn sys_timer_getoverrun(timer_id: usize) -> Result {
+    Ok(current_timer(timer_id)?.overrun() as isize)
+}
+
+// timer_delete 111
+pub fn sys_timer_delete(timer_id: usize) -> Result {
+    if !current_task().unwrap().thread_group.delete_timer(timer_id) {
+        return_errno!(Errno::EINVAL, "no timer {}", timer_id);
+    }
     Ok(0)
 }
 
@@ -227,7 +311,7 @@ pub fn sys_recvfrom(
     let src = "x";
     let token = current_user_token();
     let len = src.as_bytes().len();
-    let mut buffer = UserBuffer::wrap(translated_bytes_buffer(token, buf, len));
+    let mut buffer = UserBuffer::wrap(translated_bytes_buffer(token, buf, len)?);
     buffer.write(src.as_bytes());
     Ok(1)
 }
@@ -1,26 +1,32 @@
 //! About syscall detail: https://man7.org/linux/man-pages/dir_section_2.html
 
 use crate::boards::CLOCK_FREQ;
+use crate::consts::PAGE_SIZE;
 use crate::fs::{make_pipe, open};
 use crate::mm::{
     copyin, copyout, translated_bytes_buffer, translated_mut, translated_ref, translated_str,
-    UserBuffer,
+    UserBuffer, VirtAddr,
 };
 use crate::return_errno;
 use crate::task::{
-    current_task, current_user_token, exit_current_and_run_next, pid2task,
-    suspend_current_and_run_next, SignalContext,
+    all_thread_groups, current_task, current_user_token, exit_current_and_run_next,
+    exit_group_and_run_next, pid2task, process_group, tgid2group, JobEvent, SignalContext,
+    ThreadGroup,
 };
-use crate::timer::{get_time, NSEC_PER_SEC};
+use crate::timer::{get_time, get_time_ns, NSEC_PER_SEC};
 use alloc::{string::String, string::ToString, sync::Arc, vec::Vec};
+use core::mem::size_of;
 use core::usize;
 use nix::info::RUsage;
-use nix::resource::{RLimit, Resource};
+use nix::resource::{RLimit, Resource, RLIM_INFINITY};
 use nix::robustlist::RobustList;
 use nix::time::TimeSpec;
 use nix::{
-    CloneFlags, CpuMask, CreateMode, MaskFlags, OpenFlags, SchedParam, SigAction, SigInfo, SigMask,
-    Signal, UContext, MAX_SIGNUM, RUSAGE_SELF, SCHED_OTHER,
+    CloneArgs, CloneFlags, CpuMask, CreateMode, MaskFlags, OpenFlags, SchedParam, SigAction,
+    SigInfo, SigMask, SigSet, Signal, SignalStack, UContext, WaitOptions, CLD_CONTINUED,
+    CLD_EXITED, CLD_KILLED, CLD_STOPPED, CLONE_ARGS_SIZE_VER0, CSIGNAL, MAX_SIGNUM, MINSIGSTKSZ,
+    P_ALL, P_PGID, P_PID, RUSAGE_CHILDREN, RUSAGE_SELF, SCHED_OTHER, SI_TKILL, SI_USER, SS_DISABLE,
+    SS_ONSTACK,
 };
 
 use super::super::errno::*;
@@ -29,11 +35,122 @@ use crate::task::*;
 
 // clone 220
 pub fn sys_do_fork(flags: usize, stack_ptr: usize, ptid: usize, tls: usize, ctid: usize) -> Result {
+    // 与 Linux 一样, clone 只使用低 32 位的标志, 更高的位只能通过 clone3 指定
+    let flags = flags as u32 as usize;
+    let exit_signal = flags & CSIGNAL;
+    if exit_signal > MAX_SIGNUM as usize {
+        return_errno!(Errno::EINVAL, "invalid exit signal {}", exit_signal);
+    }
+    let flags = match CloneFlags::from_bits(flags & !CSIGNAL) {
+        Some(flags) => flags,
+        None => return_errno!(Errno::EINVAL, "unknown clone flags {:#x}", flags),
+    };
+    do_clone(flags, exit_signal, stack_ptr, ptid, tls, ctid)
+}
+
+// clone3 435
+pub fn sys_clone3(uargs: *const CloneArgs, size: usize) -> Result {
+    if size < CLONE_ARGS_SIZE_VER0 {
+        return_errno!(Errno::EINVAL, "clone_args size {} is too small", size);
+    }
+    if size > PAGE_SIZE {
+        return_errno!(Errno::E2BIG, "clone_args size {} is too large", size);
+    }
+    // 用户程序的 clone_args 可能比内核的新或旧: 缺少的字段视为 0, 多出的字段必须为 0
+    let mut bytes = vec![0u8; size];
+    let mut pos = 0;
+    for buffer in translated_bytes_buffer(current_user_token(), uargs as *const u8, size)? {
+        bytes[pos..pos + buffer.len()].copy_from_slice(buffer);
+        pos += buffer.len();
+    }
+    let known = size_of::<CloneArgs>();
+    if size > known && bytes[known..].iter().any(|&b| b != 0) {
+        return_errno!(Errno::E2BIG, "unknown fields in clone_args are not zero");
+    }
+    let mut args = CloneArgs::default();
+    let len = size.min(known);
+    unsafe {
+        core::ptr::copy_nonoverlapping(bytes.as_ptr(), &mut args as *mut _ as *mut u8, len);
+    }
+
+    let flags = match CloneFlags::from_bits(args.flags as usize) {
+        Some(flags) => flags,
+        None => return_errno!(Errno::EINVAL, "unknown clone3 flags {:#x}", args.flags),
+    };
+    if args.exit_signal > MAX_SIGNUM as u64 {
+        return_errno!(Errno::EINVAL, "invalid exit signal {}", args.exit_signal);
+    }
+    if args.exit_signal != 0 && flags.intersects(CloneFlags::THREAD | CloneFlags::PARENT) {
+        return_errno!(Errno::EINVAL, "exit signal with {:?}", flags);
+    }
+    if (args.stack == 0) != (args.stack_size == 0) {
+        return_errno!(Errno::EINVAL, "stack and stack_size must be set together");
+    }
+    if args.set_tid != 0 || args.set_tid_size != 0 {
+        return_errno!(Errno::EINVAL, "set_tid is not supported");
+    }
+    if flags.contains(CloneFlags::CLEAR_SIGHAND) && flags.contains(CloneFlags::SIGHAND) {
+        return_errno!(Errno::EINVAL, "CLONE_CLEAR_SIGHAND with CLONE_SIGHAND");
+    }
+    // 栈向下增长, 子任务从栈的最高地址开始使用
+    let stack = (args.stack + args.stack_size) as usize;
+    do_clone(
+        flags,
+        args.exit_signal as usize,
+        stack,
+        args.parent_tid as usize,
+        args.tls as usize,
+        args.child_tid as usize,
+    )
+}
+
+/// clone 与 clone3 的公共部分. 新进程退出时向父进程发送 `exit_signal`, 0 表示不发送;
+/// CLONE_PARENT 创建的进程沿用调用者的 exit_signal, 线程则忽略它
+fn do_clone(
+    flags: CloneFlags,
+    exit_signal: usize,
+    stack_ptr: usize,
+    ptid: usize,
+    tls: usize,
+    ctid: usize,
+) -> Result {
     let current_task = current_task().unwrap();
-    let _signal = flags & 0xff;
-    let flags = CloneFlags::from_bits(flags & !0xff).unwrap();
 
-    let new_task = current_task.fork(flags);
+    if flags.contains(CloneFlags::THREAD) && !flags.contains(CloneFlags::SIGHAND) {
+        return_errno!(Errno::EINVAL, "CLONE_THREAD without CLONE_SIGHAND");
+    }
+    if flags.contains(CloneFlags::SIGHAND) && !flags.contains(CloneFlags::VM) {
+        return_errno!(Errno::EINVAL, "CLONE_SIGHAND without CLONE_VM");
+    }
+    // initproc 不能创建兄弟进程, 否则它们会成为没有父进程的孤儿
+    if flags.contains(CloneFlags::PARENT) && current_task.tgid == INITPROC.tgid {
+        return_errno!(Errno::EINVAL, "initproc cannot create siblings");
+    }
+    let unsupported = CloneFlags::PIDFD
+        | CloneFlags::INTO_CGROUP
+        | CloneFlags::NEWNS
+        | CloneFlags::NEWCGROUP
+        | CloneFlags::NEWUTS
+        | CloneFlags::NEWIPC
+        | CloneFlags::NEWUSER
+        | CloneFlags::NEWPID
+        | CloneFlags::NEWNET;
+    if flags.intersects(unsupported) {
+        let flags = flags & unsupported;
+        return_errno!(Errno::EINVAL, "unsupported clone flags {:?}", flags);
+    }
+    // 与 Linux 一样, 线程也计入 RLIMIT_NPROC
+    let nproc_limit = current_task.inner_ref().rlimit_nproc.rlim_cur;
+    if PID2TCB.lock().len() >= nproc_limit {
+        return_errno!(Errno::EAGAIN, "RLIMIT_NPROC {} reached", nproc_limit);
+    }
+
+    let exit_signal = if flags.contains(CloneFlags::PARENT) {
+        current_task.thread_group.exit_signal
+    } else {
+        exit_signal
+    };
+    let new_task = current_task.fork(flags, exit_signal)?;
 
     if stack_ptr != 0 {
         let trap_cx = new_task.inner_mut().trap_context();
@@ -41,15 +158,11 @@ pub fn sys_do_fork(flags: usize, stack_ptr: usize, ptid: usize, tls: usize, ctid
     }
     let new_pid = new_task.pid.0;
 
-    let memory_set = new_task.memory_set.read();
-    let child_token = memory_set.token();
-    drop(memory_set);
-
     if flags.contains(CloneFlags::PARENT_SETTID) {
-        *translated_mut(current_user_token(), ptid as *mut u32) = new_pid as u32;
+        put_tid(&current_task, ptid, new_pid);
     }
     if flags.contains(CloneFlags::CHILD_SETTID) {
-        *translated_mut(child_token, ctid as *mut u32) = new_pid as u32;
+        put_tid(&new_task, ctid, new_pid);
     }
     if flags.contains(CloneFlags::CHILD_CLEARTID) {
         new_task.inner_mut().clear_child_tid = ctid;
@@ -66,14 +179,31 @@ pub fn sys_do_fork(flags: usize, stack_ptr: usize, ptid: usize, tls: usize, ctid
     // 使得它回到用户态之后, 会从发出系统调用的 ecall 指令的下一条指令开始执行
 
     trap_cx.x[10] = 0; // 对于子进程, 返回值是0
-    add_task(new_task); // 将 fork 到的进程加入任务调度器
+    add_task(new_task.clone()); // 将 fork 到的进程加入任务调度器
     unsafe {
         core::arch::asm!("sfence.vma");
         core::arch::asm!("fence.i");
     }
+    if flags.contains(CloneFlags::VFORK) {
+        current_task.wait_vfork_done(&new_task);
+    }
     Ok(new_pid as isize) // 对于父进程, 返回值是子进程的 PID
 }
 
+/// 将线程号写入任务 `task` 地址空间中的 `addr`. 写时复制的页面先复制一份, 与 Linux 一样,
+/// 地址无效时不报错
+fn put_tid(task: &TaskControlBlock, addr: usize, tid: usize) {
+    let va = VirtAddr::from(addr);
+    let writable = task
+        .memory_set()
+        .read()
+        .translate(va.floor())
+        .map_or(false, |pte| pte.is_valid() && pte.writable());
+    if writable || task.check_lazy(va) == 0 {
+        *translated_mut(task.token(), addr as *mut u32) = tid as u32;
+    }
+}
+
 // execve 221
 pub fn sys_exec(path: *const u8, mut argv: *const usize, mut envp: *const usize) -> Result {
     let token = current_user_token();
@@ -97,7 +227,7 @@ pub fn sys_exec(path: *const u8, mut argv: *const usize, mut envp: *const usize)
     if argv as usize != 0 {
         loop {
             let mut arg_str_ptr = 0;
-            copyin(token, &mut arg_str_ptr, argv);
+            copyin(token, &mut arg_str_ptr, argv)?;
             if arg_str_ptr == 0 {
                 // 读到下一参数地址为0表示参数结束
                 break;
@@ -111,7 +241,7 @@ pub fn sys_exec(path: *const u8, mut argv: *const usize, mut envp: *const usize)
     if envp as usize != 0 {
         loop {
             let mut env_str_ptr = 0;
-            copyin(token, &mut env_str_ptr, envp);
+            copyin(token, &mut env_str_ptr, envp)?;
             if env_str_ptr == 0 {
                 // 读到下一参数地址为0表示参数结束
                 break;
@@ -129,94 +259,276 @@ pub fn sys_exec(path: *const u8, mut argv: *const usize, mut envp: *const usize)
     envs_vec.push("ENOUGH=5000".to_string());
     let task = current_task().unwrap();
 
-    let inner = task.inner_mut();
-    let new_path = inner.cwd.clone().cd(path);
-    let app_inode = open(new_path.clone(), OpenFlags::O_RDONLY, CreateMode::empty())?;
+    let new_path = task.get_work_path().cd(path);
+    let app_inode = open(new_path.clone(), OpenFlags::empty(), CreateMode::empty())?;
     if app_inode.file_size() < 64 {
         return_errno!(Errno::ENOEXEC);
     }
-    drop(inner);
-    task.exec(app_inode, args_vec, envs_vec);
+    task.exec(app_inode, args_vec, envs_vec)?;
     Ok(0)
     // } else {
     //     return_errno!(Errno::ENOENT, "path {:?} not exits", new_path);
     // }
 }
 
-// wait4 260
-pub fn sys_wait4(pid: isize, exit_code_ptr: *mut i32) -> Result {
-    let task = current_task().unwrap();
+/// wait4/waitid 等待的子进程
+#[derive(Clone, Copy, Debug)]
+enum WaitTarget {
+    Any,
+    Pid(usize),
+    Pgid(usize),
+}
 
-    let inner = task.inner_ref();
+/// 子进程的状态改变
+enum ChildEvent {
+    Exited(i32),
+    Job(JobEvent),
+}
 
-    // 根据pid参数查找有没有符合要求的进程
-    if pid == -1 && inner.children.len() == 0 {
-        return Ok(0);
+/// wait4/waitid 获取到的子进程状态
+struct WaitResult {
+    pid: usize,
+    event: ChildEvent,
+    rusage: RUsage,
+}
+
+impl WaitResult {
+    /// wait4 的 wstatus
+    fn wstatus(&self) -> i32 {
+        match self.event {
+            // 被信号结束的任务以 -(signum) 为退出码
+            ChildEvent::Exited(code) if code < 0 => -code & 0x7f,
+            ChildEvent::Exited(code) => (code & 0xff) << 8,
+            ChildEvent::Job(JobEvent::Stopped(signum)) => ((signum as i32) << 8) | 0x7f,
+            ChildEvent::Job(JobEvent::Continued) => 0xffff,
+        }
     }
-    if !inner
-        .children
-        .iter()
-        .any(|p| pid == -1 || pid as usize == p.pid())
-    {
-        return_errno!(Errno::ECHILD, "pid {} does not exist", pid);
+    /// waitid 的 siginfo
+    fn siginfo(&self) -> SigInfo {
+        let (code, status) = match self.event {
+            ChildEvent::Exited(code) if code < 0 => (CLD_KILLED, -code),
+            ChildEvent::Exited(code) => (CLD_EXITED, code & 0xff),
+            ChildEvent::Job(JobEvent::Stopped(signum)) => (CLD_STOPPED, signum as i32),
+            ChildEvent::Job(JobEvent::Continued) => (CLD_CONTINUED, Signal::SIGCONT as i32),
+        };
+        SigInfo::new(Signal::SIGCHLD as i32, code, self.pid as u32, status)
     }
-    drop(inner);
+}
 
-    loop {
-        let mut inner = task.inner_mut();
-        // 查找所有符合PID要求的处于僵尸状态的进程, 如果有的话还需要同时找出它在当前进程控制块子进程向量中的下标
-        let pair = inner
-            .children
-            .iter()
-            .enumerate()
-            .find(|(_, p)| p.inner_ref().is_zombie() && (pid == -1 || pid as usize == p.pid()));
-        if let Some((idx, _)) = pair {
-            // 将子进程从向量中移除并置于当前上下文中
-            let child = inner.children.remove(idx);
-            // 确认这是对于该子进程控制块的唯一一次强引用, 即它不会出现在某个进程的子进程向量中,
-            // 更不会出现在处理器监控器或者任务管理器中.当它所在的代码块结束, 这次引用变量的生命周期结束,
-            // 将导致该子进程进程控制块的引用计数变为 0 , 彻底回收掉它占用的所有资源,
-            // 包括: 内核栈和它的 PID 还有它的应用地址空间存放页表的那些物理页帧等等
-            assert_eq!(Arc::strong_count(&child), 1);
-            // 收集的子进程信息返回
-            let cpid = child.pid();
-            let exit_code = child.inner_ref().exit_code;
-            // ++++ release child PCB
-            // 将子进程的退出码写入到当前进程的应用地址空间中
-            if exit_code_ptr as usize != 0 {
-                let memory_set = task.memory_set.read();
-                let token = memory_set.token();
-                drop(memory_set);
-                *translated_mut(token, exit_code_ptr) = exit_code << 8;
+/// 子进程是否有 `options` 关心的状态改变. 没有 WNOWAIT 时停止/恢复事件只报告一次
+fn child_event(child: &TaskControlBlock, options: WaitOptions) -> Option<ChildEvent> {
+    if !child.thread_group.is_alive() {
+        if !options.contains(WaitOptions::WEXITED) {
+            return None;
+        }
+        // exit_group 或致命信号结束的进程以线程组的退出码为准
+        let exit_code = child
+            .thread_group
+            .exit_code()
+            .unwrap_or(child.inner_ref().exit_code);
+        return Some(ChildEvent::Exited(exit_code));
+    }
+    let mut group_inner = child.thread_group.inner();
+    let event = match group_inner.job_event {
+        Some(event @ JobEvent::Stopped(_)) if options.contains(WaitOptions::WSTOPPED) => event,
+        Some(event @ JobEvent::Continued) if options.contains(WaitOptions::WCONTINUED) => event,
+        _ => return None,
+    };
+    if !options.contains(WaitOptions::WNOWAIT) {
+        group_inner.job_event = None;
+    }
+    Some(ChildEvent::Job(event))
+}
+
+/// 等待子进程状态改变. WNOHANG 且没有子进程状态改变时返回 None
+fn do_wait(
+    target: WaitTarget,
+    options: WaitOptions,
+) -> core::result::Result<Option<WaitResult>, Errno> {
+    let task = current_task().unwrap();
+    let group = task.thread_group.clone();
+    let is_target = |child: &&Arc<TaskControlBlock>| {
+        // 子线程也在 children 中
+        child.tgid != task.tgid
+            && match target {
+                WaitTarget::Any => true,
+                WaitTarget::Pid(pid) => child.pid() == pid,
+                WaitTarget::Pgid(pgid) => child.thread_group.pgid() == pgid,
             }
+    };
 
-            return Ok(cpid as isize);
+    loop {
+        // 持有等待队列的锁检查子进程的状态, 子进程退出时需要获取该锁才能唤醒等待者, 因此不会错过唤醒
+        let mut waiters = group.child_waiters();
+        // 线程组内所有线程的子进程都可以被等待
+        let threads = if options.contains(WaitOptions::__WNOTHREAD) {
+            vec![task.clone()]
         } else {
-            drop(inner); // 因为下个函数会切换上下文, 所以需要手动释放锁
-            suspend_current_and_run_next();
+            group.threads()
+        };
+        let mut has_target = false;
+        let mut found = None;
+        'search: for thread in threads.iter() {
+            let children: Vec<_> = thread
+                .inner_ref()
+                .children
+                .iter()
+                .filter(is_target)
+                .cloned()
+                .collect();
+            for child in children {
+                has_target = true;
+                if let Some(event) = child_event(&child, options) {
+                    found = Some((thread.clone(), child, event));
+                    break 'search;
+                }
+            }
+        }
+        drop(threads);
+
+        if let Some((parent, child, event)) = found {
+            let mut rusage = RUsage::new();
+            let child_inner = child.thread_group.inner();
+            rusage.ru_utime = child_inner.utime + child_inner.cutime;
+            rusage.ru_stime = child_inner.stime + child_inner.cstime;
+            drop(child_inner);
+            let pid = child.pid();
+            if matches!(event, ChildEvent::Exited(_)) && !options.contains(WaitOptions::WNOWAIT) {
+                // 将子进程从父线程的子进程向量中移除, 并计入父进程的子进程时间
+                parent
+                    .inner_mut()
+                    .children
+                    .retain(|p| !Arc::ptr_eq(p, &child));
+                let mut group_inner = group.inner();
+                group_inner.cutime = group_inner.cutime + rusage.ru_utime;
+                group_inner.cstime = group_inner.cstime + rusage.ru_stime;
+                drop(group_inner);
+                drop(waiters);
+                drop(parent);
+                // 确认这是对于该子进程控制块的唯一一次强引用, 即它不会出现在某个进程的子进程向量中,
+                // 更不会出现在处理器监控器或者任务管理器中.当它所在的代码块结束, 这次引用变量的生命周期结束,
+                // 将导致该子进程进程控制块的引用计数变为 0 , 彻底回收掉它占用的所有资源,
+                // 包括: 内核栈和它的 PID 还有它的应用地址空间存放页表的那些物理页帧等等
+                assert_eq!(Arc::strong_count(&child), 1);
+            }
+            return Ok(Some(WaitResult { pid, event, rusage }));
+        }
+        if !has_target {
+            return_errno!(Errno::ECHILD, "no child matches {:?}", target);
         }
+        if options.contains(WaitOptions::WNOHANG) {
+            return Ok(None);
+        }
+        if task.has_unmasked_signal() {
+            return_errno!(Errno::ERESTARTSYS);
+        }
+
+        // 阻塞直到子进程退出或者被信号打断
+        waiters.push(task.clone());
+        let mut task_inner = task.inner_mut();
+        let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+        task_inner.task_status = TaskStatus::Blocking;
+        block_task(task.clone());
+        drop(task_inner);
+        drop(waiters);
+        schedule(task_cx_ptr);
+        // 被信号唤醒时仍在等待队列中
+        group.remove_child_waiter(&task);
     }
 }
 
+// wait4 260
+pub fn sys_wait4(pid: isize, wstatus: *mut i32, options: u32, rusage: *mut RUsage) -> Result {
+    let options = match WaitOptions::from_bits(options) {
+        Some(options) if !options.intersects(WaitOptions::WEXITED | WaitOptions::WNOWAIT) => {
+            options | WaitOptions::WEXITED
+        }
+        _ => return_errno!(Errno::EINVAL, "invalid options: {:#x}", options),
+    };
+    let target = match pid {
+        -1 => WaitTarget::Any,
+        0 => WaitTarget::Pgid(current_task().unwrap().thread_group.pgid()),
+        pid if pid < 0 => WaitTarget::Pgid(pid.unsigned_abs()),
+        pid => WaitTarget::Pid(pid as usize),
+    };
+    let result = match do_wait(target, options)? {
+        Some(result) => result,
+        None => return Ok(0),
+    };
+    // 将子进程的状态写入到当前进程的应用地址空间中
+    let token = current_user_token();
+    if !wstatus.is_null() {
+        copyout(token, wstatus, &result.wstatus())?;
+    }
+    if !rusage.is_null() {
+        copyout(token, rusage, &result.rusage)?;
+    }
+    Ok(result.pid as isize)
+}
+
+// waitid 95
+pub fn sys_waitid(
+    idtype: usize,
+    id: usize,
+    infop: *mut SigInfo,
+    options: u32,
+    rusage: *mut RUsage,
+) -> Result {
+    let options = match WaitOptions::from_bits(options) {
+        Some(options)
+            if options.intersects(
+                WaitOptions::WEXITED | WaitOptions::WSTOPPED | WaitOptions::WCONTINUED,
+            ) =>
+        {
+            options
+        }
+        _ => return_errno!(Errno::EINVAL, "invalid options: {:#x}", options),
+    };
+    let target = match idtype {
+        P_ALL => WaitTarget::Any,
+        P_PID if id > 0 => WaitTarget::Pid(id),
+        P_PGID if id == 0 => WaitTarget::Pgid(current_task().unwrap().thread_group.pgid()),
+        P_PGID => WaitTarget::Pgid(id),
+        _ => return_errno!(Errno::EINVAL, "idtype: {}, id: {}", idtype, id),
+    };
+    let result = do_wait(target, options)?;
+    let token = current_user_token();
+    if !infop.is_null() {
+        // WNOHANG 且没有子进程状态改变时 si_pid 为 0
+        let siginfo = result
+            .as_ref()
+            .map_or(SigInfo::empty(), WaitResult::siginfo);
+        copyout(token, infop, &siginfo)?;
+    }
+    if !rusage.is_null() {
+        let empty = RUsage::new();
+        let usage = result.as_ref().map_or(&empty, |result| &result.rusage);
+        copyout(token, rusage, usage)?;
+    }
+    Ok(0)
+}
+
 // exit 93
 pub fn sys_exit(exit_code: i32) -> ! {
-    exit_current_and_run_next(exit_code);
+    // 只保留低 8 位, 负数的退出码表示被信号结束
+    exit_current_and_run_next(exit_code & 0xff);
     unreachable!("unreachable in sys_exit!");
 }
 
+// exit_group 94
 pub fn sys_exit_group(exit_code: i32) -> ! {
-    exit_current_and_run_next(exit_code);
-    panic!("Unreachable in sys_exit!");
+    exit_group_and_run_next(exit_code & 0xff);
+    panic!("Unreachable in sys_exit_group!");
 }
 
 // getppid 173
 pub fn sys_getppid() -> Result {
-    Ok(current_task().unwrap().tgid as isize)
+    Ok(current_task().unwrap().ppid() as isize)
 }
 
 // getpid 172
 pub fn sys_getpid() -> Result {
-    Ok(current_task().unwrap().pid.0 as isize)
+    Ok(current_task().unwrap().tgid as isize)
 }
 
 // set_tid_address 96
@@ -231,9 +543,74 @@ pub fn sys_getuid() -> Result {
     Ok(0)
 }
 
+/// pid 为 0 时指当前进程
+fn pid2group(pid: usize) -> core::result::Result<Arc<ThreadGroup>, Errno> {
+    if pid == 0 {
+        return Ok(current_task().unwrap().thread_group.clone());
+    }
+    match tgid2group(pid) {
+        Some(group) => Ok(group),
+        None => return_errno!(Errno::ESRCH, "could not find process {}", pid),
+    }
+}
+
+// setpgid 154
+pub fn sys_setpgid(pid: usize, pgid: isize) -> Result {
+    if pgid < 0 {
+        return_errno!(Errno::EINVAL, "invalid pgid: {}", pgid);
+    }
+    let current = current_task().unwrap();
+    let group = pid2group(pid)?;
+    // 只能设置自己或者子进程的进程组
+    if group.tgid != current.tgid {
+        match pid2task(group.tgid) {
+            Some(leader) if leader.ppid() == current.tgid => {}
+            _ => return_errno!(Errno::ESRCH, "process {} is not a child", group.tgid),
+        }
+    }
+    let sid = current.thread_group.sid();
+    if group.sid() != sid {
+        return_errno!(Errno::EPERM, "process {} is in another session", group.tgid);
+    }
+    if group.tgid == sid {
+        return_errno!(Errno::EPERM, "cannot move session leader {}", sid);
+    }
+    let pgid = if pgid == 0 { group.tgid } else { pgid as usize };
+    // 加入已有的进程组时, 该进程组必须在同一会话中
+    if pgid != group.tgid && !process_group(pgid).iter().any(|p| p.sid() == sid) {
+        return_errno!(Errno::EPERM, "no process group {} in session {}", pgid, sid);
+    }
+    group.inner().pgid = pgid;
+    Ok(0)
+}
+
+// getpgid 155
+pub fn sys_getpgid(pid: usize) -> Result {
+    Ok(pid2group(pid)?.pgid() as isize)
+}
+
+// getsid 156
+pub fn sys_getsid(pid: usize) -> Result {
+    Ok(pid2group(pid)?.sid() as isize)
+}
+
+// setsid 157
+pub fn sys_setsid() -> Result {
+    let group = current_task().unwrap().thread_group.clone();
+    // 进程组组长不能创建会话, 否则原进程组中的其他进程会与组长不在同一会话中
+    if !process_group(group.tgid).is_empty() {
+        return_errno!(Errno::EPERM, "{} is a process group leader", group.tgid);
+    }
+    // 新会话没有控制终端
+    let mut inner = group.inner();
+    inner.sid = group.tgid;
+    inner.pgid = group.tgid;
+    Ok(group.tgid as isize)
+}
+
 // gettid 178
 pub fn sys_gettid() -> Result {
-    Ok(0)
+    Ok(current_task().unwrap().pid() as isize)
 }
 
 // geteuid 175
@@ -267,99 +644,121 @@ pub fn sys_clock_gettime(_clk_id: usize, ts: *mut u64) -> Result {
 }
 
 // kill 129
-pub fn sys_kill(pid: usize, signal: u32) -> Result {
-    //TODO pid==-1
-    if signal == 0 {
-        return Ok(0);
+pub fn sys_kill(pid: isize, signal: usize) -> Result {
+    if signal > MAX_SIGNUM as usize {
+        return_errno!(Errno::EINVAL, "invalid signal, signum: {}", signal);
     }
-    let signal = 1 << (signal - 1);
-    if let Some(task) = pid2task(pid) {
-        if let Some(flag) = SigMask::from_bits(signal) {
-            task.inner_mut().pending_signals |= flag;
-            Ok(0)
-        } else {
-            return_errno!(Errno::EINVAL, "invalid signal, signum: {}", signal);
+    let current = current_task().unwrap();
+    let groups: Vec<Arc<ThreadGroup>> = match pid {
+        // 当前进程所在的进程组
+        0 => process_group(current.thread_group.pgid()),
+        -1 => all_thread_groups()
+            .into_iter()
+            .filter(|group| group.tgid != INITPROC.tgid && group.tgid != current.tgid)
+            .collect(),
+        pid if pid < 0 => process_group(pid.unsigned_abs()),
+        pid => {
+            // 也可以用组内任一线程的 tid 指定线程组
+            let pid = pid as usize;
+            tgid2group(pid)
+                .or_else(|| pid2task(pid).map(|task| task.thread_group.clone()))
+                .into_iter()
+                .collect()
+        }
+    };
+    let sender = current.tgid;
+    drop(current);
+    if groups.is_empty() {
+        if pid < 0 {
+            return_errno!(Errno::ESRCH, "no process in process group {}", -pid);
         }
-    } else {
         // return_errno!(Errno::ESRCH, "could not find task with pid: {}", pid); // for hackbench
-        Ok(0)
+        return Ok(0);
     }
+    if signal == 0 {
+        return Ok(0);
+    }
+    let info = SigInfo::new(signal as i32, SI_USER, sender as u32, 0);
+    let mut queued = false;
+    for group in groups {
+        queued |= group.send_siginfo(info);
+    }
+    if !queued {
+        return_errno!(Errno::EAGAIN, "signal queue is full, signum: {}", signal);
+    }
+    Ok(0)
 }
 
-// tkill 130
-pub fn sys_tkill(tid: usize, signal: usize) -> Result {
-    //TODO pid==-1
-    // println!("[DEBUG] tkill tid:{:?} signal:0x{:x?}", tid, signal);
+/// 向线程发送信号
+fn send_thread_signal(tid: usize, tgid: Option<usize>, signal: usize) -> Result {
+    if signal > MAX_SIGNUM as usize {
+        return_errno!(Errno::EINVAL, "invalid signal, signum: {}", signal);
+    }
+    let task = match pid2task(tid) {
+        Some(task) if tgid.map_or(true, |tgid| tgid == task.tgid) => task,
+        _ => return_errno!(Errno::ESRCH, "could not find thread {} in {:?}", tid, tgid),
+    };
     if signal == 0 {
         return Ok(0);
     }
-    let pid = if tid == 0 {
+    let sender = current_task().unwrap().tgid;
+    let info = SigInfo::new(signal as i32, SI_TKILL, sender as u32, 0);
+    if !task.send_siginfo(info) {
+        return_errno!(Errno::EAGAIN, "signal queue is full, signum: {}", signal);
+    }
+    Ok(0)
+}
+
+// tkill 130
+pub fn sys_tkill(tid: usize, signal: usize) -> Result {
+    // println!("[DEBUG] tkill tid:{:?} signal:0x{:x?}", tid, signal);
+    let tid = if tid == 0 {
         current_task().unwrap().pid.0
     } else {
         tid
     };
-
-    let signal = 1 << (signal - 1);
-    if let Some(task) = pid2task(pid) {
-        if let Some(flag) = SigMask::from_bits(signal) {
-            task.inner_mut().pending_signals |= flag;
-            Ok(0)
-        } else {
-            return_errno!(Errno::EINVAL, "invalid signal, signum: {}", signal);
-        }
-    } else {
-        return_errno!(Errno::ESRCH, "could not find task with pid: {}", pid);
-    }
+    send_thread_signal(tid, None, signal)
 }
 
 // getrusgae 165
 pub fn sys_getrusage(who: isize, usage: *mut u8) -> Result {
-    if who != RUSAGE_SELF {
-        return_errno!(Errno::EINVAL, "currently only supports RUSAGE_SELF");
+    if who != RUSAGE_SELF && who != RUSAGE_CHILDREN {
+        return_errno!(Errno::EINVAL, "unsupported who: {}", who);
     }
     let token = current_user_token();
     let mut userbuf = UserBuffer::wrap(translated_bytes_buffer(
         token,
         usage,
         core::mem::size_of::<RUsage>(),
-    ));
+    )?);
     let mut rusage = RUsage::new();
     let task = current_task().unwrap();
-    let inner = task.inner_ref();
-    rusage.ru_stime = inner.stime;
-    rusage.ru_utime = inner.utime;
+    if who == RUSAGE_CHILDREN {
+        let group_inner = task.thread_group.inner();
+        rusage.ru_stime = group_inner.cstime;
+        rusage.ru_utime = group_inner.cutime;
+    } else {
+        let inner = task.inner_ref();
+        rusage.ru_stime = inner.stime;
+        rusage.ru_utime = inner.utime;
+    }
     userbuf.write(rusage.as_bytes());
     Ok(0)
 }
 
 // tgkill 131
-// TODO
-pub fn sys_tgkill(tgid: isize, tid: usize, _sig: isize) -> Result {
-    if tgid == -1 {
-        todo!(
-            "Send the corresponding signal to all threads within\n
-        the thread group associated with the current TGID"
-        )
-    }
-    let master_pid = tgid as usize;
-    let son_pid = tid;
-    if let Some(parent_task) = pid2task(master_pid) {
-        let inner = parent_task.inner_mut();
-        if let Some(_target_task) = inner.children.iter().find(|child| child.pid() == son_pid) {
-            todo!("Send Signal")
-        } else {
-            todo!("errno")
-        }
-    } else {
-        todo!("errno")
+pub fn sys_tgkill(tgid: isize, tid: usize, signal: isize) -> Result {
+    if tgid <= 0 || signal < 0 {
+        return_errno!(Errno::EINVAL, "tgid: {}, signal: {}", tgid, signal);
     }
+    send_thread_signal(tid, Some(tgid as usize), signal as usize)
 }
 
 // sched_getaffinity 123
 // TODO
 pub fn sys_sched_getaffinity(_pid: usize, cpusetsize: usize, mask: *mut u8) -> Result {
     let token = current_user_token();
-    let mut userbuf = UserBuffer::wrap(translated_bytes_buffer(token, mask, cpusetsize));
+    let mut userbuf = UserBuffer::wrap(translated_bytes_buffer(token, mask, cpusetsize)?);
 
     // The scheduler in the kernel maintains a bitmap that records the CPU affinity
     // information of processes or threads. When the sched_getaffinity system call
@@ -375,7 +774,7 @@ pub fn sys_sched_getaffinity(_pid: usize, cpusetsize: usize, mask: *mut u8) -> R
 // TODO
 pub fn sys_sched_setaffinity(_pid: usize, cpusetsize: usize, mask: *const u8) -> Result {
     let token = current_user_token();
-    let userbuf = UserBuffer::wrap(translated_bytes_buffer(token, mask, cpusetsize));
+    let userbuf = UserBuffer::wrap(translated_bytes_buffer(token, mask, cpusetsize)?);
 
     let mut cpuset = CpuMask::new();
     userbuf.read(cpuset.as_bytes_mut());
@@ -485,11 +884,46 @@ pub fn sys_sigreturn() -> Result {
     *trap_cx = sig_context.context.clone();
     // 还原 signal handler 之前的 signal mask
     task_inner.sigmask = sigmask;
-    trap_cx.sepc = ucontext.uc_mcontext.greps[1];
+    trap_cx.sepc = ucontext.uc_mcontext.greps[0];
 
     Ok(0)
 }
 
+// sigaltstack 132
+pub fn sys_sigaltstack(ss: *const SignalStack, old_ss: *mut SignalStack) -> Result {
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let mut inner = task.inner_mut();
+    let on_alt_stack = inner.sig_alt_stack.contains(inner.trap_context().x[2]);
+    let mut old_stack = inner.sig_alt_stack;
+    if on_alt_stack {
+        old_stack.ss_flags = SS_ONSTACK;
+    }
+    if !ss.is_null() {
+        let mut stack = SignalStack::disabled();
+        copyin(token, &mut stack, ss)?;
+        // 正在备用栈上处理信号时不能修改
+        if on_alt_stack {
+            return_errno!(Errno::EPERM, "running on the alternate signal stack");
+        }
+        match stack.ss_flags {
+            SS_DISABLE => stack = SignalStack::disabled(),
+            0 | SS_ONSTACK => {
+                if stack.ss_size < MINSIGSTKSZ {
+                    return_errno!(Errno::ENOMEM, "signal stack too small: {}", stack.ss_size);
+                }
+                stack.ss_flags = 0;
+            }
+            flags => return_errno!(Errno::EINVAL, "invalid ss_flags: {:#x}", flags),
+        }
+        inner.sig_alt_stack = stack;
+    }
+    if !old_ss.is_null() {
+        copyout(token, old_ss, &old_stack)?;
+    }
+    Ok(0)
+}
+
 // sigaction 134
 pub fn sys_sigaction(signum: isize, act: *const SigAction, oldact: *mut SigAction) -> Result {
     let token = current_user_token();
@@ -510,11 +944,11 @@ pub fn sys_sigaction(signum: isize, act: *const SigAction, oldact: *mut SigActio
     if act as usize != 0 {
         let mut sigaction = task.sigactions.write();
         if oldact as usize != 0 {
-            copyout(token, oldact, &sigaction[signum as usize]);
+            copyout(token, oldact, &sigaction[signum as usize])?;
         }
         //在 pcb 中注册给定的 signaction
         let mut sa = SigAction::new();
-        copyin(token, &mut sa, act);
+        copyin(token, &mut sa, act)?;
         // kill 和 stop 信号不能被屏蔽
         sa.sa_mask.sub(Signal::SIGKILL as u32); // sub 函数保证即使不存在 SIGKILL 也无影响
         sa.sa_mask.sub(Signal::SIGSTOP as u32);
@@ -575,6 +1009,83 @@ pub fn sys_sigprocmask(
     Ok(0)
 }
 
+// rt_sigpending 136
+pub fn sys_rt_sigpending(set: *mut SigSet, _sigsetsize: usize) -> Result {
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let inner = task.inner_ref();
+    // 未被屏蔽的信号在返回用户态前就会被处理, 因此只有被屏蔽的信号处于待处理状态
+    let pending =
+        (inner.pending_signals | task.thread_group.inner().pending_signals) & inner.sigmask;
+    drop(inner);
+    copyout(token, set, &pending)?;
+    Ok(0)
+}
+
+// rt_sigtimedwait 137
+pub fn sys_rt_sigtimedwait(
+    set: *const SigSet,
+    info: *mut SigInfo,
+    timeout: *const TimeSpec,
+    _sigsetsize: usize,
+) -> Result {
+    let token = current_user_token();
+    let mut wait_set = SigSet::empty();
+    copyin(token, &mut wait_set, set)?;
+    wait_set.remove(SigSet::SIGKILL | SigSet::SIGSTOP);
+    let deadline = if timeout.is_null() {
+        None
+    } else {
+        let mut timeout_spec = TimeSpec::empty();
+        copyin(token, &mut timeout_spec, timeout)?;
+        Some(get_time_ns() + timeout_spec.into_ns())
+    };
+    loop {
+        let task = current_task().unwrap();
+        let siginfo = dequeue_signal(&mut task.inner_mut(), &task.thread_group, !wait_set);
+        if let Some(siginfo) = siginfo {
+            if !info.is_null() {
+                copyout(token, info, &siginfo)?;
+            }
+            return Ok(siginfo.si_signo as isize);
+        }
+        if task.has_unmasked_signal() {
+            return_errno!(Errno::EINTR, "interrupted while waiting for {:?}", wait_set);
+        }
+        if deadline.map_or(false, |deadline| get_time_ns() >= deadline) {
+            return_errno!(Errno::EAGAIN, "timed out waiting for {:?}", wait_set);
+        }
+        drop(task);
+        suspend_current_and_run_next();
+    }
+}
+
+// rt_sigqueueinfo 138
+pub fn sys_rt_sigqueueinfo(tgid: isize, signal: usize, uinfo: *const SigInfo) -> Result {
+    if signal > MAX_SIGNUM as usize {
+        return_errno!(Errno::EINVAL, "invalid signal, signum: {}", signal);
+    }
+    let mut info = SigInfo::empty();
+    copyin(current_user_token(), &mut info, uinfo)?;
+    // 不能冒充内核或 kill/tkill 向其他进程发送信号
+    let current_tgid = current_task().unwrap().tgid;
+    if tgid != current_tgid as isize && (info.si_code >= 0 || info.si_code == SI_TKILL) {
+        return_errno!(Errno::EPERM, "si_code {} is reserved", info.si_code);
+    }
+    let group = match tgid2group(tgid as usize) {
+        Some(group) if tgid > 0 => group,
+        _ => return_errno!(Errno::ESRCH, "could not find process {}", tgid),
+    };
+    if signal == 0 {
+        return Ok(0);
+    }
+    info.si_signo = signal as i32;
+    if !group.send_siginfo(info) {
+        return_errno!(Errno::EAGAIN, "signal queue is full, signum: {}", signal);
+    }
+    Ok(0)
+}
+
 // set_robust_list 99
 pub fn sys_set_robust_list(head: usize, len: usize) -> Result {
     if len != RobustList::HEAD_SIZE {
@@ -600,8 +1111,8 @@ pub fn sys_get_robust_list(pid: usize, head_ptr: *mut usize, len_ptr: *mut usize
     let token = current_user_token();
     let inner = task.inner_ref();
     let robust_list = &inner.robust_list;
-    copyout(token, head_ptr, &robust_list.head);
-    copyout(token, len_ptr, &robust_list.len);
+    copyout(token, head_ptr, &robust_list.head)?;
+    copyout(token, len_ptr, &robust_list.len)?;
     drop(inner);
     Ok(0)
 }
@@ -613,42 +1124,44 @@ pub fn sys_prlimit64(
     new_limit: *const RLimit,
     old_limit: *mut RLimit,
 ) -> Result {
-    if pid == 0 {
-        let task = current_task().unwrap();
-        let token = current_user_token();
-        let resource = if resource == 7 {
-            Resource::NOFILE
-        } else {
-            Resource::ILLEAGAL
-        };
-        if !old_limit.is_null() {
-            match resource {
-                Resource::NOFILE => {
-                    let inner = task.inner_ref();
-                    let rlimit_nofile = &inner.rlimit_nofile;
-                    copyout(token, old_limit, &rlimit_nofile);
-                    drop(inner)
-                }
-                // TODO: Resource::ILLEAGAL => return_errno!(Errno::EINVAL),
-                _ => (),
-            }
+    let task = if pid == 0 {
+        current_task().unwrap()
+    } else {
+        match pid2task(pid) {
+            Some(task) => task,
+            None => return_errno!(Errno::ESRCH, "could not find task with pid: {}", pid),
         }
-        if !new_limit.is_null() {
-            let mut rlimit = RLimit::new(0, 0);
-            copyin(token, &mut rlimit, new_limit);
-            match resource {
-                Resource::NOFILE => {
-                    let mut inner = task.inner_mut();
-                    let rlimit_nofile = &mut inner.rlimit_nofile;
-                    *rlimit_nofile = rlimit;
-                    drop(inner)
-                }
-                // TODO: Resource::ILLEAGAL => return_errno!(Errno::EINVAL),
-                _ => (),
-            }
+    };
+    let token = current_user_token();
+    let resource = match resource {
+        2 => Resource::DATA,
+        3 => Resource::STACK,
+        6 => Resource::NPROC,
+        7 => Resource::NOFILE,
+        9 => Resource::AS,
+        _ => Resource::ILLEAGAL,
+    };
+    // 不支持的资源没有限制
+    let rlimit = task.inner_mut().rlimit_mut(resource).copied();
+    // 读写用户内存时可能缺页, 此时不能持有 inner
+    if !old_limit.is_null() {
+        let old = rlimit.unwrap_or(RLimit::new(RLIM_INFINITY, RLIM_INFINITY));
+        copyout(token, old_limit, &old)?;
+    }
+    if !new_limit.is_null() {
+        let mut new = RLimit::new(0, 0);
+        copyin(token, &mut new, new_limit)?;
+        if new.rlim_cur > new.rlim_max {
+            return_errno!(
+                Errno::EINVAL,
+                "soft limit {:#x} is above hard limit {:#x}",
+                new.rlim_cur,
+                new.rlim_max
+            );
+        }
+        if let Some(limit) = task.inner_mut().rlimit_mut(resource) {
+            *limit = new;
         }
-        Ok(0)
-    } else {
-        todo!()
     }
+    Ok(0)
 }
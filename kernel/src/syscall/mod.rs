@@ -62,6 +62,7 @@ gen_syscallid! {
     SYS_IOCTL = 29,
     SYS_MKDIRAT = 34,
     SYS_UNLINKAT = 35,
+    SYS_SYMLINKAT = 36,
     SYS_LINKAT = 37,
     SYS_UMOUNT2 = 39,
     SYS_MOUNT = 40,
@@ -88,9 +89,11 @@ gen_syscallid! {
     SYS_FSTAT = 80,
     SYS_SYNC = 81,
     SYS_FSYNC = 82,
+    SYS_FDATASYNC = 83,
     SYS_UTIMENSAT = 88,
     SYS_EXIT = 93,
     SYS_EXIT_GROUP = 94,
+    SYS_WAITID = 95,
     SYS_SET_TID_ADDRESS = 96,
     SYS_FUTEX = 98,
     SYS_SET_ROBUST_LIST = 99,
@@ -103,10 +106,14 @@ gen_syscallid! {
     SYS_KILL = 129,
     SYS_TKILL = 130,
     SYS_TGKILL = 131,
+    SYS_SIGPENDING = 136,
     SYS_SIGTIMEDWAIT = 137,
+    SYS_SIGQUEUEINFO = 138,
     SYS_TIMES = 153,
     SYS_SETPGID = 154,
     SYS_GETPGID = 155,
+    SYS_GETSID = 156,
+    SYS_SETSID = 157,
     SYS_UNAME = 160,
     SYS_GETRUSAGE = 165,
     SYS_UMASK = 166,
@@ -125,11 +132,13 @@ gen_syscallid! {
     SYS_SHMDT = 197,
     SYS_BRK = 214,
     SYS_MUNMAP = 215,
+    SYS_MREMAP = 216,
     SYS_CLONE = 220,
     SYS_EXECVE = 221,
     SYS_MMAP = 222,
     SYS_MPROTECT = 226,
     SYS_MSYNC = 227,
+    SYS_MINCORE = 232,
     SYS_WAIT4 = 260,
     SYS_PRLIMIT64 = 261,
     SYS_RENAMEAT2 = 276,
@@ -144,6 +153,7 @@ gen_syscallid! {
     SYS_SOCKETPAIR = 199,
     SYS_MADVISE = 233,
     SYS_CLOCK_NANOSLEEP = 115,
+    SYS_SIGALTSTACK = 132,
     SYS_SIGACTION = 134,
     SYS_SIGPROCMASK = 135,
     SYS_SIGRETURN = 139,
@@ -156,7 +166,11 @@ gen_syscallid! {
     SYS_SENDTO = 206,
     SYS_RECVFROM = 207,
     SYS_SETSOCKOPT = 208,
-    SYS_TIMER_SETTIME = 110,
+    SYS_TIMER_CREATE = 107,
+    SYS_TIMER_GETTIME = 108,
     SYS_TIMER_GETOVERRUN = 109,
+    SYS_TIMER_SETTIME = 110,
+    SYS_TIMER_DELETE = 111,
     SYS_COPY_FILE_RANGE = 285,
+    SYS_CLONE3 = 435,
 }
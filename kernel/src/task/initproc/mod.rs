@@ -29,10 +29,14 @@ pub static INITPROC: Lazy<Arc<TaskControlBlock>> = Lazy::new(|| {
     let inode =
         open(path, OpenFlags::O_CREAT, CreateMode::empty()).expect("initproc create failed");
     // inode.write_all(&initproc.to_owned());
-    inode.write_from_kspace(&initproc.to_owned());
+    inode
+        .write_from_kspace(&initproc.to_owned())
+        .expect("initproc write failed");
 
     let task = Arc::new(TaskControlBlock::new(inode.clone()));
-    inode.delete();
+    if let Err(errno) = inode.delete() {
+        warn!("[initproc] failed to delete /initproc: {:?}", errno);
+    }
 
     load_test();
 
@@ -56,7 +60,9 @@ pub static TEST: Lazy<Mutex<()>> = Lazy::new(|| {
     let path_test_all = AbsolutePath::from_str("/test_all_custom.sh");
     let inode = open(path_test_all, OpenFlags::O_CREAT, CreateMode::empty())
         .expect("no kernel/src/task/initproc/test_all_custom.sh");
-    inode.write_from_kspace(&initproc.to_owned());
+    inode
+        .write_from_kspace(&initproc.to_owned())
+        .expect("test_all_custom.sh write failed");
 
     // TODO for ramfs
     extern "C" {
@@ -70,7 +76,9 @@ pub static TEST: Lazy<Mutex<()>> = Lazy::new(|| {
     let path_busy_box = AbsolutePath::from_str("/busybox");
     let inode = open(path_busy_box, OpenFlags::O_CREAT, CreateMode::empty())
         .expect("no kernel/src/task/initproc/busybox");
-    inode.write_from_kspace(&initproc.to_owned());
+    inode
+        .write_from_kspace(&initproc.to_owned())
+        .expect("busybox write failed");
 
     extern "C" {
         fn busybox_testcode_entry();
@@ -83,7 +91,9 @@ pub static TEST: Lazy<Mutex<()>> = Lazy::new(|| {
     let path_busybox_test = AbsolutePath::from_str("/busybox_testcode.sh");
     let inode = open(path_busybox_test, OpenFlags::O_CREAT, CreateMode::empty())
         .expect("no kernel/src/task/initproc/busybox_testcode.sh");
-    inode.write_from_kspace(&initproc.to_owned());
+    inode
+        .write_from_kspace(&initproc.to_owned())
+        .expect("busybox_testcode.sh write failed");
 
     extern "C" {
         fn busybox_test_cmd_entry();
@@ -100,7 +110,9 @@ pub static TEST: Lazy<Mutex<()>> = Lazy::new(|| {
         CreateMode::empty(),
     )
     .expect("no kernel/src/task/initproc/busybox_cmd.txt");
-    inode.write_from_kspace(&initproc.to_owned());
+    inode
+        .write_from_kspace(&initproc.to_owned())
+        .expect("busybox_cmd.txt write failed");
 
     Mutex::new(())
 });
@@ -109,6 +121,7 @@ mod static_busybox {
     use crate::fs::open;
     use crate::fs::File;
     use crate::mm::MemorySet;
+    use crate::syscall::impls::Errno;
     use crate::task::TaskControlBlock;
     use alloc::vec::Vec;
     use alloc::{borrow::ToOwned, sync::Arc};
@@ -134,8 +147,9 @@ mod static_busybox {
         pub fn aux(&self) -> Vec<AuxEntry> {
             unsafe { STATIC_BUSYBOX_AUX.clone() }
         }
-        pub fn memory_set(&self) -> MemorySet {
-            let mut memory_set = self.inner.memory_set.write();
+        pub fn memory_set(&self) -> Result<MemorySet, Errno> {
+            let memory_set = self.inner.memory_set();
+            let mut memory_set = memory_set.write();
             MemorySet::from_copy_on_write(&mut memory_set)
         }
     }
@@ -155,10 +169,17 @@ mod static_busybox {
 
         let inode = open(path, OpenFlags::O_CREAT, CreateMode::empty())
             .expect("static-busybox create failed");
-        inode.write_from_kspace(&busybox.to_owned());
+        inode
+            .write_from_kspace(&busybox.to_owned())
+            .expect("static-busybox write failed");
 
         let task = Arc::new(TaskControlBlock::new(inode.clone()));
-        inode.delete();
+        if let Err(errno) = inode.delete() {
+            warn!(
+                "[static_busybox] failed to delete /static-busybox: {:?}",
+                errno
+            );
+        }
 
         RwLock::new(Busybox { inner: task })
     });
@@ -0,0 +1,172 @@
+//! setitimer/getitimer 的间隔定时器
+//!
+//! 每个进程 (线程组) 有三个互相独立的间隔定时器:
+//! - ITIMER_REAL 按实际时间倒数, 由内核时间轮驱动, 到期时发送 SIGALRM;
+//! - ITIMER_VIRTUAL 只在组内线程运行于用户态时倒数, 到期时发送 SIGVTALRM;
+//! - ITIMER_PROF 在用户态与内核态都倒数, 到期时发送 SIGPROF.
+//!
+//! 后两者在线程进出内核、计入 CPU 时间时倒数 (见 [`ThreadGroup::charge_cpu_time`]),
+//! 精度为一个时间片. 间隔定时器不被 fork 的子进程继承, 在 exec 之后保留.
+
+use super::ThreadGroup;
+use crate::return_errno;
+use crate::syscall::impls::Errno;
+use crate::timer::{add_timer, get_time_ns, TimerCallback, USEC_PER_SEC};
+use alloc::sync::{Arc, Weak};
+use nix::{itimerval, SigSet, TimeVal};
+use spin::Mutex;
+
+/// ITIMER_REAL
+pub struct RealTimer {
+    group: Weak<ThreadGroup>,
+    inner: Mutex<RealTimerInner>,
+}
+
+struct RealTimerInner {
+    /// 每次设置定时器时加一, 时间轮中旧的表项随之失效
+    generation: usize,
+    /// 下一次到期的时间 (ns), None 表示定时器未启动
+    deadline: Option<usize>,
+    /// 周期 (ns), 0 表示只到期一次
+    interval: usize,
+}
+
+impl RealTimer {
+    pub fn new(group: Weak<ThreadGroup>) -> Arc<Self> {
+        Arc::new(Self {
+            group,
+            inner: Mutex::new(RealTimerInner {
+                generation: 0,
+                deadline: None,
+                interval: 0,
+            }),
+        })
+    }
+    pub fn get(&self) -> itimerval {
+        let inner = self.inner.lock();
+        match inner.deadline {
+            Some(deadline) => {
+                // 已经到期但还没有被时间轮处理的定时器至少还剩 1us
+                let remaining = deadline.saturating_sub(get_time_ns()) / 1000;
+                itimerval {
+                    it_interval: TimeVal::from_us(inner.interval / 1000),
+                    it_value: TimeVal::from_us(remaining.max(1)),
+                }
+            }
+            None => itimerval::empty(),
+        }
+    }
+    /// 设置定时器, `it_value` 为 0 时停止定时器. 返回原来的值,
+    /// 时间换算为纳秒后溢出时返回 EINVAL, 定时器保持不变
+    pub fn set(self: &Arc<Self>, new: itimerval) -> Result<itimerval, Errno> {
+        let interval = match timeval_to_ns(new.it_interval) {
+            Some(interval) => interval,
+            None => return_errno!(Errno::EINVAL, "it_interval {:?} overflows", new.it_interval),
+        };
+        let deadline = if new.it_value.is_zero() {
+            None
+        } else {
+            match timeval_to_ns(new.it_value).and_then(|value| get_time_ns().checked_add(value)) {
+                Some(deadline) => Some(deadline),
+                None => return_errno!(Errno::EINVAL, "it_value {:?} overflows", new.it_value),
+            }
+        };
+        let old = self.get();
+        let mut inner = self.inner.lock();
+        inner.generation += 1;
+        inner.interval = interval;
+        inner.deadline = deadline;
+        if let Some(deadline) = inner.deadline {
+            let callback: Weak<dyn TimerCallback> = Arc::downgrade(self) as _;
+            add_timer(deadline, callback, inner.generation);
+        }
+        Ok(old)
+    }
+    /// 停止定时器
+    pub fn stop(&self) {
+        let mut inner = self.inner.lock();
+        inner.generation += 1;
+        inner.deadline = None;
+        inner.interval = 0;
+    }
+}
+
+/// 将时间换算为纳秒, 溢出时返回 None
+fn timeval_to_ns(time: TimeVal) -> Option<usize> {
+    time.sec
+        .checked_mul(USEC_PER_SEC)?
+        .checked_add(time.usec)?
+        .checked_mul(1000)
+}
+
+impl TimerCallback for RealTimer {
+    fn on_expire(self: Arc<Self>, key: usize) {
+        let mut inner = self.inner.lock();
+        let deadline = match inner.deadline {
+            Some(deadline) if inner.generation == key => deadline,
+            // 定时器已经被重新设置或停止
+            _ => return,
+        };
+        if inner.interval > 0 {
+            // 跳过时间轮推迟触发时错过的周期, 它们的 SIGALRM 会与这一次合并
+            let missed = get_time_ns().saturating_sub(deadline) / inner.interval;
+            let next = deadline.saturating_add((missed + 1).saturating_mul(inner.interval));
+            inner.deadline = Some(next);
+            let callback: Weak<dyn TimerCallback> = Arc::downgrade(&self) as _;
+            add_timer(next, callback, key);
+        } else {
+            inner.deadline = None;
+        }
+        drop(inner);
+        if let Some(group) = self.group.upgrade() {
+            group.send_signal(SigSet::SIGALRM);
+        }
+    }
+}
+
+/// ITIMER_VIRTUAL 与 ITIMER_PROF, 随进程消耗的 CPU 时间倒数
+#[derive(Clone, Copy, Debug)]
+pub struct CpuTimer {
+    /// 剩余时间, 0 表示定时器未启动
+    value: TimeVal,
+    interval: TimeVal,
+}
+
+impl CpuTimer {
+    pub fn new() -> Self {
+        Self {
+            value: TimeVal::zero(),
+            interval: TimeVal::zero(),
+        }
+    }
+    pub fn get(&self) -> itimerval {
+        itimerval {
+            it_interval: self.interval,
+            it_value: self.value,
+        }
+    }
+    /// 设置定时器, 返回原来的值
+    pub fn set(&mut self, new: itimerval) -> itimerval {
+        let old = self.get();
+        self.value = new.it_value;
+        self.interval = new.it_interval;
+        old
+    }
+    /// 消耗了 `time` 的 CPU 时间, 返回定时器是否到期
+    pub fn charge(&mut self, time: TimeVal) -> bool {
+        if self.value.is_zero() || time.is_zero() {
+            return false;
+        }
+        if time < self.value {
+            self.value = self.value - time;
+            return false;
+        }
+        // 周期定时器扣除超出的时间后重新倒数
+        let over = time - self.value;
+        self.value = self.interval - over;
+        if self.value.is_zero() {
+            self.value = self.interval;
+        }
+        true
+    }
+}
@@ -3,6 +3,7 @@
 use crate::{
     consts::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE},
     mm::{acquire_kvmm, MapPermission, VirtAddr, VmAreaType},
+    syscall::impls::Errno,
 };
 
 use super::PidHandle;
@@ -19,7 +20,7 @@ pub struct KernelStack {
 }
 impl KernelStack {
     /// Generate a kernel stack for a process with a given pid.
-    pub fn new(pid_handle: &PidHandle) -> Self {
+    pub fn new(pid_handle: &PidHandle) -> Result<Self, Errno> {
         let pid = pid_handle.0;
         let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(pid);
         acquire_kvmm().insert_framed_area(
@@ -27,9 +28,9 @@ impl KernelStack {
             kernel_stack_top.into(),
             MapPermission::R | MapPermission::W,
             VmAreaType::KernelStack,
-        );
+        )?;
 
-        KernelStack { pid: pid_handle.0 }
+        Ok(KernelStack { pid: pid_handle.0 })
     }
     /// Get the top address of the kernel stack in the kernel address space.(This address is only related to app_id)
     pub fn top(&self) -> usize {
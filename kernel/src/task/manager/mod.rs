@@ -37,6 +37,16 @@ pub fn unblock_task(task: Arc<TaskControlBlock>) {
 pub fn block_task(task: Arc<TaskControlBlock>) {
     TASK_MANAGER.lock().block(task);
 }
+/// Put a stopped task back to the ready queue
+pub fn resume_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.lock().add(task);
+}
+/// Make a blocked or sleeping task runnable, so that it can handle a fatal signal
+pub fn wake_task(task: Arc<TaskControlBlock>) {
+    let mut manager = TASK_MANAGER.lock();
+    manager.wake_hanging(&task);
+    manager.unblock_task(task);
+}
 
 pub static THREAD_CLEANER: Mutex<CancelledThreads> = Mutex::new(CancelledThreads::new());
 pub fn recycle_child_threads_res() {
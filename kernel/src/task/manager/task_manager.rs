@@ -57,9 +57,8 @@ impl TaskManager {
         mut global_futex_queue: RwLockWriteGuard<HashMap<usize, FutexQueue>>,
     ) -> Option<Arc<TaskControlBlock>> {
         for tcb in self.waiting_queue.iter() {
-            let lock = tcb.inner_ref();
             // { info!("[check_interupt] pid: {:?}, pending_signals: {:?}, sigmask: {:?}", tcb.pid(), lock.pending_signals, lock.sigmask); }
-            if !lock.pending_signals.difference(lock.sigmask).is_empty() {
+            if tcb.has_unmasked_signal() {
                 return Some(tcb.clone());
             }
         }
@@ -70,6 +69,17 @@ impl TaskManager {
         }
         None
     }
+    /// Wake up a sleeping task before its time, moving it to the ready queue
+    pub fn wake_hanging(&mut self, task: &Arc<TaskControlBlock>) {
+        let hanging_queue = core::mem::take(&mut self.hanging_queue);
+        for hanging_task in hanging_queue.into_iter() {
+            if Arc::ptr_eq(&hanging_task.inner(), task) {
+                self.add(hanging_task.inner());
+            } else {
+                self.hanging_queue.push(hanging_task);
+            }
+        }
+    }
     pub fn unblock_task(&mut self, task: Arc<TaskControlBlock>) {
         let p = self
             .waiting_queue
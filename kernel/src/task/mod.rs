@@ -1,49 +1,62 @@
 mod context;
 mod id;
 mod initproc;
+mod itimer;
 mod kstack;
 mod manager;
+mod oom;
+mod posix_timer;
 mod processor;
 mod signal;
 mod switch;
 mod task;
+mod thread_group;
 pub use context::*;
 pub use id::*;
 pub use initproc::*;
+pub use itimer::*;
 pub use kstack::*;
 pub use manager::*;
-use nix::{SAFlags, SigInfo, SigSet, Signal, UContext, SIG_DFL, SIG_IGN};
+use nix::{SAFlags, SigInfo, SigMask, Signal, UContext, SIG_DFL, SIG_IGN, SS_DISABLE, SS_ONSTACK};
+pub use oom::*;
+pub use posix_timer::*;
 pub use processor::*;
 pub use signal::*;
 pub use switch::*;
 pub use task::*;
+pub use thread_group::*;
 
 use crate::{
     consts::SIGNAL_TRAMPOLINE,
+    fs::{sync_all_fs, tty_session_exit},
     mm::{copyout, translated_mut},
-    syscall::impls::futex::futex_wake,
+    syscall::impls::{futex::futex_wake, Errno},
 };
 use alloc::sync::Arc;
-
-use fat32::sync_all;
-// use crate::fat32::sync_all;
+use riscv::register::scause::{Exception, Trap};
 
 pub use self::{
     initproc::INITPROC,
     processor::{acquire_processor, schedule},
 };
 
+/// Yield the CPU. Returns -1 if an unmasked signal is pending when the task runs again.
+/// Signals are only handled on the way back to user mode, so a syscall waiting in the kernel
+/// should then return ERESTARTSYS.
 pub fn suspend_current_and_run_next() -> isize {
-    exec_signal_handlers();
-
     let task = current_task().unwrap();
     let mut inner = task.inner_mut();
     let task_cx_ptr = &mut inner.task_cx as *mut TaskContext;
     inner.task_status = TaskStatus::Ready;
     drop(inner);
+    drop(task);
 
     schedule(task_cx_ptr);
-    0
+    if current_task().unwrap().has_unmasked_signal() {
+        -1
+    } else {
+        0
+    }
 }
 
 // TODO
@@ -53,37 +66,51 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     let pid = task.pid();
     let token = task.token();
     let is_child_thread = task.is_child_thread();
+    // taken before the inner lock, it walks up the parents of this thread
+    let leader = is_child_thread.then(|| task.group_leader());
 
     remove_from_pid2task(pid);
 
     let mut inner = task.inner_mut();
+    let live_threads = task.thread_group.exit_thread(inner.utime, inner.stime);
     let clear_child_tid = inner.clear_child_tid;
     if clear_child_tid != 0 {
-        *translated_mut(token, clear_child_tid as *mut usize) = 0;
+        *translated_mut(token, clear_child_tid as *mut u32) = 0;
         futex_wake(clear_child_tid, 1).unwrap();
     }
+    // a vfork parent waits until the child has exited or exec'd
+    task.release_vfork_parent();
     inner.task_status = TaskStatus::Zombie;
     inner.exit_code = exit_code;
 
     if pid == 0 {
-        sync_all();
+        sync_all_fs();
         panic!("initproc return!");
     }
 
-    assert!(if is_child_thread {
-        inner.children.is_empty()
-    } else {
-        true
-    });
-
-    // Move the child of this process to the child of the initproc process.
-    for child in inner.children.iter() {
-        // There is no need to distinguish between child threads and child processes.
-        // Transfer all children to initproc, and further processing will be done for child threads.
-        let mut initproc_inner = INITPROC.inner_mut();
-        child.inner_mut().parent = Some(Arc::downgrade(&INITPROC));
-        initproc_inner.children.push(child.clone());
+    // Move the child processes of this thread to another thread of the group, or to the
+    // initproc when the whole group has exited, so that getppid still sees the same process.
+    // Child threads are moved to the group leader, which is kept as a zombie until the
+    // group has exited, so the leader keeps its own child threads.
+    let new_parent = task
+        .thread_group
+        .threads()
+        .into_iter()
+        .min_by_key(|thread| thread.pid())
+        .unwrap_or_else(|| INITPROC.clone());
+    for child in core::mem::take(&mut inner.children) {
+        let target = match &leader {
+            _ if child.tgid != task.tgid => &new_parent,
+            Some(leader) => leader,
+            None => {
+                inner.children.push(child);
+                continue;
+            }
+        };
+        child.inner_mut().parent = Some(Arc::downgrade(target));
+        target.inner_mut().children.push(child);
     }
+    drop(new_parent);
 
     if is_child_thread {
         let parent = inner.parent.as_ref().unwrap().upgrade().unwrap();
@@ -97,6 +124,10 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         drop(parent_inner);
         drop(parent);
         drop(inner);
+        drop(leader);
+        if live_threads == 0 {
+            thread_group_exited(&task);
+        }
         assert!(Arc::strong_count(&task) == 1);
         collect_cancelled_chiled_thread(task);
         schedule(&mut TaskContext::empty() as *mut _);
@@ -104,10 +135,80 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     }
 
     drop(inner);
+    if live_threads == 0 {
+        thread_group_exited(&task);
+    }
     drop(task);
     schedule(&mut TaskContext::empty() as *mut _);
 }
 
+/// Called after the last thread of a group has exited. The user pages are freed right
+/// away unless another process shares the address space (CLONE_VM), so that memory comes
+/// back before the zombie is reaped. A session leader gives up the controlling tty, then
+/// the parent process gets the exit signal chosen at clone (normally SIGCHLD) and the
+/// threads waiting for children in wait4/waitid are woken up.
+fn thread_group_exited(task: &Arc<TaskControlBlock>) {
+    let memory_set = task.memory_set();
+    let shared = PID2TCB
+        .lock()
+        .values()
+        .any(|other| Arc::ptr_eq(&other.memory_set(), &memory_set));
+    if !shared {
+        memory_set.write().recycle_data_pages();
+    }
+    drop(memory_set);
+    if task.thread_group.sid() == task.tgid {
+        tty_session_exit(task.tgid);
+    }
+    let parent = tgid2group(task.ppid()).unwrap_or_else(|| INITPROC.thread_group.clone());
+    match task.thread_group.exit_signal {
+        0 => {}
+        signum if signum == Signal::SIGCHLD as usize => send_sigchld(&parent, false),
+        signum => {
+            let mut signal = SigMask::empty();
+            signal.add(signum as u32);
+            parent.send_signal(signal);
+        }
+    }
+    parent.wake_child_waiters();
+}
+
+/// Tell the parent process that `group` has stopped or continued, see [`JobEvent`].
+pub fn notify_job_event(group: &ThreadGroup) {
+    let parent = group
+        .threads()
+        .first()
+        .and_then(|thread| tgid2group(thread.ppid()))
+        .unwrap_or_else(|| INITPROC.thread_group.clone());
+    send_sigchld(&parent, true);
+    parent.wake_child_waiters();
+}
+
+/// Send SIGCHLD to a thread group. Like Linux, it is discarded when it would be ignored:
+/// the handler is SIG_IGN, or SIG_DFL (whose action is to ignore it) while SIGCHLD is not
+/// blocked. A blocked SIGCHLD stays pending, so that sigwait or sigpending can see it.
+/// SA_NOCLDSTOP suppresses it for children that stop or continue.
+fn send_sigchld(group: &ThreadGroup, job_event: bool) {
+    if let Some(thread) = group.threads().first() {
+        let sigaction = thread.sigactions.read()[Signal::SIGCHLD as usize];
+        let handler = sigaction.sa_handler;
+        let blocked = thread.inner_ref().sigmask.contains(SigMask::SIGCHLD);
+        let ignored = handler == SIG_IGN || (handler == SIG_DFL && !blocked);
+        if !ignored && !(job_event && sigaction.sa_flags.contains(SAFlags::SA_NOCLDSTOP)) {
+            group.add_signal(SigMask::SIGCHLD);
+        }
+    }
+}
+
+/// Exit every thread of the current thread group, used by exit_group and fatal signals.
+/// See [`ThreadGroup::kill`].
+pub fn exit_group_and_run_next(exit_code: i32) {
+    let task = current_task().unwrap();
+    let exit_code = task.thread_group.kill(exit_code, Some(task.pid()));
+    drop(task);
+    exit_current_and_run_next(exit_code);
+}
+
 pub fn hanging_current_and_run_next(sleep_time: usize, duration: usize) {
     let task = current_task().unwrap();
     let mut inner = task.inner_mut();
@@ -137,25 +238,54 @@ pub fn add_initproc() {
     add_task(INITPROC.clone());
 }
 
-pub fn exec_signal_handlers() {
-    let task = current_task().unwrap();
-    let mut task_inner = task.inner_mut();
-
-    if task_inner.pending_signals == SigSet::empty() {
+/// A syscall interrupted by a signal returns ERESTARTSYS. It is executed again if `restart`,
+/// i.e. no handler runs or the handler has SA_RESTART, otherwise it fails with EINTR.
+fn restart_syscall(task_inner: &mut TaskControlBlockInner, restart: bool) {
+    let in_syscall = matches!(
+        task_inner.trap_cause.map(|scause| scause.cause()),
+        Some(Trap::Exception(Exception::UserEnvCall))
+    );
+    let orig_a0 = task_inner.orig_a0;
+    let trap_cx = task_inner.trap_context();
+    if !in_syscall || trap_cx.x[10] as isize != -(Errno::ERESTARTSYS as isize) {
         return;
     }
+    if restart {
+        // back to the ecall instruction
+        trap_cx.sepc -= 4;
+        trap_cx.x[10] = orig_a0;
+    } else {
+        trap_cx.x[10] = -(Errno::EINTR as isize) as usize;
+    }
+}
+
+pub fn exec_signal_handlers() {
+    let task = current_task().unwrap();
 
     loop {
-        // take out the first signal of pending
-        let signum = match task_inner
-            .pending_signals
-            .difference(task_inner.sigmask)
-            .fetch()
-        {
-            Some(s) => s,
-            None => return,
+        let mut task_inner = task.inner_mut();
+        // A stopped thread group parks its threads here, outside of the ready queue,
+        // until SIGCONT or SIGKILL puts them back.
+        let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+        task_inner.task_status = TaskStatus::Stopped;
+        if task.thread_group.park(&task) {
+            drop(task_inner);
+            schedule(task_cx_ptr);
+            continue;
+        }
+        task_inner.task_status = TaskStatus::Running;
+
+        let sigmask = task_inner.sigmask;
+        let siginfo = dequeue_signal(&mut task_inner, &task.thread_group, sigmask);
+        let siginfo = match siginfo {
+            Some(info) => info,
+            None => {
+                // no handler runs, an interrupted syscall is executed again
+                restart_syscall(&mut task_inner, true);
+                return;
+            }
         };
-        task_inner.pending_signals.sub(signum);
+        let signum = siginfo.si_signo as u32;
         let sigaction = task.sigactions.read()[signum as usize];
 
         // if signal handler exists, then prepare to jump to handler
@@ -165,16 +295,34 @@ pub fn exec_signal_handlers() {
                 // return;
                 continue; // loop
             }
-            SIG_DFL => {
-                if signum == Signal::SIGKILL as u32 || signum == Signal::SIGSEGV as u32 {
+            SIG_DFL => match default_action(signum) {
+                DefaultAction::Terminate | DefaultAction::Core => {
                     // info!("[Kernel] task/mod(exec_signal_handlers) pid:{} signal_num:{}, SIG_DFL kill process", pid, signum);
                     drop(task_inner);
                     drop(task);
-                    exit_current_and_run_next(-(signum as i32));
+                    // a fatal signal kills the whole process
+                    exit_group_and_run_next(-(signum as i32));
+                    unreachable!()
                 }
-                return;
-            }
+                DefaultAction::Stop => {
+                    // park at the top of the loop
+                    drop(task_inner);
+                    if task.thread_group.stop(signum) {
+                        notify_job_event(&task.thread_group);
+                    }
+                    continue;
+                }
+                DefaultAction::Ignore | DefaultAction::Continue => continue,
+            },
             _ => {
+                restart_syscall(
+                    &mut task_inner,
+                    sigaction.sa_flags.contains(SAFlags::SA_RESTART),
+                );
+                // a one-shot handler
+                if sigaction.sa_flags.contains(SAFlags::SA_RESETHAND) {
+                    task.sigactions.write()[signum as usize].sa_handler = SIG_DFL;
+                }
                 // block the current signal and the signals in sigaction.sa_mask
                 let mut sigmask = sigaction.sa_mask.clone();
                 if !sigaction.sa_flags.contains(SAFlags::SA_NODEFER) {
@@ -185,18 +333,26 @@ pub fn exec_signal_handlers() {
                 sigmask.add_other(old_sigmask);
                 // set the signal mask to sigmask
                 task_inner.sigmask = sigmask;
+                let alt_stack = task_inner.sig_alt_stack;
                 // put the SignalContext data into the stack.
                 let trap_cx = task_inner.trap_context();
                 // save the trap context and old_sigmask to sig_context
                 let sig_context = SignalContext::from_another(trap_cx, old_sigmask);
                 trap_cx.x[10] = signum as usize; // a0 (args0 = signum)
 
+                // switch to the alternate stack unless the handler is already running on it
+                let on_alt_stack = alt_stack.contains(trap_cx.x[2]);
+                if sigaction.sa_flags.contains(SAFlags::SA_ONSTACK)
+                    && alt_stack.ss_flags & SS_DISABLE == 0
+                    && !on_alt_stack
+                {
+                    trap_cx.x[2] = alt_stack.ss_sp + alt_stack.ss_size;
+                }
+
                 // If SA_SIGINFO is included in sa_flags, put siginfo and ucontext into the stack.
                 // However, we did not differentiate whether the flag is present or not. We handled it uniformly.
 
-                let memory_set = task.memory_set.read();
-                let token = memory_set.token();
-                drop(memory_set);
+                let token = task.token();
 
                 trap_cx.x[2] -= core::mem::size_of::<UContext>(); // sp -= sizeof(ucontext)
                 let ucontext_ptr = trap_cx.x[2];
@@ -206,12 +362,26 @@ pub fn exec_signal_handlers() {
                 trap_cx.x[11] = siginfo_ptr; // a1 (args1 = siginfo)
                 trap_cx.x[12] = ucontext_ptr; // a2 (args2 = ucontext)
                 let mut ucontext = UContext::empty();
-                ucontext.uc_mcontext.greps[1] = trap_cx.sepc; //pc
-                copyout(token, ucontext_ptr as *mut UContext, &ucontext);
+                ucontext.uc_stack = alt_stack;
+                if on_alt_stack {
+                    ucontext.uc_stack.ss_flags = SS_ONSTACK;
+                }
+                ucontext.sigmask = old_sigmask;
+                ucontext.uc_mcontext.greps = sig_context.context.x;
+                ucontext.uc_mcontext.greps[0] = sig_context.context.sepc; //pc
 
                 trap_cx.x[2] -= core::mem::size_of::<SignalContext>(); // sp -= sizeof(sigcontext)
                 let sig_context_ptr = trap_cx.x[2] as *mut SignalContext;
-                copyout(token, sig_context_ptr, &sig_context);
+                let frame = copyout(token, siginfo_ptr as *mut SigInfo, &siginfo)
+                    .and_then(|_| copyout(token, ucontext_ptr as *mut UContext, &ucontext))
+                    .and_then(|_| copyout(token, sig_context_ptr, &sig_context));
+                if frame.is_err() {
+                    // 与 Linux 一样, 无法在用户栈上放入信号帧时以 SIGSEGV 结束进程
+                    drop(task_inner);
+                    drop(task);
+                    exit_group_and_run_next(-(Signal::SIGSEGV as i32));
+                    unreachable!()
+                }
 
                 trap_cx.x[1] = SIGNAL_TRAMPOLINE; // ra = user_sigreturn
 
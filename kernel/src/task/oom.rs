@@ -0,0 +1,40 @@
+//! OOM killer
+//!
+//! 缺页时页帧耗尽, 选出驻留页帧最多的进程并发送 SIGKILL, 而不是让内核 panic.
+//! 进程退出时立即释放用户页 (见 [`MemorySet::recycle_data_pages`]), 缺页的任务让出 CPU,
+//! 等被选中的进程退出后重试.
+
+use super::{tgid2group, INITPROC, PID2TCB};
+use crate::mm::MemorySet;
+use alloc::{sync::Arc, vec::Vec};
+use nix::SigSet;
+use spin::RwLock;
+
+/// 向驻留页帧最多的进程发送 SIGKILL, 返回它的 tgid. 共享地址空间的任务只计算一次,
+/// initproc 不会被选中, 已经退出的任务不在 PID2TCB 中. 没有可以结束的进程时返回 None
+pub fn oom_kill() -> Option<usize> {
+    let tasks: Vec<_> = PID2TCB.lock().values().cloned().collect();
+    let mut counted: Vec<*const RwLock<MemorySet>> = Vec::new();
+    let mut victim: Option<(usize, usize)> = None;
+    for task in tasks {
+        if task.tgid == INITPROC.tgid {
+            continue;
+        }
+        let memory_set = task.memory_set();
+        if counted.contains(&Arc::as_ptr(&memory_set)) {
+            continue;
+        }
+        counted.push(Arc::as_ptr(&memory_set));
+        let pages = memory_set.read().resident_pages();
+        if victim.map_or(true, |(_, max_pages)| pages > max_pages) {
+            victim = Some((task.tgid, pages));
+        }
+    }
+    let (tgid, pages) = victim?;
+    warn!(
+        "[oom_kill] out of memory, kill process {} with {} resident pages",
+        tgid, pages
+    );
+    tgid2group(tgid)?.send_signal(SigSet::SIGKILL);
+    Some(tgid)
+}
@@ -0,0 +1,168 @@
+//! POSIX 定时器
+//!
+//! 每个进程 (线程组) 有自己的定时器表, 以 timer_create 返回的 id 索引. fork 的子进程不继承定时器,
+//! exec 与进程退出时删除所有定时器.
+//!
+//! 定时器由内核时间轮驱动, 到期时按 sigevent 向进程或进程中的指定线程发送信号.
+//! 信号尚未被取出时定时器再次到期不会重复发送, 而是计入 overrun, 信号被取出时通过
+//! si_overrun 与 timer_getoverrun 交给用户.
+
+use super::{pid2task, ThreadGroup};
+use crate::timer::{add_timer, get_time_ns, TimerCallback};
+use alloc::sync::{Arc, Weak};
+use nix::{SigInfo, SI_TIMER};
+use spin::Mutex;
+
+/// overrun 计数的上限
+pub const DELAYTIMER_MAX: usize = i32::MAX as usize;
+
+/// 定时器到期时的通知方式
+#[derive(Clone, Copy, Debug)]
+pub enum TimerNotify {
+    /// 不发送信号, 只能通过 timer_gettime 查询 (SIGEV_NONE)
+    None,
+    /// 向进程发送信号 (SIGEV_SIGNAL)
+    Process { signo: i32, value: usize },
+    /// 向进程中的指定线程发送信号 (SIGEV_THREAD_ID)
+    Thread {
+        signo: i32,
+        value: usize,
+        tid: usize,
+    },
+}
+
+pub struct PosixTimer {
+    pub id: usize,
+    notify: TimerNotify,
+    group: Weak<ThreadGroup>,
+    inner: Mutex<PosixTimerInner>,
+}
+
+struct PosixTimerInner {
+    /// 每次设置定时器时加一, 时间轮中旧的表项随之失效
+    generation: usize,
+    /// 下一次到期的时间 (ns), None 表示定时器未启动
+    deadline: Option<usize>,
+    /// 周期 (ns), 0 表示只到期一次
+    interval: usize,
+    /// 信号已经发送但尚未被取出
+    signal_pending: bool,
+    /// 信号发送之后定时器又到期的次数
+    overrun: usize,
+    /// 最近一次被取出的信号对应的 overrun
+    overrun_last: usize,
+}
+
+impl PosixTimer {
+    pub fn new(id: usize, notify: TimerNotify, group: &Arc<ThreadGroup>) -> Arc<Self> {
+        Arc::new(Self {
+            id,
+            notify,
+            group: Arc::downgrade(group),
+            inner: Mutex::new(PosixTimerInner {
+                generation: 0,
+                deadline: None,
+                interval: 0,
+                signal_pending: false,
+                overrun: 0,
+                overrun_last: 0,
+            }),
+        })
+    }
+    /// 距离下一次到期的时间与周期 (ns), 定时器未启动时都为 0
+    pub fn get(&self) -> (usize, usize) {
+        let inner = self.inner.lock();
+        match inner.deadline {
+            // 已经到期但还没有被时间轮处理的定时器至少还剩 1ns
+            Some(deadline) => (
+                deadline.saturating_sub(get_time_ns()).max(1),
+                inner.interval,
+            ),
+            None => (0, 0),
+        }
+    }
+    /// 设置下一次到期的时间与周期 (ns), `deadline` 为 None 时停止定时器.
+    /// 返回原来的剩余时间与周期
+    pub fn set(self: &Arc<Self>, deadline: Option<usize>, interval: usize) -> (usize, usize) {
+        let old = self.get();
+        let mut inner = self.inner.lock();
+        inner.generation += 1;
+        inner.deadline = deadline;
+        inner.interval = interval;
+        inner.overrun = 0;
+        if let Some(deadline) = deadline {
+            let callback: Weak<dyn TimerCallback> = Arc::downgrade(self) as _;
+            add_timer(deadline, callback, inner.generation);
+        }
+        old
+    }
+    /// 定时器的信号被取出, 返回此前累计的 overrun
+    pub fn signal_delivered(&self) -> usize {
+        let mut inner = self.inner.lock();
+        inner.signal_pending = false;
+        inner.overrun_last = inner.overrun.min(DELAYTIMER_MAX);
+        inner.overrun = 0;
+        inner.overrun_last
+    }
+    /// 最近一次被取出的信号对应的 overrun
+    pub fn overrun(&self) -> usize {
+        self.inner.lock().overrun_last
+    }
+    /// 按 sigevent 发送信号, 返回信号是否进入了队列
+    fn send_signal(&self) -> bool {
+        let (signo, value, tid) = match self.notify {
+            TimerNotify::None => return false,
+            TimerNotify::Process { signo, value } => (signo, value, None),
+            TimerNotify::Thread { signo, value, tid } => (signo, value, Some(tid)),
+        };
+        let group = match self.group.upgrade() {
+            Some(group) => group,
+            None => return false,
+        };
+        // si_timerid 与 si_overrun 分别占用 si_pid 与 si_uid 的位置, overrun 在信号被取出时填入
+        let mut info = SigInfo::new(signo, SI_TIMER, self.id as u32, 0);
+        info.si_value = value;
+        match tid {
+            // 线程已经退出时丢弃信号
+            Some(tid) => pid2task(tid)
+                .filter(|task| task.tgid == group.tgid)
+                .map_or(false, |task| task.send_siginfo(info)),
+            None => group.send_siginfo(info),
+        }
+    }
+}
+
+impl TimerCallback for PosixTimer {
+    fn on_expire(self: Arc<Self>, key: usize) {
+        let mut inner = self.inner.lock();
+        let deadline = match inner.deadline {
+            Some(deadline) if inner.generation == key => deadline,
+            // 定时器已经被重新设置或停止
+            _ => return,
+        };
+        // 时间轮推迟触发时错过的周期也计入 overrun
+        let mut missed = 0;
+        if inner.interval > 0 {
+            missed = get_time_ns().saturating_sub(deadline) / inner.interval;
+            let next = deadline.saturating_add((missed + 1).saturating_mul(inner.interval));
+            inner.deadline = Some(next);
+            let callback: Weak<dyn TimerCallback> = Arc::downgrade(&self) as _;
+            add_timer(next, callback, key);
+        } else {
+            inner.deadline = None;
+        }
+        if let TimerNotify::None = self.notify {
+            return;
+        }
+        if inner.signal_pending {
+            inner.overrun = inner.overrun.saturating_add(missed + 1);
+            return;
+        }
+        inner.signal_pending = true;
+        inner.overrun = inner.overrun.saturating_add(missed);
+        drop(inner);
+        if !self.send_signal() {
+            self.inner.lock().signal_pending = false;
+        }
+    }
+}
@@ -18,9 +18,7 @@ pub fn current_task() -> Option<Arc<TaskControlBlock>> {
 
 pub fn current_user_token() -> usize {
     let task = current_task().unwrap();
-    let memory_set = task.memory_set.read();
-    let token = memory_set.token();
-    drop(memory_set);
+    let token = task.token();
     token
 }
 pub fn current_trap_cx() -> &'static mut TrapContext {
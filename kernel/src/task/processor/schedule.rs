@@ -2,6 +2,7 @@ use core::cell::RefMut;
 
 use alloc::sync::Arc;
 
+use crate::fs::{console_poll, writeback_tick};
 use crate::task::{
     add_task, check_hanging,
     manager::{check_futex_interupt_or_expire, fetch_task},
@@ -10,6 +11,7 @@ use crate::task::{
     task::TaskStatus,
     unblock_task, TaskContext, TaskControlBlock,
 };
+use crate::timer::timer_tick;
 
 #[cfg(feature = "static-busybox")]
 use crate::task::initproc::BUSYBOX;
@@ -25,6 +27,12 @@ pub fn run_tasks() {
         drop(busybox);
     }
     loop {
+        // 在空闲的调度循环中写回过期的脏页, 此时不持有任何任务的锁
+        writeback_tick();
+        // 轮询控制台输入, 以便 Ctrl-C 等能打断不读终端的前台作业
+        console_poll();
+        // 触发到期的 POSIX 定时器等内核定时器
+        timer_tick();
         let mut processor = acquire_processor();
 
         if let Some(last_task) = processor.take_current() {
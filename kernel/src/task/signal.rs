@@ -1,8 +1,9 @@
 use crate::trap::TrapContext;
 
-use nix::SigMask;
+use alloc::collections::VecDeque;
+use nix::{SigInfo, SigMask, SigSet, Signal, SIGRTMIN, SI_KERNEL, SI_TIMER};
 
-use super::current_task;
+use super::{current_task, TaskControlBlockInner, ThreadGroup};
 
 pub fn current_add_signal(signal: SigMask) {
     let task = current_task().unwrap();
@@ -10,6 +11,144 @@ pub fn current_add_signal(signal: SigMask) {
     task_inner.pending_signals.set(signal, true);
 }
 
+/// Default action of a signal, see signal(7)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefaultAction {
+    /// Terminate the process
+    Terminate,
+    /// Terminate the process and dump core. No core file is written, so this is the same
+    /// as `Terminate` for now.
+    Core,
+    /// Ignore the signal
+    Ignore,
+    /// Stop the process
+    Stop,
+    /// Continue the process if it is stopped. Resuming happens when the signal is sent,
+    /// see `ThreadGroup::send_signal`, so it is ignored on delivery.
+    Continue,
+}
+
+/// Signals whose default action stops the process
+pub const STOP_SIGNALS: SigMask = SigMask::SIGSTOP
+    .union(SigMask::SIGTSTP)
+    .union(SigMask::SIGTTIN)
+    .union(SigMask::SIGTTOU);
+
+pub fn default_action(signum: u32) -> DefaultAction {
+    const IGNORE: [Signal; 3] = [Signal::SIGCHLD, Signal::SIGURG, Signal::SIGWINCH];
+    const CORE: [Signal; 10] = [
+        Signal::SIGQUIT,
+        Signal::SIGILL,
+        Signal::SIGTRAP,
+        Signal::SIGABRT,
+        Signal::SIGBUS,
+        Signal::SIGFPE,
+        Signal::SIGSEGV,
+        Signal::SIGXCPU,
+        Signal::SIGXFSZ,
+        Signal::SIGSYS,
+    ];
+    let is = |signals: &[Signal]| signals.iter().any(|signal| *signal as u32 == signum);
+    if is(&IGNORE) {
+        DefaultAction::Ignore
+    } else if signum == Signal::SIGCONT as u32 {
+        DefaultAction::Continue
+    } else if STOP_SIGNALS.if_contains(signum) {
+        DefaultAction::Stop
+    } else if is(&CORE) {
+        DefaultAction::Core
+    } else {
+        // including the real-time signals
+        DefaultAction::Terminate
+    }
+}
+
+/// Maximum number of queued siginfo records per task or thread group, like RLIMIT_SIGPENDING
+pub const SIGQUEUE_MAX: usize = 1024;
+
+/// The siginfo records of pending signals, kept next to the pending bitmask.
+///
+/// Standard signals coalesce: a second one sent while the first is still pending is dropped.
+/// Real-time signals (SIGRTMIN and above) queue, and are delivered once per send in order.
+/// A pending bit without a record (set directly by the kernel) is delivered with `SI_KERNEL`.
+pub struct SigQueue {
+    queue: VecDeque<SigInfo>,
+}
+
+impl SigQueue {
+    pub fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+    /// Mark the signal pending and record its siginfo.
+    /// Returns false if it is a real-time signal and the queue is full.
+    pub fn enqueue(&mut self, pending: &mut SigSet, info: SigInfo) -> bool {
+        let signum = info.si_signo as u32;
+        if signum < SIGRTMIN && pending.if_contains(signum) {
+            return true;
+        }
+        if self.queue.len() >= SIGQUEUE_MAX {
+            if signum >= SIGRTMIN {
+                return false;
+            }
+        } else {
+            self.queue.push_back(info);
+        }
+        pending.add(signum);
+        true
+    }
+    /// Take the lowest numbered pending signal that is not in `mask`, the pending bit is
+    /// cleared once no more records of that signal are queued
+    pub fn dequeue(&mut self, pending: &mut SigSet, mask: SigMask) -> Option<SigInfo> {
+        let signum = pending.difference(mask).fetch()?;
+        let info = match self
+            .queue
+            .iter()
+            .position(|info| info.si_signo as u32 == signum)
+        {
+            Some(index) => self.queue.remove(index).unwrap(),
+            None => SigInfo::new(signum as i32, SI_KERNEL, 0, 0),
+        };
+        if !self.queue.iter().any(|info| info.si_signo as u32 == signum) {
+            pending.sub(signum);
+        }
+        Some(info)
+    }
+    /// Drop the pending signals in `signals` together with their records
+    pub fn discard(&mut self, pending: &mut SigSet, signals: SigSet) {
+        pending.remove(signals);
+        self.queue
+            .retain(|info| !signals.if_contains(info.si_signo as u32));
+    }
+}
+
+/// Take the first pending signal that is not in `mask`. Signals sent to this thread come first,
+/// then those sent to the whole thread group. A POSIX timer signal carries the overrun count
+/// of its timer, which is reset here.
+pub fn dequeue_signal(
+    task_inner: &mut TaskControlBlockInner,
+    group: &ThreadGroup,
+    mask: SigMask,
+) -> Option<SigInfo> {
+    let mut info = task_inner
+        .sig_queue
+        .dequeue(&mut task_inner.pending_signals, mask)
+        .or_else(|| {
+            let mut group_inner = group.inner();
+            let group_inner = &mut *group_inner;
+            group_inner
+                .sig_queue
+                .dequeue(&mut group_inner.pending_signals, mask)
+        })?;
+    if info.si_code == SI_TIMER {
+        if let Some(timer) = group.timer(info.si_pid as usize) {
+            info.si_uid = timer.signal_delivered() as u32;
+        }
+    }
+    Some(info)
+}
+
 // Pushing the signal processing context onto the stack.
 // [man7: 关于 signal context 的要求](https://man7.org/linux/man-pages/man7/signal.7.html)
 #[derive(Debug, Clone)]
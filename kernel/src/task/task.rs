@@ -1,12 +1,22 @@
 use super::kstack::KernelStack;
 use super::TaskContext;
-use super::{pid_alloc, PidHandle, SigSet};
+use super::{
+    block_task, pid_alloc, schedule, unblock_task, PidHandle, SigQueue, SigSet, ThreadGroup,
+    INITPROC,
+};
 use crate::consts::*;
 use crate::fs::{File, Stdin, Stdout};
 use crate::mm::acquire_kvmm;
 use crate::mm::copyout;
 use crate::mm::LoadedELF;
-use crate::mm::{MemorySet, PhysPageNum, VirtAddr, VirtPageNum};
+use crate::mm::{
+    MapPermission, MapType, MemorySet, PhysPageNum, VirtAddr, VirtPageNum, VmArea, VmAreaType,
+    VmBacking,
+};
+use crate::random::get_random_bytes;
+use crate::return_errno;
+use crate::syscall::impls::Errno;
+use crate::timer::get_timeval;
 use crate::trap::user_trap_handler;
 use crate::trap::TrapContext;
 use alloc::string::String;
@@ -16,12 +26,12 @@ use alloc::vec::Vec;
 use core::fmt::Debug;
 use nix::time::TimeVal;
 use nix::{
-    AuxEntry, CloneFlags, IntervalTimer, MmapFlags, MmapProts, RLimit, RobustList, SigAction,
-    SigMask, MAX_SIGNUM,
+    AuxEntry, CloneFlags, MmapFlags, MmapProts, RLimit, Resource, RobustList, SigAction, SigInfo,
+    SigMask, Signal, SignalStack, AT_RANDOM, MAX_SIGNUM, RLIM_INFINITY, SIG_IGN,
 };
 use path::AbsolutePath;
 use riscv::register::scause::Scause;
-use spin::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use spin::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 #[cfg(feature = "static-busybox")]
 use super::initproc::{STATIC_BUSYBOX_AUX, STATIC_BUSYBOX_ENTRY};
@@ -30,12 +40,18 @@ pub struct TaskControlBlock {
     // immutable
     pub pid: PidHandle,
     pub tgid: usize,
+    pub thread_group: Arc<ThreadGroup>,
     pub kernel_stack: KernelStack,
 
     // mutable according to clone flags
     pub sigactions: Arc<RwLock<[SigAction; MAX_SIGNUM as usize]>>,
-    pub memory_set: Arc<RwLock<MemorySet>>,
+    /// 地址空间, 以 CLONE_VM 创建的任务共享. exec 时换成新的地址空间, 通过 [`Self::memory_set`] 获取
+    memory_set: RwLock<Arc<RwLock<MemorySet>>>,
     pub fd_table: Arc<RwLock<FDTable>>,
+    pub fs: Arc<RwLock<FsContext>>,
+
+    /// 以 CLONE_VFORK 创建时等待本任务 exec 或退出的父线程
+    vfork_parent: Mutex<Option<Arc<TaskControlBlock>>>,
 
     // mutable
     inner: RwLock<TaskControlBlockInner>,
@@ -46,6 +62,47 @@ impl TaskControlBlock {
     pub fn is_child_thread(&self) -> bool {
         self.pid.0 != self.tgid
     }
+    /// 父进程的 tgid, 见 [`Self::parent_process`]
+    pub fn ppid(&self) -> usize {
+        self.parent_process().map_or(0, |task| task.tgid)
+    }
+    /// 父进程中的父节点. 线程的父节点是创建它的线程, 因此沿父节点向上找到线程组之外的父进程
+    pub fn parent_process(&self) -> Option<Arc<Self>> {
+        let mut parent = self.inner_ref().parent.as_ref().and_then(Weak::upgrade);
+        while let Some(task) = parent {
+            if task.tgid != self.tgid {
+                return Some(task);
+            }
+            parent = task.inner_ref().parent.as_ref().and_then(Weak::upgrade);
+        }
+        None
+    }
+    /// 线程组的组长. 线程的父节点是组内的线程, 组长在线程组的其他线程退出前不会被回收
+    pub fn group_leader(self: &Arc<Self>) -> Arc<Self> {
+        let mut task = self.clone();
+        while task.pid() != task.tgid {
+            let parent = task.inner_ref().parent.as_ref().and_then(Weak::upgrade);
+            task = parent.expect("thread without group leader");
+        }
+        task
+    }
+    /// 信号是否被忽略或屏蔽
+    pub fn ignores_signal(&self, signum: u32) -> bool {
+        self.sigactions.read()[signum as usize].sa_handler == SIG_IGN
+            || self.inner_ref().sigmask.if_contains(signum)
+    }
+    /// 是否有未被屏蔽的待处理信号, 包括发给线程组的信号
+    pub fn has_unmasked_signal(&self) -> bool {
+        let inner = self.inner_ref();
+        let pending = inner.pending_signals | self.thread_group.inner().pending_signals;
+        !pending.difference(inner.sigmask).is_empty()
+    }
+    /// 向线程发送带有 siginfo 的信号. 实时信号排队, 队列已满时返回 false
+    pub fn send_siginfo(&self, info: SigInfo) -> bool {
+        let mut inner = self.inner_mut();
+        let inner = &mut *inner;
+        inner.sig_queue.enqueue(&mut inner.pending_signals, info)
+    }
 }
 
 impl Debug for TaskControlBlock {
@@ -56,23 +113,38 @@ impl Debug for TaskControlBlock {
     }
 }
 
+/// 文件系统相关的上下文, 以 CLONE_FS 创建的任务共享
+#[derive(Clone)]
+pub struct FsContext {
+    /// 当前工作目录
+    pub cwd: AbsolutePath,
+    /// 创建文件时从权限中去掉的位
+    pub umask: u32,
+}
+
 pub struct TaskControlBlockInner {
     pub trap_cx_ppn: PhysPageNum,
+    /// Trap 上下文在地址空间中的位置, 见 [`trap_context_position`]. 共享地址空间的任务各不相同
+    pub trap_cx_slot: usize,
     pub task_cx: TaskContext,
     pub task_status: TaskStatus,
     pub trap_cause: Option<Scause>,
+    /// 进入内核时的 a0, 被信号打断的系统调用重新执行时用于恢复第一个参数
+    pub orig_a0: usize,
 
     pub parent: Option<Weak<TaskControlBlock>>,
     // child process and thread collection
     pub children: Vec<Arc<TaskControlBlock>>,
 
     pub pending_signals: SigSet,
+    /// 待处理信号的 siginfo
+    pub sig_queue: SigQueue,
     pub sigmask: SigMask,
+    /// 信号处理的备用栈
+    pub sig_alt_stack: SignalStack,
 
-    pub cwd: AbsolutePath,
     pub exit_code: i32,
 
-    pub interval_timer: Option<IntervalTimer>,
     pub utime: TimeVal,
     pub stime: TimeVal,
     pub last_enter_umode_time: TimeVal,
@@ -80,8 +152,21 @@ pub struct TaskControlBlockInner {
 
     pub robust_list: RobustList,
     pub rlimit_nofile: RLimit,
+    /// 地址空间的大小
+    pub rlimit_as: RLimit,
+    /// 堆及私有可写映射的大小
+    pub rlimit_data: RLimit,
+    /// 用户栈的大小
+    pub rlimit_stack: RLimit,
+    /// 任务 (包括线程) 的数量
+    pub rlimit_nproc: RLimit,
 
     pub clear_child_tid: usize, /* CLONE_CHILD_CLEARTID */
+
+    // 以下信息供 procfs 使用
+    pub exe: AbsolutePath,
+    pub cmdline: Vec<String>,
+    pub start_time: TimeVal,
 }
 
 pub type FDTable = Vec<Option<Arc<dyn File>>>;
@@ -96,9 +181,6 @@ impl TaskControlBlockInner {
     pub fn is_zombie(&self) -> bool {
         self.status() == TaskStatus::Zombie
     }
-    pub fn get_work_path(&self) -> AbsolutePath {
-        self.cwd.clone()
-    }
     pub fn add_utime(&mut self, new_time: TimeVal) {
         self.utime = self.utime + new_time;
     }
@@ -111,11 +193,28 @@ impl TaskControlBlockInner {
     pub fn set_last_enter_smode(&mut self, new_time: TimeVal) {
         self.last_enter_smode_time = new_time;
     }
+    /// `resource` 的资源限制, 不支持的资源返回 None
+    pub fn rlimit_mut(&mut self, resource: Resource) -> Option<&mut RLimit> {
+        match resource {
+            Resource::NOFILE => Some(&mut self.rlimit_nofile),
+            Resource::AS => Some(&mut self.rlimit_as),
+            Resource::DATA => Some(&mut self.rlimit_data),
+            Resource::STACK => Some(&mut self.rlimit_stack),
+            Resource::NPROC => Some(&mut self.rlimit_nproc),
+            _ => None,
+        }
+    }
 }
 
 impl TaskControlBlock {
+    pub fn memory_set(&self) -> Arc<RwLock<MemorySet>> {
+        self.memory_set.read().clone()
+    }
     pub fn token(&self) -> usize {
-        self.memory_set.read().token()
+        self.memory_set().read().token()
+    }
+    pub fn get_work_path(&self) -> AbsolutePath {
+        self.fs.read().cwd.clone()
     }
 
     /// Find an empty slot in the file descriptor table
@@ -148,7 +247,7 @@ impl TaskControlBlock {
             elf_entry: entry_point,
             user_stack_top: user_sp,
             auxs,
-        } = MemorySet::load_elf(elf.clone());
+        } = MemorySet::load_elf(elf.clone()).expect("failed to load initproc");
 
         #[cfg(feature = "static-busybox")]
         if &elf.name() == "static-busybox" {
@@ -165,7 +264,7 @@ impl TaskControlBlock {
             .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
             .unwrap()
             .ppn();
-        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack = KernelStack::new(&pid_handle).expect("failed to map kernel stack");
         let kernel_stack_top = kernel_stack.top();
 
         // Push the initialized task context on the kernel stack of the process,
@@ -174,8 +273,9 @@ impl TaskControlBlock {
         let task_control_block = Self {
             pid: pid_handle,
             tgid,
+            thread_group: ThreadGroup::new(tgid, tgid, tgid, Signal::SIGCHLD as usize),
             kernel_stack,
-            memory_set: Arc::new(RwLock::new(memory_set)),
+            memory_set: RwLock::new(Arc::new(RwLock::new(memory_set))),
             fd_table: Arc::new(RwLock::new(vec![
                 // 0 -> stdin
                 Some(Arc::new(Stdin)),
@@ -184,26 +284,40 @@ impl TaskControlBlock {
                 // 2 -> stderr
                 Some(Arc::new(Stdout)),
             ])),
+            fs: Arc::new(RwLock::new(FsContext {
+                cwd: AbsolutePath::from_str("/"),
+                umask: 0o022,
+            })),
+            vfork_parent: Mutex::new(None),
 
             inner: RwLock::new(TaskControlBlockInner {
                 trap_cx_ppn,
+                trap_cx_slot: 0,
                 task_cx: TaskContext::readied_for_switching(kernel_stack_top),
                 task_status: TaskStatus::Ready,
                 parent: None,
                 children: Vec::new(),
                 robust_list: RobustList::default(),
                 rlimit_nofile: RLimit::new(FD_LIMIT, FD_LIMIT),
+                rlimit_as: RLimit::new(RLIM_INFINITY, RLIM_INFINITY),
+                rlimit_data: RLimit::new(RLIM_INFINITY, RLIM_INFINITY),
+                rlimit_stack: RLimit::new(USER_STACK_SIZE, RLIM_INFINITY),
+                rlimit_nproc: RLimit::new(RLIM_INFINITY, RLIM_INFINITY),
                 exit_code: 0,
                 sigmask: SigMask::empty(),
                 pending_signals: SigSet::empty(),
-                cwd: AbsolutePath::from_str("/"),
+                sig_queue: SigQueue::new(),
+                sig_alt_stack: SignalStack::disabled(),
                 utime: TimeVal { sec: 0, usec: 0 },
                 stime: TimeVal { sec: 0, usec: 0 },
                 last_enter_umode_time: TimeVal { sec: 0, usec: 0 },
                 last_enter_smode_time: TimeVal { sec: 0, usec: 0 },
                 clear_child_tid: 0,
                 trap_cause: None,
-                interval_timer: None,
+                orig_a0: 0,
+                exe: elf.path(),
+                cmdline: vec![elf.name()],
+                start_time: get_timeval(),
             }),
             sigactions: Arc::new(RwLock::new([SigAction::new(); MAX_SIGNUM as usize])),
         };
@@ -222,16 +336,16 @@ impl TaskControlBlock {
         task_control_block
     }
 
+    /// 在用户栈上放入参数, 环境变量与辅助向量, 返回新的栈顶及参数, 环境变量指针数组的位置.
+    /// 无法写入用户栈时返回相应的错误
     pub fn init_ustack(
         &self,
         user_sp: usize,
         args: Vec<String>,
         envs: Vec<String>,
         auxv: &mut Vec<AuxEntry>,
-    ) -> (usize, usize, usize) {
-        let memory_set = self.memory_set.read();
-        let token = memory_set.token();
-        drop(memory_set);
+    ) -> Result<(usize, usize, usize), Errno> {
+        let token = self.token();
         let mut user_sp = user_sp;
 
         #[cfg(feature = "u740")]
@@ -252,6 +366,13 @@ impl TaskControlBlock {
             *translated_mut(token, user_sp as *mut usize) = 456;
         }
 
+        // 16 字节随机数放在栈顶, 由 AT_RANDOM 指向, 用于 libc 的 stack protector 与哈希种子
+        user_sp -= 16;
+        let mut random = [0u8; 16];
+        get_random_bytes(&mut random);
+        copyout(token, user_sp as *mut [u8; 16], &random)?;
+        auxv.push(AuxEntry(AT_RANDOM, user_sp));
+
         // alloc envs space, and add the position of dynamic link libraryc
         let envs_ptrv: Vec<_> = (0..envs.len())
             .map(|idx| {
@@ -259,13 +380,13 @@ impl TaskControlBlock {
                 let mut ptr = user_sp;
                 for c in envs[idx].as_bytes() {
                     // 将参数写入到用户栈
-                    copyout(token, unsafe { (ptr as *mut u8).as_mut().unwrap() }, c);
+                    copyout(token, unsafe { (ptr as *mut u8).as_mut().unwrap() }, c)?;
                     ptr += 1;
                 } // 写入字符串结束标记
-                copyout(token, unsafe { (ptr as *mut u8).as_mut().unwrap() }, &0);
-                user_sp
+                copyout(token, unsafe { (ptr as *mut u8).as_mut().unwrap() }, &0)?;
+                Ok(user_sp)
             })
-            .collect();
+            .collect::<Result<_, Errno>>()?;
 
         // alloc args space, and write string data, save the string address in argv
         // Here the high address puts the previous parameter, that is, store argv[0] first
@@ -275,14 +396,14 @@ impl TaskControlBlock {
                 let mut ptr = user_sp;
                 for c in args[idx].as_bytes() {
                     // copyout the parameter to the user stack
-                    copyout(token, unsafe { (ptr as *mut u8).as_mut().unwrap() }, c);
+                    copyout(token, unsafe { (ptr as *mut u8).as_mut().unwrap() }, c)?;
                     ptr += 1;
                 }
                 // write the string end mark
-                copyout(token, unsafe { (ptr as *mut u8).as_mut().unwrap() }, &0);
-                user_sp
+                copyout(token, unsafe { (ptr as *mut u8).as_mut().unwrap() }, &0)?;
+                Ok(user_sp)
             })
-            .collect();
+            .collect::<Result<_, Errno>>()?;
 
         // padding 0 to indicate the end of AT_NULL aux entry
         user_sp -= core::mem::size_of::<usize>();
@@ -290,7 +411,7 @@ impl TaskControlBlock {
             token,
             unsafe { (user_sp as *mut usize).as_mut().unwrap() },
             &0,
-        );
+        )?;
 
         // alloc auxs space, and write data
         for i in 0..auxv.len() {
@@ -299,7 +420,7 @@ impl TaskControlBlock {
                 token,
                 unsafe { (user_sp as *mut AuxEntry).as_mut().unwrap() },
                 &auxv[i],
-            );
+            )?;
         }
         // auxv.push(AuxEntry(AT_EXECFN,args_ptrv[0] ));
 
@@ -309,7 +430,7 @@ impl TaskControlBlock {
             token,
             unsafe { (user_sp as *mut usize).as_mut().unwrap() },
             &0,
-        );
+        )?;
 
         // envs_ptr
         user_sp -= (envs.len()) * core::mem::size_of::<usize>();
@@ -323,7 +444,7 @@ impl TaskControlBlock {
                         .unwrap()
                 },
                 &envs_ptrv[i],
-            );
+            )?;
         }
 
         // padding 0 to indicate the end of envs
@@ -332,7 +453,7 @@ impl TaskControlBlock {
             token,
             unsafe { (user_sp as *mut usize).as_mut().unwrap() },
             &0,
-        );
+        )?;
 
         // args_ptr
         user_sp -= (args.len()) * core::mem::size_of::<usize>();
@@ -346,7 +467,7 @@ impl TaskControlBlock {
                         .unwrap()
                 },
                 &args_ptrv[i],
-            );
+            )?;
         }
 
         // argc
@@ -356,21 +477,29 @@ impl TaskControlBlock {
             token,
             unsafe { (user_sp as *mut usize).as_mut().unwrap() },
             &len,
-        );
+        )?;
 
-        (user_sp, args_ptr_base as usize, envs_ptr_base as usize)
+        Ok((user_sp, args_ptr_base as usize, envs_ptr_base as usize))
     }
 
     /// Used to implement the exec system call, which loads and executes another ELF
     /// format executable file within the current process.
-    pub fn exec(&self, elf_file: Arc<dyn File>, args: Vec<String>, envs: Vec<String>) {
+    ///
+    /// If the ELF file cannot be loaded, the error is returned and the current address space is kept.
+    pub fn exec(
+        &self,
+        elf_file: Arc<dyn File>,
+        args: Vec<String>,
+        envs: Vec<String>,
+    ) -> Result<(), Errno> {
         // Generate a brand new address space from an ELF file and replace the current address space directly.
         let LoadedELF {
-            memory_set,
+            mut memory_set,
             user_stack_top: user_sp,
             elf_entry: entry_point,
             mut auxs,
-        } = MemorySet::load_elf(elf_file);
+        } = MemorySet::load_elf(elf_file.clone())?;
+        memory_set.limit_stack(self.inner_ref().rlimit_stack.rlim_cur);
         assert!(
             self.pid.0 == self.tgid,
             "exec task must be main thread(process)"
@@ -390,9 +519,8 @@ impl TaskControlBlock {
         // and all the physical page frames contained within it will be reclaimed.
         // The result is that the reference count (ppn) of all page table entries in
         // the original address space will be decremented by 1.
-        let mut ms = self.memory_set.write();
-        *ms = memory_set;
-        drop(ms); // to avoid deadlock
+        // 与其他任务共享的地址空间 (CLONE_VM) 则留给它们继续使用
+        *self.memory_set.write() = Arc::new(RwLock::new(memory_set));
 
         let mut fd_table = self.fd_table.write();
         fd_table
@@ -400,12 +528,27 @@ impl TaskControlBlock {
             .find(|fd| fd.is_some() && !fd.as_ref().unwrap().available())
             .take();
 
+        // exec 删除进程的 POSIX 定时器
+        self.thread_group.clear_timers();
+
         let mut inner = self.inner_mut();
         inner.trap_cx_ppn = trap_cx_ppn;
+        inner.trap_cx_slot = 0;
+        inner.exe = elf_file.path();
+        inner.cmdline = args.clone();
+        inner.sig_alt_stack = SignalStack::disabled();
         let trap_cx = inner.trap_context();
         drop(inner); // to avoid deadlock
 
-        let (user_sp, _args_ptr, _envs_ptr) = self.init_ustack(user_sp, args, envs, &mut auxs);
+        let user_sp = match self.init_ustack(user_sp, args, envs, &mut auxs) {
+            Ok((user_sp, _args_ptr, _envs_ptr)) => user_sp,
+            Err(errno) => {
+                // 原来的地址空间已经释放, 无法再向调用者返回错误, 回到用户态前以 SIGKILL 结束进程
+                warn!("[exec] failed to initialize the user stack: {:?}", errno);
+                self.thread_group.send_signal(SigSet::SIGKILL);
+                user_sp
+            }
+        };
         // Modify the Trap context in the new address space to initialize the resolved application
         // entry point, user stack location, and some kernel information
         *trap_cx = TrapContext::app_init_context(
@@ -415,47 +558,94 @@ impl TaskControlBlock {
             self.kernel_stack.top(),
             user_trap_handler as usize,
         );
+        self.release_vfork_parent();
+        Ok(())
+    }
+
+    /// 以 CLONE_VFORK 创建的任务 exec 或退出时, 唤醒等待它的父线程
+    pub fn release_vfork_parent(&self) {
+        let parent = self.vfork_parent.lock().take();
+        if let Some(parent) = parent {
+            unblock_task(parent);
+        }
+    }
+    /// 等待以 CLONE_VFORK 创建的子任务 `child` exec 或退出. 期间只有使线程组退出的信号能打断等待,
+    /// 其他信号留到等待结束后处理
+    pub fn wait_vfork_done(self: &Arc<Self>, child: &TaskControlBlock) {
+        loop {
+            // 持有 vfork_parent 的锁检查并阻塞自己, 子任务需要获取该锁才能唤醒父线程, 因此不会错过唤醒
+            let mut vfork_parent = child.vfork_parent.lock();
+            if vfork_parent.is_none() {
+                return;
+            }
+            if self.thread_group.exit_code().is_some() {
+                *vfork_parent = None;
+                return;
+            }
+            let mut inner = self.inner_mut();
+            let task_cx_ptr = &mut inner.task_cx as *mut TaskContext;
+            inner.task_status = TaskStatus::Blocking;
+            block_task(self.clone());
+            drop(inner);
+            drop(vfork_parent);
+            schedule(task_cx_ptr);
+        }
     }
 
     /// Used to implement the fork system call, which creates a nearly identical child process/thread
-    /// from the current process.
-    pub fn fork(self: &Arc<TaskControlBlock>, flags: CloneFlags) -> Arc<TaskControlBlock> {
+    /// from the current process. A new process sends `exit_signal` to its parent when it exits.
+    pub fn fork(
+        self: &Arc<TaskControlBlock>,
+        flags: CloneFlags,
+        exit_signal: usize,
+    ) -> Result<Arc<TaskControlBlock>, Errno> {
         // 分配一个 PID
         let pid_handle = pid_alloc();
-        let tgid = if flags.contains(CloneFlags::THREAD) {
-            self.pid.0
+        // 共享地址空间的任务各自需要一个 Trap 上下文, 以任务号与地址空间中第一个任务的任务号之差区分
+        let trap_cx_slot = if flags.contains(CloneFlags::VM) {
+            pid_handle.0 - (self.pid() - self.inner_ref().trap_cx_slot)
         } else {
-            pid_handle.0
+            0
         };
-        let private_tid = pid_handle.0 - tgid;
         // create a kernel stack for the new process according to the PID
-        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack = KernelStack::new(&pid_handle)?;
 
         let kernel_stack_top = kernel_stack.top();
 
         // copy user address space
         let memory_set = if flags.contains(CloneFlags::VM) {
-            self.memory_set.clone()
+            self.memory_set()
         } else {
             Arc::new(RwLock::new(MemorySet::from_copy_on_write(
-                &mut self.memory_set.write(),
-            )))
+                &mut self.memory_set().write(),
+            )?))
         };
 
-        if flags.contains(CloneFlags::THREAD) {
-            memory_set.write().map_thread_trap_context(private_tid);
+        if trap_cx_slot != 0 {
+            memory_set.write().map_thread_trap_context(trap_cx_slot)?;
         }
+        // 之后的步骤不会失败, 此时才加入或创建线程组
+        let (tgid, thread_group) = if flags.contains(CloneFlags::THREAD) {
+            self.thread_group.add_thread();
+            (self.tgid, self.thread_group.clone())
+        } else {
+            // 子进程继承父进程的进程组与会话
+            let (pgid, sid) = (self.thread_group.pgid(), self.thread_group.sid());
+            (
+                pid_handle.0,
+                ThreadGroup::new(pid_handle.0, pgid, sid, exit_signal),
+            )
+        };
 
         let trap_cx_ppn = memory_set
             .read()
-            .translate(trap_context_position(private_tid).into())
+            .translate(trap_context_position(trap_cx_slot).into())
             .unwrap()
             .ppn();
 
-        if flags.contains(CloneFlags::THREAD) {
-            let trap_cx: &mut TrapContext = trap_cx_ppn.as_mut() as &mut TrapContext;
-            *trap_cx = self.inner_ref().trap_context().clone();
-        }
+        // 复制的地址空间中位置 0 的是主线程的 Trap 上下文, 由其他线程 fork 时也需要复制
+        let trap_cx: &mut TrapContext = trap_cx_ppn.as_mut() as &mut TrapContext;
+        *trap_cx = self.inner_ref().trap_context().clone();
         // copy fd table
         let fd_table = if flags.contains(CloneFlags::FILES) {
             self.fd_table.clone()
@@ -481,54 +671,90 @@ impl TaskControlBlock {
             let sa = Arc::new(RwLock::new([SigAction::new(); MAX_SIGNUM as usize]));
             let mut sa_mut = sa.write();
             for i in 1..MAX_SIGNUM as usize {
-                sa_mut[i] = psa_ref[i].clone();
+                // CLONE_CLEAR_SIGHAND 将没有被忽略的信号恢复为默认处理
+                if !flags.contains(CloneFlags::CLEAR_SIGHAND) || psa_ref[i].sa_handler == SIG_IGN {
+                    sa_mut[i] = psa_ref[i].clone();
+                }
             }
             drop(sa_mut);
             sa
         };
 
-        let mut parent_inner = self.inner_mut();
+        let fs = if flags.contains(CloneFlags::FS) {
+            self.fs.clone()
+        } else {
+            Arc::new(RwLock::new(self.fs.read().clone()))
+        };
+
+        // CLONE_PARENT 创建的子进程与调用者有相同的父进程. 线程的父节点总是创建它的线程
+        let parent = if flags.contains(CloneFlags::PARENT) && !flags.contains(CloneFlags::THREAD) {
+            self.parent_process().unwrap_or_else(|| INITPROC.clone())
+        } else {
+            self.clone()
+        };
+
+        let parent_inner = self.inner_ref();
 
         let task_control_block = Arc::new(TaskControlBlock {
             pid: pid_handle,
             tgid,
-            memory_set,
+            thread_group,
+            memory_set: RwLock::new(memory_set),
             fd_table,
+            fs,
             sigactions,
             kernel_stack,
+            vfork_parent: Mutex::new(flags.contains(CloneFlags::VFORK).then(|| self.clone())),
             inner: RwLock::new(TaskControlBlockInner {
                 trap_cx_ppn,
+                trap_cx_slot,
                 task_cx: TaskContext::readied_for_switching(kernel_stack_top),
                 task_status: TaskStatus::Ready,
-                parent: Some(Arc::downgrade(self)),
+                parent: Some(Arc::downgrade(&parent)),
                 children: Vec::new(),
                 exit_code: 0,
 
                 rlimit_nofile: RLimit::new(FD_LIMIT, FD_LIMIT),
+                // 其余的资源限制由子进程继承
+                rlimit_as: parent_inner.rlimit_as,
+                rlimit_data: parent_inner.rlimit_data,
+                rlimit_stack: parent_inner.rlimit_stack,
+                rlimit_nproc: parent_inner.rlimit_nproc,
                 robust_list: RobustList::default(),
 
                 // [signal: msg about fork](https://man7.org/linux/man-pages/man7/signal.7.html)
                 sigmask: parent_inner.sigmask.clone(),
                 pending_signals: SigSet::empty(),
+                sig_queue: SigQueue::new(),
+                // 共享地址空间的线程不能共用备用栈
+                sig_alt_stack: if flags.contains(CloneFlags::VM) {
+                    SignalStack::disabled()
+                } else {
+                    parent_inner.sig_alt_stack
+                },
 
-                cwd: parent_inner.cwd.clone(),
                 utime: TimeVal { sec: 0, usec: 0 },
                 stime: TimeVal { sec: 0, usec: 0 },
                 last_enter_umode_time: TimeVal { sec: 0, usec: 0 },
                 last_enter_smode_time: TimeVal { sec: 0, usec: 0 },
                 clear_child_tid: 0,
                 trap_cause: None,
-                interval_timer: None,
+                orig_a0: 0,
+                exe: parent_inner.exe.clone(),
+                cmdline: parent_inner.cmdline.clone(),
+                start_time: get_timeval(),
             }),
         });
 
+        drop(parent_inner);
+
         // push the new process/thread into the children vector
-        parent_inner.children.push(task_control_block.clone());
+        parent.inner_mut().children.push(task_control_block.clone());
         // update the stack pointer in the child process trap context
         let trap_cx = task_control_block.inner_mut().trap_context();
         trap_cx.kernel_sp = kernel_stack_top;
 
-        task_control_block
+        Ok(task_control_block)
     }
 
     /// Attempt to load a page on demand.
@@ -537,15 +763,14 @@ impl TaskControlBlock {
     /// Used for:
     /// - User mode: handler page fault
     /// - Kernel mode: translate_bytes_buffer
+    ///
+    /// Returns -ENOMEM when frames run out. It never waits for memory here, since
+    /// kernel-mode callers may hold the address space or file system locks; the user
+    /// trap handler runs the OOM killer and retries the fault instead.
     pub fn check_lazy(&self, va: VirtAddr) -> isize {
-        let mut memory_set = self.memory_set.write();
-
-        let mmap_start = memory_set.mmap_manager.mmap_start;
-        let mmap_end = memory_set.mmap_manager.mmap_top;
-        let heap_start = VirtAddr::from(memory_set.brk_start);
-        let heap_end = VirtAddr::from(memory_set.brk_start + USER_HEAP_SIZE);
-        let stack_start = VirtAddr::from(memory_set.user_stack_start);
-        let stack_end = VirtAddr::from(memory_set.user_stack_end);
+        let memory_set = self.memory_set();
+        let mut memory_set = memory_set.write();
+
         // fork
         let vpn: VirtPageNum = va.floor();
         let pte = memory_set.translate(vpn);
@@ -555,30 +780,25 @@ impl TaskControlBlock {
         } else {
             if let Some(pte1) = pte {
                 if pte1.is_valid() {
+                    if memory_set.shared_write_fault(vpn) {
+                        return 0;
+                    }
                     return -4;
                 }
             }
         }
 
         // lazy map / lazy alloc heap / lazy alloc stack
-        if va >= stack_start && va < stack_end {
-            memory_set.lazy_alloc_stack(va.floor())
-        } else if va >= heap_start && va <= heap_end {
-            memory_set.lazy_alloc_heap(va.floor())
-        } else if va >= mmap_start && va < mmap_end {
-            memory_set.lazy_mmap(vpn);
-            0
-        } else {
-            warn!("[check_lazy] {:x?}", va);
-            warn!("[check_lazy] mmap_start: 0x{:x}", mmap_start.0);
-            warn!("[check_lazy] mmap_end: 0x{:x}", mmap_end.0);
-            warn!("[check_lazy] heap_start: 0x{:x}", heap_start.0);
-            warn!("[check_lazy] heap_end: 0x{:x}", heap_end.0);
-            -2
+        let result = memory_set.lazy_map(vpn);
+        if result == -2 {
+            warn!("[check_lazy] {:x?} is not in any area", va);
         }
+        result
     }
 
     // Allocate and create a virtual memory address mapping in the process's virtual address space.
+    // Returns ENOMEM when RLIMIT_AS or RLIMIT_DATA would be exceeded, or no free range is left.
+    // MAP_FIXED fails with ENOMEM outside user space and EINVAL over areas owned by the kernel.
     pub fn mmap(
         &self,
         addr: usize,
@@ -587,48 +807,73 @@ impl TaskControlBlock {
         flags: MmapFlags,
         fd: isize,
         offset: usize,
-    ) -> usize {
-        if addr % PAGE_SIZE != 0 {
-            panic!("mmap: addr not aligned");
-        }
-
+    ) -> Result<usize, Errno> {
+        let (as_limit, data_limit) = {
+            let inner = self.inner_ref();
+            (inner.rlimit_as.rlim_cur, inner.rlimit_data.rlim_cur)
+        };
         let fd_table = self.fd_table.read().clone();
-        let mut memory_set = self.memory_set.write();
-        let mut start_va = VirtAddr::from(0);
-        // Right-shift "prot<<1" by one bit to match the permission definition of MapPermission.
-        // Add "1<<4" to include the MapPermission::U permission.
-        if addr == 0 {
-            start_va = memory_set.mmap_manager.get_mmap_top();
-        }
-
-        if flags.contains(MmapFlags::MAP_FIXED) {
-            start_va = VirtAddr::from(addr);
-            memory_set.mmap_manager.remove(start_va, length);
+        let memory_set = self.memory_set();
+        let mut memory_set = memory_set.write();
+        let page_count = length / PAGE_SIZE;
+        let permission = MapPermission::from_vm_prot(prot) | MapPermission::U;
+        let private_writable =
+            !flags.contains(MmapFlags::MAP_SHARED) && permission.contains(MapPermission::W);
+        if memory_set.total_pages() + page_count > as_limit / PAGE_SIZE
+            || private_writable && memory_set.data_pages() + page_count > data_limit / PAGE_SIZE
+        {
+            return_errno!(
+                Errno::ENOMEM,
+                "over RLIMIT_AS/RLIMIT_DATA, length: {:#x}",
+                length
+            );
         }
-        let file = if flags.contains(MmapFlags::MAP_ANONYMOUS) {
-            None
+        let start_vpn = VirtAddr::from(addr).floor();
+        let start_vpn = if flags.contains(MmapFlags::MAP_FIXED) {
+            memory_set.check_fixed_range(addr, length)?;
+            memory_set.unmap_range(start_vpn, VirtPageNum(start_vpn.0 + page_count));
+            start_vpn
+        } else if addr != 0
+            && addr
+                .checked_add(length)
+                .map_or(false, |end| end <= USER_SPACE_END)
+            && memory_set.is_range_free(start_vpn, VirtPageNum(start_vpn.0 + page_count))
+        {
+            // 指定的地址可用时将其作为提示, 否则另外寻找空闲的区间
+            start_vpn
         } else {
-            fd_table[fd as usize].clone()
+            match memory_set.find_free_area(page_count) {
+                Some(start_vpn) => start_vpn,
+                None => return_errno!(Errno::ENOMEM, "no free range for {:#x} bytes", length),
+            }
         };
-        memory_set
-            .mmap_manager
-            .push(start_va, length, prot, flags, offset, file);
+        let start_va = VirtAddr::from(start_vpn);
+        let backing = if flags.contains(MmapFlags::MAP_ANONYMOUS) {
+            VmBacking::Anonymous
+        } else {
+            VmBacking::File {
+                file: fd_table[fd as usize].clone().unwrap(),
+                offset,
+                len: usize::MAX,
+            }
+        };
+        memory_set.insert_area(VmArea::new(
+            start_va,
+            VirtAddr::from(start_va.0 + length),
+            MapType::Framed,
+            VmAreaType::Mmap,
+            permission,
+            backing,
+            flags.contains(MmapFlags::MAP_SHARED),
+        ));
         drop(memory_set);
-        start_va.0
+        Ok(start_va.0)
     }
 
     pub fn munmap(&self, addr: usize, length: usize) -> isize {
         let start_va = VirtAddr(addr);
-        // There may be cases where munmap is called without accessing the mmaped region directly.
-        // It is necessary to check whether the mmaped area has been accessed (i.e., whether
-        // lazy_mmap has been triggered) to prevent panics caused by unmapping page table entries
-        // that do not exist.
-        // This situation occurred during copy-on-write and has been handled (MmapPage.valid).
-        // Refer to the from_copy_on_write function for details.
-        self.memory_set
-            .write()
-            .mmap_manager
-            .remove(start_va, length);
+        // Pages of an area that have never been accessed hold no frame and are not unmapped.
+        self.memory_set().write().munmap(start_va, length);
         0
     }
 
@@ -636,31 +881,34 @@ impl TaskControlBlock {
         self.pid.0
     }
 
+    /// Move the program break by `grow_size` bytes and return the new break.
+    /// The break is left unchanged and the old one is returned if the heap would leave
+    /// [brk_start, brk_start + USER_HEAP_SIZE], exceed RLIMIT_DATA or RLIMIT_AS,
+    /// or overlap another mapping.
     pub fn grow_proc(&self, grow_size: isize) -> usize {
-        let mut memory_set = self.memory_set.write();
+        let (as_limit, data_limit) = {
+            let inner = self.inner_ref();
+            (inner.rlimit_as.rlim_cur, inner.rlimit_data.rlim_cur)
+        };
+        let memory_set = self.memory_set();
+        let mut memory_set = memory_set.write();
         let brk = memory_set.brk;
         let brk_start = memory_set.brk_start;
+        let new_brk = brk as isize + grow_size;
+        if new_brk < brk_start as isize || new_brk as usize > brk_start + USER_HEAP_SIZE {
+            return brk;
+        }
+        let new_brk = new_brk as usize;
         if grow_size > 0 {
-            let growed_addr: usize = brk + grow_size as usize;
-            let limit = brk_start + USER_HEAP_SIZE;
-            if growed_addr > limit {
-                panic!(
-                    "process doesn't have enough memsize to grow! limit:0x{:x}, heap_pt:0x{:x}, growed_addr:0x{:x}, pid:{}",
-                    limit,
-                    brk,
-                    growed_addr,
-                    self.pid.0
-                );
-            }
-            memory_set.brk = growed_addr;
-        } else {
-            let shrinked_addr: usize = brk + grow_size as usize;
-            if shrinked_addr < brk_start {
-                panic!("Memory shrinked to the lowest boundary!")
+            let grow_pages = VirtAddr::from(new_brk).ceil().0 - VirtAddr::from(brk).ceil().0;
+            if memory_set.total_pages() + grow_pages > as_limit / PAGE_SIZE
+                || memory_set.data_pages() + grow_pages > data_limit / PAGE_SIZE
+            {
+                return brk;
             }
-            memory_set.brk = shrinked_addr;
         }
-        return memory_set.brk;
+        memory_set.set_brk(new_brk);
+        memory_set.brk
     }
 }
 
@@ -678,6 +926,8 @@ pub enum TaskStatus {
     Running,
     Blocking,
     Hanging,
+    /// 被信号停止, 见 `ThreadGroup::stop`
+    Stopped,
     Zombie,
 }
 pub fn trap_context_position(tid: usize) -> VirtAddr {
@@ -0,0 +1,374 @@
+//! 线程组
+//!
+//! 以 `CLONE_THREAD` 创建的线程与创建者属于同一个线程组 (即用户态看到的进程),
+//! 线程组以组长 (主线程) 的 pid 即 `tgid` 标识. 线程组记录:
+//! - 组内尚未退出的线程数, 最后一个线程退出时线程组被移除;
+//! - 发给整个进程的信号 (kill), 由组内任一未屏蔽该信号的线程处理;
+//! - 作业控制: 停止信号使组内所有线程在返回用户态之前停下, SIGCONT 或 SIGKILL 使它们恢复运行;
+//! - exit_group 或致命信号结束整个线程组时的退出码;
+//! - 进程组号与会话号, 已退出线程与已回收子进程的 CPU 时间;
+//! - timer_create 创建的 POSIX 定时器与 setitimer 设置的间隔定时器;
+//! - 在 wait4/waitid 中等待子进程状态改变的线程, 子进程退出时将其唤醒.
+
+use super::{
+    notify_job_event, resume_task, unblock_task, wake_task, CpuTimer, PosixTimer, RealTimer,
+    SigQueue, TaskControlBlock, TaskStatus, TimerNotify, PID2TCB, STOP_SIGNALS,
+};
+use crate::syscall::impls::Errno;
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use nix::{itimerval, IntervalTimerType, SigInfo, SigSet, Signal, TimeVal, SI_KERNEL};
+use spin::{lazy::Lazy, Mutex, MutexGuard};
+
+pub static TGID2GROUP: Lazy<Mutex<BTreeMap<usize, Arc<ThreadGroup>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+pub fn tgid2group(tgid: usize) -> Option<Arc<ThreadGroup>> {
+    TGID2GROUP.lock().get(&tgid).cloned()
+}
+
+/// 所有线程组的快照
+pub fn all_thread_groups() -> Vec<Arc<ThreadGroup>> {
+    TGID2GROUP.lock().values().cloned().collect()
+}
+
+/// 进程组 `pgid` 中的所有进程
+pub fn process_group(pgid: usize) -> Vec<Arc<ThreadGroup>> {
+    all_thread_groups()
+        .into_iter()
+        .filter(|group| group.pgid() == pgid)
+        .collect()
+}
+
+/// 向进程组发送信号, 进程组不存在时返回 false
+pub fn kill_process_group(pgid: usize, signal: SigSet) -> bool {
+    let groups = process_group(pgid);
+    for group in groups.iter() {
+        group.send_signal(signal);
+    }
+    !groups.is_empty()
+}
+
+pub struct ThreadGroup {
+    pub tgid: usize,
+    /// 进程退出时发给父进程的信号, 0 表示不发送
+    pub exit_signal: usize,
+    inner: Mutex<ThreadGroupInner>,
+    /// ITIMER_REAL
+    real_timer: Arc<RealTimer>,
+    /// 等待子进程状态改变的线程
+    child_waiters: Mutex<Vec<Arc<TaskControlBlock>>>,
+}
+
+/// 子进程停止或恢复运行, 等待父进程通过 wait4/waitid 获取
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobEvent {
+    /// 被信号停止
+    Stopped(u32),
+    /// 被 SIGCONT 恢复
+    Continued,
+}
+
+pub struct ThreadGroupInner {
+    /// 组内尚未退出的线程数
+    live_threads: usize,
+    /// 发给整个线程组的待处理信号
+    pub pending_signals: SigSet,
+    /// 发给整个线程组的待处理信号的 siginfo
+    pub sig_queue: SigQueue,
+    /// 线程组整体退出 (exit_group 或致命信号) 时的退出码, 以第一次为准
+    pub exit_code: Option<i32>,
+    /// 进程组号
+    pub pgid: usize,
+    /// 会话号
+    pub sid: usize,
+    /// 线程组是否被停止
+    stopped: bool,
+    /// 已经停下的线程, 不在任何调度队列中
+    stopped_threads: Vec<Arc<TaskControlBlock>>,
+    /// 尚未被父进程获取的停止/恢复事件
+    pub job_event: Option<JobEvent>,
+    /// 已退出线程的用户态/内核态时间
+    pub utime: TimeVal,
+    pub stime: TimeVal,
+    /// 已回收子进程 (及其回收的子进程) 的用户态/内核态时间
+    pub cutime: TimeVal,
+    pub cstime: TimeVal,
+    /// POSIX 定时器, 以定时器 id 索引
+    timers: BTreeMap<usize, Arc<PosixTimer>>,
+    /// ITIMER_VIRTUAL 与 ITIMER_PROF
+    virtual_timer: CpuTimer,
+    prof_timer: CpuTimer,
+}
+
+impl ThreadGroup {
+    /// 为新进程创建线程组, 组内只有组长一个线程
+    pub fn new(tgid: usize, pgid: usize, sid: usize, exit_signal: usize) -> Arc<Self> {
+        let group = Arc::new_cyclic(|group| Self {
+            tgid,
+            exit_signal,
+            inner: Mutex::new(ThreadGroupInner {
+                live_threads: 1,
+                pending_signals: SigSet::empty(),
+                sig_queue: SigQueue::new(),
+                exit_code: None,
+                pgid,
+                sid,
+                stopped: false,
+                stopped_threads: Vec::new(),
+                job_event: None,
+                utime: TimeVal::new(),
+                stime: TimeVal::new(),
+                cutime: TimeVal::new(),
+                cstime: TimeVal::new(),
+                timers: BTreeMap::new(),
+                virtual_timer: CpuTimer::new(),
+                prof_timer: CpuTimer::new(),
+            }),
+            real_timer: RealTimer::new(group.clone()),
+            child_waiters: Mutex::new(Vec::new()),
+        });
+        TGID2GROUP.lock().insert(tgid, group.clone());
+        group
+    }
+    pub fn inner(&self) -> MutexGuard<'_, ThreadGroupInner> {
+        self.inner.lock()
+    }
+    /// 组内加入一个新线程
+    pub fn add_thread(&self) {
+        self.inner().live_threads += 1;
+    }
+    /// 组内一个线程退出并计入它的 CPU 时间, 返回组内剩余的线程数.
+    /// 最后一个线程退出时删除定时器, 停止 ITIMER_REAL 并将线程组移除
+    pub fn exit_thread(&self, utime: TimeVal, stime: TimeVal) -> usize {
+        let mut inner = self.inner();
+        inner.live_threads -= 1;
+        inner.utime = inner.utime + utime;
+        inner.stime = inner.stime + stime;
+        let live_threads = inner.live_threads;
+        if live_threads == 0 {
+            inner.timers.clear();
+        }
+        drop(inner);
+        if live_threads == 0 {
+            self.real_timer.stop();
+            TGID2GROUP.lock().remove(&self.tgid);
+        }
+        live_threads
+    }
+    /// 组内是否还有未退出的线程
+    pub fn is_alive(&self) -> bool {
+        self.inner().live_threads > 0
+    }
+    /// 线程组整体退出时的退出码
+    pub fn exit_code(&self) -> Option<i32> {
+        self.inner().exit_code
+    }
+    pub fn pgid(&self) -> usize {
+        self.inner().pgid
+    }
+    pub fn sid(&self) -> usize {
+        self.inner().sid
+    }
+    /// 结束整个线程组, 返回生效的退出码 (以第一次为准). 第一次调用时向组内除 `except` 外的线程
+    /// 发送 SIGKILL 并将其唤醒, 它们在下次返回用户态之前退出
+    pub fn kill(&self, exit_code: i32, except: Option<usize>) -> i32 {
+        let mut inner = self.inner();
+        if let Some(exit_code) = inner.exit_code {
+            return exit_code;
+        }
+        inner.exit_code = Some(exit_code);
+        drop(inner);
+        for thread in self.threads() {
+            if Some(thread.pid()) == except {
+                continue;
+            }
+            thread
+                .inner_mut()
+                .pending_signals
+                .add(Signal::SIGKILL as u32);
+            wake_task(thread);
+        }
+        // 停下的线程也要恢复运行才能退出
+        self.continue_threads();
+        exit_code
+    }
+    /// 线程组是否被停止
+    pub fn is_stopped(&self) -> bool {
+        self.inner().stopped
+    }
+    /// 以信号 `signum` 停止线程组, 组内线程在返回用户态之前停下. 线程组正在退出时不会停止.
+    /// 返回是否产生了新的停止事件
+    pub fn stop(&self, signum: u32) -> bool {
+        let mut inner = self.inner();
+        if inner.exit_code.is_some() || inner.stopped {
+            return false;
+        }
+        inner.stopped = true;
+        inner.job_event = Some(JobEvent::Stopped(signum));
+        true
+    }
+    /// 当前线程在线程组被停止时停下, 调用者随后切换到其他任务. 线程组没有被停止时返回 false
+    pub fn park(&self, task: &Arc<TaskControlBlock>) -> bool {
+        let mut inner = self.inner();
+        if !inner.stopped {
+            return false;
+        }
+        inner.stopped_threads.push(task.clone());
+        true
+    }
+    /// 恢复被停止的线程组, 返回线程组之前是否被停止
+    fn continue_threads(&self) -> bool {
+        let mut inner = self.inner();
+        let stopped = core::mem::replace(&mut inner.stopped, false);
+        let threads = core::mem::take(&mut inner.stopped_threads);
+        drop(inner);
+        for thread in threads {
+            thread.inner_mut().task_status = TaskStatus::Ready;
+            resume_task(thread);
+        }
+        stopped
+    }
+    /// 收到 SIGCONT 时恢复运行, 并丢弃尚未处理的停止信号
+    fn resume(&self) {
+        self.discard_signals(STOP_SIGNALS);
+        for thread in self.threads() {
+            let mut inner = thread.inner_mut();
+            let inner = &mut *inner;
+            inner
+                .sig_queue
+                .discard(&mut inner.pending_signals, STOP_SIGNALS);
+        }
+        if self.continue_threads() {
+            self.inner().job_event = Some(JobEvent::Continued);
+            notify_job_event(self);
+        }
+    }
+    /// 丢弃发给线程组的待处理信号
+    fn discard_signals(&self, signals: SigSet) {
+        let mut inner = self.inner();
+        let inner = &mut *inner;
+        inner.sig_queue.discard(&mut inner.pending_signals, signals);
+    }
+    /// 向线程组发送信号
+    pub fn add_signal(&self, signal: SigSet) {
+        self.inner().pending_signals |= signal;
+    }
+    /// 向进程发送内核产生的信号, si_code 为 SI_KERNEL
+    pub fn send_signal(&self, signal: SigSet) {
+        let mut signal = signal;
+        while let Some(signum) = signal.fetch() {
+            signal.sub(signum);
+            self.send_siginfo(SigInfo::new(signum as i32, SI_KERNEL, 0, 0));
+        }
+    }
+    /// 向进程发送带有 siginfo 的信号, 实时信号排队, 队列已满时返回 false.
+    /// SIGKILL 立即结束组内所有线程, 其余信号由组内任一未屏蔽该信号的线程处理.
+    /// 与 Linux 一样, SIGCONT 在发送时就恢复线程组, 即使它被屏蔽或忽略
+    pub fn send_siginfo(&self, info: SigInfo) -> bool {
+        let signum = info.si_signo as u32;
+        if signum == Signal::SIGKILL as u32 {
+            self.kill(-(Signal::SIGKILL as i32), None);
+            return true;
+        }
+        if STOP_SIGNALS.if_contains(signum) {
+            self.discard_signals(SigSet::SIGCONT);
+        }
+        if signum == Signal::SIGCONT as u32 {
+            self.resume();
+        }
+        let mut inner = self.inner();
+        let inner = &mut *inner;
+        inner.sig_queue.enqueue(&mut inner.pending_signals, info)
+    }
+    /// 创建 POSIX 定时器, 使用最小的空闲 id. `notify` 由定时器 id 得到到期时的通知方式
+    pub fn create_timer(self: &Arc<Self>, notify: impl FnOnce(usize) -> TimerNotify) -> usize {
+        let mut inner = self.inner();
+        let id = (0usize..)
+            .find(|id| !inner.timers.contains_key(id))
+            .unwrap();
+        inner
+            .timers
+            .insert(id, PosixTimer::new(id, notify(id), self));
+        id
+    }
+    pub fn timer(&self, id: usize) -> Option<Arc<PosixTimer>> {
+        self.inner().timers.get(&id).cloned()
+    }
+    /// 停止并删除 POSIX 定时器, 定时器不存在时返回 false
+    pub fn delete_timer(&self, id: usize) -> bool {
+        let timer = self.inner().timers.remove(&id);
+        match timer {
+            Some(timer) => {
+                timer.set(None, 0);
+                true
+            }
+            None => false,
+        }
+    }
+    /// 删除所有 POSIX 定时器, 用于 exec
+    pub fn clear_timers(&self) {
+        let timers = core::mem::take(&mut self.inner().timers);
+        for timer in timers.values() {
+            timer.set(None, 0);
+        }
+    }
+    /// 间隔定时器的当前值
+    pub fn itimer(&self, which: IntervalTimerType) -> itimerval {
+        match which {
+            IntervalTimerType::Real => self.real_timer.get(),
+            IntervalTimerType::Virtual => self.inner().virtual_timer.get(),
+            IntervalTimerType::Profile => self.inner().prof_timer.get(),
+        }
+    }
+    /// 设置间隔定时器, 返回原来的值
+    pub fn set_itimer(&self, which: IntervalTimerType, new: itimerval) -> Result<itimerval, Errno> {
+        match which {
+            IntervalTimerType::Real => self.real_timer.set(new),
+            IntervalTimerType::Virtual => Ok(self.inner().virtual_timer.set(new)),
+            IntervalTimerType::Profile => Ok(self.inner().prof_timer.set(new)),
+        }
+    }
+    /// 组内线程消耗了用户态/内核态时间, ITIMER_VIRTUAL 与 ITIMER_PROF 随之倒数,
+    /// 到期时向线程组发送 SIGVTALRM/SIGPROF
+    pub fn charge_cpu_time(&self, utime: TimeVal, stime: TimeVal) {
+        let mut inner = self.inner();
+        let mut signals = SigSet::empty();
+        if inner.virtual_timer.charge(utime) {
+            signals |= SigSet::SIGVTALRM;
+        }
+        if inner.prof_timer.charge(utime + stime) {
+            signals |= SigSet::SIGPROF;
+        }
+        drop(inner);
+        if !signals.is_empty() {
+            self.send_signal(signals);
+        }
+    }
+    /// 组内线程开始等待子进程状态改变. 调用者需要在释放返回的锁之前阻塞自己,
+    /// 并在持有该锁时检查子进程的状态, 以免错过唤醒
+    pub fn child_waiters(&self) -> MutexGuard<'_, Vec<Arc<TaskControlBlock>>> {
+        self.child_waiters.lock()
+    }
+    /// 线程被信号唤醒时将其从等待队列中移除
+    pub fn remove_child_waiter(&self, task: &Arc<TaskControlBlock>) {
+        self.child_waiters
+            .lock()
+            .retain(|waiter| !Arc::ptr_eq(waiter, task));
+    }
+    /// 子进程状态改变, 唤醒所有等待的线程
+    pub fn wake_child_waiters(&self) {
+        let waiters = core::mem::take(&mut *self.child_waiters.lock());
+        for waiter in waiters {
+            unblock_task(waiter);
+        }
+    }
+    /// 组内尚未退出的线程
+    pub fn threads(&self) -> Vec<Arc<TaskControlBlock>> {
+        PID2TCB
+            .lock()
+            .values()
+            .filter(|task| task.tgid == self.tgid)
+            .cloned()
+            .collect()
+    }
+}
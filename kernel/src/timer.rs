@@ -1,14 +1,12 @@
 #![allow(unused)]
 
-use crate::{
-    boards::CLOCK_FREQ,
-    sbi::set_timer,
-    task::{current_add_signal, current_task, TaskControlBlock},
-};
-use alloc::sync::Arc;
+use crate::{boards::CLOCK_FREQ, sbi::set_timer};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 use core::ops::{Add, Sub};
-use nix::{SigMask, TimeVal};
+use nix::TimeVal;
 use riscv::register::time;
+use spin::{lazy::Lazy, Mutex};
 
 pub const TIME_SLICE: usize = 100;
 pub const MSEC_PER_SEC: usize = 1000;
@@ -52,23 +50,89 @@ pub fn set_next_trigger() {
     set_timer((get_time() + CLOCK_FREQ / TIME_SLICE) as u64);
 }
 
-pub fn check_interval_timer() {
-    let task = current_task().unwrap();
-    let mut inner = task.inner_mut();
-    if inner.interval_timer.is_none() {
-        return;
+/// 内核定时器到期时的回调
+pub trait TimerCallback: Send + Sync {
+    /// `key` 为加入定时器时给出的值, 可用于识别已经被取消的定时器
+    fn on_expire(self: Arc<Self>, key: usize);
+}
+
+/// 时间轮的槽数
+const WHEEL_SLOTS: usize = 256;
+/// 每个槽对应的时间 (ns), 与时钟中断的间隔相同
+const WHEEL_GRANULARITY: usize = NSEC_PER_SEC / TIME_SLICE;
+
+struct WheelEntry {
+    /// 到期时间 (ns)
+    deadline: usize,
+    callback: Weak<dyn TimerCallback>,
+    key: usize,
+}
+
+/// 哈希时间轮: 到期时间落在第 n 个时间片的定时器放在第 n % WHEEL_SLOTS 个槽中,
+/// 每次推进时只检查经过的槽, 超过一圈的定时器留在槽中等待下一圈
+struct TimerWheel {
+    slots: Vec<Vec<WheelEntry>>,
+    /// 下一个需要检查的时间片, 当前时间片在推进后仍会被再次检查
+    next_tick: usize,
+}
+
+impl TimerWheel {
+    fn new() -> Self {
+        Self {
+            slots: (0..WHEEL_SLOTS).map(|_| Vec::new()).collect(),
+            next_tick: get_time_ns() / WHEEL_GRANULARITY,
+        }
     }
+    fn add(&mut self, entry: WheelEntry) {
+        // 已经过期的定时器在下一次推进时触发
+        let tick = (entry.deadline / WHEEL_GRANULARITY).max(self.next_tick);
+        self.slots[tick % WHEEL_SLOTS].push(entry);
+    }
+    /// 推进到当前时间, 取出所有到期的定时器
+    fn advance(&mut self, now: usize) -> Vec<WheelEntry> {
+        let now_tick = now / WHEEL_GRANULARITY;
+        let mut expired = Vec::new();
+        if now_tick < self.next_tick {
+            return expired;
+        }
+        let ticks = (now_tick - self.next_tick + 1).min(WHEEL_SLOTS);
+        for tick in self.next_tick..self.next_tick + ticks {
+            let slot = &mut self.slots[tick % WHEEL_SLOTS];
+            let mut i = 0;
+            while i < slot.len() {
+                if slot[i].deadline <= now {
+                    expired.push(slot.swap_remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        self.next_tick = now_tick;
+        expired
+    }
+}
+
+static TIMER_WHEEL: Lazy<Mutex<TimerWheel>> = Lazy::new(|| Mutex::new(TimerWheel::new()));
+
+/// 加入一个在 `deadline` (ns) 到期的内核定时器. 回调对象被释放后定时器自动失效
+pub fn add_timer(deadline: usize, callback: Weak<dyn TimerCallback>, key: usize) {
+    TIMER_WHEEL.lock().add(WheelEntry {
+        deadline,
+        callback,
+        key,
+    });
+}
 
-    let itimer = inner.interval_timer.as_mut().unwrap();
-    let duration = get_timeval() - itimer.creation_time;
-    let itime_value = itimer.timer_value;
-    // INFO: 现在只处理 ITIMER_REAL
-    if duration > itime_value.it_value {
-        if itime_value.it_interval == TimeVal::zero() {
-            inner.interval_timer = None;
-        } else {
-            itimer.timer_value.it_value = itimer.timer_value.it_interval;
+/// 触发到期的内核定时器. 由调度循环反复调用, 回调在释放时间轮的锁之后执行
+pub fn timer_tick() {
+    // 其他核正在推进时跳过
+    let expired = match TIMER_WHEEL.try_lock() {
+        Some(mut wheel) => wheel.advance(get_time_ns()),
+        None => return,
+    };
+    for entry in expired {
+        if let Some(callback) = entry.callback.upgrade() {
+            callback.on_expire(entry.key);
         }
-        inner.pending_signals.set(SigMask::SIGALRM, true);
     }
 }
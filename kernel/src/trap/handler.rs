@@ -3,14 +3,15 @@ use crate::mm::VirtAddr;
 use crate::syscall::SYS_SIGRETURN;
 use crate::{
     consts::TRAMPOLINE,
-    syscall::dispatcher::syscall,
+    random::add_randomness,
+    syscall::{dispatcher::syscall, impls::Errno},
     task::{
-        current_add_signal, current_task, current_trap_cx, exec_signal_handlers,
+        current_add_signal, current_task, current_trap_cx, exec_signal_handlers, oom_kill,
         suspend_current_and_run_next,
     },
-    timer::{check_interval_timer, get_timeval},
+    timer::get_timeval,
 };
-use nix::SigSet;
+use nix::{SigSet, TimeVal};
 use riscv::register::{mcause, mtval};
 use riscv::register::{
     scause::{self, Exception, Interrupt, Trap},
@@ -28,11 +29,14 @@ pub fn user_trap_handler() -> ! {
     let mut inner = task.inner_mut();
 
     inner.trap_cause = Some(scause);
+    inner.orig_a0 = inner.trap_context().a0();
+    add_randomness(scause.bits() ^ inner.trap_context().sepc);
 
     let diff = get_timeval() - inner.last_enter_umode_time;
     inner.add_utime(diff);
     inner.set_last_enter_smode(get_timeval());
     drop(inner);
+    task.thread_group.charge_cpu_time(diff, TimeVal::zero());
     drop(task);
     let mut is_sigreturn = false;
 
@@ -68,7 +72,19 @@ pub fn user_trap_handler() -> ! {
             }
             let task = current_task().unwrap();
             let lazy = task.check_lazy(va);
-            if lazy != 0 {
+            let tgid = task.tgid;
+            drop(task);
+            if lazy == -(Errno::ENOMEM as isize) {
+                // 页帧耗尽时结束驻留页帧最多的进程, 让出 CPU 等它退出后回到用户态,
+                // 重新执行触发缺页的指令. 这里不持有任何锁, 可以安全地让出 CPU
+                match oom_kill() {
+                    Some(victim) if victim != tgid => suspend_current_and_run_next(),
+                    _ => current_add_signal(SigSet::SIGSEGV),
+                }
+            } else if lazy == -(Errno::EIO as isize) {
+                // 读取映射的文件出错
+                current_add_signal(SigSet::SIGBUS);
+            } else if lazy != 0 {
                 current_add_signal(SigSet::SIGSEGV);
             }
         }
@@ -99,8 +115,6 @@ pub fn user_trap_handler() -> ! {
         ),
     }
 
-    check_interval_timer();
-
     if !is_sigreturn {
         exec_signal_handlers();
     }
@@ -9,6 +9,7 @@ use crate::task::trap_context_position;
 use crate::task::{current_task, current_user_token};
 use crate::timer::{get_timeval, set_next_trigger};
 use core::arch::{asm, global_asm};
+use nix::TimeVal;
 use riscv::register::{mtvec::TrapMode, sie, stvec};
 
 global_asm!(include_str!("trampoline.S"));
@@ -59,8 +60,9 @@ pub fn trap_return() -> ! {
         }
         inner.trap_cause = None;
     }
-    let trap_addr = trap_context_position(task.pid() - task.tgid).0;
+    let trap_addr = trap_context_position(inner.trap_cx_slot).0;
     drop(inner);
+    task.thread_group.charge_cpu_time(TimeVal::zero(), diff);
     drop(task);
 
     let trapret_addr = user_trapret as usize - user_trapvec as usize + TRAMPOLINE;